@@ -0,0 +1,142 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use stylist::embedding::*;
+
+/// Number of entries of the benchmarked stores
+const ENTRIES: usize = 100_000;
+/// Dimensions of the benchmarked vectors, as in the served stores
+const DIMENSIONS: usize = 30;
+/// Number of results the searches ask for
+const TOP_N: usize = 10;
+
+// Helper function to create reproducible pseudo-random vectors in [-1, 1)
+fn create_vectors(count: usize, seed: u64) -> Vec<Vec<f64>> {
+    let mut state: u64 = seed;
+    (0..count)
+        .map(|_| {
+            (0..DIMENSIONS)
+                .map(|_| {
+                    state = state
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    (state >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Helper function to create a store holding `ENTRIES` random entries
+fn create_store() -> InMemoryVectorStore {
+    let mut store = InMemoryVectorStore::new(DIMENSIONS, vec![], vec![], 0).unwrap();
+    let entries: Vec<DataEntry> = create_vectors(ENTRIES, 1)
+        .into_iter()
+        .enumerate()
+        .map(|(index, vector)| DataEntry {
+            id: index + 1,
+            name: format!("entry {}", index + 1),
+            vector,
+            descriptions: vec![],
+            image: None,
+            created_at: 0,
+            image_hash: None,
+            metadata: serde_json::Value::Null,
+            version: 0,
+            gender: None,
+        })
+        .collect();
+    store.set_entries(entries).unwrap();
+    store
+}
+
+// Helper function ranking every entry with a full sort, the selection the
+// search heap replaced
+fn full_sort(store: &InMemoryVectorStore, query: &[f64], top_n: usize) -> Vec<SearchResult> {
+    let entries: Vec<&DataEntry> = store.iter().collect();
+    let mut scored: Vec<(f64, usize)> = entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| (cosine_similarity(query, &entry.vector), index))
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+    scored
+        .into_iter()
+        .take(top_n)
+        .map(|(score, index)| SearchResult {
+            data_entry: (*entries[index]).clone(),
+            score,
+        })
+        .collect()
+}
+
+fn bench_top_n_selection(c: &mut Criterion) {
+    let store: InMemoryVectorStore = create_store();
+    let query: Vec<f64> = create_vectors(1, 2).remove(0);
+    let options = SearchOptions::top_n(TOP_N);
+
+    let mut group = c.benchmark_group("top_n_selection_100k");
+    group.bench_function("heap", |b| {
+        b.iter(|| {
+            store
+                .search_vector(black_box(query.clone()), &options)
+                .unwrap()
+        })
+    });
+    group.bench_function("full_sort", |b| {
+        b.iter(|| full_sort(&store, black_box(&query), TOP_N))
+    });
+    group.finish();
+}
+
+fn bench_cosine_similarity(c: &mut Criterion) {
+    let rows: Vec<Vec<f64>> = create_vectors(ENTRIES, 1);
+    let query: Vec<f64> = create_vectors(1, 2).remove(0);
+
+    let mut group = c.benchmark_group("cosine_similarity_100k");
+    group.bench_function("scalar", |b| {
+        b.iter(|| {
+            rows.iter()
+                .map(|row| cosine_similarity(black_box(&query), row))
+                .sum::<f64>()
+        })
+    });
+    group.bench_function("simd", |b| {
+        b.iter(|| {
+            rows.iter()
+                .map(|row| cosine_similarity_simd(black_box(&query), row))
+                .sum::<f64>()
+        })
+    });
+    group.finish();
+}
+
+fn bench_contiguous_vectors(c: &mut Criterion) {
+    let scattered: InMemoryVectorStore = create_store();
+    let contiguous: InMemoryVectorStore = create_store().with_contiguous_vectors(true);
+    let query: Vec<f64> = create_vectors(1, 2).remove(0);
+    let options = SearchOptions::top_n(TOP_N);
+    // build the buffer up front, it is reused until the store changes
+    contiguous.search_vector(query.clone(), &options).unwrap();
+
+    let mut group = c.benchmark_group("vector_layout_100k");
+    for (name, store) in [("per_entry", &scattered), ("contiguous", &contiguous)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                store
+                    .search_vector(black_box(query.clone()), &options)
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_top_n_selection,
+    bench_cosine_similarity,
+    bench_contiguous_vectors
+);
+criterion_main!(benches);
@@ -0,0 +1,94 @@
+use std::fmt::Display;
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    HttpResponse,
+};
+use log::warn;
+
+use crate::{config, routes::BasicResponse};
+
+/// Error variants raised when a request to a protected route is not authorized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthErrors {
+    /// No API token is configured, so protected routes are disabled
+    NoTokenConfigured,
+    /// The request carries no bearer token
+    MissingToken,
+    /// The request's bearer token does not match the configured one
+    InvalidToken,
+}
+
+impl std::error::Error for AuthErrors {}
+
+impl Display for AuthErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoTokenConfigured => write!(
+                f,
+                "This route is disabled until STYLIST_API_TOKEN is configured!"
+            ),
+            Self::MissingToken => write!(f, "A bearer token is required!"),
+            Self::InvalidToken => write!(f, "The bearer token is invalid!"),
+        }
+    }
+}
+
+/// Check an `Authorization` header value against the configured API token
+///
+/// # Arguments
+/// * `authorization` - Value of the request's `Authorization` header, if any
+/// * `token` - The configured API token, if any
+pub fn authorize(authorization: Option<&str>, token: Option<&str>) -> Result<(), AuthErrors> {
+    let token: &str = token
+        .filter(|token| !token.is_empty())
+        .ok_or(AuthErrors::NoTokenConfigured)?;
+    let provided: &str = authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(AuthErrors::MissingToken)?;
+
+    if constant_time_eq(provided.trim().as_bytes(), token.as_bytes()) {
+        Ok(())
+    } else {
+        Err(AuthErrors::InvalidToken)
+    }
+}
+
+// Helper function to compare secrets without leaking where they differ
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Middleware requiring the configured API token as a bearer token
+///
+/// Attach it to protected routes with `wrap = "from_fn(require_token)"`.
+/// Requests without a valid token get 401 Unauthorized, and every request
+/// gets 403 Forbidden while `STYLIST_API_TOKEN` is not set.
+pub async fn require_token<B: MessageBody>(
+    request: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let authorization: Option<&str> = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+
+    if let Err(e) = authorize(authorization, config::get().api_token.as_deref()) {
+        warn!("Rejected request to {}: {}", request.path(), e);
+        let mut response = match e {
+            AuthErrors::NoTokenConfigured => HttpResponse::Forbidden(),
+            AuthErrors::MissingToken | AuthErrors::InvalidToken => HttpResponse::Unauthorized(),
+        };
+        let response = response.json(BasicResponse::<String> {
+            status: false,
+            message: e.to_string(),
+            data: None,
+        });
+        return Ok(request.into_response(response).map_into_right_body());
+    }
+
+    Ok(next.call(request).await?.map_into_left_body())
+}
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+
+use crate::embedding::{cosine_similarity, SearchResult};
+
+/// Maximum number of assignment rounds of [`kmeans`]
+const KMEANS_ITERATIONS: usize = 20;
+
+/// A group of similar search results, represented by its best-scoring member
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusteredResult {
+    /// The best-scoring result of the cluster
+    pub representative: SearchResult,
+    /// Number of results in the cluster, the representative included
+    pub size: usize,
+}
+
+/// Group vectors into at most `k` clusters with k-means over cosine similarity
+///
+/// The initial centroids are picked farthest-first, starting with the first
+/// vector, so the clustering is deterministic. Vectors are assigned to the
+/// centroid they are most similar to, and a cluster left empty keeps its
+/// previous centroid.
+///
+/// # Arguments
+/// * `vectors` - The vectors to cluster
+/// * `k` - Number of clusters, clamped between 1 and the number of vectors
+/// * `iterations` - Maximum number of assignment rounds
+///
+/// # Returns
+/// The cluster label of each vector, in `0..k`
+pub fn kmeans(vectors: &[&[f64]], k: usize, iterations: usize) -> Vec<usize> {
+    if vectors.is_empty() {
+        return Vec::new();
+    }
+    let k: usize = k.clamp(1, vectors.len());
+
+    // farthest-first: each new centroid is the vector least similar to the
+    // closest centroid picked so far
+    let mut seeds: Vec<usize> = vec![0];
+    while seeds.len() < k {
+        let next: Option<usize> = (0..vectors.len())
+            .filter(|index| !seeds.contains(index))
+            .map(|index| {
+                let closest: f64 = seeds
+                    .iter()
+                    .map(|seed| cosine_similarity(vectors[index], vectors[*seed]))
+                    .fold(f64::NEG_INFINITY, f64::max);
+                (index, closest)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index);
+        match next {
+            Some(index) => seeds.push(index),
+            None => break,
+        }
+    }
+    let mut centroids: Vec<Vec<f64>> = seeds.iter().map(|seed| vectors[*seed].to_vec()).collect();
+
+    let mut labels: Vec<usize> = vec![usize::MAX; vectors.len()];
+    for _ in 0..iterations {
+        let assigned: Vec<usize> = vectors
+            .iter()
+            .map(|vector| nearest_centroid(vector, &centroids))
+            .collect();
+        if assigned == labels {
+            break;
+        }
+        labels = assigned;
+
+        for (cluster, centroid) in centroids.iter_mut().enumerate() {
+            let members: Vec<&[f64]> = vectors
+                .iter()
+                .zip(&labels)
+                .filter(|(_, label)| **label == cluster)
+                .map(|(vector, _)| *vector)
+                .collect();
+            if !members.is_empty() {
+                *centroid = mean(&members);
+            }
+        }
+    }
+
+    labels
+}
+
+/// Cluster ranked search results, keeping one representative per cluster
+///
+/// Results are clustered by their entries' vectors with [`kmeans`]. As the
+/// results come ranked, the first result of each cluster is its best-scoring
+/// one and becomes the representative. Clusters are returned in the order of
+/// their representatives, i.e. by descending score.
+///
+/// # Arguments
+/// * `results` - Search results, most similar first
+/// * `clusters` - Number of clusters to form
+pub fn cluster_results(results: Vec<SearchResult>, clusters: usize) -> Vec<ClusteredResult> {
+    let labels: Vec<usize> = {
+        let vectors: Vec<&[f64]> = results
+            .iter()
+            .map(|result| result.data_entry.vector.as_slice())
+            .collect();
+        kmeans(&vectors, clusters, KMEANS_ITERATIONS)
+    };
+
+    let mut grouped: Vec<(usize, ClusteredResult)> = Vec::new();
+    for (result, label) in results.into_iter().zip(labels) {
+        match grouped.iter_mut().find(|(existing, _)| *existing == label) {
+            Some((_, cluster)) => cluster.size += 1,
+            None => grouped.push((
+                label,
+                ClusteredResult {
+                    representative: result,
+                    size: 1,
+                },
+            )),
+        }
+    }
+
+    grouped.into_iter().map(|(_, cluster)| cluster).collect()
+}
+
+// Helper function to find the centroid a vector is most similar to
+fn nearest_centroid(vector: &[f64], centroids: &[Vec<f64>]) -> usize {
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(cluster, centroid)| (cluster, cosine_similarity(vector, centroid)))
+        .fold((0, f64::NEG_INFINITY), |best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        })
+        .0
+}
+
+// Helper function to average vectors of the same length
+fn mean(vectors: &[&[f64]]) -> Vec<f64> {
+    let mut sum: Vec<f64> = vec![0.0; vectors[0].len()];
+    for vector in vectors {
+        for (total, value) in sum.iter_mut().zip(vector.iter()) {
+            *total += value;
+        }
+    }
+
+    sum.iter()
+        .map(|total| total / vectors.len() as f64)
+        .collect()
+}
@@ -0,0 +1,307 @@
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    fs::{self, File},
+    io::{BufWriter, ErrorKind, Write},
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Error, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::embedding::Gender;
+
+/// An upload whose embedding failed, kept so it can be retried
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// Position of the upload in the queue, assigned by [`DeadLetterQueue::push`]
+    pub id: u64,
+    /// Name of the uploaded clothes
+    pub name: String,
+    /// Gender the clothes are meant for
+    pub gender: Gender,
+    /// Descriptions of the uploaded clothes
+    #[serde(default)]
+    pub descriptions: Vec<String>,
+    /// Metadata of the uploaded clothes
+    #[serde(default)]
+    pub metadata: Value,
+    /// The uploaded image, in base64
+    pub image: String,
+    /// Why the last embedding attempt failed
+    pub error: String,
+    /// When the last attempt failed, in seconds since the Unix epoch
+    pub failed_at: u64,
+}
+
+impl DeadLetter {
+    /// Describe a failed upload, to be pushed onto a [`DeadLetterQueue`]
+    ///
+    /// # Arguments
+    /// * `name` - Name of the uploaded clothes
+    /// * `gender` - Gender the clothes are meant for
+    /// * `descriptions` - Descriptions of the uploaded clothes
+    /// * `metadata` - Metadata of the uploaded clothes
+    /// * `image` - The uploaded image, in base64
+    /// * `error` - Why the embedding failed
+    pub fn new(
+        name: String,
+        gender: Gender,
+        descriptions: Vec<String>,
+        metadata: Value,
+        image: String,
+        error: &Error,
+    ) -> Self {
+        Self {
+            id: 0,
+            name,
+            gender,
+            descriptions,
+            metadata,
+            image,
+            error: error.to_string(),
+            failed_at: now_secs(),
+        }
+    }
+}
+
+/// What the queue file holds
+#[derive(Debug, Serialize, Deserialize)]
+struct PersistedQueue<'a> {
+    /// ID given to the next pushed upload, so IDs are never reused
+    next_id: u64,
+    /// Queued uploads, from oldest to newest
+    letters: Cow<'a, VecDeque<DeadLetter>>,
+}
+
+/// Bounded queue of failed uploads, dropping the oldest beyond a capacity
+///
+/// When a path is set, the queue is written to it after every change, so
+/// queued uploads survive a restart. Writes happen on a thread of their own,
+/// in order, and dropping the queue waits for the pending ones.
+#[derive(Debug)]
+pub struct DeadLetterQueue {
+    /// Maximum number of uploads kept
+    capacity: usize,
+    /// ID given to the next pushed upload
+    next_id: u64,
+    /// Queued uploads, from oldest to newest
+    letters: VecDeque<DeadLetter>,
+    /// Sends the serialized queue to the writer thread, if persisted
+    writer: Option<Sender<Vec<u8>>>,
+    /// Thread writing the queue to its file, if persisted
+    writer_thread: Option<JoinHandle<()>>,
+}
+
+impl DeadLetterQueue {
+    /// Create an empty, in-memory queue
+    ///
+    /// # Arguments
+    /// * `capacity` - Maximum number of uploads to keep, 0 keeps none
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            next_id: 1,
+            letters: VecDeque::new(),
+            writer: None,
+            writer_thread: None,
+        }
+    }
+
+    /// Persist the queue to a file, restoring the uploads already saved there
+    ///
+    /// A missing file starts an empty queue. A file that cannot be parsed,
+    /// e.g. one torn by a crash, is moved aside with a `.corrupt` suffix and
+    /// an empty queue is started. Files holding only the list of uploads, as
+    /// written before the next ID was persisted, are still read.
+    ///
+    /// # Arguments
+    /// * `path` - JSON file holding the queued uploads
+    pub fn with_path(mut self, path: &str) -> Result<Self, Error> {
+        match fs::read(path) {
+            Ok(content) => match parse_queue(&content) {
+                Ok(persisted) => {
+                    let next_id: u64 = persisted
+                        .letters
+                        .iter()
+                        .map(|letter| letter.id + 1)
+                        .max()
+                        .unwrap_or(1);
+                    self.next_id = persisted.next_id.max(next_id);
+                    self.letters = persisted.letters.into_owned();
+                    self.evict();
+                }
+                Err(e) => {
+                    let aside: String = format!("{}.corrupt", path);
+                    warn!(
+                        "Failed to parse the dead-letter queue in {}, moving it to {} and starting empty: {}",
+                        path, aside, e
+                    );
+                    fs::rename(path, &aside)?;
+                }
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+        let target: String = path.to_string();
+        self.writer_thread = Some(thread::spawn(move || write_queue(&target, receiver)));
+        self.writer = Some(sender);
+
+        Ok(self)
+    }
+
+    /// Queue a failed upload, evicting the oldest ones to make room
+    ///
+    /// # Returns
+    /// ID of the queued upload
+    pub fn push(&mut self, mut letter: DeadLetter) -> u64 {
+        letter.id = self.next_id;
+        self.next_id += 1;
+        self.letters.push_back(letter);
+        self.evict();
+        self.persist();
+
+        self.next_id - 1
+    }
+
+    /// Whether an upload is still queued, i.e. was neither retried nor evicted
+    pub fn contains(&self, id: u64) -> bool {
+        self.letters.iter().any(|letter| letter.id == id)
+    }
+
+    /// Remove a queued upload, e.g. once a retry stored it
+    pub fn remove(&mut self, id: u64) -> Option<DeadLetter> {
+        let index: usize = self.letters.iter().position(|letter| letter.id == id)?;
+        let letter: Option<DeadLetter> = self.letters.remove(index);
+        self.persist();
+        letter
+    }
+
+    /// Record that retrying a queued upload failed again, keeping it queued
+    ///
+    /// # Returns
+    /// Whether the upload was still queued
+    pub fn record_failure(&mut self, id: u64, error: &Error) -> bool {
+        let Some(letter) = self.letters.iter_mut().find(|letter| letter.id == id) else {
+            return false;
+        };
+        letter.error = error.to_string();
+        letter.failed_at = now_secs();
+        self.persist();
+        true
+    }
+
+    /// Queued uploads, from oldest to newest
+    pub fn list(&self) -> Vec<DeadLetter> {
+        self.letters.iter().cloned().collect()
+    }
+
+    /// Number of queued uploads
+    pub fn len(&self) -> usize {
+        self.letters.len()
+    }
+
+    /// Whether no upload is queued
+    pub fn is_empty(&self) -> bool {
+        self.letters.is_empty()
+    }
+
+    // Helper function to drop the oldest uploads beyond the capacity
+    fn evict(&mut self) {
+        while self.letters.len() > self.capacity {
+            if let Some(evicted) = self.letters.pop_front() {
+                warn!(
+                    "Dead-letter queue is full, dropping upload {} ({})",
+                    evicted.id, evicted.name
+                );
+            }
+        }
+    }
+
+    // Helper function to hand the queue to the writer thread, if it has one.
+    // Serializing is all that happens on the caller's thread.
+    fn persist(&self) {
+        let Some(writer) = &self.writer else {
+            return;
+        };
+
+        let persisted = PersistedQueue {
+            next_id: self.next_id,
+            letters: Cow::Borrowed(&self.letters),
+        };
+        match serde_json::to_vec(&persisted) {
+            Ok(content) => {
+                if writer.send(content).is_err() {
+                    warn!("The dead-letter queue writer stopped, the queue is no longer persisted");
+                }
+            }
+            Err(e) => warn!("Failed to serialize the dead-letter queue: {}", e),
+        }
+    }
+}
+
+impl Drop for DeadLetterQueue {
+    fn drop(&mut self) {
+        // closing the channel lets the writer finish the pending writes
+        self.writer.take();
+        if let Some(writer_thread) = self.writer_thread.take() {
+            let _ = writer_thread.join();
+        }
+    }
+}
+
+// Helper function to get the current time in seconds since the Unix epoch
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+// Helper function to read a queue file, falling back to the older format
+// holding only the list of uploads
+fn parse_queue(content: &[u8]) -> Result<PersistedQueue<'static>, Error> {
+    match serde_json::from_slice::<PersistedQueue>(content) {
+        Ok(persisted) => Ok(persisted),
+        Err(e) => match serde_json::from_slice::<VecDeque<DeadLetter>>(content) {
+            Ok(letters) => Ok(PersistedQueue {
+                next_id: 1,
+                letters: Cow::Owned(letters),
+            }),
+            Err(_) => Err(e.into()),
+        },
+    }
+}
+
+// Helper function run by the writer thread. Only the latest of the queued
+// writes matters, so older ones are skipped. Writing to a temporary file and
+// renaming it means a crash never tears the queue file. A failed write is
+// logged rather than failing the upload that triggered it.
+fn write_queue(path: &str, receiver: Receiver<Vec<u8>>) {
+    while let Ok(mut content) = receiver.recv() {
+        if let Some(latest) = receiver.try_iter().last() {
+            content = latest;
+        }
+
+        if let Err(e) = write_atomically(path, &content) {
+            warn!("Failed to persist the dead-letter queue to {}: {}", path, e);
+        }
+    }
+}
+
+// Helper function to write next to the target and rename
+fn write_atomically(path: &str, content: &[u8]) -> Result<(), Error> {
+    let temp_path: String = format!("{}.tmp", path);
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    writer.write_all(content)?;
+    let file: File = writer.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)?;
+
+    Ok(())
+}
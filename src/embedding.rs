@@ -1,20 +1,51 @@
-use std::fmt::Display;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap, HashSet},
+    fmt::Display,
+    fs,
+    future::Future,
+    io::Cursor,
+    ops::Deref,
+    path::PathBuf,
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering as AtomicOrdering},
+        Arc, Mutex as StdMutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::{Error, Ok, Result};
-use async_openai::{config::OpenAIConfig, Client};
+use async_openai::{config::OpenAIConfig, error::OpenAIError, Client};
+use base64::{engine::general_purpose::STANDARD, Engine};
 use dim::{
     llm::instantiate_client,
     vector::{self, Vector},
     vectorizations::vectorize_image_concurrently,
 };
-use image::DynamicImage;
-use serde::{Deserialize, Serialize};
+use futures_util::{stream, StreamExt};
+use image::{load_from_memory, DynamicImage, ImageFormat};
+use log::{info, warn};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use strsim::levenshtein;
+use tokio::sync::mpsc::Sender;
+use wide::f32x8;
+
+use crate::{config, prompts::shuffle_seeded};
 
 /// Error variants related to DataEntry operations
 #[derive(Debug, Clone, Copy)]
 pub enum DataEntryErrors {
     /// Indicates that no data entry was found for the given criteria
     NoDataWasFound,
+    /// Indicates that the entry exists but its source image was not stored
+    ImageNotStored,
+    /// Indicates that the same image is already stored under the given ID
+    DuplicateImage(usize),
+    /// Indicates that the entry was edited since the client read it
+    VersionConflict { expected: u64, actual: u64 },
 }
 
 impl std::error::Error for DataEntryErrors {}
@@ -23,289 +54,4267 @@ impl Display for DataEntryErrors {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::NoDataWasFound => write!(f, "No data entry was found!"),
+            Self::ImageNotStored => write!(f, "The image of this data entry was not stored!"),
+            Self::DuplicateImage(id) => {
+                write!(f, "This image is already stored as entry {}!", id)
+            }
+            Self::VersionConflict { expected, actual } => write!(
+                f,
+                "The entry was changed since it was read: expected version {}, but it is at {}!",
+                expected, actual
+            ),
         }
     }
 }
 
-/// Represents a single data entry in the vector store
-#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
-pub struct DataEntry {
-    /// Unique identifier for the data entry
-    pub id: usize,
-    /// Name associated with the data entry
-    pub name: String,
-    /// Vector representation of the data
-    pub vector: Vec<f64>,
-    /// List of descriptions associated with the data
-    pub descriptions: Vec<String>,
+/// Error variants raised by the embedding provider rather than the client
+#[derive(Debug, Clone)]
+pub enum EmbeddingErrors {
+    /// The provider rejected the credentials, e.g. a missing or invalid API key
+    UpstreamAuth(String),
+    /// The provider kept returning vectors of the wrong length
+    UnexpectedDimensions { expected: usize, actual: usize },
+    /// Every component of the vector is about the same, so it cannot be
+    /// told apart from other vectors by cosine similarity
+    ConstantVector { variance: f64 },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SearchResult {
-    pub score: f64,
-    pub data_entry: DataEntry,
+impl std::error::Error for EmbeddingErrors {}
+
+impl Display for EmbeddingErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UpstreamAuth(message) => write!(
+                f,
+                "The embedding provider rejected the credentials, check OPENAI_API_KEY: {}!",
+                message
+            ),
+            Self::UnexpectedDimensions { expected, actual } => write!(
+                f,
+                "The embedding provider returned {} dimensions instead of {}!",
+                actual, expected
+            ),
+            Self::ConstantVector { variance } => write!(
+                f,
+                "The embedding provider returned a constant vector (variance {}), check the prompts and the image!",
+                variance
+            ),
+        }
+    }
 }
 
-/// Defines essential operations that must be implemented by vector stores
-pub trait VectorStore {
-    /// Search for similar entries given an image
-    ///
-    /// # Arguments
-    /// * `image` - The image to search for similar entries
-    /// * `top_n` - Number of most similar entries to return
-    async fn search(&self, image: DynamicImage, top_n: usize) -> Result<Vec<SearchResult>, Error>;
+/// Error variants raised when client-supplied entry fields fail validation
+#[derive(Debug, Clone, Copy)]
+pub enum ValidationErrors {
+    /// More descriptions were supplied than the configured maximum allows
+    TooManyDescriptions { max: usize, actual: usize },
+    /// A description is longer than the configured maximum length
+    DescriptionTooLong { max: usize, actual: usize },
+    /// Some entries of an import failed validation
+    InvalidImport { invalid: usize, total: usize },
+    /// The name is empty once trimmed and stripped of control characters
+    EmptyName,
+    /// The name is longer than the configured maximum length
+    NameTooLong { max: usize, actual: usize },
+    /// Two entries of a store share the same ID
+    DuplicateId(usize),
+    /// An entry's vector does not match the store's dimensions
+    DimensionMismatch {
+        id: usize,
+        expected: usize,
+        actual: usize,
+    },
+    /// An entry's vector contains NaN or infinite values
+    NonFiniteVector(usize),
+    /// An entry's vector is not unit length although the store is normalized
+    UnnormalizedVector(usize),
+    /// The query's normalization does not match the stored vectors'
+    NormalizationMismatch { store: bool, query: bool },
+    /// A precomputed query vector does not match the store's dimensions
+    QueryDimensionMismatch { expected: usize, actual: usize },
+    /// A projection matrix does not have one row per dimension
+    InvalidProjection { expected_rows: usize, rows: usize },
+    /// A projection matrix row is empty, not as long as the first, or not finite
+    RaggedProjection { row: usize },
+    /// Vectors cannot be truncated to zero or more dimensions than they have
+    InvalidTruncation { requested: usize, available: usize },
+    /// A store was configured with zero-dimensional vectors
+    ZeroDimensions,
+    /// The encoded image is larger than the configured maximum, in bytes
+    ImageTooLarge { max: usize, actual: usize },
+    /// A side of the image is longer than the configured maximum, in pixels
+    ImageDimensionsTooLarge { max: u32, width: u32, height: u32 },
+    /// A search asked for more results than the configured maximum allows
+    TopNTooLarge { max: usize, actual: usize },
+    /// The prompts do not fill the embedded dimensions exactly
+    PromptLayoutMismatch {
+        prompts: usize,
+        prompt_size: usize,
+        dimensions: usize,
+    },
+    /// The epsilon of a tie shuffle is negative or not a number
+    InvalidTieEpsilon(f64),
+}
 
-    /// Add a new entry to the vector store
-    ///
-    /// # Arguments
-    /// * `name` - Name of the entry
-    /// * `descriptions` - List of descriptions for the entry  
-    /// * `image` - Image to store
-    async fn add(
-        &mut self,
-        name: &str,
-        descriptions: Vec<String>,
-        image: DynamicImage,
-    ) -> Result<()>;
+impl std::error::Error for ValidationErrors {}
 
-    /// Delete an entry from the store by ID
-    ///
-    /// # Arguments
-    /// * `id` - ID of the entry to delete
-    async fn delete(&mut self, id: usize) -> Result<()>;
+impl Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooManyDescriptions { max, actual } => write!(
+                f,
+                "Too many descriptions: {} were given, but at most {} are allowed!",
+                actual, max
+            ),
+            Self::DescriptionTooLong { max, actual } => write!(
+                f,
+                "Description is too long: {} characters were given, but at most {} are allowed!",
+                actual, max
+            ),
+            Self::InvalidImport { invalid, total } => write!(
+                f,
+                "{} of {} imported entries failed validation!",
+                invalid, total
+            ),
+            Self::EmptyName => write!(f, "Name must not be empty!"),
+            Self::NameTooLong { max, actual } => write!(
+                f,
+                "Name is too long: {} characters were given, but at most {} are allowed!",
+                actual, max
+            ),
+            Self::DuplicateId(id) => write!(f, "ID {} is used by more than one entry!", id),
+            Self::DimensionMismatch {
+                id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Entry {} has a vector of {} dimensions, expected {}!",
+                id, actual, expected
+            ),
+            Self::NonFiniteVector(id) => {
+                write!(f, "Entry {} has NaN or infinite vector values!", id)
+            }
+            Self::UnnormalizedVector(id) => {
+                write!(f, "Entry {} is not L2-normalized, but the store is!", id)
+            }
+            Self::NormalizationMismatch { store, query } => write!(
+                f,
+                "Query normalization ({}) does not match the stored vectors ({})!",
+                normalization_label(*query),
+                normalization_label(*store)
+            ),
+            Self::QueryDimensionMismatch { expected, actual } => write!(
+                f,
+                "Query vector has {} dimensions, expected {}!",
+                actual, expected
+            ),
+            Self::InvalidProjection {
+                expected_rows,
+                rows,
+            } => write!(
+                f,
+                "Projection has {} rows, expected one per dimension ({})!",
+                rows, expected_rows
+            ),
+            Self::RaggedProjection { row } => write!(
+                f,
+                "Projection row {} is empty, not as long as the first row, or not finite!",
+                row
+            ),
+            Self::InvalidTruncation {
+                requested,
+                available,
+            } => write!(
+                f,
+                "Cannot truncate vectors to {} dimensions, expected between 1 and {}!",
+                requested, available
+            ),
+            Self::ZeroDimensions => write!(
+                f,
+                "Vectors must have at least one dimension, every similarity would be 0!"
+            ),
+            Self::ImageTooLarge { max, actual } => write!(
+                f,
+                "Image is too large: {} bytes were given, but at most {} are allowed!",
+                actual, max
+            ),
+            Self::ImageDimensionsTooLarge { max, width, height } => write!(
+                f,
+                "Image is too large: it is {}x{} pixels, but sides of at most {} are allowed!",
+                width, height, max
+            ),
+            Self::TopNTooLarge { max, actual } => write!(
+                f,
+                "Too many results requested: {} were asked for, but at most {} are allowed!",
+                actual, max
+            ),
+            Self::PromptLayoutMismatch {
+                prompts,
+                prompt_size,
+                dimensions,
+            } => write!(
+                f,
+                "{} prompts of size {} fill {} dimensions, but the embeddings have {}!",
+                prompts,
+                prompt_size,
+                prompts * prompt_size,
+                dimensions
+            ),
+            Self::InvalidTieEpsilon(epsilon) => write!(
+                f,
+                "Tie epsilon must be a non-negative number, {} was given!",
+                epsilon
+            ),
+        }
+    }
+}
 
-    /// Edit an existing entry with new data
-    ///
-    /// # Arguments
-    /// * `image` - New image
-    /// * `data_entry` - Updated data entry
-    async fn edit(&mut self, image: DynamicImage, data_entry: DataEntry) -> Result<()>;
+// Helper function to describe a normalization state in error messages
+fn normalization_label(normalized: bool) -> &'static str {
+    if normalized {
+        "normalized"
+    } else {
+        "raw"
+    }
 }
 
-/// In-memory implementation of a vector store
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct InMemoryVectorStore {
-    /// Storage for data entry metadata
-    data_entries: Vec<DataEntry>,
-    /// Annotations used for prompting
-    prompt_annotations: Vec<String>,
-    /// Prompts used for vectorization
-    prompts: Vec<String>,
-    /// Size of prompts to use
-    prompt_size: usize,
-    /// Dimension of the vectors
-    dimensions: usize,
+/// Check that a search asks for no more results than allowed
+///
+/// # Arguments
+/// * `top_n` - Number of results asked for
+/// * `max` - Maximum number of results allowed
+pub fn validate_top_n(top_n: usize, max: usize) -> Result<(), Error> {
+    if top_n > max {
+        return Err(ValidationErrors::TopNTooLarge { max, actual: top_n }.into());
+    }
+
+    Ok(())
 }
 
-impl InMemoryVectorStore {
-    /// Create a new InMemoryVectorStore instance
-    ///
-    /// # Arguments
-    /// * `dimensions` - Dimensionality of vectors
-    /// * `prompt_annotations` - Annotations for prompts
-    /// * `prompts` - Prompts for vectorization
-    /// * `prompt_size` - Size of prompts to use
-    pub fn new(
-        dimensions: usize,
-        prompt_annotations: Vec<String>,
-        prompts: Vec<String>,
-        prompt_size: usize,
-    ) -> Self {
-        Self {
-            data_entries: Vec::new(),
-            prompts: prompts,
-            prompt_size: prompt_size,
-            prompt_annotations: prompt_annotations,
-            dimensions: dimensions,
+/// Check that the epsilon of a tie shuffle is a non-negative number
+///
+/// # Arguments
+/// * `epsilon` - Largest score difference to treat as a tie
+pub fn validate_tie_epsilon(epsilon: f64) -> Result<(), Error> {
+    if epsilon.is_nan() || epsilon < 0.0 {
+        return Err(ValidationErrors::InvalidTieEpsilon(epsilon).into());
+    }
+
+    Ok(())
+}
+
+/// Check that a list of descriptions stays within the given bounds
+///
+/// # Arguments
+/// * `descriptions` - Descriptions to validate
+/// * `max_count` - Maximum number of descriptions allowed
+/// * `max_length` - Maximum length, in characters, of each description
+pub fn validate_descriptions(
+    descriptions: &[String],
+    max_count: usize,
+    max_length: usize,
+) -> Result<(), Error> {
+    if descriptions.len() > max_count {
+        return Err(ValidationErrors::TooManyDescriptions {
+            max: max_count,
+            actual: descriptions.len(),
         }
+        .into());
     }
 
-    /// Store entry metadata in key-value storage
-    ///
-    /// # Arguments
-    /// * `name` - Name of the entry
-    /// * `descriptions` - Descriptions for the entry
-    /// * `vector` - Vector representation
-    ///
-    /// # Returns
-    /// ID of the stored entry
-    fn kv_storage(
-        &mut self,
-        name: &str,
-        descriptions: Vec<String>,
-        vector: Vec<f64>,
-    ) -> Result<usize, Error> {
-        let current_id: usize = self.data_entries.len() + 1;
+    for description in descriptions {
+        let length: usize = description.chars().count();
+        if length > max_length {
+            return Err(ValidationErrors::DescriptionTooLong {
+                max: max_length,
+                actual: length,
+            }
+            .into());
+        }
+    }
 
-        self.data_entries.push(DataEntry {
-            id: current_id,
-            name: name.to_string(),
-            vector: vector,
-            descriptions: descriptions,
-        });
+    Ok(())
+}
 
-        Ok(current_id)
+/// Check that a decoded image stays within the given limits
+///
+/// # Arguments
+/// * `byte_length` - Size of the encoded image, in bytes
+/// * `image` - The decoded image
+/// * `max_bytes` - Maximum size of the encoded image, in bytes
+/// * `max_side` - Maximum width and height, in pixels
+pub fn validate_image(
+    byte_length: usize,
+    image: &DynamicImage,
+    max_bytes: usize,
+    max_side: u32,
+) -> Result<(), Error> {
+    if byte_length > max_bytes {
+        return Err(ValidationErrors::ImageTooLarge {
+            max: max_bytes,
+            actual: byte_length,
+        }
+        .into());
     }
 
-    /// Retrieve entry metadata by ID
-    ///
-    /// # Arguments
-    /// * `id` - ID of entry to retrieve
-    fn kv_search(&self, query_vector: Vec<f64>, top_n: usize) -> Result<Vec<SearchResult>, Error> {
-        if self.data_entries.is_empty() {
-            return Err(DataEntryErrors::NoDataWasFound.into());
+    if image.width() > max_side || image.height() > max_side {
+        return Err(ValidationErrors::ImageDimensionsTooLarge {
+            max: max_side,
+            width: image.width(),
+            height: image.height(),
         }
+        .into());
+    }
 
-        // Calculate similarities and store with indices
-        let mut similarities: Vec<(usize, f64)> = self
-            .data_entries
-            .iter()
-            .enumerate()
-            .map(|(idx, entry)| (idx, self.cosine_similarity(&query_vector, &entry.vector)))
-            .collect();
+    Ok(())
+}
 
-        // Sort by similarity score in descending order
-        similarities.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+/// Clean up a client-supplied entry name
+///
+/// Control characters are stripped and surrounding whitespace trimmed. The
+/// result must not be empty or longer than `max_length` characters.
+///
+/// # Arguments
+/// * `name` - The name to sanitize
+/// * `max_length` - Maximum length, in characters, of the sanitized name
+///
+/// # Returns
+/// The sanitized name
+pub fn sanitize_name(name: &str, max_length: usize) -> Result<String, Error> {
+    let stripped: String = name.chars().filter(|c| !c.is_control()).collect();
+    let sanitized: &str = stripped.trim();
 
-        // Take top n entries
-        let top_entries: Vec<SearchResult> = similarities
-            .into_iter()
-            .take(top_n)
-            .map(|(idx, score)| SearchResult {
-                data_entry: self.data_entries[idx].clone(),
-                score: score,
-            })
-            .collect();
+    if sanitized.is_empty() {
+        return Err(ValidationErrors::EmptyName.into());
+    }
 
-        if top_entries.is_empty() {
-            return Err(DataEntryErrors::NoDataWasFound.into());
+    let length: usize = sanitized.chars().count();
+    if length > max_length {
+        return Err(ValidationErrors::NameTooLong {
+            max: max_length,
+            actual: length,
         }
+        .into());
+    }
+
+    Ok(sanitized.to_string())
+}
+
+/// Encode an image as a base64 PNG so it can be kept alongside its entry
+pub fn encode_image(image: &DynamicImage) -> Result<String, Error> {
+    let mut bytes: Vec<u8> = Vec::new();
+    image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+    Ok(STANDARD.encode(bytes))
+}
+
+/// Decode an image previously encoded with [`encode_image`]
+pub fn decode_image(encoded: &str) -> Result<DynamicImage, Error> {
+    let bytes: Vec<u8> = STANDARD.decode(encoded)?;
+    Ok(load_from_memory(&bytes)?)
+}
 
-        Ok(top_entries)
+/// Calculate the L2 (euclidean) norm of a vector
+pub fn l2_norm(vector: &[f64]) -> f64 {
+    vector.iter().map(|x| x * x).sum::<f64>().sqrt()
+}
+
+/// Scale a vector to unit L2 norm
+///
+/// A zero vector has no direction and is returned unchanged.
+pub fn l2_normalize(vector: &[f64]) -> Vec<f64> {
+    let norm: f64 = l2_norm(vector);
+
+    if norm == 0.0 {
+        return vector.to_vec();
     }
 
-    // Helper function to calculate cosine similarity between two vectors
-    fn cosine_similarity(&self, a: &[f64], b: &[f64]) -> f64 {
-        let dot_product: f64 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
-        let norm_a: f64 = a.iter().map(|x| x * x).sum::<f64>().sqrt();
-        let norm_b: f64 = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+    vector.iter().map(|x| x / norm).collect()
+}
 
-        if norm_a == 0.0 || norm_b == 0.0 {
-            return 0.0;
-        }
+/// Round a value to a number of decimal places
+pub fn round_to_decimals(value: f64, decimals: u32) -> f64 {
+    let scale: f64 = 10f64.powi(decimals as i32);
+    (value * scale).round() / scale
+}
+
+/// Number of decimal places entry vectors are serialized with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorPrecision {
+    /// Every digit, as anything written to disk must be
+    Full,
+    /// Rounded to the given number of decimal places
+    Decimals(u32),
+}
 
-        dot_product / (norm_a * norm_b)
+impl VectorPrecision {
+    /// Precision of responses, as set by `STYLIST_VECTOR_DECIMALS`
+    pub fn for_responses() -> Self {
+        config::get()
+            .vector_decimals
+            .map_or(Self::Full, Self::Decimals)
     }
+}
 
-    /// Delete entry metadata by ID
-    ///
-    /// # Arguments  
-    /// * `id` - ID of entry to delete
-    fn kv_delete(&mut self, id: usize) -> Result<(), Error> {
-        // Find the position of the entry with matching id
-        if let Some(index) = self
-            .data_entries
-            .iter()
-            .position(|entry: &DataEntry| entry.id == id)
-        {
-            // Remove the entry and return Ok if found
-            self.data_entries.remove(index);
-            Ok(())
-        } else {
-            // Return error if no matching entry was found
-            Err(DataEntryErrors::NoDataWasFound.into())
+// Borrowed vector serialized with an explicit precision
+struct PreciseVector<'a> {
+    values: &'a [f64],
+    precision: VectorPrecision,
+}
+
+impl Serialize for PreciseVector<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.precision {
+            VectorPrecision::Full => self.values.serialize(serializer),
+            VectorPrecision::Decimals(decimals) => serializer.collect_seq(
+                self.values
+                    .iter()
+                    .map(|value| round_to_decimals(*value, decimals)),
+            ),
         }
     }
+}
 
-    /// Update entry metadata by ID
-    ///
-    /// # Arguments
-    /// * `id` - ID of entry to update
-    /// * `data_entry` - New data entry
-    fn kv_edit(&mut self, id: usize, data_entry: DataEntry) -> Result<(), Error> {
-        if let Some(index) = self.data_entries.iter().position(|entry| entry.id == id) {
-            self.data_entries[index] = data_entry;
-        } else {
-            // Return error if no matching entry was found
-            return Err(DataEntryErrors::NoDataWasFound.into());
-        }
+/// Serialize entries with full vector precision, for fields that are persisted
+///
+/// `STYLIST_VECTOR_DECIMALS` only shortens responses. Persisted types, such
+/// as the store's entries and the operation log, serialize their entries
+/// through this, so rounding never loses stored precision.
+pub fn serialize_entries_full<S: Serializer>(
+    entries: &[DataEntry],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(
+        entries
+            .iter()
+            .map(|entry| entry.with_precision(VectorPrecision::Full)),
+    )
+}
 
-        Ok(())
-    }
+/// Serialize an entry with full vector precision, see [`serialize_entries_full`]
+pub fn serialize_entry_full<S: Serializer>(
+    entry: &DataEntry,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    entry
+        .with_precision(VectorPrecision::Full)
+        .serialize(serializer)
+}
 
-    pub fn get_all(&self) -> Vec<DataEntry> {
-        self.data_entries.clone()
+/// Maximum absolute difference between [`cosine_similarity_simd`] and
+/// [`cosine_similarity`] for the same inputs
+pub const COSINE_SIMD_EPSILON: f64 = 1e-4;
+
+/// Normalize a description used as a tag: trimmed and lowercased
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Check whether metadata contains a filter
+///
+/// Objects contain a filter object when they hold every key of it, with
+/// values containing the filter's values in turn, so `{"brand": "X"}`
+/// matches any metadata with that brand. Other values must be equal.
+///
+/// # Arguments
+/// * `metadata` - Metadata of an entry
+/// * `filter` - The filter to look for
+pub fn metadata_contains(metadata: &Value, filter: &Value) -> bool {
+    match (metadata, filter) {
+        (Value::Object(metadata), Value::Object(filter)) => filter.iter().all(|(key, value)| {
+            metadata
+                .get(key)
+                .is_some_and(|existing| metadata_contains(existing, value))
+        }),
+        (metadata, filter) => metadata == filter,
     }
 }
 
-impl VectorStore for InMemoryVectorStore {
-    async fn add(
-        &mut self,
-        name: &str,
-        descriptions: Vec<String>,
-        image: DynamicImage,
-    ) -> Result<(), Error> {
-        let client: Client<OpenAIConfig> = instantiate_client::<OpenAIConfig>(None)?;
-
-        // initialize the vectorization mechanics
-        let mut vector: vector::Vector<DynamicImage> = Vector::new(
-            self.dimensions,
-            self.prompt_annotations.clone(),
-            self.prompts.clone(),
-            self.prompt_size,
-            image,
-        );
+/// Calculate the cosine similarity between two vectors in scalar f64
+///
+/// Returns 0 if either vector has a zero norm.
+pub fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let dot_product: f64 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    let norm_a: f64 = l2_norm(a);
+    let norm_b: f64 = l2_norm(b);
 
-        println!("Vectorizing...");
-        vectorize_image_concurrently::<OpenAIConfig>(&mut vector, client).await?;
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
 
-        println!("Try getting vectors...");
-        let new_vector: Vec<f64> = vector.get_vector();
-        println!("{:?}", &new_vector);
+    dot_product / (norm_a * norm_b)
+}
 
-        // store the information to a kv storage, and get a corresponding
-        // key for later retrieval.
-        let _: usize = self.kv_storage(name, descriptions, new_vector.clone())?;
+/// Calculate the cosine similarity between two vectors with 8-lane f32 SIMD
+///
+/// This trades precision for speed: the result stays within
+/// [`COSINE_SIMD_EPSILON`] of [`cosine_similarity`]. On targets without SIMD
+/// support `wide` falls back to scalar code, so this is always safe to call.
+pub fn cosine_similarity_simd(a: &[f64], b: &[f64]) -> f64 {
+    let length: usize = a.len().min(b.len());
+    let chunked: usize = length - length % 8;
 
-        Ok(())
+    let mut dot_product = f32x8::splat(0.0);
+    let mut norm_a = f32x8::splat(0.0);
+    let mut norm_b = f32x8::splat(0.0);
+    for start in (0..chunked).step_by(8) {
+        let lanes_a: f32x8 = to_lanes(&a[start..start + 8]);
+        let lanes_b: f32x8 = to_lanes(&b[start..start + 8]);
+        dot_product += lanes_a * lanes_b;
+        norm_a += lanes_a * lanes_a;
+        norm_b += lanes_b * lanes_b;
     }
 
-    async fn edit(&mut self, image: DynamicImage, data_entry: DataEntry) -> Result<(), Error> {
-        // delete the original data entry first
-        self.kv_delete(data_entry.id)?;
+    let mut dot_product: f32 = dot_product.reduce_add();
+    let mut norm_a: f32 = norm_a.reduce_add();
+    let mut norm_b: f32 = norm_b.reduce_add();
+    for index in chunked..length {
+        let (x, y) = (a[index] as f32, b[index] as f32);
+        dot_product += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
 
-        // store the new data entry
-        self.add(&data_entry.name, data_entry.descriptions, image)
-            .await?;
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
 
-        Ok(())
+    (dot_product / (norm_a.sqrt() * norm_b.sqrt())) as f64
+}
+
+// Helper function to load up to 8 values into SIMD lanes, zero-padded
+fn to_lanes(values: &[f64]) -> f32x8 {
+    let mut lanes: [f32; 8] = [0.0; 8];
+    for (lane, value) in lanes.iter_mut().zip(values) {
+        *lane = *value as f32;
     }
+    f32x8::from(lanes)
+}
 
-    async fn delete(&mut self, id: usize) -> Result<()> {
-        // delete both the vectors and the data entry
-        self.kv_delete(id)?;
+/// Gender a piece of clothing is meant for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Gender {
+    Male,
+    Female,
+    /// Worn by any gender, matches every gender filter
+    Unisex,
+}
 
-        Ok(())
+impl Gender {
+    /// Whether an entry of this gender passes a search's gender filter
+    pub fn matches(self, filter: Gender) -> bool {
+        self == filter || self == Gender::Unisex
+    }
+}
+
+/// Represents a single data entry in the vector store
+///
+/// Serializing an entry rounds its vector to `STYLIST_VECTOR_DECIMALS`, as
+/// for responses. Use [`DataEntry::with_precision`] to choose the precision.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DataEntry {
+    /// Unique identifier for the data entry
+    pub id: usize,
+    /// Name associated with the data entry
+    pub name: String,
+    /// Vector representation of the data
+    pub vector: Vec<f64>,
+    /// List of descriptions associated with the data
+    pub descriptions: Vec<String>,
+    /// Source image as a base64 PNG, kept only when image storage is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Creation time in seconds since the Unix epoch, 0 for entries stored
+    /// before timestamps were recorded
+    #[serde(default)]
+    pub created_at: u64,
+    /// Hash of the source image's pixels, used to detect duplicate uploads.
+    /// Missing for imported entries and entries stored before hashing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_hash: Option<u64>,
+    /// Arbitrary JSON metadata attached by clients, e.g. price, brand or SKU
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub metadata: Value,
+    /// Number of times the entry was changed, used to detect concurrent edits.
+    /// Edits, description changes, re-embedding and truncation all bump it.
+    #[serde(default)]
+    pub version: u64,
+    /// Gender the entry is meant for, missing for imported entries and
+    /// entries stored before genders were kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gender: Option<Gender>,
+}
+
+impl DataEntry {
+    /// View of the entry serializing its vector with the given precision
+    ///
+    /// # Arguments
+    /// * `precision` - Decimal places of the serialized vector
+    pub fn with_precision(&self, precision: VectorPrecision) -> PreciseEntry<'_> {
+        PreciseEntry {
+            id: self.id,
+            name: &self.name,
+            vector: PreciseVector {
+                values: &self.vector,
+                precision,
+            },
+            descriptions: &self.descriptions,
+            image: self.image.as_deref(),
+            created_at: self.created_at,
+            image_hash: self.image_hash,
+            metadata: (!self.metadata.is_null()).then_some(&self.metadata),
+            version: self.version,
+            gender: self.gender,
+        }
     }
+}
 
-    async fn search(&self, image: DynamicImage, top_n: usize) -> Result<Vec<SearchResult>, Error> {
-        let client: Client<OpenAIConfig> = instantiate_client::<OpenAIConfig>(None)?;
-
-        // initialize the vectorization mechanics
-        let mut vector: vector::Vector<DynamicImage> = Vector::new(
-            self.dimensions,
-            self.prompt_annotations.clone(),
-            self.prompts.clone(),
-            self.prompt_size,
-            image,
-        );
+impl Serialize for DataEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.with_precision(VectorPrecision::for_responses())
+            .serialize(serializer)
+    }
+}
 
-        vectorize_image_concurrently::<OpenAIConfig>(&mut vector, client).await?;
+/// A [`DataEntry`] serialized with an explicit vector precision, see
+/// [`DataEntry::with_precision`]
+///
+/// Serializes to the same fields as the entry, so it deserializes as one.
+#[derive(Serialize)]
+pub struct PreciseEntry<'a> {
+    id: usize,
+    name: &'a str,
+    vector: PreciseVector<'a>,
+    descriptions: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<&'a str>,
+    created_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_hash: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<&'a Value>,
+    version: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gender: Option<Gender>,
+}
 
-        let new_vector: Vec<f64> = vector.get_vector();
+impl PreciseEntry<'_> {
+    /// Serialize the version as 0, e.g. to compare only the content of entries
+    pub fn without_version(self) -> Self {
+        Self { version: 0, ..self }
+    }
+}
 
-        let data_entries: Vec<SearchResult> = self.kv_search(new_vector, top_n)?;
+/// An entry with a precomputed vector, as accepted by [`InMemoryVectorStore::import`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportEntry {
+    /// Name associated with the entry
+    pub name: String,
+    /// List of descriptions associated with the entry
+    #[serde(default)]
+    pub descriptions: Vec<String>,
+    /// Precomputed vector of the entry
+    pub vector: Vec<f64>,
+}
 
-        Ok(data_entries)
+/// Validation outcome of a single [`ImportEntry`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportValidation {
+    /// Position of the entry in the import payload
+    pub index: usize,
+    /// Whether the entry can be imported
+    pub valid: bool,
+    /// Reasons the entry was rejected, empty when valid
+    pub errors: Vec<String>,
+    /// Changes the import makes to a valid entry, e.g. skipping it or
+    /// coercing its vector, see [`LengthMismatchPolicy`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// Outcome of [`InMemoryVectorStore::import_with_report`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportOutcome {
+    /// IDs of the inserted entries, in payload order
+    pub ids: Vec<usize>,
+    /// Payload positions of the entries left out for their vector length
+    pub skipped: Vec<usize>,
+    /// Payload positions of the entries whose vector was padded or truncated
+    pub coerced: Vec<usize>,
+}
+
+/// Similarity of two stored entries, for understanding how they rank
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EntryComparison {
+    /// ID of the first entry
+    pub a: usize,
+    /// ID of the second entry
+    pub b: usize,
+    /// Cosine similarity computed in scalar f64
+    pub cosine: f64,
+    /// Similarity as searches compute it, i.e. through SIMD when enabled
+    pub score: f64,
+    /// L2 norm of the first entry's vector
+    pub norm_a: f64,
+    /// L2 norm of the second entry's vector
+    pub norm_b: f64,
+}
+
+/// A query of a labeled evaluation set
+#[derive(Debug, Clone)]
+pub struct EvaluationQuery {
+    /// The image to search with
+    pub image: DynamicImage,
+    /// IDs of the entries a good search returns
+    pub relevant_ids: Vec<usize>,
+}
+
+/// A query of a labeled evaluation set, already embedded
+#[derive(Debug, Clone)]
+pub struct EmbeddedEvaluationQuery {
+    /// The vector to search with
+    pub query_vector: Vec<f64>,
+    /// IDs of the entries a good search returns
+    pub relevant_ids: Vec<usize>,
+}
+
+/// Retrieval quality of a single evaluation query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryEvaluation {
+    /// IDs the search returned, best first
+    pub retrieved_ids: Vec<usize>,
+    /// Number of relevant entries among them
+    pub hits: usize,
+    /// Share of the relevant entries that were retrieved
+    pub recall: f64,
+    /// Share of the `k` result slots holding a relevant entry
+    pub precision: f64,
+}
+
+/// Retrieval quality across a labeled evaluation set
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Evaluation {
+    /// Number of results each query retrieved
+    pub k: usize,
+    /// Recall@k averaged across the queries
+    pub recall: f64,
+    /// Precision@k averaged across the queries
+    pub precision: f64,
+    /// Scores of each query, in request order
+    pub queries: Vec<QueryEvaluation>,
+}
+
+/// Score the results of one query against the entries known to be relevant
+///
+/// Recall is the share of relevant entries among the first `k` results, 0
+/// when none are relevant. Precision divides the hits by `k`, so a store
+/// returning fewer than `k` results is not rewarded for it.
+///
+/// # Arguments
+/// * `retrieved_ids` - IDs of the results, best first
+/// * `relevant_ids` - IDs of the entries a good search returns
+/// * `k` - Number of results to consider
+pub fn evaluate_retrieval(
+    retrieved_ids: &[usize],
+    relevant_ids: &[usize],
+    k: usize,
+) -> QueryEvaluation {
+    let relevant: HashSet<usize> = relevant_ids.iter().copied().collect();
+    let retrieved_ids: Vec<usize> = retrieved_ids.iter().take(k).copied().collect();
+    let hits: usize = retrieved_ids
+        .iter()
+        .filter(|id| relevant.contains(id))
+        .count();
+
+    QueryEvaluation {
+        retrieved_ids,
+        hits,
+        recall: if relevant.is_empty() {
+            0.0
+        } else {
+            hits as f64 / relevant.len() as f64
+        },
+        precision: if k == 0 { 0.0 } else { hits as f64 / k as f64 },
+    }
+}
+
+/// Values a single prompt contributed to a vector
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptContribution {
+    /// The prompt
+    pub prompt: String,
+    /// First dimension the prompt wrote to
+    pub offset: usize,
+    /// Values of the dimensions the prompt wrote to
+    pub values: Vec<f64>,
+}
+
+/// A stored vector next to the prompts it was embedded with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VectorExplanation {
+    /// ID of the entry
+    pub id: usize,
+    /// The stored vector
+    pub vector: Vec<f64>,
+    /// Prompts of the store, in dimension order
+    pub prompts: Vec<String>,
+    /// Number of dimensions each prompt writes to
+    pub prompt_size: usize,
+    /// The vector split by prompt, missing when its length does not match
+    /// one `prompt_size` slice per prompt
+    pub contributions: Option<Vec<PromptContribution>>,
+}
+
+/// What `add` does when the uploaded image is already stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateStrategy {
+    /// Fail with [`DataEntryErrors::DuplicateImage`], leaving the store untouched
+    Reject,
+    /// Append the new descriptions the existing entry does not have yet, and
+    /// return the existing ID. The entry is not re-embedded and keeps its name.
+    Merge,
+    /// Store the upload as a new entry, like any other image
+    #[default]
+    Allow,
+}
+
+impl FromStr for DuplicateStrategy {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "reject" => Ok(Self::Reject),
+            "merge" => Ok(Self::Merge),
+            "allow" => Ok(Self::Allow),
+            other => Err(anyhow::anyhow!(
+                "Unknown duplicate strategy '{}', expected reject, merge or allow",
+                other
+            )),
+        }
+    }
+}
+
+/// What imports do with vectors whose length is not the store's dimensions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LengthMismatchPolicy {
+    /// Fail the whole import, leaving the store untouched
+    #[default]
+    Reject,
+    /// Leave such entries out and import the others
+    Skip,
+    /// Zero-pad short vectors and truncate long ones to the dimensions
+    PadTruncate,
+}
+
+impl FromStr for LengthMismatchPolicy {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "reject" => Ok(Self::Reject),
+            "skip" => Ok(Self::Skip),
+            "pad_truncate" => Ok(Self::PadTruncate),
+            other => Err(anyhow::anyhow!(
+                "Unknown length mismatch policy '{}', expected reject, skip or pad_truncate",
+                other
+            )),
+        }
+    }
+}
+
+/// Hash the pixels of an image to detect duplicate uploads
+///
+/// This is FNV-1a over the dimensions and RGBA pixels, so the hash is stable
+/// across builds and can be persisted. Re-encoding an image without changing
+/// its pixels keeps the hash.
+///
+/// # Arguments
+/// * `image` - The image to hash
+pub fn image_hash(image: &DynamicImage) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    let rgba = image.to_rgba8();
+    let header = [rgba.width().to_le_bytes(), rgba.height().to_le_bytes()].concat();
+
+    header
+        .iter()
+        .chain(rgba.as_raw().iter())
+        .fold(OFFSET_BASIS, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(PRIME)
+        })
+}
+
+/// Keys entries can be listed by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// By entry ID
+    Id,
+    /// Alphabetically by name
+    Name,
+    /// By creation time
+    Recent,
+}
+
+/// Direction entries are listed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+// Helper function to get the current time in seconds since the Unix epoch
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+// Helper function to draw a seed from the clock, for shuffles that need not
+// be reproducible
+fn random_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos() as u64)
+}
+
+/// Variance below which a vector counts as constant
+pub const CONSTANT_VECTOR_VARIANCE: f64 = 1e-12;
+
+/// Population variance of a vector's components, 0 for an empty vector
+pub fn vector_variance(vector: &[f64]) -> f64 {
+    if vector.is_empty() {
+        return 0.0;
+    }
+
+    let mean: f64 = vector.iter().sum::<f64>() / vector.len() as f64;
+    vector
+        .iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>()
+        / vector.len() as f64
+}
+
+/// Whether every component of a vector is about the same
+///
+/// Vectors with fewer than two components have no variance to speak of and
+/// never count as constant.
+pub fn is_constant_vector(vector: &[f64]) -> bool {
+    vector.len() >= 2 && vector_variance(vector) < CONSTANT_VECTOR_VARIANCE
+}
+
+/// Shuffle search results whose scores are nearly tied
+///
+/// Results are expected in descending score order. They are split into
+/// tiers, each holding the results within `epsilon` of the tier's first
+/// score, and only shuffled within their tier, so the ranking across tiers
+/// is kept, whether it is descending or, for farthest searches, ascending.
+/// The same seed always yields the same order.
+///
+/// # Arguments
+/// * `results` - Results to shuffle in place
+/// * `epsilon` - Largest score difference treated as a tie
+/// * `seed` - Seed of the shuffle
+pub fn shuffle_ties(results: &mut [SearchResult], epsilon: f64, seed: u64) {
+    let mut start: usize = 0;
+    let mut tier: u64 = 0;
+    while start < results.len() {
+        let first: f64 = results[start].score;
+        let end: usize = results[start..]
+            .iter()
+            .position(|result| (first - result.score).abs() > epsilon)
+            .map_or(results.len(), |offset| start + offset)
+            // a negative epsilon ties nothing, not even the first result
+            .max(start + 1);
+
+        // give every tier a seed of its own, so equal tiers do not move alike
+        shuffle_seeded(&mut results[start..end], seed.wrapping_add(tier));
+        start = end;
+        tier += 1;
+    }
+}
+
+/// Factor a score is multiplied by to favour recent entries
+///
+/// The score is `similarity * 0.5^(age / half_life)`, halving every
+/// `half_life_secs`: an entry one half-life old keeps half of its similarity.
+/// This relies on `created_at`, so entries stored before timestamps were
+/// recorded count as created at the epoch and decay to almost nothing.
+///
+/// # Arguments
+/// * `age_secs` - Seconds since the entry was created
+/// * `half_life_secs` - Seconds after which the factor halves, 0 disables the decay
+pub fn decay_factor(age_secs: u64, half_life_secs: u64) -> f64 {
+    if half_life_secs == 0 {
+        return 1.0;
+    }
+
+    0.5f64.powf(age_secs as f64 / half_life_secs as f64)
+}
+
+/// Decay a score with [`decay_factor`], favouring recent entries
+///
+/// Only positive scores decay. Scaling a negative score towards 0 would
+/// raise it, ranking old dissimilar entries above recent ones, so those are
+/// left as they are.
+///
+/// # Arguments
+/// * `score` - Similarity of the entry
+/// * `age_secs` - Seconds since the entry was created
+/// * `half_life_secs` - Seconds after which the factor halves, 0 disables the decay
+pub fn decay_score(score: f64, age_secs: u64, half_life_secs: u64) -> f64 {
+    if score <= 0.0 {
+        return score;
+    }
+
+    score * decay_factor(age_secs, half_life_secs)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub score: f64,
+    pub data_entry: DataEntry,
+}
+
+impl Deref for SearchResult {
+    type Target = DataEntry;
+
+    /// The matched entry, so its fields can be read off the result directly
+    fn deref(&self) -> &DataEntry {
+        &self.data_entry
+    }
+}
+
+/// An entry whose name is close to a queried one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NameMatch {
+    /// Levenshtein distance between the lowercased names
+    pub distance: usize,
+    pub data_entry: DataEntry,
+}
+
+/// A bucket of a score histogram, covering `[lower, upper)`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HistogramBucket {
+    pub lower: f64,
+    pub upper: f64,
+    pub count: usize,
+}
+
+/// Bin cosine scores into equally wide buckets spanning `[-1, 1]`
+///
+/// The range is fixed rather than fitted to the scores, so histograms of
+/// different queries can be compared directly. A score of exactly 1 lands
+/// in the last bucket.
+///
+/// # Arguments
+/// * `scores` - Scores to bin
+/// * `buckets` - Number of buckets, at least 1
+pub fn score_histogram(scores: &[f64], buckets: usize) -> Vec<HistogramBucket> {
+    let buckets: usize = buckets.max(1);
+    let width: f64 = 2.0 / buckets as f64;
+
+    let mut histogram: Vec<HistogramBucket> = (0..buckets)
+        .map(|bucket| HistogramBucket {
+            lower: -1.0 + bucket as f64 * width,
+            upper: -1.0 + (bucket + 1) as f64 * width,
+            count: 0,
+        })
+        .collect();
+
+    for score in scores {
+        let bucket: usize = (((score + 1.0) / width).floor().max(0.0) as usize).min(buckets - 1);
+        histogram[bucket].count += 1;
+    }
+
+    histogram
+}
+
+/// Options controlling a similarity search
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchOptions {
+    /// Number of most similar entries to return
+    pub top_n: usize,
+    /// Whether to L2-normalize the query before comparing it. Defaults to the
+    /// store's normalization state, and must agree with it when given.
+    pub normalize_query: Option<bool>,
+    /// IDs of entries to leave out of the results
+    #[serde(default)]
+    pub exclude_ids: Vec<usize>,
+    /// Descriptions every result must have, used as tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// JSON every result's metadata must contain, see [`metadata_contains`]
+    #[serde(default)]
+    pub metadata_filter: Option<Value>,
+    /// Half-life, in seconds, of a decay favouring recent entries, see
+    /// [`decay_score`]. Scores are not decayed when unset or 0.
+    #[serde(default)]
+    pub decay_half_life_secs: Option<u64>,
+    /// Shuffle results whose scores are within this epsilon of each other,
+    /// see [`shuffle_ties`]. Results keep their order when unset.
+    #[serde(default)]
+    pub shuffle_ties: Option<f64>,
+    /// Seed of the tie shuffle, a random one is drawn when unset
+    #[serde(default)]
+    pub shuffle_seed: Option<u64>,
+    /// Return the `top_n` least similar entries instead, least similar first.
+    /// Meant for suggesting something different from the query, not for
+    /// finding matches.
+    #[serde(default)]
+    pub farthest: bool,
+    /// Gender every result must be meant for. Unisex entries match any
+    /// gender, entries without a gender match none.
+    #[serde(default)]
+    pub gender: Option<Gender>,
+}
+
+impl SearchOptions {
+    /// Default options returning the `top_n` most similar entries
+    pub fn top_n(top_n: usize) -> Self {
+        Self {
+            top_n,
+            ..Self::default()
+        }
+    }
+}
+
+/// How many results a search was asked for, returned, and filtered out
+///
+/// Fewer than `requested` results come back when the store, minus the
+/// entries removed by filters, holds fewer entries than that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchCounts {
+    /// Number of results asked for, i.e. `top_n`
+    pub requested: usize,
+    /// Number of results returned
+    pub returned: usize,
+    /// Number of entries the filters removed from the candidates
+    pub filtered_out: usize,
+}
+
+/// Event of a streamed search, see [`InMemoryVectorStore::search_stream`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum SearchEvent {
+    /// ID and score of an entry scoring at least the threshold, sent in scan
+    /// order rather than rank order as soon as it is scored
+    Candidate { id: usize, score: f64 },
+    /// The ranked results, sent once the scan is complete
+    Summary {
+        results: Vec<SearchResult>,
+        counts: SearchCounts,
+    },
+    /// The search failed and no summary follows
+    Error { message: String },
+}
+
+/// Number of searches each entry was returned by, keyed by entry ID
+///
+/// Searches only hold the read lock of a store, so the counts sit behind a
+/// lock of their own. Entries missing from the map were never returned.
+#[derive(Debug, Default)]
+pub struct MatchCounts(StdMutex<HashMap<usize, u64>>);
+
+impl MatchCounts {
+    /// Count one more match of each entry
+    fn record(&self, ids: impl IntoIterator<Item = usize>) {
+        let mut counts = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        for id in ids {
+            *counts.entry(id).or_insert(0) += 1;
+        }
+    }
+
+    /// Number of searches an entry was returned by
+    pub fn get(&self, id: usize) -> u64 {
+        let counts = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        counts.get(&id).copied().unwrap_or(0)
+    }
+
+    // Helper function to forget a deleted entry
+    fn remove(&self, id: usize) {
+        let mut counts = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        counts.remove(&id);
+    }
+
+    // Helper function to copy the counts out of the lock
+    fn snapshot(&self) -> HashMap<usize, u64> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl Clone for MatchCounts {
+    fn clone(&self) -> Self {
+        Self(StdMutex::new(self.snapshot()))
+    }
+}
+
+impl Serialize for MatchCounts {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.snapshot().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MatchCounts {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let counts: HashMap<usize, u64> = HashMap::deserialize(deserializer)?;
+        std::result::Result::Ok(Self(StdMutex::new(counts)))
+    }
+}
+
+/// Counter bumped on every mutation of a store, e.g. to invalidate caches
+///
+/// Readers only need the store's read lock to observe it. Clones start from
+/// the same value.
+#[derive(Debug, Default)]
+pub struct StoreGeneration(AtomicU64);
+
+impl StoreGeneration {
+    /// Current value of the counter
+    pub fn get(&self) -> u64 {
+        self.0.load(AtomicOrdering::SeqCst)
+    }
+
+    // Helper function to advance the counter
+    fn bump(&self) {
+        self.0.fetch_add(1, AtomicOrdering::SeqCst);
+    }
+}
+
+impl Clone for StoreGeneration {
+    fn clone(&self) -> Self {
+        Self(AtomicU64::new(self.get()))
+    }
+}
+
+/// Nearest other entries of every entry: `(id, [(neighbor id, score)])`, in
+/// store order with the neighbors best first
+pub type Neighbors = Vec<(usize, Vec<(usize, f64)>)>;
+
+/// A reviewer's judgement of whether two entries are similar
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Verdict {
+    /// ID of the entry the pair was drawn for
+    pub query_id: usize,
+    /// ID of the neighbor shown next to it
+    pub candidate_id: usize,
+    /// Whether the reviewer found the two entries similar
+    pub similar: bool,
+    /// Similarity of the two entries when the verdict was recorded
+    pub score: f64,
+    /// When the verdict was recorded, in seconds since the Unix epoch
+    pub created_at: u64,
+}
+
+/// Neighbor lists of the last `neighbors` call, with the generation and `k`
+/// they were computed for
+///
+/// Searches only hold the read lock of a store, so the cache sits behind a
+/// lock of their own. Clones start out empty.
+#[derive(Debug, Default)]
+struct NeighborCache(StdMutex<Option<(u64, usize, Neighbors)>>);
+
+impl Clone for NeighborCache {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+/// Number of values every row of a [`VectorBuffer`] is padded to a multiple
+/// of, the lane count of [`cosine_similarity_simd`]
+pub const VECTOR_LANES: usize = 8;
+
+/// Vectors of many entries in one contiguous, row-major buffer
+///
+/// Scanning a single allocation is friendlier to the cache than following a
+/// pointer per entry. Rows are zero-padded to a multiple of
+/// [`VECTOR_LANES`] values, so each row starts on a lane boundary of the
+/// buffer and SIMD loads never straddle two rows.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VectorBuffer {
+    /// Every row back to back, padding included
+    values: Vec<f64>,
+    /// Number of values between the starts of two rows
+    stride: usize,
+    /// Unpadded length of each row
+    lengths: Vec<usize>,
+}
+
+impl VectorBuffer {
+    /// Copy vectors into a buffer, one row each and in order
+    ///
+    /// # Arguments
+    /// * `rows` - The vectors, which may differ in length
+    pub fn from_rows<'a>(rows: impl IntoIterator<Item = &'a [f64]> + Clone) -> Self {
+        let longest: usize = rows
+            .clone()
+            .into_iter()
+            .map(<[f64]>::len)
+            .max()
+            .unwrap_or(0);
+        let stride: usize = longest.div_ceil(VECTOR_LANES) * VECTOR_LANES;
+
+        let mut values: Vec<f64> = Vec::new();
+        let mut lengths: Vec<usize> = Vec::new();
+        for row in rows {
+            values.extend_from_slice(row);
+            values.resize(values.len() + stride - row.len(), 0.0);
+            lengths.push(row.len());
+        }
+
+        Self {
+            values,
+            stride,
+            lengths,
+        }
+    }
+
+    /// A row without its padding
+    ///
+    /// # Arguments
+    /// * `index` - Position of the row, panics when out of bounds
+    pub fn row(&self, index: usize) -> &[f64] {
+        let start: usize = index * self.stride;
+        &self.values[start..start + self.lengths[index]]
+    }
+
+    /// Number of values between the starts of two rows
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Number of rows
+    pub fn len(&self) -> usize {
+        self.lengths.len()
+    }
+
+    /// Whether the buffer holds no rows
+    pub fn is_empty(&self) -> bool {
+        self.lengths.is_empty()
+    }
+}
+
+/// Contiguous copy of the stored vectors, with the generation it was built at
+///
+/// Searches only hold the read lock of a store, so the copy sits behind a
+/// lock of their own, only held to fetch or swap it: a stale copy is rebuilt
+/// without the lock and swapped in, so searches never queue behind a
+/// rebuild. Clones start out empty.
+#[derive(Debug, Default)]
+struct VectorBufferCache(StdMutex<Option<(u64, Arc<VectorBuffer>)>>);
+
+impl Clone for VectorBufferCache {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+/// Index of a scored entry, ordered by score so a heap can keep the best ones
+///
+/// Ties are broken in favour of the lower index, matching insertion order.
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    score: f64,
+    index: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+/// Future returned by an [`Embedder`]
+pub type EmbeddingFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<f64>, Error>> + 'a>>;
+
+/// Settings handed to an [`Embedder`] for a single vectorization
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbeddingRequest {
+    /// Dimension of the vectors
+    pub dimensions: usize,
+    /// Annotations used for prompting
+    pub prompt_annotations: Vec<String>,
+    /// Prompts used for vectorization
+    pub prompts: Vec<String>,
+    /// Size of prompts to use
+    pub prompt_size: usize,
+}
+
+/// Turns an image into a vector
+///
+/// Stores use [`OpenAIEmbedder`] unless another embedder is supplied through
+/// [`InMemoryVectorStore::with_embedder`], e.g. a mock in tests.
+///
+/// Implementations must write the scores of the prompt at index `i` to
+/// dimensions `i * prompt_size .. (i + 1) * prompt_size`, however they
+/// schedule the prompts. Together with the file name order of
+/// [`crate::prompts::load_prompt_files`], this keeps the layout of a vector
+/// stable across runs, so the same prompts and image map to the same
+/// dimensions.
+pub trait Embedder: std::fmt::Debug + Send + Sync {
+    /// Vectorize an image according to the given request
+    fn embed(&self, request: EmbeddingRequest, image: DynamicImage) -> EmbeddingFuture<'_>;
+}
+
+/// Default embedder, scoring the prompts with OpenAI through `dim`
+///
+/// `dim` scores the prompts concurrently but writes each result at its
+/// prompt's index. The scores themselves come from a language model and may
+/// still vary slightly between calls.
+#[derive(Debug, Clone, Default)]
+pub struct OpenAIEmbedder {
+    /// Client configuration, the global one from the environment when unset
+    config: Option<OpenAIConfig>,
+}
+
+impl OpenAIEmbedder {
+    /// Create an embedder using its own client configuration, e.g. to send
+    /// one store's requests to a different endpoint or deployment
+    ///
+    /// # Arguments
+    /// * `config` - Configuration of the OpenAI client
+    pub fn with_config(config: OpenAIConfig) -> Self {
+        Self {
+            config: Some(config),
+        }
+    }
+}
+
+impl Embedder for OpenAIEmbedder {
+    fn embed(&self, request: EmbeddingRequest, image: DynamicImage) -> EmbeddingFuture<'_> {
+        Box::pin(async move {
+            let client: Client<OpenAIConfig> = match &self.config {
+                Some(config) => Client::with_config(config.clone()),
+                None => {
+                    // a missing key never reaches the provider, so it is
+                    // reported here instead of being classified from a response
+                    if std::env::var("OPENAI_API_KEY").map_or(true, |key| key.trim().is_empty()) {
+                        return Err(EmbeddingErrors::UpstreamAuth(
+                            "OPENAI_API_KEY is not set".to_string(),
+                        )
+                        .into());
+                    }
+                    instantiate_client::<OpenAIConfig>(None).map_err(classify_upstream_error)?
+                }
+            };
+
+            // initialize the vectorization mechanics
+            let mut vector: vector::Vector<DynamicImage> = Vector::new(
+                request.dimensions,
+                request.prompt_annotations,
+                request.prompts,
+                request.prompt_size,
+                image,
+            );
+
+            vectorize_image_concurrently::<OpenAIConfig>(&mut vector, client)
+                .await
+                .map_err(classify_upstream_error)?;
+
+            Ok(vector.get_vector())
+        })
+    }
+}
+
+// Helper function to tell authentication failures of the provider apart from
+// other errors, by finding the typed `OpenAIError` in the error chain. OpenAI
+// reports a rejected key as an API error with the `invalid_api_key` code, or
+// as a bare 401/403 when the response carries no error body.
+fn classify_upstream_error(error: impl Into<Error>) -> Error {
+    let error: Error = error.into();
+    let is_auth: bool = error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<OpenAIError>())
+        .any(|cause| match cause {
+            OpenAIError::ApiError(api_error) => matches!(
+                api_error.code.as_deref(),
+                Some("invalid_api_key") | Some("invalid_organization")
+            ),
+            OpenAIError::Reqwest(reqwest_error) => matches!(
+                reqwest_error.status().map(|status| status.as_u16()),
+                Some(401) | Some(403)
+            ),
+            _ => false,
+        });
+
+    if is_auth {
+        EmbeddingErrors::UpstreamAuth(error.to_string()).into()
+    } else {
+        error
+    }
+}
+
+/// Defines essential operations that must be implemented by vector stores
+pub trait VectorStore {
+    /// Search for similar entries given an image
+    ///
+    /// # Arguments
+    /// * `image` - The image to search for similar entries
+    /// * `top_n` - Number of most similar entries to return
+    async fn search(&self, image: DynamicImage, top_n: usize) -> Result<Vec<SearchResult>, Error>;
+
+    /// Add a new entry to the vector store
+    ///
+    /// # Arguments
+    /// * `name` - Name of the entry
+    /// * `descriptions` - List of descriptions for the entry  
+    /// * `image` - Image to store
+    ///
+    /// # Returns
+    /// ID of the entry holding the image
+    async fn add(
+        &mut self,
+        name: &str,
+        descriptions: Vec<String>,
+        image: DynamicImage,
+    ) -> Result<usize>;
+
+    /// Delete an entry from the store by ID
+    ///
+    /// # Arguments
+    /// * `id` - ID of the entry to delete
+    async fn delete(&mut self, id: usize) -> Result<()>;
+
+    /// Edit an existing entry with new data
+    ///
+    /// # Arguments
+    /// * `image` - New image
+    /// * `data_entry` - Updated data entry
+    async fn edit(&mut self, image: DynamicImage, data_entry: DataEntry) -> Result<()>;
+}
+
+/// The persisted configuration of a store, without its entries
+///
+/// Prompts and layout change far less often than the entries, so they can be
+/// exported and versioned on their own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoreConfig {
+    /// Dimension of the vectors
+    pub dimensions: usize,
+    /// Prompts used for vectorization, in dimension order
+    pub prompts: Vec<String>,
+    /// Annotations used for prompting
+    #[serde(default)]
+    pub prompt_annotations: Vec<String>,
+    /// Number of dimensions each prompt fills
+    pub prompt_size: usize,
+    /// Whether stored vectors are L2-normalized
+    #[serde(default)]
+    pub normalized: bool,
+    /// Projection of raw embeddings into the stored space, see
+    /// [`InMemoryVectorStore::set_projection`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub projection: Option<Vec<Vec<f64>>>,
+}
+
+/// Check that prompts fill the dimensions of an embedding exactly
+///
+/// Embedders write the scores of each prompt to `prompt_size` consecutive
+/// dimensions, so any other prompt count yields vectors of the wrong length.
+///
+/// # Arguments
+/// * `prompts` - Number of prompts
+/// * `prompt_size` - Number of dimensions each prompt fills
+/// * `dimensions` - Dimensions of the embeddings, before any projection
+pub fn validate_prompt_layout(
+    prompts: usize,
+    prompt_size: usize,
+    dimensions: usize,
+) -> Result<(), Error> {
+    if prompts * prompt_size != dimensions {
+        return Err(ValidationErrors::PromptLayoutMismatch {
+            prompts,
+            prompt_size,
+            dimensions,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+// Helper function to check that a projection matrix has one row per stored
+// dimension, all equally long and finite
+fn validate_projection(matrix: &[Vec<f64>], dimensions: usize) -> Result<(), Error> {
+    if matrix.len() != dimensions {
+        return Err(ValidationErrors::InvalidProjection {
+            expected_rows: dimensions,
+            rows: matrix.len(),
+        }
+        .into());
+    }
+
+    let columns: usize = matrix.first().map_or(0, Vec::len);
+    for (row, values) in matrix.iter().enumerate() {
+        if values.is_empty()
+            || values.len() != columns
+            || values.iter().any(|value| !value.is_finite())
+        {
+            return Err(ValidationErrors::RaggedProjection { row }.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// In-memory implementation of a vector store
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InMemoryVectorStore {
+    /// Storage for data entry metadata
+    #[serde(serialize_with = "serialize_entries_full")]
+    data_entries: Vec<DataEntry>,
+    /// Annotations used for prompting
+    prompt_annotations: Vec<String>,
+    /// Prompts used for vectorization
+    prompts: Vec<String>,
+    /// Size of prompts to use
+    prompt_size: usize,
+    /// Dimension of the vectors
+    dimensions: usize,
+    /// Lowest ID handed to the next entry, so IDs of deleted entries are not reused
+    #[serde(default)]
+    next_id: usize,
+    /// Whether stored vectors are L2-normalized on insertion
+    #[serde(default)]
+    normalized: bool,
+    /// Matrix projecting raw embeddings into the stored space, identity when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    projection: Option<Vec<Vec<f64>>>,
+    /// What `add` does with images that are already stored
+    #[serde(skip)]
+    duplicate_strategy: DuplicateStrategy,
+    /// What `import` does with vectors of the wrong length
+    #[serde(skip)]
+    length_mismatch_policy: LengthMismatchPolicy,
+    /// Extra embedding attempts when a vector of the wrong length comes back
+    #[serde(skip)]
+    embedding_retries: usize,
+    /// Number of prompts handed to the embedder at once, 0 hands over all
+    #[serde(skip)]
+    prompt_batch_size: usize,
+    /// Whether descriptions are trimmed and lowercased on insertion
+    #[serde(skip)]
+    normalize_tags: bool,
+    /// Whether searches count how often each entry is returned
+    #[serde(skip)]
+    track_matches: bool,
+    /// Whether `add` rejects constant vectors, rather than only warning
+    #[serde(skip)]
+    reject_constant_vectors: bool,
+    /// Whether searches scan a contiguous copy of the vectors
+    #[serde(skip)]
+    contiguous_vectors: bool,
+    /// How often each entry was returned by searches, while tracking is enabled
+    #[serde(default)]
+    match_counts: MatchCounts,
+    /// Reviewer verdicts on pairs of entries, kept for later aggregation
+    #[serde(default)]
+    verdicts: Vec<Verdict>,
+    /// Bumped on every mutation, restarting from 0 with the process
+    #[serde(skip)]
+    generation: StoreGeneration,
+    /// Last result of `neighbors`, valid while the generation is unchanged
+    #[serde(skip)]
+    neighbor_cache: NeighborCache,
+    /// Contiguous copy of the vectors scanned by searches, while enabled
+    #[serde(skip)]
+    vector_buffer: VectorBufferCache,
+    /// Named prompt sets loaded at startup, by name
+    #[serde(skip)]
+    prompt_sets: HashMap<String, Vec<String>>,
+    /// Embedder override, the OpenAI embedder is used when unset
+    #[serde(skip)]
+    embedder: Option<Arc<dyn Embedder>>,
+}
+
+impl InMemoryVectorStore {
+    /// Create a new InMemoryVectorStore instance, rejecting zero dimensions
+    ///
+    /// # Arguments
+    /// * `dimensions` - Dimensionality of vectors, at least 1
+    /// * `prompt_annotations` - Annotations for prompts
+    /// * `prompts` - Prompts for vectorization
+    /// * `prompt_size` - Size of prompts to use
+    pub fn new(
+        dimensions: usize,
+        prompt_annotations: Vec<String>,
+        prompts: Vec<String>,
+        prompt_size: usize,
+    ) -> Result<Self, Error> {
+        if dimensions == 0 {
+            return Err(ValidationErrors::ZeroDimensions.into());
+        }
+
+        Ok(Self {
+            data_entries: Vec::new(),
+            prompts: prompts,
+            prompt_size: prompt_size,
+            prompt_annotations: prompt_annotations,
+            dimensions: dimensions,
+            next_id: 1,
+            normalized: false,
+            projection: None,
+            duplicate_strategy: DuplicateStrategy::default(),
+            length_mismatch_policy: LengthMismatchPolicy::default(),
+            embedding_retries: 0,
+            prompt_batch_size: 0,
+            normalize_tags: false,
+            track_matches: false,
+            reject_constant_vectors: false,
+            contiguous_vectors: false,
+            match_counts: MatchCounts::default(),
+            verdicts: Vec::new(),
+            generation: StoreGeneration::default(),
+            neighbor_cache: NeighborCache::default(),
+            vector_buffer: VectorBufferCache::default(),
+            prompt_sets: HashMap::new(),
+            embedder: None,
+        })
+    }
+
+    /// L2-normalize every vector on insertion
+    ///
+    /// This is part of the persisted store, and should be chosen before
+    /// entries are added: searches error if the query's normalization does
+    /// not match, and [`Self::validate`] rejects unnormalized vectors.
+    ///
+    /// # Arguments
+    /// * `normalized` - Whether stored vectors are normalized
+    pub fn with_normalized_vectors(mut self, normalized: bool) -> Self {
+        self.normalized = normalized;
+        self
+    }
+
+    /// Whether stored vectors are L2-normalized
+    pub fn is_normalized(&self) -> bool {
+        self.normalized
+    }
+
+    /// Choose what `add` does with images that are already stored
+    ///
+    /// # Arguments
+    /// * `strategy` - How duplicate uploads are handled
+    pub fn with_duplicate_strategy(mut self, strategy: DuplicateStrategy) -> Self {
+        self.duplicate_strategy = strategy;
+        self
+    }
+
+    /// Choose what `import` does with vectors of the wrong length
+    ///
+    /// # Arguments
+    /// * `policy` - How vectors not matching the dimensions are handled
+    pub fn with_length_mismatch_policy(mut self, policy: LengthMismatchPolicy) -> Self {
+        self.length_mismatch_policy = policy;
+        self
+    }
+
+    /// Retry embeddings that come back with the wrong number of dimensions
+    ///
+    /// # Arguments
+    /// * `retries` - Extra attempts after the first one, 0 fails right away
+    pub fn with_embedding_retries(mut self, retries: usize) -> Self {
+        self.embedding_retries = retries;
+        self
+    }
+
+    /// Score the prompts of an image in batches rather than all at once
+    ///
+    /// The default embedder scores every prompt it is handed concurrently, so
+    /// large prompt sets open as many connections at once. Batches are
+    /// embedded one after another, which lowers that peak at the cost of
+    /// latency: an image then takes about as many round trips as batches.
+    ///
+    /// Batching needs one `prompt_size` slice of the vector per prompt, and is
+    /// skipped with a warning for stores laid out otherwise.
+    ///
+    /// # Arguments
+    /// * `batch_size` - Prompts per batch, 0 hands every prompt over at once
+    pub fn with_prompt_batch_size(mut self, batch_size: usize) -> Self {
+        self.prompt_batch_size = batch_size;
+        self
+    }
+
+    /// Trim and lowercase descriptions on insertion, so they work as tags
+    ///
+    /// This is lossy for display: "Navy Blue " is stored as "navy blue".
+    /// Tag filters of searches are normalized the same way, so "Blue" and
+    /// "blue " both match entries described as "blue". Entries stored before
+    /// enabling this keep their descriptions as they are.
+    ///
+    /// # Arguments
+    /// * `normalize_tags` - Whether descriptions are normalized by `add` and `add_description`
+    pub fn with_normalized_tags(mut self, normalize_tags: bool) -> Self {
+        self.normalize_tags = normalize_tags;
+        self
+    }
+
+    /// Count how often searches return each entry
+    ///
+    /// Only the final results of `search`, `search_with`, `search_with_query`
+    /// and `search_stream` count, not `similar_to`. Every counted search
+    /// briefly locks the counts, which adds contention between concurrent
+    /// searches, so this is opt-in. The counts are persisted with the store.
+    ///
+    /// # Arguments
+    /// * `track_matches` - Whether searches count their results
+    pub fn with_match_tracking(mut self, track_matches: bool) -> Self {
+        self.track_matches = track_matches;
+        self
+    }
+
+    /// Reject images whose embedding is constant instead of only warning
+    ///
+    /// When every prompt scores an image the same, the vector has no
+    /// variance and ties with every other constant vector, which usually
+    /// means a broken embedding pipeline or a blank image. `add` always logs
+    /// a warning for such vectors, see [`is_constant_vector`].
+    ///
+    /// # Arguments
+    /// * `reject` - Whether `add` fails for constant vectors
+    pub fn with_constant_vector_rejection(mut self, reject: bool) -> Self {
+        self.reject_constant_vectors = reject;
+        self
+    }
+
+    /// Scan a contiguous copy of the vectors when searching
+    ///
+    /// The copy is a [`VectorBuffer`], built on the first search after each
+    /// change of the store, so it pays off for stores searched more often
+    /// than they change. The entries keep their own vectors, which roughly
+    /// doubles the memory spent on vectors. Scores are unchanged.
+    ///
+    /// # Arguments
+    /// * `contiguous` - Whether searches scan the contiguous copy
+    pub fn with_contiguous_vectors(mut self, contiguous: bool) -> Self {
+        self.contiguous_vectors = contiguous;
+        self
+    }
+
+    /// Number of mutations of the store so far
+    ///
+    /// Every add, edit, delete and change of the entries or configuration
+    /// advances it, reads never do, so an unchanged value means unchanged
+    /// search results. It is not persisted: loading a store keeps counting
+    /// from the current value, so it never repeats within a process.
+    pub fn generation(&self) -> u64 {
+        self.generation.get()
+    }
+
+    /// Whether searches count how often each entry is returned
+    pub fn tracks_matches(&self) -> bool {
+        self.track_matches
+    }
+
+    /// How often searches returned each entry
+    pub fn match_counts(&self) -> &MatchCounts {
+        &self.match_counts
+    }
+
+    /// Get the entries no counted search has returned, in store order
+    pub fn unmatched_entries(&self) -> Vec<DataEntry> {
+        self.data_entries
+            .iter()
+            .filter(|entry| self.match_counts.get(entry.id) == 0)
+            .cloned()
+            .collect()
+    }
+
+    /// Get the entries created within a time range, in store order
+    ///
+    /// # Arguments
+    /// * `since` - Earliest creation time included, in seconds since the Unix epoch
+    /// * `until` - Creation time from which entries are left out, unbounded when unset
+    pub fn created_between(&self, since: u64, until: Option<u64>) -> Vec<DataEntry> {
+        self.data_entries
+            .iter()
+            .filter(|entry| {
+                entry.created_at >= since && !until.is_some_and(|until| entry.created_at >= until)
+            })
+            .cloned()
+            .collect()
+    }
+
+    // Helper function to count the results of a search, if tracking is enabled
+    fn record_matches(&self, results: &[SearchResult]) {
+        if self.track_matches {
+            self.match_counts
+                .record(results.iter().map(|result| result.data_entry.id));
+        }
+    }
+
+    // Helper function to bring a description or tag into its stored form
+    fn tag_form(&self, description: String) -> String {
+        if self.normalize_tags {
+            normalize_tag(&description)
+        } else {
+            description
+        }
+    }
+
+    /// Dimension of the vectors
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// Estimate the bytes held by the entries, for capacity planning
+    ///
+    /// This sums the heap sizes of every entry's fields, plus the entry
+    /// itself. Metadata is counted by its JSON length. Takes one pass over
+    /// the entries, so it is cheap enough to poll.
+    pub fn estimated_memory(&self) -> usize {
+        self.data_entries
+            .iter()
+            .map(|entry| {
+                std::mem::size_of::<DataEntry>()
+                    + entry.vector.capacity() * std::mem::size_of::<f64>()
+                    + entry.name.capacity()
+                    + entry
+                        .descriptions
+                        .iter()
+                        .map(|description| std::mem::size_of::<String>() + description.capacity())
+                        .sum::<usize>()
+                    + entry.image.as_ref().map_or(0, |image| image.capacity())
+                    + if entry.metadata.is_null() {
+                        0
+                    } else {
+                        entry.metadata.to_string().len()
+                    }
+            })
+            .sum()
+    }
+
+    /// Number of entries in the store
+    pub fn len(&self) -> usize {
+        self.data_entries.len()
+    }
+
+    /// Whether the store has no entries
+    pub fn is_empty(&self) -> bool {
+        self.data_entries.is_empty()
+    }
+
+    /// Project raw embeddings into the stored space with a matrix
+    ///
+    /// This aligns a new embedding model with vectors stored from another,
+    /// short of re-embedding every entry. The matrix has one row per stored
+    /// dimension, each as long as the raw embedding: the embedder is asked
+    /// for that many dimensions, and stored value `i` is the dot product of
+    /// row `i` with the raw vector. `None`, the default, stores raw vectors
+    /// as they are, like an identity matrix. The projection is persisted.
+    ///
+    /// # Arguments
+    /// * `projection` - The matrix, in row-major order, or `None` to remove it
+    pub fn set_projection(&mut self, projection: Option<Vec<Vec<f64>>>) -> Result<(), Error> {
+        if let Some(matrix) = &projection {
+            validate_projection(matrix, self.dimensions)?;
+        }
+        self.projection = projection;
+        self.generation.bump();
+
+        Ok(())
+    }
+
+    // Helper function to get the length of the vectors the embedder returns
+    fn raw_dimensions(&self) -> usize {
+        match &self.projection {
+            Some(matrix) => matrix.first().map_or(0, Vec::len),
+            None => self.dimensions,
+        }
+    }
+
+    // Helper function to project a raw embedding into the stored space
+    fn project(&self, vector: Vec<f64>) -> Vec<f64> {
+        match &self.projection {
+            Some(matrix) => matrix
+                .iter()
+                .map(|row| row.iter().zip(&vector).map(|(a, b)| a * b).sum())
+                .collect(),
+            None => vector,
+        }
+    }
+
+    /// Check that the store is consistent: at least one dimension, unique
+    /// IDs, and finite vectors matching the store's dimensions
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.dimensions == 0 {
+            return Err(ValidationErrors::ZeroDimensions.into());
+        }
+        if let Some(matrix) = &self.projection {
+            validate_projection(matrix, self.dimensions)?;
+        }
+
+        let mut ids: HashSet<usize> = HashSet::with_capacity(self.data_entries.len());
+
+        for entry in &self.data_entries {
+            if !ids.insert(entry.id) {
+                return Err(ValidationErrors::DuplicateId(entry.id).into());
+            }
+            if entry.vector.len() != self.dimensions {
+                return Err(ValidationErrors::DimensionMismatch {
+                    id: entry.id,
+                    expected: self.dimensions,
+                    actual: entry.vector.len(),
+                }
+                .into());
+            }
+            if entry.vector.iter().any(|value| !value.is_finite()) {
+                return Err(ValidationErrors::NonFiniteVector(entry.id).into());
+            }
+            // zero vectors cannot be normalized and are left as they are
+            let norm: f64 = l2_norm(&entry.vector);
+            if self.normalized && norm != 0.0 && (norm - 1.0).abs() > 1e-6 {
+                return Err(ValidationErrors::UnnormalizedVector(entry.id).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace this store's contents with another store's
+    ///
+    /// Runtime-only settings that are not persisted, such as the embedder,
+    /// are kept from this store.
+    ///
+    /// # Arguments
+    /// * `other` - The store to take the entries and configuration from
+    pub fn replace(&mut self, other: InMemoryVectorStore) {
+        let embedder: Option<Arc<dyn Embedder>> = self.embedder.take();
+        let duplicate_strategy: DuplicateStrategy = self.duplicate_strategy;
+        let length_mismatch_policy: LengthMismatchPolicy = self.length_mismatch_policy;
+        let embedding_retries: usize = self.embedding_retries;
+        let prompt_batch_size: usize = self.prompt_batch_size;
+        let normalize_tags: bool = self.normalize_tags;
+        let track_matches: bool = self.track_matches;
+        let reject_constant_vectors: bool = self.reject_constant_vectors;
+        let contiguous_vectors: bool = self.contiguous_vectors;
+        let generation: StoreGeneration = std::mem::take(&mut self.generation);
+        let prompt_sets: HashMap<String, Vec<String>> = std::mem::take(&mut self.prompt_sets);
+        *self = other;
+        self.embedder = embedder;
+        self.duplicate_strategy = duplicate_strategy;
+        self.length_mismatch_policy = length_mismatch_policy;
+        self.embedding_retries = embedding_retries;
+        self.prompt_batch_size = prompt_batch_size;
+        self.normalize_tags = normalize_tags;
+        self.track_matches = track_matches;
+        self.reject_constant_vectors = reject_constant_vectors;
+        self.contiguous_vectors = contiguous_vectors;
+        self.generation = generation;
+        self.generation.bump();
+        self.neighbor_cache = NeighborCache::default();
+        self.vector_buffer = VectorBufferCache::default();
+        self.prompt_sets = prompt_sets;
+    }
+
+    /// The store's configuration, without its entries
+    pub fn store_config(&self) -> StoreConfig {
+        StoreConfig {
+            dimensions: self.dimensions,
+            prompts: self.prompts.clone(),
+            prompt_annotations: self.prompt_annotations.clone(),
+            prompt_size: self.prompt_size,
+            normalized: self.normalized,
+            projection: self.projection.clone(),
+        }
+    }
+
+    /// Replace the store's configuration, keeping its entries
+    ///
+    /// The prompts must fill the embedded dimensions exactly, that is the
+    /// projection's columns or, without one, the dimensions. The entries must
+    /// still pass [`Self::validate`] under the new configuration, e.g. match
+    /// its dimensions. Otherwise the store is left untouched. Changing prompts
+    /// does not re-embed existing entries.
+    ///
+    /// # Arguments
+    /// * `store_config` - The new configuration
+    pub fn set_store_config(&mut self, store_config: StoreConfig) -> Result<(), Error> {
+        let raw_dimensions: usize = match &store_config.projection {
+            Some(matrix) => matrix.first().map_or(0, Vec::len),
+            None => store_config.dimensions,
+        };
+        validate_prompt_layout(
+            store_config.prompts.len(),
+            store_config.prompt_size,
+            raw_dimensions,
+        )?;
+
+        let previous: StoreConfig = self.store_config();
+        self.apply_store_config(store_config);
+        if let Err(e) = self.validate() {
+            self.apply_store_config(previous);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    // Helper function to overwrite the configuration fields
+    fn apply_store_config(&mut self, store_config: StoreConfig) {
+        self.dimensions = store_config.dimensions;
+        self.prompts = store_config.prompts;
+        self.prompt_annotations = store_config.prompt_annotations;
+        self.prompt_size = store_config.prompt_size;
+        self.normalized = store_config.normalized;
+        self.projection = store_config.projection;
+        self.generation.bump();
+    }
+
+    /// Replace every entry of the store at once
+    ///
+    /// This lets a store be built offline, e.g. when using the crate as a
+    /// library, and handed over. The entries must pass [`Self::validate`],
+    /// otherwise the store is left untouched. New entries get IDs above the
+    /// highest given one.
+    ///
+    /// # Arguments
+    /// * `entries` - The new entries, with vectors in the store's form
+    pub fn set_entries(&mut self, entries: Vec<DataEntry>) -> Result<(), Error> {
+        let previous: Vec<DataEntry> = std::mem::replace(&mut self.data_entries, entries);
+        if let Err(e) = self.validate() {
+            self.data_entries = previous;
+            return Err(e);
+        }
+
+        self.next_id = self.max_id() + 1;
+        self.generation.bump();
+
+        Ok(())
+    }
+
+    // Helper function to get the highest ID in use, 0 for an empty store
+    fn max_id(&self) -> usize {
+        self.data_entries
+            .iter()
+            .map(|entry| entry.id)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Prompts used for vectorization, in dimension order
+    pub fn prompts(&self) -> &[String] {
+        &self.prompts
+    }
+
+    /// Number of embedding calls it takes to vectorize one image
+    ///
+    /// The embedder scores every prompt with a call of its own, batched or
+    /// not, so this is the number of prompts. Retries are not counted.
+    pub fn calls_per_image(&self) -> usize {
+        self.prompts.len()
+    }
+
+    /// Make named prompt sets available next to the store's own prompts
+    ///
+    /// See [`crate::prompts::load_prompt_sets`]. The sets are not persisted
+    /// with the store, they are loaded again at every start.
+    ///
+    /// # Arguments
+    /// * `prompt_sets` - Prompts by set name
+    pub fn with_prompt_sets(mut self, prompt_sets: HashMap<String, Vec<String>>) -> Self {
+        self.prompt_sets = prompt_sets;
+        self
+    }
+
+    /// Names of the available prompt sets, sorted
+    pub fn prompt_set_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.prompt_sets.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Prompts of a named prompt set
+    pub fn prompt_set(&self, name: &str) -> Option<&[String]> {
+        self.prompt_sets.get(name).map(Vec::as_slice)
+    }
+
+    /// Annotations used for prompting
+    pub fn prompt_annotations(&self) -> &[String] {
+        &self.prompt_annotations
+    }
+
+    /// Number of dimensions each prompt fills
+    pub fn prompt_size(&self) -> usize {
+        self.prompt_size
+    }
+
+    /// Use a custom embedder instead of the default OpenAI one
+    ///
+    /// # Arguments
+    /// * `embedder` - Embedder used by `add`, `search` and `embed_only`
+    pub fn with_embedder(mut self, embedder: Arc<dyn Embedder>) -> Self {
+        self.embedder = Some(embedder);
+        self
+    }
+
+    /// Use an OpenAI client configuration of its own instead of the global one
+    ///
+    /// # Arguments
+    /// * `config` - Configuration of the OpenAI client used by this store
+    pub fn with_openai_config(self, config: OpenAIConfig) -> Self {
+        self.with_embedder(Arc::new(OpenAIEmbedder::with_config(config)))
+    }
+
+    /// Replace the prompts used for vectorization
+    ///
+    /// Existing vectors are left untouched, so they should be re-embedded if
+    /// the new prompts differ in meaning.
+    ///
+    /// # Arguments
+    /// * `prompts` - New prompts for vectorization
+    pub fn set_prompts(&mut self, prompts: Vec<String>) {
+        self.prompts = prompts;
+        self.generation.bump();
+    }
+
+    /// Prompt size passed to the embedder
+    ///
+    /// This is `prompt_size` clamped to the number of loaded prompts, so a
+    /// shrunken prompt set never hands an invalid size to `Vector::new`.
+    pub fn effective_prompt_size(&self) -> usize {
+        if self.prompt_size > self.prompts.len() {
+            warn!(
+                "prompt_size {} exceeds the {} loaded prompts, clamping it to {}",
+                self.prompt_size,
+                self.prompts.len(),
+                self.prompts.len()
+            );
+            return self.prompts.len();
+        }
+
+        self.prompt_size
+    }
+
+    /// Store entry metadata in key-value storage
+    ///
+    /// # Arguments
+    /// * `name` - Name of the entry
+    /// * `descriptions` - Descriptions for the entry
+    /// * `vector` - Vector representation
+    /// * `image` - Encoded source image, if it should be kept
+    /// * `image_hash` - Hash of the source image, if there is one
+    /// * `metadata` - Arbitrary JSON metadata of the entry
+    /// * `gender` - Gender the entry is meant for, if known
+    ///
+    /// # Returns
+    /// ID of the stored entry
+    #[allow(clippy::too_many_arguments)]
+    fn kv_storage(
+        &mut self,
+        name: &str,
+        descriptions: Vec<String>,
+        vector: Vec<f64>,
+        image: Option<String>,
+        image_hash: Option<u64>,
+        metadata: Value,
+        gender: Option<Gender>,
+    ) -> Result<usize, Error> {
+        // stores loaded from older snapshots have no counter yet
+        let current_id: usize = self.next_id.max(self.max_id() + 1);
+        self.next_id = current_id + 1;
+
+        self.data_entries.push(DataEntry {
+            id: current_id,
+            name: name.to_string(),
+            vector: self.storage_form(vector),
+            // no descriptions means no tags, not a blank one
+            descriptions: descriptions
+                .into_iter()
+                .filter(|description| !description.trim().is_empty())
+                .collect(),
+            image,
+            created_at: unix_timestamp(),
+            image_hash,
+            metadata,
+            version: 0,
+            gender,
+        });
+        self.generation.bump();
+
+        Ok(current_id)
+    }
+
+    // Helper function to bring a vector into the form it is stored in
+    fn storage_form(&self, vector: Vec<f64>) -> Vec<f64> {
+        if self.normalized {
+            l2_normalize(&vector)
+        } else {
+            vector
+        }
+    }
+
+    // Helper function to bring a freshly embedded query into the form of the
+    // stored vectors, as requested by the search options
+    fn prepare_query(
+        &self,
+        query_vector: Vec<f64>,
+        options: &SearchOptions,
+    ) -> Result<Vec<f64>, Error> {
+        let normalize_query: bool = options.normalize_query.unwrap_or(self.normalized);
+        if normalize_query != self.normalized {
+            return Err(ValidationErrors::NormalizationMismatch {
+                store: self.normalized,
+                query: normalize_query,
+            }
+            .into());
+        }
+
+        Ok(self.storage_form(query_vector))
+    }
+
+    /// Retrieve the entries most similar to a query vector
+    ///
+    /// Finding no candidates is an error, unless filters removed them.
+    ///
+    /// # Arguments
+    /// * `query_vector` - Vector to compare the entries with, in the form of the stored vectors
+    /// * `options` - Number of results, and the IDs and tags filtering the entries
+    ///
+    /// # Returns
+    /// The results, and how many were requested, returned and filtered out
+    fn kv_search(
+        &self,
+        query_vector: &[f64],
+        options: &SearchOptions,
+    ) -> Result<(Vec<SearchResult>, SearchCounts), Error> {
+        self.kv_search_with(query_vector, options, |_, _| {})
+    }
+
+    // Helper function behind `kv_search`, calling `on_scored` with every
+    // entry that passes the filters and its score, in scan order
+    fn kv_search_with(
+        &self,
+        query_vector: &[f64],
+        options: &SearchOptions,
+        mut on_scored: impl FnMut(&DataEntry, f64),
+    ) -> Result<(Vec<SearchResult>, SearchCounts), Error> {
+        if self.data_entries.is_empty() {
+            return Err(DataEntryErrors::NoDataWasFound.into());
+        }
+
+        let top_n: usize = options.top_n;
+        let exclude_ids: HashSet<usize> = options.exclude_ids.iter().copied().collect();
+        let tags: Vec<String> = options
+            .tags
+            .iter()
+            .map(|tag| self.tag_form(tag.clone()))
+            .collect();
+        let mut filtered_out: usize = 0;
+        let now: u64 = unix_timestamp();
+        // ranking negated scores keeps the least similar entries instead
+        let sign: f64 = if options.farthest { -1.0 } else { 1.0 };
+        let buffer: Option<Arc<VectorBuffer>> =
+            self.contiguous_vectors.then(|| self.vector_buffer());
+
+        // Keep the best n candidates in a min-heap while scanning, so memory
+        // stays bounded by top_n, and never by more than the store size
+        let mut best: BinaryHeap<Reverse<Candidate>> =
+            BinaryHeap::with_capacity(top_n.min(self.data_entries.len()).saturating_add(1));
+        for (index, entry) in self.data_entries.iter().enumerate() {
+            if exclude_ids.contains(&entry.id)
+                || !tags.iter().all(|tag| entry.descriptions.contains(tag))
+                || options
+                    .metadata_filter
+                    .as_ref()
+                    .is_some_and(|filter| !metadata_contains(&entry.metadata, filter))
+                || options.gender.is_some_and(|filter| {
+                    !entry.gender.is_some_and(|gender| gender.matches(filter))
+                })
+            {
+                filtered_out += 1;
+                continue;
+            }
+
+            // decay before ranking, so recent entries can overtake older ones
+            let vector: &[f64] = match &buffer {
+                Some(buffer) => buffer.row(index),
+                None => &entry.vector,
+            };
+            let mut score: f64 = self.cosine_similarity(query_vector, vector);
+            if let Some(half_life_secs) = options.decay_half_life_secs {
+                score = decay_score(score, now.saturating_sub(entry.created_at), half_life_secs);
+            }
+            on_scored(entry, score);
+            let candidate = Candidate {
+                score: sign * score,
+                index,
+            };
+
+            if best.len() < top_n {
+                best.push(Reverse(candidate));
+            } else if best.peek().is_some_and(|Reverse(worst)| candidate > *worst) {
+                best.pop();
+                best.push(Reverse(candidate));
+            }
+        }
+
+        // Sorting the reversed candidates ascending yields descending scores
+        let mut top_entries: Vec<SearchResult> = best
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(candidate)| SearchResult {
+                data_entry: self.data_entries[candidate.index].clone(),
+                score: sign * candidate.score,
+            })
+            .collect();
+
+        if top_entries.is_empty() && filtered_out == 0 {
+            return Err(DataEntryErrors::NoDataWasFound.into());
+        }
+        if let Some(epsilon) = options.shuffle_ties {
+            let seed: u64 = options.shuffle_seed.unwrap_or_else(random_seed);
+            shuffle_ties(&mut top_entries, epsilon, seed);
+        }
+
+        let counts = SearchCounts {
+            requested: top_n,
+            returned: top_entries.len(),
+            filtered_out,
+        };
+        if counts.returned < counts.requested {
+            info!(
+                "Returning {} of {} requested results, {} entries were filtered out",
+                counts.returned, counts.requested, counts.filtered_out
+            );
+        }
+
+        Ok((top_entries, counts))
+    }
+
+    /// Add every image of a directory, named after its file
+    ///
+    /// Files are added in file name order, with `STYLIST_EMBED_CONCURRENCY`
+    /// images embedded at once, see [`Self::add_batch`]. Files that are not
+    /// images, or fail to be added, are skipped with a warning.
+    ///
+    /// # Arguments
+    /// * `directory` - Directory containing the images
+    ///
+    /// # Returns
+    /// Number of added images
+    pub async fn seed_from_directory(&mut self, directory: &str) -> Result<usize, Error> {
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for entry in fs::read_dir(directory)? {
+            let path: PathBuf = entry?.path();
+            if path.is_file() && ImageFormat::from_path(&path).is_ok() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let total: usize = paths.len();
+        let concurrency: usize = config::get().embed_concurrency.max(1);
+        let mut added: usize = 0;
+        // decode a few batches' worth of images at a time, so a large
+        // directory is never held in memory at once
+        for (chunk_index, chunk) in paths.chunks(concurrency * 4).enumerate() {
+            let mut items: Vec<(String, Vec<String>, DynamicImage)> = Vec::new();
+            let mut loaded: Vec<&PathBuf> = Vec::new();
+            for (offset, path) in chunk.iter().enumerate() {
+                let name: String = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let index: usize = chunk_index * concurrency * 4 + offset;
+                info!("Seeding {}/{}: {}", index + 1, total, path.display());
+
+                match image::open(path) {
+                    std::result::Result::Ok(image) => {
+                        items.push((name, vec![], image));
+                        loaded.push(path);
+                    }
+                    Err(e) => warn!("Skipping unreadable image {}: {}", path.display(), e),
+                }
+            }
+
+            let results: Vec<Result<usize, Error>> = self.add_batch(items, concurrency).await;
+            for (path, result) in loaded.into_iter().zip(results) {
+                match result {
+                    std::result::Result::Ok(_) => added += 1,
+                    Err(e) => warn!("Failed to seed {}: {}", path.display(), e),
+                }
+            }
+        }
+
+        Ok(added)
+    }
+
+    /// Find the entries most similar to a stored entry
+    ///
+    /// The entry itself is never part of the results.
+    ///
+    /// # Arguments
+    /// * `id` - ID of the entry to compare with
+    /// * `top_n` - Number of most similar entries to return
+    /// * `exclude_same_name` - Whether to leave out entries sharing the entry's name
+    pub fn similar_to(
+        &self,
+        id: usize,
+        top_n: usize,
+        exclude_same_name: bool,
+    ) -> Result<Vec<SearchResult>, Error> {
+        let entry: &DataEntry = self.get(id).ok_or(DataEntryErrors::NoDataWasFound)?;
+
+        // rank everything, as filtering may drop any number of the best results
+        let options = SearchOptions {
+            top_n: self.len(),
+            exclude_ids: vec![id],
+            ..SearchOptions::default()
+        };
+        let (results, _) = self.kv_search(&entry.vector, &options)?;
+
+        Ok(results
+            .into_iter()
+            .filter(|result| !exclude_same_name || result.data_entry.name != entry.name)
+            .take(top_n)
+            .collect())
+    }
+
+    /// Find the `k` nearest other entries of every entry
+    ///
+    /// This compares every pair of entries, so it takes O(n²) similarity
+    /// computations and is slow for large stores. The result is cached until
+    /// the store changes, see [`Self::generation`]. The cache keeps the
+    /// largest `k` asked for, so asking again for it or any smaller `k` is
+    /// cheap. Ties are broken by ID.
+    ///
+    /// # Arguments
+    /// * `k` - Number of neighbors per entry
+    pub fn neighbors(&self, k: usize) -> Neighbors {
+        let generation: u64 = self.generation();
+        let mut cache = self
+            .neighbor_cache
+            .0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some((cached_generation, cached_k, neighbors)) = cache.as_ref() {
+            if *cached_generation == generation && *cached_k >= k {
+                return neighbors
+                    .iter()
+                    .map(|(id, scored)| (*id, scored.iter().take(k).copied().collect()))
+                    .collect();
+            }
+        }
+
+        let neighbors: Neighbors = self
+            .data_entries
+            .iter()
+            .map(|entry| {
+                let mut scored: Vec<(usize, f64)> = self
+                    .data_entries
+                    .iter()
+                    .filter(|other| other.id != entry.id)
+                    .map(|other| {
+                        (
+                            other.id,
+                            self.cosine_similarity(&entry.vector, &other.vector),
+                        )
+                    })
+                    .collect();
+                scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+                scored.truncate(k);
+                (entry.id, scored)
+            })
+            .collect();
+
+        *cache = Some((generation, k, neighbors.clone()));
+        neighbors
+    }
+
+    /// Draw a random entry and one of its `k` nearest neighbors
+    ///
+    /// Pairs come from [`Self::neighbors`], so drawing again for the same `k`
+    /// is cheap while the store is unchanged. Returns the two IDs and their
+    /// similarity, or `None` when the store holds fewer than two entries.
+    ///
+    /// # Arguments
+    /// * `k` - Number of neighbors of the query to draw from
+    /// * `seed` - Seed of the draw, a random one is drawn when unset
+    pub fn random_pair(&self, k: usize, seed: Option<u64>) -> Option<(usize, usize, f64)> {
+        let mut pairs: Vec<(usize, usize, f64)> = self
+            .neighbors(k)
+            .into_iter()
+            .flat_map(|(id, neighbors)| {
+                neighbors
+                    .into_iter()
+                    .map(move |(neighbor, score)| (id, neighbor, score))
+            })
+            .collect();
+        shuffle_seeded(&mut pairs, seed.unwrap_or_else(random_seed));
+
+        pairs.into_iter().next()
+    }
+
+    /// Record a reviewer's verdict on a pair of entries
+    ///
+    /// Verdicts are saved with the store but do not count as a change of its
+    /// entries, so the generation is left alone.
+    ///
+    /// # Arguments
+    /// * `query_id` - ID of the entry the pair was drawn for
+    /// * `candidate_id` - ID of the neighbor shown next to it
+    /// * `similar` - Whether the reviewer found the two entries similar
+    pub fn record_verdict(
+        &mut self,
+        query_id: usize,
+        candidate_id: usize,
+        similar: bool,
+    ) -> Result<Verdict, Error> {
+        let query: &DataEntry = self.get(query_id).ok_or(DataEntryErrors::NoDataWasFound)?;
+        let candidate: &DataEntry = self
+            .get(candidate_id)
+            .ok_or(DataEntryErrors::NoDataWasFound)?;
+
+        let verdict = Verdict {
+            query_id,
+            candidate_id,
+            similar,
+            score: self.cosine_similarity(&query.vector, &candidate.vector),
+            created_at: unix_timestamp(),
+        };
+        self.verdicts.push(verdict.clone());
+
+        Ok(verdict)
+    }
+
+    /// Keep a verdict recorded earlier, e.g. when replaying the operation log
+    ///
+    /// A verdict the store already holds is not added again.
+    ///
+    /// # Arguments
+    /// * `verdict` - The verdict, as returned by `record_verdict`
+    pub fn add_verdict(&mut self, verdict: Verdict) {
+        if !self.verdicts.contains(&verdict) {
+            self.verdicts.push(verdict);
+        }
+    }
+
+    /// Every recorded verdict, oldest first
+    pub fn verdicts(&self) -> &[Verdict] {
+        &self.verdicts
+    }
+
+    /// Score every entry against a query vector, in store order
+    ///
+    /// # Arguments
+    /// * `query_vector` - Vector to compare the entries with
+    pub fn score_all(&self, query_vector: &[f64]) -> Vec<f64> {
+        self.data_entries
+            .iter()
+            .map(|entry| self.cosine_similarity(query_vector, &entry.vector))
+            .collect()
+    }
+
+    // Helper function to get the contiguous copy of the vectors, rebuilding it
+    // when the store changed since it was built
+    fn vector_buffer(&self) -> Arc<VectorBuffer> {
+        let generation: u64 = self.generation();
+        if let Some((cached_generation, buffer)) = self
+            .vector_buffer
+            .0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+        {
+            if *cached_generation == generation {
+                return buffer.clone();
+            }
+        }
+
+        // searches hold the store's read lock, so the generation cannot move
+        // while building, and concurrent rebuilds all build the same copy
+        let buffer = Arc::new(VectorBuffer::from_rows(
+            self.data_entries
+                .iter()
+                .map(|entry| entry.vector.as_slice()),
+        ));
+        *self
+            .vector_buffer
+            .0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some((generation, buffer.clone()));
+        buffer
+    }
+
+    // Helper function to calculate cosine similarity between two vectors,
+    // taking the SIMD path when it is enabled
+    fn cosine_similarity(&self, a: &[f64], b: &[f64]) -> f64 {
+        if config::get().simd {
+            cosine_similarity_simd(a, b)
+        } else {
+            cosine_similarity(a, b)
+        }
+    }
+
+    /// Delete entry metadata by ID
+    ///
+    /// # Arguments  
+    /// * `id` - ID of entry to delete
+    fn kv_delete(&mut self, id: usize) -> Result<(), Error> {
+        // Find the position of the entry with matching id
+        if let Some(index) = self
+            .data_entries
+            .iter()
+            .position(|entry: &DataEntry| entry.id == id)
+        {
+            // Remove the entry and return Ok if found
+            self.data_entries.remove(index);
+            self.match_counts.remove(id);
+            self.generation.bump();
+            Ok(())
+        } else {
+            // Return error if no matching entry was found
+            Err(DataEntryErrors::NoDataWasFound.into())
+        }
+    }
+
+    /// Insert an entry as it is, replacing any entry with the same ID
+    ///
+    /// Unlike `add`, nothing is validated or embedded. This is meant for
+    /// restoring entries that were stored before, e.g. from the operation log.
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to store
+    pub fn upsert(&mut self, entry: DataEntry) {
+        match self
+            .data_entries
+            .iter_mut()
+            .find(|existing| existing.id == entry.id)
+        {
+            Some(existing) => *existing = entry,
+            None => {
+                self.next_id = self.next_id.max(entry.id + 1);
+                self.data_entries.push(entry);
+            }
+        }
+        self.generation.bump();
+    }
+
+    /// Update entry metadata by ID
+    ///
+    /// # Arguments
+    /// * `id` - ID of entry to update
+    /// * `data_entry` - New data entry
+    fn kv_edit(&mut self, id: usize, data_entry: DataEntry) -> Result<(), Error> {
+        if let Some(index) = self.data_entries.iter().position(|entry| entry.id == id) {
+            self.data_entries[index] = data_entry;
+        } else {
+            // Return error if no matching entry was found
+            return Err(DataEntryErrors::NoDataWasFound.into());
+        }
+        self.generation.bump();
+
+        Ok(())
+    }
+
+    /// Get every entry, in insertion order
+    ///
+    /// Nothing reorders the entries: deletes keep the order of the remaining
+    /// ones, searches only read, and `SharedStores::save` followed by
+    /// `SharedStores::load` restores the exact same order. Clients may rely
+    /// on it for pagination.
+    pub fn get_all(&self) -> Vec<DataEntry> {
+        self.data_entries.clone()
+    }
+
+    /// Iterate over every entry in insertion order, without copying them
+    ///
+    /// Prefer this over [`Self::get_all`] to inspect or aggregate entries,
+    /// the order is the same.
+    pub fn iter(&self) -> impl Iterator<Item = &DataEntry> {
+        self.data_entries.iter()
+    }
+
+    /// Get entries whose vector is empty or does not match the store's dimensions
+    ///
+    /// Such entries can be left behind by imports or failed embeddings and
+    /// cannot be ranked meaningfully.
+    pub fn invalid_entries(&self) -> Vec<DataEntry> {
+        self.data_entries
+            .iter()
+            .filter(|entry| entry.vector.is_empty() || entry.vector.len() != self.dimensions)
+            .cloned()
+            .collect()
+    }
+
+    /// IDs and vectors of every entry not listed by [`Self::invalid_entries`],
+    /// in store order
+    pub fn valid_vectors(&self) -> (Vec<usize>, Vec<&[f64]>) {
+        self.data_entries
+            .iter()
+            .filter(|entry| !entry.vector.is_empty() && entry.vector.len() == self.dimensions)
+            .map(|entry| (entry.id, entry.vector.as_slice()))
+            .unzip()
+    }
+
+    /// Get the mean vector of the store
+    ///
+    /// Entries listed by [`Self::invalid_entries`] are left out.
+    ///
+    /// # Returns
+    /// The centroid, or `None` when there is no valid entry
+    pub fn centroid(&self) -> Option<Vec<f64>> {
+        self.mean_vector(
+            self.data_entries
+                .iter()
+                .filter(|entry| entry.vector.len() == self.dimensions),
+        )
+    }
+
+    // Helper function to average the vectors of some entries, `None` for none
+    fn mean_vector<'a>(&self, entries: impl Iterator<Item = &'a DataEntry>) -> Option<Vec<f64>> {
+        let mut sum: Vec<f64> = vec![0.0; self.dimensions];
+        let mut count: usize = 0;
+
+        for entry in entries {
+            for (total, value) in sum.iter_mut().zip(&entry.vector) {
+                *total += value;
+            }
+            count += 1;
+        }
+
+        if count == 0 {
+            return None;
+        }
+
+        Some(sum.into_iter().map(|total| total / count as f64).collect())
+    }
+
+    /// Find the entries that best complete a collection, e.g. an outfit
+    ///
+    /// The store is searched for the centroid of the collection's vectors,
+    /// leaving out the collection itself. Like `similar_to`, this does not
+    /// count towards match tracking.
+    ///
+    /// # Arguments
+    /// * `ids` - IDs of the entries making up the collection, all of which must exist
+    /// * `top_n` - Number of results to return
+    pub fn search_collection(
+        &self,
+        ids: &[usize],
+        top_n: usize,
+    ) -> Result<(Vec<SearchResult>, SearchCounts), Error> {
+        let mut entries: Vec<&DataEntry> = Vec::with_capacity(ids.len());
+        for id in ids {
+            let entry: &DataEntry = self.get(*id).ok_or(DataEntryErrors::NoDataWasFound)?;
+            if entry.vector.len() != self.dimensions {
+                return Err(ValidationErrors::DimensionMismatch {
+                    id: entry.id,
+                    expected: self.dimensions,
+                    actual: entry.vector.len(),
+                }
+                .into());
+            }
+            entries.push(entry);
+        }
+
+        let centroid: Vec<f64> = self
+            .mean_vector(entries.into_iter())
+            .ok_or(DataEntryErrors::NoDataWasFound)?;
+        let options = SearchOptions {
+            top_n,
+            exclude_ids: ids.to_vec(),
+            ..SearchOptions::default()
+        };
+
+        // averaged unit vectors are shorter than unit length
+        self.kv_search(&self.storage_form(centroid), &options)
+    }
+
+    /// Get all entries ordered by the given key
+    ///
+    /// Ties are broken by ID so the order is deterministic.
+    ///
+    /// # Arguments
+    /// * `key` - Key to sort by
+    /// * `direction` - Whether to sort ascending or descending
+    pub fn get_all_sorted(&self, key: SortKey, direction: SortDirection) -> Vec<DataEntry> {
+        let mut entries: Vec<DataEntry> = self.data_entries.clone();
+
+        entries.sort_by(|a, b| {
+            let ordering: Ordering = match key {
+                SortKey::Id => a.id.cmp(&b.id),
+                SortKey::Name => a.name.cmp(&b.name).then(a.id.cmp(&b.id)),
+                SortKey::Recent => a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)),
+            };
+
+            match direction {
+                SortDirection::Ascending => ordering,
+                SortDirection::Descending => ordering.reverse(),
+            }
+        });
+
+        entries
+    }
+
+    /// Copy the store's configuration and embedder without its entries
+    ///
+    /// The copy embeds images exactly like the store, so a handler can take
+    /// it under a short read lock and embed after releasing the lock,
+    /// instead of blocking writers for the whole provider call.
+    pub fn without_entries(&self) -> InMemoryVectorStore {
+        Self {
+            data_entries: Vec::new(),
+            prompt_annotations: self.prompt_annotations.clone(),
+            prompts: self.prompts.clone(),
+            prompt_size: self.prompt_size,
+            dimensions: self.dimensions,
+            next_id: self.next_id,
+            normalized: self.normalized,
+            projection: self.projection.clone(),
+            duplicate_strategy: self.duplicate_strategy,
+            length_mismatch_policy: self.length_mismatch_policy,
+            embedding_retries: self.embedding_retries,
+            prompt_batch_size: self.prompt_batch_size,
+            normalize_tags: self.normalize_tags,
+            track_matches: false,
+            reject_constant_vectors: self.reject_constant_vectors,
+            contiguous_vectors: false,
+            match_counts: MatchCounts::default(),
+            verdicts: Vec::new(),
+            generation: StoreGeneration::default(),
+            neighbor_cache: NeighborCache::default(),
+            vector_buffer: VectorBufferCache::default(),
+            prompt_sets: self.prompt_sets.clone(),
+            embedder: self.embedder.clone(),
+        }
+    }
+
+    /// Vectorize an image with this store's prompts without storing anything
+    ///
+    /// The configured preprocessing is applied first, like for every other
+    /// embedding of the store. By default this converts the image to RGB8, so
+    /// the embedder never sees an alpha channel.
+    ///
+    /// A vector whose length differs from the store's dimensions is never
+    /// returned: the embedding is retried as configured, then fails with
+    /// [`EmbeddingErrors::UnexpectedDimensions`]. With a projection, the raw
+    /// vector is checked against the matrix's rows, then projected.
+    ///
+    /// # Arguments
+    /// * `image` - The image to vectorize
+    pub async fn embed_only(&self, image: DynamicImage) -> Result<Vec<f64>, Error> {
+        let image: DynamicImage = config::get().preprocessing.apply(image);
+        let raw_dimensions: usize = self.raw_dimensions();
+        let request = EmbeddingRequest {
+            dimensions: raw_dimensions,
+            prompt_annotations: self.prompt_annotations.clone(),
+            prompts: self.prompts.clone(),
+            prompt_size: self.effective_prompt_size(),
+        };
+
+        let default_embedder = OpenAIEmbedder::default();
+        let embedder: &dyn Embedder = match &self.embedder {
+            Some(embedder) => embedder.as_ref(),
+            None => &default_embedder,
+        };
+
+        let attempts: usize = self.embedding_retries + 1;
+        let mut actual: usize = 0;
+        for attempt in 1..=attempts {
+            let vector: Vec<f64> = self.embed_batched(embedder, &request, &image).await?;
+            if vector.len() == raw_dimensions {
+                return Ok(self.project(vector));
+            }
+
+            actual = vector.len();
+            warn!(
+                "Embedding attempt {}/{} returned {} dimensions instead of {}",
+                attempt, attempts, actual, raw_dimensions
+            );
+        }
+
+        Err(EmbeddingErrors::UnexpectedDimensions {
+            expected: raw_dimensions,
+            actual,
+        }
+        .into())
+    }
+
+    /// Check that the embedder answers, with the cheapest request possible
+    ///
+    /// A 1×1 image is scored against the first prompt only, so a probe costs
+    /// a single provider call instead of [`Self::calls_per_image`]. The
+    /// result is not checked beyond being a vector.
+    pub async fn probe_embedder(&self) -> Result<(), Error> {
+        let prompts: Vec<String> = self.prompts.iter().take(1).cloned().collect();
+        if prompts.is_empty() {
+            return Err(anyhow::anyhow!(
+                "The store has no prompts to probe the embedder with"
+            ));
+        }
+        let request = EmbeddingRequest {
+            dimensions: 1,
+            prompt_annotations: self.prompt_annotations.clone(),
+            prompts,
+            prompt_size: 1,
+        };
+
+        let default_embedder = OpenAIEmbedder::default();
+        let embedder: &dyn Embedder = match &self.embedder {
+            Some(embedder) => embedder.as_ref(),
+            None => &default_embedder,
+        };
+        embedder
+            .embed(request, DynamicImage::new_rgb8(1, 1))
+            .await?;
+
+        Ok(())
+    }
+
+    // Helper function to warn about, or reject, a vector without variance
+    fn check_variance(&self, vector: &[f64]) -> Result<(), Error> {
+        if !is_constant_vector(vector) {
+            return Ok(());
+        }
+
+        let variance: f64 = vector_variance(vector);
+        if self.reject_constant_vectors {
+            return Err(EmbeddingErrors::ConstantVector { variance }.into());
+        }
+        warn!(
+            "The embedding is constant (variance {}), searches cannot rank it",
+            variance
+        );
+        Ok(())
+    }
+
+    // Helper function to embed an image once, one prompt batch at a time as
+    // configured, concatenating the batches' slices in prompt order
+    async fn embed_batched(
+        &self,
+        embedder: &dyn Embedder,
+        request: &EmbeddingRequest,
+        image: &DynamicImage,
+    ) -> Result<Vec<f64>, Error> {
+        let batch_size: usize = self.prompt_batch_size;
+        if batch_size == 0 || batch_size >= request.prompts.len() {
+            return embedder.embed(request.clone(), image.clone()).await;
+        }
+        if request.prompts.len() * request.prompt_size != request.dimensions {
+            warn!(
+                "Cannot batch {} prompts of size {} into {} dimensions, embedding them at once",
+                request.prompts.len(),
+                request.prompt_size,
+                request.dimensions
+            );
+            return embedder.embed(request.clone(), image.clone()).await;
+        }
+
+        let mut vector: Vec<f64> = Vec::with_capacity(request.dimensions);
+        for prompts in request.prompts.chunks(batch_size) {
+            let batch = EmbeddingRequest {
+                dimensions: prompts.len() * request.prompt_size,
+                prompt_annotations: request.prompt_annotations.clone(),
+                prompts: prompts.to_vec(),
+                prompt_size: request.prompt_size,
+            };
+            vector.extend(embedder.embed(batch, image.clone()).await?);
+        }
+
+        Ok(vector)
+    }
+
+    /// Check entries with precomputed vectors without mutating the store
+    ///
+    /// Every entry needs a non-empty name, descriptions within the configured
+    /// limits, and a vector without NaN or infinite components. The vector
+    /// must have the store's dimensions unless the [`LengthMismatchPolicy`]
+    /// skips or coerces it, which is reported as a warning instead.
+    ///
+    /// # Arguments
+    /// * `entries` - Entries to validate
+    pub fn validate_import(&self, entries: &[ImportEntry]) -> Vec<ImportValidation> {
+        let config = config::get();
+
+        entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let mut errors: Vec<String> = Vec::new();
+                let mut warnings: Vec<String> = Vec::new();
+
+                if let Err(error) = sanitize_name(&entry.name, config.max_name_length) {
+                    errors.push(error.to_string());
+                }
+                if entry.vector.len() != self.dimensions {
+                    let mismatch: String = format!(
+                        "Vector has {} dimensions, expected {}",
+                        entry.vector.len(),
+                        self.dimensions
+                    );
+                    match self.length_mismatch_policy {
+                        LengthMismatchPolicy::Reject => errors.push(mismatch),
+                        LengthMismatchPolicy::Skip => {
+                            warnings.push(format!("{}, the entry is skipped", mismatch))
+                        }
+                        LengthMismatchPolicy::PadTruncate => warnings
+                            .push(format!("{}, the vector is padded or truncated", mismatch)),
+                    }
+                }
+                if entry.vector.iter().any(|value| !value.is_finite()) {
+                    errors.push("Vector contains NaN or infinite values".to_string());
+                }
+                if let Err(error) = validate_descriptions(
+                    &entry.descriptions,
+                    config.max_descriptions,
+                    config.max_description_length,
+                ) {
+                    errors.push(error.to_string());
+                }
+
+                ImportValidation {
+                    index,
+                    valid: errors.is_empty(),
+                    errors,
+                    warnings,
+                }
+            })
+            .collect()
+    }
+
+    /// Insert entries with precomputed vectors, skipping the embedding step
+    ///
+    /// Nothing is inserted unless every entry passes [`Self::validate_import`].
+    ///
+    /// # Arguments
+    /// * `entries` - Entries to insert
+    ///
+    /// # Returns
+    /// IDs of the inserted entries, in payload order
+    pub fn import(&mut self, entries: Vec<ImportEntry>) -> Result<Vec<usize>, Error> {
+        Ok(self.import_with_report(entries)?.ids)
+    }
+
+    /// Insert entries with precomputed vectors, reporting how vectors of the
+    /// wrong length were handled
+    ///
+    /// Like [`Self::import`], nothing is inserted unless every entry passes
+    /// [`Self::validate_import`]. Entries whose vector does not match the
+    /// dimensions are then skipped or coerced as the [`LengthMismatchPolicy`]
+    /// says.
+    ///
+    /// # Arguments
+    /// * `entries` - Entries to insert
+    pub fn import_with_report(
+        &mut self,
+        entries: Vec<ImportEntry>,
+    ) -> Result<ImportOutcome, Error> {
+        let invalid: usize = self
+            .validate_import(&entries)
+            .iter()
+            .filter(|validation| !validation.valid)
+            .count();
+
+        if invalid > 0 {
+            return Err(ValidationErrors::InvalidImport {
+                invalid,
+                total: entries.len(),
+            }
+            .into());
+        }
+
+        let mut outcome = ImportOutcome::default();
+        for (index, mut entry) in entries.into_iter().enumerate() {
+            if entry.vector.len() != self.dimensions {
+                match self.length_mismatch_policy {
+                    // rejected by the validation above
+                    LengthMismatchPolicy::Reject => {}
+                    LengthMismatchPolicy::Skip => {
+                        outcome.skipped.push(index);
+                        continue;
+                    }
+                    LengthMismatchPolicy::PadTruncate => {
+                        entry.vector.resize(self.dimensions, 0.0);
+                        outcome.coerced.push(index);
+                    }
+                }
+            }
+
+            let name: String = sanitize_name(&entry.name, config::get().max_name_length)?;
+            outcome.ids.push(self.kv_storage(
+                &name,
+                entry.descriptions,
+                entry.vector,
+                None,
+                None,
+                Value::Null,
+                None,
+            )?);
+        }
+        if !outcome.skipped.is_empty() || !outcome.coerced.is_empty() {
+            warn!(
+                "Import skipped {} and coerced {} vectors of the wrong length",
+                outcome.skipped.len(),
+                outcome.coerced.len()
+            );
+        }
+
+        Ok(outcome)
+    }
+
+    /// Get an entry by ID
+    ///
+    /// # Arguments
+    /// * `id` - ID of the entry to look up
+    pub fn get(&self, id: usize) -> Option<&DataEntry> {
+        self.data_entries.iter().find(|entry| entry.id == id)
+    }
+
+    /// Get several entries by ID
+    ///
+    /// # Arguments
+    /// * `ids` - IDs of the entries to look up
+    ///
+    /// # Returns
+    /// The entries in the order of `ids`, with `None` for unknown IDs
+    pub fn get_many(&self, ids: &[usize]) -> Vec<Option<DataEntry>> {
+        ids.iter().map(|id| self.get(*id).cloned()).collect()
+    }
+
+    /// Align an entry's vector with the prompts that produced it
+    ///
+    /// The embedder does not report per-prompt scores, so this relies on the
+    /// [`Embedder`] layout: prompt `i` fills dimensions
+    /// `i * prompt_size .. (i + 1) * prompt_size`. Stored vectors may have
+    /// been normalized or truncated, in which case only the raw vector and
+    /// prompts are returned. The current prompts are used, so vectors
+    /// embedded before the prompts changed are misaligned.
+    ///
+    /// # Arguments
+    /// * `id` - ID of the entry to explain
+    pub fn explain(&self, id: usize) -> Result<VectorExplanation, Error> {
+        let entry: &DataEntry = self.get(id).ok_or(DataEntryErrors::NoDataWasFound)?;
+        let prompt_size: usize = self.effective_prompt_size();
+
+        let contributions: Option<Vec<PromptContribution>> =
+            if prompt_size > 0 && entry.vector.len() == self.prompts.len() * prompt_size {
+                Some(
+                    self.prompts
+                        .iter()
+                        .zip(entry.vector.chunks(prompt_size))
+                        .enumerate()
+                        .map(|(index, (prompt, values))| PromptContribution {
+                            prompt: prompt.clone(),
+                            offset: index * prompt_size,
+                            values: values.to_vec(),
+                        })
+                        .collect(),
+                )
+            } else {
+                None
+            };
+
+        Ok(VectorExplanation {
+            id,
+            vector: entry.vector.clone(),
+            prompts: self.prompts.clone(),
+            prompt_size,
+            contributions,
+        })
+    }
+
+    /// Compare the vectors of two stored entries
+    ///
+    /// # Arguments
+    /// * `a` - ID of the first entry
+    /// * `b` - ID of the second entry
+    pub fn compare(&self, a: usize, b: usize) -> Result<EntryComparison, Error> {
+        let entry_a: &DataEntry = self.get(a).ok_or(DataEntryErrors::NoDataWasFound)?;
+        let entry_b: &DataEntry = self.get(b).ok_or(DataEntryErrors::NoDataWasFound)?;
+
+        for entry in [entry_a, entry_b] {
+            if entry.vector.len() != self.dimensions {
+                return Err(ValidationErrors::DimensionMismatch {
+                    id: entry.id,
+                    expected: self.dimensions,
+                    actual: entry.vector.len(),
+                }
+                .into());
+            }
+        }
+
+        Ok(EntryComparison {
+            a,
+            b,
+            cosine: cosine_similarity(&entry_a.vector, &entry_b.vector),
+            score: self.cosine_similarity(&entry_a.vector, &entry_b.vector),
+            norm_a: l2_norm(&entry_a.vector),
+            norm_b: l2_norm(&entry_b.vector),
+        })
+    }
+
+    /// Get the stored source image of an entry, as a base64 PNG
+    ///
+    /// # Arguments
+    /// * `id` - ID of the entry to look up
+    pub fn get_image(&self, id: usize) -> Result<String, Error> {
+        self.get(id)
+            .ok_or(DataEntryErrors::NoDataWasFound)?
+            .image
+            .clone()
+            .ok_or(DataEntryErrors::ImageNotStored.into())
+    }
+
+    /// Re-embed a single entry from its stored image
+    ///
+    /// The vector is replaced in place, so the id and metadata are preserved.
+    ///
+    /// # Arguments
+    /// * `id` - ID of the entry to re-embed
+    ///
+    /// # Returns
+    /// L2 norm of the new vector
+    pub async fn reembed(&mut self, id: usize) -> Result<f64, Error> {
+        let encoded_image: String = self.get_image(id)?;
+
+        let new_vector: Vec<f64> =
+            self.storage_form(self.embed_only(decode_image(&encoded_image)?).await?);
+        let norm: f64 = l2_norm(&new_vector);
+
+        if let Some(entry) = self.data_entries.iter_mut().find(|entry| entry.id == id) {
+            entry.vector = new_vector;
+            entry.version += 1;
+            self.generation.bump();
+        }
+
+        Ok(norm)
+    }
+
+    /// Whether vectors of another store can be used here as they are
+    ///
+    /// That is the case when both stores use the same prompts, in the same
+    /// order, with the same layout and normalization.
+    pub fn shares_layout(&self, other: &InMemoryVectorStore) -> bool {
+        self.prompts == other.prompts
+            && self.prompt_size == other.prompt_size
+            && self.dimensions == other.dimensions
+            && self.normalized == other.normalized
+            && self.projection == other.projection
+    }
+
+    /// Store a copy of another store's entry under a new ID
+    ///
+    /// The vector is reused when both stores share a layout. Otherwise the
+    /// entry is re-embedded from its stored image with this store's prompts.
+    /// The source store is left untouched.
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to copy
+    /// * `source` - The store holding the entry
+    ///
+    /// # Returns
+    /// ID of the entry in this store
+    pub async fn adopt(
+        &mut self,
+        entry: &DataEntry,
+        source: &InMemoryVectorStore,
+    ) -> Result<usize, Error> {
+        let vector: Vec<f64> = self.adoption_vector(entry, source).await?;
+        self.adopt_with_vector(entry, vector)
+    }
+
+    /// Vector another store's entry gets in this store, see [`Self::adopt`]
+    ///
+    /// Embedding may call the provider, so this only needs the stores'
+    /// layouts: copies from [`Self::without_entries`] let the caller embed
+    /// without holding either store.
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to copy
+    /// * `source` - The store holding the entry, or a copy of its layout
+    pub async fn adoption_vector(
+        &self,
+        entry: &DataEntry,
+        source: &InMemoryVectorStore,
+    ) -> Result<Vec<f64>, Error> {
+        if self.shares_layout(source) {
+            return Ok(entry.vector.clone());
+        }
+
+        let encoded_image: &String = entry
+            .image
+            .as_ref()
+            .ok_or(DataEntryErrors::ImageNotStored)?;
+        self.embed_only(decode_image(encoded_image)?).await
+    }
+
+    /// Store a copy of another store's entry under a new ID, with the vector
+    /// from [`Self::adoption_vector`]
+    ///
+    /// # Arguments
+    /// * `entry` - The entry to copy
+    /// * `vector` - Vector of the entry in this store
+    ///
+    /// # Returns
+    /// ID of the entry in this store
+    pub fn adopt_with_vector(
+        &mut self,
+        entry: &DataEntry,
+        vector: Vec<f64>,
+    ) -> Result<usize, Error> {
+        self.kv_storage(
+            &entry.name,
+            entry.descriptions.clone(),
+            vector,
+            entry.image.clone(),
+            entry.image_hash,
+            entry.metadata.clone(),
+            entry.gender,
+        )
+    }
+
+    /// Truncate every stored vector to its first `dimensions` values
+    ///
+    /// This is lossy and meant as a migration path after switching to a model
+    /// producing longer vectors, short of re-embedding everything. Normalized
+    /// stores re-normalize the truncated vectors.
+    ///
+    /// New embeddings keep the same layout: the projection is cut down to its
+    /// first `dimensions` rows, and a store without one gets a projection
+    /// keeping the first `dimensions` values of the embeddings, so queries
+    /// and new uploads match the truncated vectors.
+    ///
+    /// # Arguments
+    /// * `dimensions` - Number of values to keep, at most the shortest vector's length
+    ///
+    /// # Returns
+    /// Number of truncated entries
+    pub fn truncate_vectors(&mut self, dimensions: usize) -> Result<usize, Error> {
+        let available: usize = self
+            .data_entries
+            .iter()
+            .map(|entry| entry.vector.len())
+            .min()
+            .unwrap_or(self.dimensions);
+
+        if dimensions == 0 || dimensions > available {
+            return Err(ValidationErrors::InvalidTruncation {
+                requested: dimensions,
+                available,
+            }
+            .into());
+        }
+
+        let raw_dimensions: usize = self.raw_dimensions();
+        let mut projection: Vec<Vec<f64>> = self.projection.take().unwrap_or_else(|| {
+            (0..raw_dimensions)
+                .map(|row| {
+                    let mut unit: Vec<f64> = vec![0.0; raw_dimensions];
+                    unit[row] = 1.0;
+                    unit
+                })
+                .collect()
+        });
+        projection.truncate(dimensions);
+        self.projection = Some(projection);
+
+        let mut entries: Vec<DataEntry> = std::mem::take(&mut self.data_entries);
+        for entry in entries.iter_mut() {
+            entry.vector.truncate(dimensions);
+            entry.vector = self.storage_form(std::mem::take(&mut entry.vector));
+            entry.version += 1;
+        }
+        self.data_entries = entries;
+        self.dimensions = dimensions;
+        self.generation.bump();
+
+        Ok(self.data_entries.len())
+    }
+
+    /// Append a description to an existing entry
+    ///
+    /// # Arguments
+    /// * `id` - ID of the entry to update
+    /// * `description` - Description to append
+    pub fn add_description(&mut self, id: usize, description: String) -> Result<(), Error> {
+        let config = config::get();
+
+        let description: String = self.tag_form(description);
+        let entry: &mut DataEntry = self
+            .data_entries
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .ok_or(DataEntryErrors::NoDataWasFound)?;
+
+        let mut descriptions: Vec<String> = entry.descriptions.clone();
+        descriptions.push(description);
+        validate_descriptions(
+            &descriptions,
+            config.max_descriptions,
+            config.max_description_length,
+        )?;
+
+        entry.descriptions = descriptions;
+        entry.version += 1;
+        self.generation.bump();
+
+        Ok(())
+    }
+
+    /// Remove every copy of a description from an existing entry
+    ///
+    /// With tag normalization, descriptions are compared in their normalized
+    /// form. Returns how many copies were removed.
+    ///
+    /// # Arguments
+    /// * `id` - ID of the entry to update
+    /// * `description` - Description to remove
+    pub fn remove_description(&mut self, id: usize, description: &str) -> Result<usize, Error> {
+        let description: String = self.tag_form(description.to_string());
+        let normalize_tags: bool = self.normalize_tags;
+        let entry: &mut DataEntry = self
+            .data_entries
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .ok_or(DataEntryErrors::NoDataWasFound)?;
+
+        let before: usize = entry.descriptions.len();
+        entry.descriptions.retain(|existing| {
+            if normalize_tags {
+                normalize_tag(existing) != description
+            } else {
+                *existing != description
+            }
+        });
+
+        let removed: usize = before - entry.descriptions.len();
+        if removed > 0 {
+            entry.version += 1;
+            self.generation.bump();
+        }
+
+        Ok(removed)
+    }
+
+    /// Remove and add descriptions on an existing entry in one step
+    ///
+    /// Removals are applied before additions, and a description already
+    /// present is not added twice. The result is validated against the
+    /// description limits before the entry is touched, so on error the entry
+    /// is left as it was.
+    ///
+    /// # Arguments
+    /// * `id` - ID of the entry to update
+    /// * `add` - Descriptions to add
+    /// * `remove` - Descriptions to remove
+    pub fn retag_descriptions(
+        &mut self,
+        id: usize,
+        add: &[String],
+        remove: &[String],
+    ) -> Result<(), Error> {
+        let config = config::get();
+
+        let add: Vec<String> = add
+            .iter()
+            .map(|description| self.tag_form(description.clone()))
+            .collect();
+        let remove: Vec<String> = remove
+            .iter()
+            .map(|description| self.tag_form(description.clone()))
+            .chain(add.iter().cloned())
+            .collect();
+        let normalize_tags: bool = self.normalize_tags;
+        let entry: &mut DataEntry = self
+            .data_entries
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .ok_or(DataEntryErrors::NoDataWasFound)?;
+
+        let mut descriptions: Vec<String> = entry
+            .descriptions
+            .iter()
+            .filter(|existing| {
+                let existing: String = if normalize_tags {
+                    normalize_tag(existing)
+                } else {
+                    existing.to_string()
+                };
+                !remove.contains(&existing)
+            })
+            .cloned()
+            .collect();
+        for description in add {
+            if !descriptions.contains(&description) {
+                descriptions.push(description);
+            }
+        }
+        validate_descriptions(
+            &descriptions,
+            config.max_descriptions,
+            config.max_description_length,
+        )?;
+
+        if descriptions != entry.descriptions {
+            entry.descriptions = descriptions;
+            entry.version += 1;
+            self.generation.bump();
+        }
+
+        Ok(())
+    }
+
+    /// Find the entry whose source image has the given hash
+    ///
+    /// # Arguments
+    /// * `hash` - Hash as computed by [`image_hash`]
+    pub fn find_by_image_hash(&self, hash: u64) -> Option<usize> {
+        self.data_entries
+            .iter()
+            .find(|entry| entry.image_hash == Some(hash))
+            .map(|entry| entry.id)
+    }
+
+    /// Count how many entries carry each description, most frequent first
+    ///
+    /// Descriptions are tallied in their tag form, so with tag normalization
+    /// enabled, entries stored before it was enabled count towards the same
+    /// tags. Blank descriptions are left out. Ties are ordered by tag.
+    pub fn tag_frequencies(&self) -> Vec<(String, usize)> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for entry in &self.data_entries {
+            for description in &entry.descriptions {
+                if description.trim().is_empty() {
+                    continue;
+                }
+                *counts
+                    .entry(self.tag_form(description.clone()))
+                    .or_insert(0) += 1;
+            }
+        }
+
+        let mut frequencies: Vec<(String, usize)> = counts.into_iter().collect();
+        frequencies.sort_by(|(tag_a, count_a), (tag_b, count_b)| {
+            count_b.cmp(count_a).then_with(|| tag_a.cmp(tag_b))
+        });
+
+        frequencies
+    }
+
+    /// Remove blank descriptions from every entry
+    ///
+    /// Uploads without descriptions used to store a single empty one, which
+    /// shows up as a phantom tag. This cleans up stores saved back then.
+    ///
+    /// # Returns
+    /// Number of removed descriptions
+    pub fn strip_blank_descriptions(&mut self) -> usize {
+        let mut removed: usize = 0;
+        for entry in self.data_entries.iter_mut() {
+            let before: usize = entry.descriptions.len();
+            entry
+                .descriptions
+                .retain(|description| !description.trim().is_empty());
+            if entry.descriptions.len() < before {
+                entry.version += 1;
+                removed += before - entry.descriptions.len();
+            }
+        }
+        if removed > 0 {
+            self.generation.bump();
+        }
+
+        removed
+    }
+
+    /// Find entries whose name is within an edit distance of a query, ignoring case
+    ///
+    /// Every name is compared with the query, so this is O(n) in the number
+    /// of entries times the names' lengths.
+    ///
+    /// # Arguments
+    /// * `query` - Name to look for, typos included
+    /// * `max_distance` - Largest Levenshtein distance of a match
+    ///
+    /// # Returns
+    /// The matches, closest first and by ID among equally close ones
+    pub fn find_by_name_fuzzy(&self, query: &str, max_distance: usize) -> Vec<NameMatch> {
+        let query: String = query.trim().to_lowercase();
+
+        let mut matches: Vec<NameMatch> = self
+            .data_entries
+            .iter()
+            .filter_map(|entry| {
+                let distance: usize = levenshtein(&query, &entry.name.to_lowercase());
+                (distance <= max_distance).then(|| NameMatch {
+                    distance,
+                    data_entry: entry.clone(),
+                })
+            })
+            .collect();
+        matches.sort_by_key(|name_match| (name_match.distance, name_match.data_entry.id));
+
+        matches
+    }
+
+    // Helper function to append the descriptions an entry does not have yet
+    fn merge_descriptions(&mut self, id: usize, descriptions: Vec<String>) -> Result<(), Error> {
+        let config = config::get();
+
+        let entry: &mut DataEntry = self
+            .data_entries
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .ok_or(DataEntryErrors::NoDataWasFound)?;
+
+        let mut merged: Vec<String> = entry.descriptions.clone();
+        for description in descriptions {
+            if !merged.contains(&description) {
+                merged.push(description);
+            }
+        }
+        validate_descriptions(
+            &merged,
+            config.max_descriptions,
+            config.max_description_length,
+        )?;
+
+        if merged.len() > entry.descriptions.len() {
+            entry.descriptions = merged;
+            entry.version += 1;
+            self.generation.bump();
+        }
+
+        Ok(())
+    }
+}
+
+impl InMemoryVectorStore {
+    /// Add an entry carrying arbitrary JSON metadata, e.g. price, brand or SKU,
+    /// and the gender it is meant for
+    ///
+    /// Otherwise this behaves like `add`. Merging into a duplicate keeps the
+    /// metadata and gender of the existing entry.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the entry
+    /// * `descriptions` - Descriptions of the entry
+    /// * `image` - The image to vectorize
+    /// * `metadata` - Metadata returned with the entry, `null` for none
+    /// * `gender` - Gender the entry is meant for, used by gender filters
+    pub async fn add_with_metadata(
+        &mut self,
+        name: &str,
+        descriptions: Vec<String>,
+        image: DynamicImage,
+        metadata: Value,
+        gender: Option<Gender>,
+    ) -> Result<usize, Error> {
+        self.add_embedded(name, descriptions, image, metadata, gender, None)
+            .await
+    }
+
+    /// Add an image already embedded by [`Self::embed_only`], with the given
+    /// metadata and gender
+    ///
+    /// This lets a caller embed without holding the store, e.g. under a
+    /// short read lock on a copy from [`Self::without_entries`], and only
+    /// lock the store to insert. Otherwise this behaves like
+    /// `add_with_metadata`.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the entry
+    /// * `descriptions` - Descriptions of the entry
+    /// * `image` - The embedded image
+    /// * `metadata` - Metadata returned with the entry, `null` for none
+    /// * `gender` - Gender the entry is meant for, used by gender filters
+    /// * `vector` - Vector of the image
+    pub async fn add_with_vector(
+        &mut self,
+        name: &str,
+        descriptions: Vec<String>,
+        image: DynamicImage,
+        metadata: Value,
+        gender: Option<Gender>,
+        vector: Vec<f64>,
+    ) -> Result<usize, Error> {
+        self.add_embedded(name, descriptions, image, metadata, gender, Some(vector))
+            .await
+    }
+
+    /// Add several images, embedding up to `concurrency` of them at once
+    ///
+    /// Embedding dominates the cost of an upload and mostly waits on the
+    /// provider, so overlapping it across images speeds up large batches.
+    /// The entries are then stored one at a time in input order, exactly like
+    /// repeated `add` calls: IDs follow the input order, and duplicates within
+    /// the batch are settled by the duplicate strategy. Images with invalid
+    /// fields, or whose duplicate is already stored while duplicates are not
+    /// allowed, are not embedded.
+    ///
+    /// # Arguments
+    /// * `items` - Name, descriptions and image of each entry
+    /// * `concurrency` - Number of images embedded at once, at least 1
+    ///
+    /// # Returns
+    /// The ID of each entry or why it was not added, in input order
+    pub async fn add_batch(
+        &mut self,
+        items: Vec<(String, Vec<String>, DynamicImage)>,
+        concurrency: usize,
+    ) -> Vec<Result<usize, Error>> {
+        let store: &Self = self;
+        let mut embedded: Vec<(usize, Option<Result<Vec<f64>, Error>>)> =
+            stream::iter(items.iter().enumerate())
+                .map(|(index, (name, descriptions, image))| async move {
+                    let settled: bool = store.prepare_fields(name, descriptions.clone()).is_err()
+                        || (store.duplicate_strategy != DuplicateStrategy::Allow
+                            && store.find_by_image_hash(image_hash(image)).is_some());
+                    if settled {
+                        return (index, None);
+                    }
+
+                    (index, Some(store.embed_only(image.clone()).await))
+                })
+                .buffer_unordered(concurrency.max(1))
+                .collect()
+                .await;
+        embedded.sort_by_key(|(index, _)| *index);
+
+        let mut results: Vec<Result<usize, Error>> = Vec::with_capacity(items.len());
+        for ((name, descriptions, image), (_, vector)) in items.into_iter().zip(embedded) {
+            let result: Result<usize, Error> = match vector.transpose() {
+                Err(e) => Err(e),
+                Ok(vector) => {
+                    self.add_embedded(&name, descriptions, image, Value::Null, None, vector)
+                        .await
+                }
+            };
+            results.push(result);
+        }
+
+        results
+    }
+
+    // Helper function behind `add_with_metadata`, `add_with_vector` and
+    // `add_batch`, taking the image's vector when it was embedded ahead of time
+    async fn add_embedded(
+        &mut self,
+        name: &str,
+        descriptions: Vec<String>,
+        image: DynamicImage,
+        metadata: Value,
+        gender: Option<Gender>,
+        vector: Option<Vec<f64>>,
+    ) -> Result<usize, Error> {
+        let config = config::get();
+        let (name, descriptions) = self.prepare_fields(name, descriptions)?;
+
+        // duplicates are settled before paying for an embedding
+        let hash: u64 = image_hash(&image);
+        if let Some(existing_id) = self.find_by_image_hash(hash) {
+            match self.duplicate_strategy {
+                DuplicateStrategy::Reject => {
+                    return Err(DataEntryErrors::DuplicateImage(existing_id).into());
+                }
+                DuplicateStrategy::Merge => {
+                    self.merge_descriptions(existing_id, descriptions)?;
+                    return Ok(existing_id);
+                }
+                DuplicateStrategy::Allow => {}
+            }
+        }
+
+        // keep the source image around for re-embedding and serving
+        let encoded_image: Option<String> = if config.store_images {
+            Some(encode_image(&image)?)
+        } else {
+            None
+        };
+
+        let new_vector: Vec<f64> = match vector {
+            Some(vector) => vector,
+            None => self.embed_only(image).await?,
+        };
+        self.check_variance(&new_vector)?;
+
+        // store the information to a kv storage, and get a corresponding
+        // key for later retrieval.
+        let id: usize = self.kv_storage(
+            &name,
+            descriptions,
+            new_vector.clone(),
+            encoded_image,
+            Some(hash),
+            metadata,
+            gender,
+        )?;
+
+        Ok(id)
+    }
+
+    /// Replace an entry's name, descriptions, image and metadata, keeping its ID
+    ///
+    /// Clients pass the `version` they read to detect concurrent edits: if
+    /// the entry was edited since, nothing changes and a
+    /// [`DataEntryErrors::VersionConflict`] is returned.
+    ///
+    /// # Arguments
+    /// * `image` - The new image to vectorize
+    /// * `data_entry` - The entry's new fields, found by its `id`
+    /// * `expected_version` - Version the entry must still be at, `None` to skip the check
+    ///
+    /// # Returns
+    /// The entry's new version
+    pub async fn edit_versioned(
+        &mut self,
+        image: DynamicImage,
+        data_entry: DataEntry,
+        expected_version: Option<u64>,
+    ) -> Result<u64, Error> {
+        let current: &DataEntry = self
+            .get(data_entry.id)
+            .ok_or(DataEntryErrors::NoDataWasFound)?;
+        if let Some(expected) = expected_version {
+            if expected != current.version {
+                return Err(DataEntryErrors::VersionConflict {
+                    expected,
+                    actual: current.version,
+                }
+                .into());
+            }
+        }
+        let created_at: u64 = current.created_at;
+        let version: u64 = current.version + 1;
+        let gender: Option<Gender> = data_entry.gender.or(current.gender);
+
+        let (name, descriptions) =
+            self.prepare_fields(&data_entry.name, data_entry.descriptions)?;
+        let encoded_image: Option<String> = if config::get().store_images {
+            Some(encode_image(&image)?)
+        } else {
+            None
+        };
+        let hash: u64 = image_hash(&image);
+        let new_vector: Vec<f64> = self.embed_only(image).await?;
+
+        self.kv_edit(
+            data_entry.id,
+            DataEntry {
+                id: data_entry.id,
+                name,
+                vector: self.storage_form(new_vector),
+                descriptions,
+                image: encoded_image,
+                created_at,
+                image_hash: Some(hash),
+                metadata: data_entry.metadata,
+                version,
+                gender,
+            },
+        )?;
+
+        Ok(version)
+    }
+
+    // Helper function to sanitize a name and validate descriptions in their stored form
+    fn prepare_fields(
+        &self,
+        name: &str,
+        descriptions: Vec<String>,
+    ) -> Result<(String, Vec<String>), Error> {
+        let config = config::get();
+        let name: String = sanitize_name(name, config.max_name_length)?;
+        let descriptions: Vec<String> = descriptions
+            .into_iter()
+            .filter(|description| !description.trim().is_empty())
+            .map(|description| self.tag_form(description))
+            .collect();
+        validate_descriptions(
+            &descriptions,
+            config.max_descriptions,
+            config.max_description_length,
+        )?;
+
+        Ok((name, descriptions))
+    }
+}
+
+impl VectorStore for InMemoryVectorStore {
+    async fn add(
+        &mut self,
+        name: &str,
+        descriptions: Vec<String>,
+        image: DynamicImage,
+    ) -> Result<usize, Error> {
+        self.add_with_metadata(name, descriptions, image, Value::Null, None)
+            .await
+    }
+
+    async fn edit(&mut self, image: DynamicImage, data_entry: DataEntry) -> Result<(), Error> {
+        // edited in place, so the entry keeps its ID and nothing else can
+        // observe it missing halfway
+        let _: u64 = self.edit_versioned(image, data_entry, None).await?;
+
+        Ok(())
+    }
+
+    async fn delete(&mut self, id: usize) -> Result<()> {
+        // delete both the vectors and the data entry
+        self.kv_delete(id)?;
+
+        Ok(())
+    }
+
+    async fn search(&self, image: DynamicImage, top_n: usize) -> Result<Vec<SearchResult>, Error> {
+        self.search_with(image, &SearchOptions::top_n(top_n)).await
+    }
+}
+
+impl InMemoryVectorStore {
+    /// Search for similar entries given an image, with full control over the search
+    ///
+    /// # Arguments
+    /// * `image` - The image to search for similar entries
+    /// * `options` - Options controlling the search
+    pub async fn search_with(
+        &self,
+        image: DynamicImage,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>, Error> {
+        let (_, data_entries, _) = self.search_with_query(image, options).await?;
+
+        Ok(data_entries)
+    }
+
+    /// Search for similar entries given an image, also returning the query vector
+    ///
+    /// # Arguments
+    /// * `image` - The image to search for similar entries
+    /// * `options` - Options controlling the search
+    ///
+    /// # Returns
+    /// The query vector exactly as it was used for ranking, the results, and
+    /// how many results were requested, returned and filtered out
+    pub async fn search_with_query(
+        &self,
+        image: DynamicImage,
+        options: &SearchOptions,
+    ) -> Result<(Vec<f64>, Vec<SearchResult>, SearchCounts), Error> {
+        let query_vector: Vec<f64> = self.embed_query(image, options).await?;
+        let (data_entries, counts) = self.rank(&query_vector, options)?;
+
+        Ok((query_vector, data_entries, counts))
+    }
+
+    /// Embed an image into a query vector, the first half of `search_with_query`
+    ///
+    /// Split from [`Self::rank`] so callers can time embedding and ranking apart.
+    ///
+    /// # Arguments
+    /// * `image` - The image to search for similar entries
+    /// * `options` - Options controlling the search, deciding the query's normalization
+    pub async fn embed_query(
+        &self,
+        image: DynamicImage,
+        options: &SearchOptions,
+    ) -> Result<Vec<f64>, Error> {
+        let new_vector: Vec<f64> = self.embed_only(image).await?;
+
+        self.prepare_query(new_vector, options)
+    }
+
+    /// Measure recall@k and precision@k over a labeled evaluation set
+    ///
+    /// Every query is embedded and searched like `search_with_query` with
+    /// default options, but without counting towards match tracking. Scores
+    /// are averaged across the queries, see [`evaluate_retrieval`].
+    ///
+    /// # Arguments
+    /// * `queries` - Query images and the entries relevant to each
+    /// * `k` - Number of results each query retrieves
+    pub async fn evaluate(
+        &self,
+        queries: Vec<EvaluationQuery>,
+        k: usize,
+    ) -> Result<Evaluation, Error> {
+        let queries: Vec<EmbeddedEvaluationQuery> = self.embed_evaluation(queries).await?;
+        self.evaluate_embedded(&queries, k)
+    }
+
+    /// Embed the images of an evaluation set, without searching
+    ///
+    /// Only needs the store's configuration, so it can run on a copy from
+    /// `without_entries`, outside the store's lock.
+    ///
+    /// # Arguments
+    /// * `queries` - Query images and the entries relevant to each
+    pub async fn embed_evaluation(
+        &self,
+        queries: Vec<EvaluationQuery>,
+    ) -> Result<Vec<EmbeddedEvaluationQuery>, Error> {
+        let options = SearchOptions::default();
+        let mut embedded: Vec<EmbeddedEvaluationQuery> = Vec::with_capacity(queries.len());
+        for query in queries {
+            embedded.push(EmbeddedEvaluationQuery {
+                query_vector: self.embed_query(query.image, &options).await?,
+                relevant_ids: query.relevant_ids,
+            });
+        }
+
+        Ok(embedded)
+    }
+
+    /// Measure recall@k and precision@k over an embedded evaluation set
+    ///
+    /// Like `evaluate`, with the queries embedded by `embed_evaluation`.
+    ///
+    /// # Arguments
+    /// * `queries` - Query vectors and the entries relevant to each
+    /// * `k` - Number of results each query retrieves
+    pub fn evaluate_embedded(
+        &self,
+        queries: &[EmbeddedEvaluationQuery],
+        k: usize,
+    ) -> Result<Evaluation, Error> {
+        let options = SearchOptions::top_n(k);
+        let mut evaluations: Vec<QueryEvaluation> = Vec::with_capacity(queries.len());
+        for query in queries {
+            let (results, _) = self.kv_search(&query.query_vector, &options)?;
+            let retrieved_ids: Vec<usize> =
+                results.iter().map(|result| result.data_entry.id).collect();
+            evaluations.push(evaluate_retrieval(&retrieved_ids, &query.relevant_ids, k));
+        }
+
+        let count: f64 = evaluations.len().max(1) as f64;
+        Ok(Evaluation {
+            k,
+            recall: evaluations.iter().map(|query| query.recall).sum::<f64>() / count,
+            precision: evaluations.iter().map(|query| query.precision).sum::<f64>() / count,
+            queries: evaluations,
+        })
+    }
+
+    /// Score an image against a single entry, without ranking the store
+    ///
+    /// The entry is looked up before embedding, so a missing one costs no
+    /// embedding call. Like `similar_to`, this does not count towards match
+    /// tracking.
+    ///
+    /// # Arguments
+    /// * `image` - The image to compare
+    /// * `id` - ID of the entry to compare it with
+    pub async fn match_entry(&self, image: DynamicImage, id: usize) -> Result<SearchResult, Error> {
+        let entry: &DataEntry = self.get(id).ok_or(DataEntryErrors::NoDataWasFound)?;
+        let query_vector: Vec<f64> = self.embed_query(image, &SearchOptions::default()).await?;
+
+        Ok(SearchResult {
+            score: self.cosine_similarity(&query_vector, &entry.vector),
+            data_entry: entry.clone(),
+        })
+    }
+
+    /// Rank the entries against a query vector from [`Self::embed_query`]
+    ///
+    /// # Arguments
+    /// * `query_vector` - Vector to compare the entries with, in the form of the stored vectors
+    /// * `options` - Options controlling the search
+    pub fn rank(
+        &self,
+        query_vector: &[f64],
+        options: &SearchOptions,
+    ) -> Result<(Vec<SearchResult>, SearchCounts), Error> {
+        let (data_entries, counts) = self.kv_search(query_vector, options)?;
+        self.record_matches(&data_entries);
+
+        Ok((data_entries, counts))
+    }
+
+    /// Search for similar entries given a precomputed query vector
+    ///
+    /// Nothing is embedded, so this is meant for clients sharing the store's
+    /// embedder. The vector must be raw or normalized as the options ask.
+    ///
+    /// # Arguments
+    /// * `query_vector` - Vector to compare the entries with
+    /// * `options` - Options controlling the search
+    pub fn search_vector(
+        &self,
+        query_vector: Vec<f64>,
+        options: &SearchOptions,
+    ) -> Result<(Vec<SearchResult>, SearchCounts), Error> {
+        if query_vector.len() != self.dimensions {
+            return Err(ValidationErrors::QueryDimensionMismatch {
+                expected: self.dimensions,
+                actual: query_vector.len(),
+            }
+            .into());
+        }
+        let query_vector: Vec<f64> = self.prepare_query(query_vector, options)?;
+
+        self.rank(&query_vector, options)
+    }
+
+    /// Search for similar entries given a query vector, streaming matches as
+    /// they are scored
+    ///
+    /// Every entry scoring at least `min_score` is sent as a
+    /// [`SearchEvent::Candidate`] as soon as the scan reaches it, so clients
+    /// can render matches before the scan of a large store completes. These
+    /// arrive in store order and only approximate the ranking; the ranked
+    /// results follow in a final [`SearchEvent::Summary`]. Failures are sent
+    /// as [`SearchEvent::Error`] and returned as well.
+    ///
+    /// The channel is bounded, so the scan waits for a slow receiver instead
+    /// of buffering every candidate. Sending blocks the calling thread, so
+    /// call this from a blocking thread, e.g. through `spawn_blocking`.
+    ///
+    /// # Arguments
+    /// * `query_vector` - Vector to compare the entries with, e.g. from [`Self::embed_only`]
+    /// * `options` - Options controlling the search
+    /// * `min_score` - Lowest score of entries sent before the summary
+    /// * `events` - Channel receiving the events, a closed channel is ignored
+    pub fn search_stream(
+        &self,
+        query_vector: Vec<f64>,
+        options: &SearchOptions,
+        min_score: f64,
+        events: &Sender<SearchEvent>,
+    ) -> Result<(), Error> {
+        let outcome: Result<(Vec<SearchResult>, SearchCounts), Error> = self
+            .prepare_query(query_vector, options)
+            .and_then(|query_vector| {
+                self.kv_search_with(&query_vector, options, |entry, score| {
+                    if score >= min_score {
+                        let _ = events.blocking_send(SearchEvent::Candidate {
+                            id: entry.id,
+                            score,
+                        });
+                    }
+                })
+            });
+
+        match outcome {
+            std::result::Result::Ok((results, counts)) => {
+                self.record_matches(&results);
+                let _ = events.blocking_send(SearchEvent::Summary { results, counts });
+                Ok(())
+            }
+            Err(e) => {
+                let _ = events.blocking_send(SearchEvent::Error {
+                    message: e.to_string(),
+                });
+                Err(e)
+            }
+        }
     }
 }
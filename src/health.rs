@@ -0,0 +1,71 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+/// Outcome of probing the embedding provider
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EmbedderHealth {
+    /// Whether the provider answered the probe
+    pub healthy: bool,
+    /// Time the probe took, in milliseconds
+    pub latency_ms: f64,
+    /// Why the probe failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Seconds since the probe ran, 0 for a fresh one
+    pub age_secs: f64,
+}
+
+/// Last probe of the embedding provider, reused while it is recent
+///
+/// Health checks may come every few seconds, so probing on each of them
+/// would hammer the provider. Probes are also single-flight: requests
+/// arriving while one runs wait for its outcome instead of starting another.
+#[derive(Debug)]
+pub struct HealthCache {
+    /// How long a probe is reused
+    ttl: Duration,
+    /// Last probe and when it finished
+    last: Mutex<Option<(Instant, EmbedderHealth)>>,
+}
+
+impl HealthCache {
+    /// Create an empty cache
+    ///
+    /// # Arguments
+    /// * `ttl` - How long a probe is reused, 0 probes on every call
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Get the last probe if it is recent enough, or run a new one
+    ///
+    /// # Arguments
+    /// * `probe` - Probe to run when the last one expired
+    pub async fn get_or_probe(
+        &self,
+        probe: impl Future<Output = EmbedderHealth>,
+    ) -> EmbedderHealth {
+        let mut last = self.last.lock().await;
+        if let Some((checked_at, health)) = last.as_ref() {
+            let age: Duration = checked_at.elapsed();
+            if age < self.ttl {
+                return EmbedderHealth {
+                    age_secs: age.as_secs_f64(),
+                    ..health.clone()
+                };
+            }
+        }
+
+        let health: EmbedderHealth = probe.await;
+        *last = Some((Instant::now(), health.clone()));
+        health
+    }
+}
@@ -1 +1,19 @@
+pub mod auth;
+pub mod clustering;
+pub mod config;
+pub mod dead_letters;
 pub mod embedding;
+pub mod health;
+pub mod npy;
+pub mod preprocess;
+pub mod prompts;
+pub mod rate_limit;
+pub mod readiness;
+pub mod routes;
+pub mod snapshots;
+pub mod stats;
+pub mod store;
+pub mod thumbnails;
+pub mod timing;
+
+pub use store::SharedStores;
@@ -1,31 +1,117 @@
-mod embedding;
-mod routes;
-mod store;
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
 
-use std::{sync::Arc, time::Duration};
+use actix_web::{
+    middleware::{from_fn, Logger, NormalizePath},
+    web::Data,
+    App, HttpServer,
+};
+use anyhow::{anyhow, Error};
+use async_openai::config::OpenAIConfig;
+use log::{info, warn};
+use stylist::{
+    config::{self, connections_per_worker, PROMPT_SIZE, STORE_DIMENSIONS},
+    dead_letters::DeadLetterQueue,
+    embedding::{validate_prompt_layout, InMemoryVectorStore},
+    health::HealthCache,
+    prompts::{
+        dedup_prompts, load_prompt_files, load_prompt_sets, shuffle_prompts, DEFAULT_PROMPT_SET,
+    },
+    rate_limit::RateLimiter,
+    readiness::{require_ready, Readiness},
+    routes,
+    snapshots::Snapshots,
+    stats::RequestCounters,
+    thumbnails::ThumbnailCache,
+    timing::log_slow_requests,
+    SharedStores,
+};
+use tokio::sync::RwLock;
 
-use actix_web::{middleware::Logger, web::Data, App, HttpServer};
-use anyhow::Error;
-use dim::{self, prompt::load_prompts};
+// Helper function to point a store at its own OpenAI endpoint, if one is configured
+fn with_api_base(store: InMemoryVectorStore, api_base: &Option<String>) -> InMemoryVectorStore {
+    match api_base {
+        Some(api_base) => store.with_openai_config(OpenAIConfig::new().with_api_base(api_base)),
+        None => store,
+    }
+}
+
+// Helper function to fail startup when a prompt set does not fill the store's
+// dimensions, e.g. because lenient loading skipped some of its files
+fn check_prompt_layout(name: &str, prompts: &[String]) -> Result<(), Error> {
+    validate_prompt_layout(prompts.len(), PROMPT_SIZE, STORE_DIMENSIONS).map_err(|e| {
+        anyhow!(
+            "The {} prompts do not match the store: {} Check for skipped prompt files, or set STYLIST_STRICT_PROMPTS=true to fail on them.",
+            name,
+            e
+        )
+    })
+}
 
-use embedding::InMemoryVectorStore;
-use log::info;
-use store::SharedStores;
-use tokio::sync::Mutex;
+// Helper function to deduplicate and shuffle freshly loaded prompts as configured
+fn prepare_prompts(prompts: &mut Vec<String>) {
+    if config::get().dedup_prompts {
+        let removed: usize = dedup_prompts(prompts);
+        info!("Removed {} duplicate prompts", removed);
+    }
+    if let Some(seed) = config::get().prompt_seed {
+        shuffle_prompts(prompts, seed);
+    }
+}
 
 // Helper function to create a test vector store
-pub fn initialize_clothes_store() -> InMemoryVectorStore {
-    let prompts: Vec<String> =
-        load_prompts("/Users/xinyubao/Documents/aesthetic-prototype/prompts_clothes").unwrap();
+pub fn initialize_clothes_store() -> Result<InMemoryVectorStore, Error> {
+    let mut prompt_sets: HashMap<String, Vec<String>> = load_prompt_sets(
+        &config::get().clothes_prompts,
+        &config::get().prompt_extension,
+        config::get().strict_prompts,
+    )?;
+    for (name, prompts) in prompt_sets.iter_mut() {
+        prepare_prompts(prompts);
+        check_prompt_layout(&format!("clothes ({})", name), prompts)?;
+    }
+    let prompts: Vec<String> = prompt_sets[DEFAULT_PROMPT_SET].clone();
+
+    let store = InMemoryVectorStore::new(STORE_DIMENSIONS, vec![], prompts, PROMPT_SIZE)?
+        .with_prompt_sets(prompt_sets)
+        .with_normalized_vectors(config::get().normalize_vectors)
+        .with_duplicate_strategy(config::get().duplicate_strategy)
+        .with_length_mismatch_policy(config::get().length_mismatch_policy)
+        .with_embedding_retries(config::get().embedding_retries)
+        .with_prompt_batch_size(config::get().prompt_batch_size)
+        .with_normalized_tags(config::get().normalize_tags)
+        .with_match_tracking(config::get().track_matches)
+        .with_constant_vector_rejection(config::get().reject_constant_vectors)
+        .with_contiguous_vectors(config::get().contiguous_vectors);
 
-    InMemoryVectorStore::new(30, vec![], prompts, 2)
+    Ok(with_api_base(store, &config::get().clothes_openai_api_base))
 }
 
-pub fn initialize_face_store() -> InMemoryVectorStore {
-    let prompts: Vec<String> =
-        load_prompts("/Users/xinyubao/Documents/aesthetic-prototype/prompts").unwrap();
+pub fn initialize_face_store() -> Result<InMemoryVectorStore, Error> {
+    let mut prompts: Vec<String> = load_prompt_files(
+        &config::get().face_prompts,
+        &config::get().prompt_extension,
+        config::get().strict_prompts,
+    )?;
+    prepare_prompts(&mut prompts);
+    check_prompt_layout("face", &prompts)?;
 
-    InMemoryVectorStore::new(30, vec![], prompts, 2)
+    let store = InMemoryVectorStore::new(STORE_DIMENSIONS, vec![], prompts, PROMPT_SIZE)?
+        .with_normalized_vectors(config::get().normalize_vectors)
+        .with_duplicate_strategy(config::get().duplicate_strategy)
+        .with_length_mismatch_policy(config::get().length_mismatch_policy)
+        .with_embedding_retries(config::get().embedding_retries)
+        .with_prompt_batch_size(config::get().prompt_batch_size)
+        .with_normalized_tags(config::get().normalize_tags)
+        .with_match_tracking(config::get().track_matches)
+        .with_constant_vector_rejection(config::get().reject_constant_vectors)
+        .with_contiguous_vectors(config::get().contiguous_vectors);
+
+    Ok(with_api_base(store, &config::get().face_openai_api_base))
 }
 
 #[tokio::main]
@@ -33,32 +119,161 @@ async fn main() -> Result<(), Error> {
     // initiate a logger
     simple_logger::SimpleLogger::new().env().init().unwrap();
 
+    // a missing key would otherwise only surface on the first embedding
+    if !std::env::var("OPENAI_API_KEY").is_ok_and(|key| !key.trim().is_empty()) {
+        if config::get().require_api_key {
+            return Err(anyhow!(
+                "OPENAI_API_KEY is not set, and STYLIST_REQUIRE_KEY=true"
+            ));
+        }
+        warn!("OPENAI_API_KEY is not set! Uploads and searches will fail until it is.");
+    }
+
+    // requests are answered with 503 until the stores below are populated
+    let readiness: Data<Readiness> = Data::new(Readiness::new());
+
+    // seeding is for first runs only, so check before anything is written
+    let snapshot_exists: bool = Path::new("vector_stores.json").exists();
+
     // initialize vector stores
-    let clothes_store = initialize_clothes_store();
-    let face_store = initialize_face_store();
+    let clothes_store = initialize_clothes_store()?;
+    let face_store = initialize_face_store()?;
 
     // share it between threads
-    let shared_clothes_store = Arc::new(Mutex::new(clothes_store));
-    let shared_face_store = Arc::new(Mutex::new(face_store));
-    let shared_store = Arc::new(Mutex::new(SharedStores {
-        clothes: shared_clothes_store,
-        face: shared_face_store,
-    }));
+    let shared_clothes_store = Arc::new(RwLock::new(clothes_store));
+    let shared_face_store = Arc::new(RwLock::new(face_store));
+    let mut shared_stores = SharedStores::new(shared_clothes_store, shared_face_store)
+        .with_autosave(
+            "vector_stores.json",
+            config::get().save_every_n,
+            Duration::from_millis(config::get().save_debounce_ms),
+        )
+        .with_load_backup(if config::get().backup_before_load {
+            config::get().load_backups_kept
+        } else {
+            0
+        })
+        .with_blocking_save(config::get().blocking_save);
+    if let Some(op_log_path) = &config::get().op_log_path {
+        shared_stores = shared_stores.with_op_log(
+            op_log_path,
+            "vector_stores.json",
+            config::get().op_log_compact_every,
+        );
+        let replayed: usize = shared_stores.restore().await?;
+        info!(
+            "Restored the stores, replaying {} logged operations",
+            replayed
+        );
+    }
+    if let Some(seed_dir) = &config::get().seed_dir {
+        if snapshot_exists {
+            info!(
+                "Skipping seeding from {}, vector_stores.json already exists",
+                seed_dir
+            );
+        } else {
+            let seeded: usize = shared_stores
+                .clothes
+                .write()
+                .await
+                .seed_from_directory(seed_dir)
+                .await?;
+            info!(
+                "Seeded the clothes store with {} images from {}",
+                seeded, seed_dir
+            );
+            // save right away, so the next start does not seed again
+            shared_stores.save("vector_stores.json").await?;
+        }
+    }
+    let shared_store = Arc::new(shared_stores);
 
     info!("In-Memory vector store is initialized.");
 
-    HttpServer::new(move || {
+    // counters are shared by every worker, so keep a handle for the summary
+    let counters: Data<RequestCounters> = Data::new(RequestCounters::default());
+    let app_counters: Data<RequestCounters> = counters.clone();
+
+    let thumbnails: Data<StdMutex<ThumbnailCache>> = Data::new(StdMutex::new(ThumbnailCache::new(
+        config::get().thumbnail_cache_mb * 1024 * 1024,
+    )));
+
+    let snapshots: Data<StdMutex<Snapshots>> =
+        Data::new(StdMutex::new(Snapshots::new(config::get().max_snapshots)));
+
+    let mut dead_letters = DeadLetterQueue::new(config::get().max_dead_letters);
+    if let Some(dead_letter_path) = &config::get().dead_letter_path {
+        dead_letters = dead_letters.with_path(dead_letter_path)?;
+        info!(
+            "Restored {} failed uploads from {}",
+            dead_letters.len(),
+            dead_letter_path
+        );
+    }
+    let dead_letters: Data<StdMutex<DeadLetterQueue>> = Data::new(StdMutex::new(dead_letters));
+
+    let health_cache: Data<HealthCache> = Data::new(HealthCache::new(Duration::from_secs(
+        config::get().embed_health_ttl_secs,
+    )));
+
+    let rate_limiter: Data<RateLimiter> =
+        Data::new(RateLimiter::new(config::get().rate_limit_per_minute));
+
+    let workers: usize = std::thread::available_parallelism().map_or(1, |count| count.get());
+
+    // every piece of startup work is done
+    readiness.set_ready();
+
+    let mut server = HttpServer::new(move || {
         App::new()
+            .wrap(routes::json_error_handlers())
+            .wrap(from_fn(require_ready))
+            .wrap(from_fn(log_slow_requests))
             .wrap(Logger::default())
+            // routes are lowercase and matched case-sensitively, but a
+            // trailing slash is forgiven: `/api/clothes/get/` resolves to
+            // `/api/clothes/get`
+            .wrap(NormalizePath::trim())
             .app_data(Data::new(shared_store.clone()))
+            .app_data(app_counters.clone())
+            .app_data(thumbnails.clone())
+            .app_data(health_cache.clone())
+            .app_data(snapshots.clone())
+            .app_data(dead_letters.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(readiness.clone())
             .configure(routes::config)
     })
     .client_request_timeout(Duration::from_secs(0))
     .client_disconnect_timeout(Duration::from_secs(0))
     .max_connection_rate(256)
-    .bind(("0.0.0.0".to_string(), 9500))?
-    .run()
-    .await?;
+    .workers(workers);
+
+    // the accept rate limit alone does not bound requests already in flight.
+    // actix caps connections per worker, so an instance-wide limit is split
+    // between them
+    if let Some(max_connections) = config::get().max_connections {
+        let per_worker: usize = connections_per_worker(max_connections, workers);
+        info!(
+            "Serving at most {} concurrent connections, {} on each of {} workers",
+            per_worker * workers,
+            per_worker,
+            workers
+        );
+        server = server.max_connections(per_worker);
+    }
+
+    server
+        .bind((config::HOST.to_string(), config::PORT))?
+        .run()
+        .await?;
+
+    let summary = counters.snapshot();
+    info!(
+        "Server stopped. Requests served: {} uploads, {} searches, {} deletes",
+        summary.uploads, summary.searches, summary.deletes
+    );
 
     Ok(())
 }
@@ -0,0 +1,43 @@
+/// Magic string opening every `.npy` file
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Alignment of the data section, as written by NumPy itself
+const ALIGNMENT: usize = 64;
+
+/// Encode rows of equal length as a little-endian f64 `.npy` array
+///
+/// The result is a version 1.0 `.npy` file of shape `rows.len() × columns`,
+/// loadable with `numpy.load`. Rows must all hold `columns` values.
+///
+/// # Arguments
+/// * `rows` - Rows of the matrix, in order
+/// * `columns` - Number of values in every row
+pub fn encode_f64_matrix(rows: &[&[f64]], columns: usize) -> Vec<u8> {
+    let mut header: String = format!(
+        "{{'descr': '<f8', 'fortran_order': False, 'shape': ({}, {}), }}",
+        rows.len(),
+        columns
+    );
+
+    // magic, version and header length take 10 bytes, and the header ends
+    // with a newline after the space padding
+    let unpadded: usize = MAGIC.len() + 4 + header.len() + 1;
+    let padding: usize = (ALIGNMENT - unpadded % ALIGNMENT) % ALIGNMENT;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    let mut bytes: Vec<u8> =
+        Vec::with_capacity(MAGIC.len() + 4 + header.len() + rows.len() * columns * 8);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&[1, 0]);
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+
+    for row in rows {
+        for value in row.iter() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    bytes
+}
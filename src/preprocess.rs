@@ -0,0 +1,53 @@
+use image::{imageops::FilterType, DynamicImage};
+use serde::Serialize;
+
+/// Steps applied to every image before it reaches the embedder
+///
+/// Every step is disabled by default, in which case images are embedded as
+/// uploaded. The steps run in field order. The service enables `rgb` unless
+/// `STYLIST_PREPROCESS_RGB=false`, so an RGBA upload and the same picture
+/// without alpha embed identically.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Preprocessing {
+    /// Crop the largest centered square out of the image
+    pub square_crop: bool,
+    /// Downscale so that neither side exceeds this many pixels
+    pub max_side: Option<u32>,
+    /// Convert to 8-bit RGB, dropping the alpha channel and extra precision.
+    /// Alpha is dropped, not composited, so transparent pixels keep their color.
+    pub rgb: bool,
+}
+
+impl Preprocessing {
+    /// Whether any step is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.square_crop || self.max_side.is_some() || self.rgb
+    }
+
+    /// Run the enabled steps on an image
+    ///
+    /// # Arguments
+    /// * `image` - The image to preprocess
+    pub fn apply(&self, image: DynamicImage) -> DynamicImage {
+        let mut image: DynamicImage = image;
+
+        if self.square_crop {
+            let side: u32 = image.width().min(image.height());
+            let x: u32 = (image.width() - side) / 2;
+            let y: u32 = (image.height() - side) / 2;
+            image = image.crop_imm(x, y, side, side);
+        }
+
+        if let Some(max_side) = self.max_side {
+            if image.width() > max_side || image.height() > max_side {
+                image = image.resize(max_side, max_side, FilterType::Triangle);
+            }
+        }
+
+        if self.rgb {
+            image = DynamicImage::ImageRgb8(image.to_rgb8());
+        }
+
+        image
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Error, Result};
+use log::{debug, info, warn};
+
+/// Error variants related to prompt loading
+#[derive(Debug, Clone)]
+pub enum PromptErrors {
+    /// The prompts directory did not contain a single usable prompt file
+    NoValidPromptFiles(String),
+}
+
+impl std::error::Error for PromptErrors {}
+
+impl Display for PromptErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoValidPromptFiles(directory) => {
+                write!(f, "No valid prompt files were found in {}!", directory)
+            }
+        }
+    }
+}
+
+/// Load prompts from a directory, one prompt per file
+///
+/// Only regular files ending in `.{extension}` are read. Anything else, such as
+/// `.DS_Store` or nested directories, is skipped with a debug log.
+///
+/// Prompts are returned ordered by file name. The position of a prompt decides
+/// which vector dimensions it fills, so the order must not depend on the
+/// filesystem's directory listing.
+///
+/// Files that cannot be read or are not valid UTF-8, such as stray binary
+/// files, fail the whole load when `strict`, and are skipped with a warning
+/// otherwise.
+///
+/// # Arguments
+/// * `directory` - Directory containing the prompt files
+/// * `extension` - Extension (without the dot) a prompt file must have
+/// * `strict` - Whether an unreadable prompt file is an error
+///
+/// # Returns
+/// The prompts, or an error if no valid prompt file remains
+pub fn load_prompt_files(
+    directory: &str,
+    extension: &str,
+    strict: bool,
+) -> Result<Vec<String>, Error> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+
+        if !is_prompt_file(&path, extension) {
+            debug!("Skipping non-prompt file: {}", path.display());
+            continue;
+        }
+
+        paths.push(path);
+    }
+    paths.sort();
+
+    let mut prompts: Vec<String> = Vec::with_capacity(paths.len());
+    let mut skipped: usize = 0;
+    for path in paths {
+        match fs::read_to_string(&path) {
+            Ok(prompt) => prompts.push(prompt),
+            Err(e) if !strict => {
+                warn!("Skipping unreadable prompt file {}: {}", path.display(), e);
+                skipped += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if skipped > 0 {
+        warn!(
+            "Skipped {} unreadable prompt files in {}",
+            skipped, directory
+        );
+    }
+
+    if prompts.is_empty() {
+        return Err(PromptErrors::NoValidPromptFiles(directory.to_string()).into());
+    }
+
+    info!("Loaded {} prompts from {}", prompts.len(), directory);
+
+    Ok(prompts)
+}
+
+/// Name of the prompt set loaded from the root of a prompts directory
+pub const DEFAULT_PROMPT_SET: &str = "default";
+
+/// Load named prompt sets from a directory and its subdirectories
+///
+/// The prompt files directly in `directory` form the [`DEFAULT_PROMPT_SET`],
+/// and each subdirectory a set named after it, loaded like
+/// [`load_prompt_files`]. Every set, the default one included, must hold at
+/// least one prompt. Only the first level of subdirectories is scanned. A
+/// subdirectory named like the default set is an error, as it would replace
+/// the prompts of the root.
+///
+/// # Arguments
+/// * `directory` - Directory containing the prompt files and sets
+/// * `extension` - Extension (without the dot) a prompt file must have
+/// * `strict` - Whether an unreadable prompt file is an error
+pub fn load_prompt_sets(
+    directory: &str,
+    extension: &str,
+    strict: bool,
+) -> Result<HashMap<String, Vec<String>>, Error> {
+    let mut sets: HashMap<String, Vec<String>> = HashMap::new();
+    sets.insert(
+        DEFAULT_PROMPT_SET.to_string(),
+        load_prompt_files(directory, extension, strict)?,
+    );
+
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let name: String = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name.to_string(),
+            None => {
+                warn!(
+                    "Skipping prompt set with a non-UTF-8 name: {}",
+                    path.display()
+                );
+                continue;
+            }
+        };
+        if name == DEFAULT_PROMPT_SET {
+            return Err(anyhow!(
+                "The prompt set {} is named like the set of the prompts in {}, please rename it",
+                path.display(),
+                directory
+            ));
+        }
+
+        let prompts: Vec<String> = load_prompt_files(&path.to_string_lossy(), extension, strict)?;
+        sets.insert(name, prompts);
+    }
+
+    info!("Loaded {} prompt sets from {}", sets.len(), directory);
+    Ok(sets)
+}
+
+/// Remove repeated prompts, keeping the first occurrence of each
+///
+/// Identical prompts cost an embedding call each while only repeating the
+/// same dimensions. Order is otherwise kept, so deduplicating before
+/// shuffling stays reproducible.
+///
+/// # Returns
+/// The number of prompts removed
+pub fn dedup_prompts(prompts: &mut Vec<String>) -> usize {
+    let before: usize = prompts.len();
+    let mut seen: HashSet<String> = HashSet::with_capacity(before);
+    prompts.retain(|prompt| seen.insert(prompt.clone()));
+
+    before - prompts.len()
+}
+
+/// Shuffle prompts in a reproducible way
+///
+/// The same seed always yields the same order, so a store built with a seed
+/// can be rebuilt with identical prompt-to-dimension mapping.
+///
+/// # Arguments
+/// * `prompts` - Prompts to shuffle in place
+/// * `seed` - Seed of the shuffle
+pub fn shuffle_prompts(prompts: &mut [String], seed: u64) {
+    shuffle_seeded(prompts, seed);
+}
+
+/// Shuffle a slice in a reproducible way, the same seed yielding the same order
+///
+/// # Arguments
+/// * `items` - Items to shuffle in place
+/// * `seed` - Seed of the shuffle
+pub fn shuffle_seeded<T>(items: &mut [T], seed: u64) {
+    let mut state: u64 = seed;
+
+    // Fisher-Yates driven by SplitMix64, which is stable across platforms
+    for index in (1..items.len()).rev() {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut value: u64 = state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+        value ^= value >> 31;
+
+        items.swap(index, (value % (index as u64 + 1)) as usize);
+    }
+}
+
+// Helper function to check whether a path is a prompt file we should read
+fn is_prompt_file(path: &Path, extension: &str) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    web::Data,
+    HttpResponse,
+};
+use log::warn;
+
+use crate::routes::BasicResponse;
+
+/// Token bucket of a single client
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Per-client-IP token bucket rate limiter
+///
+/// Every client may burst up to `requests_per_minute` requests, and regains
+/// them continuously over a minute. Buckets idle for longer than that are
+/// full again, so they are evicted without changing any outcome.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Size of a bucket, and number of tokens regained per minute
+    requests_per_minute: u32,
+    /// Buckets by client IP
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    /// Last time idle buckets were evicted
+    swept_at: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Create a limiter
+    ///
+    /// # Arguments
+    /// * `requests_per_minute` - Requests allowed per client and minute, 0 disables limiting
+    pub fn new(requests_per_minute: u32) -> Self {
+        Self {
+            requests_per_minute,
+            buckets: Mutex::new(HashMap::new()),
+            swept_at: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Take a token for a request from the given client
+    ///
+    /// # Returns
+    /// `Ok` when the request may proceed, otherwise how long the client has
+    /// to wait for its next token
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        if self.requests_per_minute == 0 {
+            return Ok(());
+        }
+
+        let now: Instant = Instant::now();
+        let capacity: f64 = self.requests_per_minute as f64;
+        let per_second: f64 = capacity / 60.0;
+        self.evict_idle(now);
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket: &mut Bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+
+        let elapsed: f64 = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+        }
+    }
+
+    // Helper function to drop buckets that have been idle long enough to be full
+    fn evict_idle(&self, now: Instant) {
+        let idle: Duration = Duration::from_secs(60);
+
+        let mut swept_at = self.swept_at.lock().unwrap();
+        if now.duration_since(*swept_at) < idle {
+            return;
+        }
+        *swept_at = now;
+
+        self.buckets
+            .lock()
+            .unwrap()
+            .retain(|_, bucket| now.duration_since(bucket.updated_at) < idle);
+    }
+}
+
+/// Middleware rejecting clients over their rate limit with 429 Too Many Requests
+///
+/// Attach it to the expensive routes with `wrap = "from_fn(rate_limit)"`. It
+/// uses the [`RateLimiter`] registered as app data, and lets every request
+/// through when there is none.
+pub async fn rate_limit<B: MessageBody>(
+    request: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let limiter: Option<&Data<RateLimiter>> = request.app_data::<Data<RateLimiter>>();
+    let ip: Option<IpAddr> = request.peer_addr().map(|address| address.ip());
+
+    if let (Some(limiter), Some(ip)) = (limiter, ip) {
+        if let Err(retry_after) = limiter.check(ip) {
+            let seconds: u64 = retry_after.as_secs_f64().ceil() as u64;
+            warn!("Rate limit exceeded by {} on {}", ip, request.path());
+
+            let response = HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, seconds.to_string()))
+                .json(BasicResponse::<String> {
+                    status: false,
+                    message: format!("Too many requests, retry in {} seconds", seconds),
+                    data: None,
+                });
+            return Ok(request.into_response(response).map_into_right_body());
+        }
+    }
+
+    Ok(next.call(request).await?.map_into_left_body())
+}
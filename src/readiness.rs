@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    web::Data,
+    HttpResponse,
+};
+use log::warn;
+
+use crate::routes::BasicResponse;
+
+/// Message of the response sent while the stores are still loading
+pub const INITIALIZING_MESSAGE: &str = "The service is initializing, please try again later.";
+
+/// Routes answered while the stores are loading, as they report on the
+/// process and the provider rather than the stores
+pub const READINESS_EXEMPT_PATHS: &[&str] = &["/api/live", "/api/embed/health"];
+
+/// Whether the stores finished loading, shared between all workers
+#[derive(Debug, Default)]
+pub struct Readiness {
+    ready: AtomicBool,
+}
+
+impl Readiness {
+    /// Create a flag that is not ready yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark the stores as loaded, letting requests through from now on
+    pub fn set_ready(&self) {
+        self.ready.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the stores are loaded
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+}
+
+/// Middleware answering 503 Service Unavailable until the stores are loaded
+///
+/// Wrap the `App` with it so clients get a clear signal to retry instead of
+/// errors from half-initialized stores. It uses the [`Readiness`] registered
+/// as app data, and lets every request through when there is none. The
+/// liveness and provider health routes, see [`READINESS_EXEMPT_PATHS`], are
+/// always let through.
+pub async fn require_ready<B: MessageBody>(
+    request: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let readiness: Option<&Data<Readiness>> = request.app_data::<Data<Readiness>>();
+
+    if readiness.is_some_and(|readiness| !readiness.is_ready())
+        && !READINESS_EXEMPT_PATHS.contains(&request.path())
+    {
+        warn!("Rejecting {} while the stores are loading", request.path());
+        let response = HttpResponse::ServiceUnavailable()
+            .insert_header((header::RETRY_AFTER, "1"))
+            .json(BasicResponse::<String> {
+                status: false,
+                message: INITIALIZING_MESSAGE.to_string(),
+                data: None,
+            });
+        return Ok(request.into_response(response).map_into_right_body());
+    }
+
+    Ok(next.call(request).await?.map_into_left_body())
+}
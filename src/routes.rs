@@ -1,239 +1,4162 @@
-use std::sync::Arc;
+use std::{
+    future::Future,
+    io::Cursor,
+    sync::{Arc, Mutex as StdMutex},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    delete,
+    dev::ServiceResponse,
+    error::ErrorInternalServerError,
+    get,
+    http::{header, StatusCode},
+    middleware::{from_fn, ErrorHandlerResponse, ErrorHandlers},
+    post, rt,
+    web::{self, Bytes, Data, Json},
+    HttpRequest, HttpResponse, HttpResponseBuilder, Responder,
+};
+use anyhow::{anyhow, Error};
+use async_openai::error::OpenAIError;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, FixedOffset};
+use futures_util::stream;
+use image::{guess_format, load_from_memory, DynamicImage, ImageFormat};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, RwLock};
+
+use crate::{
+    auth::require_token,
+    clustering::cluster_results,
+    config,
+    dead_letters::{DeadLetter, DeadLetterQueue},
+    embedding::{
+        decode_image, l2_norm, l2_normalize, score_histogram, validate_image, validate_tie_epsilon,
+        validate_top_n, DataEntry, DataEntryErrors, EmbeddedEvaluationQuery, EmbeddingErrors,
+        EvaluationQuery, Gender, HistogramBucket, ImportEntry, ImportValidation,
+        InMemoryVectorStore, Neighbors, SearchCounts, SearchEvent, SearchOptions, SearchResult,
+        SortDirection, SortKey, StoreConfig, ValidationErrors, VectorStore,
+    },
+    health::{EmbedderHealth, HealthCache},
+    npy::encode_f64_matrix,
+    rate_limit::rate_limit,
+    snapshots::{diff_snapshots, EntrySnapshot, Snapshots},
+    stats::RequestCounters,
+    store::{ChannelWriter, StoreKind, StoreOp},
+    thumbnails::{resize_to_png, ThumbnailCache, ThumbnailKey},
+    timing::record_phase,
+    SharedStores,
+};
+
+/// Decodes a base64 encoded image string into a DynamicImage
+///
+/// # Arguments
+/// * `b64_str` - Base64 encoded string of the image
+///
+/// # Returns
+/// * `Result<DynamicImage, Error>` - The decoded image or an error
+pub fn decode_base64_image(b64_str: &str) -> Result<DynamicImage, Error> {
+    let decoded_bytes: Vec<u8> = STANDARD.decode(b64_str)?;
+    let img: DynamicImage = load_from_memory(&decoded_bytes)?;
+    Ok(img)
+}
+
+// Helper function to decode a base64 image and check it against the
+// configured upload limits
+fn decode_validated_image(b64_str: &str) -> Result<DynamicImage, Error> {
+    let config = config::get();
+    let decoded_bytes: Vec<u8> = STANDARD.decode(b64_str)?;
+    let img: DynamicImage = load_from_memory(&decoded_bytes)?;
+    validate_image(
+        decoded_bytes.len(),
+        &img,
+        config.max_image_mb * 1024 * 1024,
+        config.max_image_side,
+    )?;
+    Ok(img)
+}
+
+/// Maps a store error to the HTTP status it should be reported with
+///
+/// Validation failures are the client's fault and surface as 400, missing
+/// entries as 404, anything else is treated as an internal error.
+fn status_for_error(error: &Error) -> StatusCode {
+    if error.downcast_ref::<ValidationErrors>().is_some() {
+        return StatusCode::BAD_REQUEST;
+    }
+    if error.downcast_ref::<EmbeddingErrors>().is_some() {
+        return StatusCode::BAD_GATEWAY;
+    }
+
+    match error.downcast_ref::<DataEntryErrors>() {
+        Some(DataEntryErrors::NoDataWasFound) => StatusCode::NOT_FOUND,
+        Some(DataEntryErrors::ImageNotStored) => StatusCode::CONFLICT,
+        Some(DataEntryErrors::DuplicateImage(_)) => StatusCode::CONFLICT,
+        Some(DataEntryErrors::VersionConflict { .. }) => StatusCode::CONFLICT,
+        None => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+// Helper function to tell failed embeddings, worth retrying once the provider
+// recovers, from uploads that were rejected. Only errors of the provider
+// call count: transport and API errors, and rejected credentials. A vector
+// the store refuses, e.g. a constant one, would be refused again.
+fn is_embedding_failure(error: &Error) -> bool {
+    error.chain().any(|cause| {
+        cause.downcast_ref::<OpenAIError>().is_some()
+            || matches!(
+                cause.downcast_ref::<EmbeddingErrors>(),
+                Some(EmbeddingErrors::UpstreamAuth(_))
+            )
+    })
+}
+
+// Helper function to answer 400 Bad Request when a search asks for more
+// results than STYLIST_MAX_TOP_N allows
+fn reject_large_top_n(top_n: usize) -> Option<HttpResponse> {
+    let e: Error = validate_top_n(top_n, config::get().max_top_n).err()?;
+    warn!("Rejecting search: {}", e);
+    Some(HttpResponse::BadRequest().json(BasicResponse::<String> {
+        status: false,
+        message: e.to_string(),
+        data: None,
+    }))
+}
+
+// Helper function to answer 400 Bad Request when a search asks to shuffle
+// ties with a negative or NaN epsilon
+fn reject_invalid_tie_epsilon(shuffle_ties: Option<f64>) -> Option<HttpResponse> {
+    let e: Error = validate_tie_epsilon(shuffle_ties?).err()?;
+    warn!("Rejecting search: {}", e);
+    Some(HttpResponse::BadRequest().json(BasicResponse::<String> {
+        status: false,
+        message: e.to_string(),
+        data: None,
+    }))
+}
+
+/// Middleware rendering actix's own 404 and 405 responses as [`BasicResponse`] JSON
+///
+/// Wrap the `App` with it so unknown routes and wrong methods get the same
+/// error shape as every handler. Responses that already are JSON, such as a
+/// handler's own 404, are left untouched. Actix itself answers a wrong
+/// method with 404, the 405 comes from the default service [`config`]
+/// registers.
+pub fn json_error_handlers<B: 'static>() -> ErrorHandlers<B> {
+    ErrorHandlers::new()
+        .handler(StatusCode::NOT_FOUND, render_json_error)
+        .handler(StatusCode::METHOD_NOT_ALLOWED, render_json_error)
+}
+
+// Helper function to replace a plain error response with a JSON one
+fn render_json_error<B>(
+    response: ServiceResponse<B>,
+) -> actix_web::Result<ErrorHandlerResponse<B>> {
+    let is_json: bool = response
+        .response()
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if is_json {
+        return Ok(ErrorHandlerResponse::Response(
+            response.map_into_left_body(),
+        ));
+    }
+
+    let status: StatusCode = response.status();
+    let (request, _) = response.into_parts();
+    let body: HttpResponse = json_error(status, &request);
+
+    Ok(ErrorHandlerResponse::Response(
+        ServiceResponse::new(request, body).map_into_right_body(),
+    ))
+}
+
+// Helper function answering requests no route matched. Method macros guard
+// the whole resource, so actix treats a wrong method like an unknown path;
+// a path some route serves with another method gets 405 instead of 404.
+async fn unmatched_route(request: HttpRequest) -> HttpResponse {
+    let status: StatusCode = if request.resource_map().has_resource(request.path()) {
+        StatusCode::METHOD_NOT_ALLOWED
+    } else {
+        StatusCode::NOT_FOUND
+    };
+
+    json_error(status, &request)
+}
+
+// Helper function to build the JSON body of a 404 or 405 response
+fn json_error(status: StatusCode, request: &HttpRequest) -> HttpResponse {
+    let message: String = match status {
+        StatusCode::METHOD_NOT_ALLOWED => format!(
+            "Method {} is not allowed on {}",
+            request.method(),
+            request.path()
+        ),
+        _ => format!("No route matches {} {}", request.method(), request.path()),
+    };
+    warn!("{}", message);
+
+    HttpResponse::build(status).json(BasicResponse::<String> {
+        status: false,
+        message,
+        data: None,
+    })
+}
+
+/// Message of the response sent when a lock cannot be acquired in time
+const LOCK_TIMEOUT_MESSAGE: &str = "The stores are busy, please try again later.";
+
+// Helper function to await a store lock for at most `STYLIST_LOCK_TIMEOUT_MS`,
+// so a lock that is never released surfaces as 503 Service Unavailable
+// rather than a request hanging forever
+async fn acquire<G>(lock: impl Future<Output = G>) -> Result<G, HttpResponse> {
+    let timeout_ms: u64 = config::get().lock_timeout_ms;
+    if timeout_ms == 0 {
+        return Ok(lock.await);
+    }
+
+    match tokio::time::timeout(Duration::from_millis(timeout_ms), lock).await {
+        Ok(guard) => Ok(guard),
+        Err(_) => {
+            warn!("Timed out after {} ms waiting for a store lock", timeout_ms);
+            Err(HttpResponse::ServiceUnavailable()
+                .insert_header((header::RETRY_AFTER, "1"))
+                .json(BasicResponse::<String> {
+                    status: false,
+                    message: LOCK_TIMEOUT_MESSAGE.to_string(),
+                    data: None,
+                }))
+        }
+    }
+}
+
+// Acquire a lock through `acquire`, returning the 503 response from the
+// handler when it times out
+macro_rules! acquire {
+    ($lock:expr) => {
+        match acquire($lock).await {
+            Ok(guard) => guard,
+            Err(response) => return response,
+        }
+    };
+}
+
+// Record operations through a `record_*` helper, returning the 500 response
+// from the handler when they could not be logged
+macro_rules! logged {
+    ($record:expr) => {
+        if let Err(response) = $record.await {
+            return response;
+        }
+    };
+}
+
+// Helper function to record operations in the log and count them towards the
+// next autosave. A mutation the log misses would be lost on a crash, so it is
+// answered with 500 Internal Server Error rather than acknowledged.
+async fn record_ops(shared_stores: &SharedStores, ops: Vec<StoreOp>) -> Result<(), HttpResponse> {
+    let logged: Result<(), Error> = shared_stores.append_ops(&ops).await;
+    for _ in &ops {
+        shared_stores.record_mutation();
+    }
+
+    logged.map_err(|e| {
+        error!("Failed to append to the operation log: {}", e);
+        HttpResponse::InternalServerError().json(BasicResponse::<String> {
+            status: false,
+            message: format!(
+                "The change was applied but could not be logged, so it may not survive a restart: {}",
+                e
+            ),
+            data: None,
+        })
+    })
+}
+
+// Helper function to record a single operation, see `record_ops`
+async fn record_op(shared_stores: &SharedStores, op: StoreOp) -> Result<(), HttpResponse> {
+    record_ops(shared_stores, vec![op]).await
+}
+
+// Helper function to describe the current state of an entry as an operation
+fn put_op(kind: StoreKind, store: &InMemoryVectorStore, id: usize) -> Option<StoreOp> {
+    store.get(id).map(|entry| StoreOp::Put {
+        store: kind,
+        entry: entry.clone(),
+    })
+}
+
+// Helper function to record the current state of an entry in the log
+async fn record_put(
+    shared_stores: &SharedStores,
+    kind: StoreKind,
+    store: &InMemoryVectorStore,
+    id: usize,
+) -> Result<(), HttpResponse> {
+    record_ops(shared_stores, put_op(kind, store, id).into_iter().collect()).await
+}
+
+// Helper function to check a request's If-None-Match header against an ETag
+fn etag_matches(request: &HttpRequest, etag: &str) -> bool {
+    request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == etag)
+        })
+}
+
+// Helper function to build the caching headers of a read endpoint
+fn cache_control() -> (header::HeaderName, String) {
+    (
+        header::CACHE_CONTROL,
+        format!(
+            "private, max-age={}, must-revalidate",
+            config::get().cache_max_age_secs
+        ),
+    )
+}
+
+// Helper function to start a successful, cacheable response
+fn cached_ok(etag: &str) -> HttpResponseBuilder {
+    let mut builder = HttpResponse::Ok();
+    builder
+        .insert_header(cache_control())
+        .insert_header((header::ETAG, etag.to_string()));
+    builder
+}
+
+// Helper function to answer a conditional request whose ETag still matches
+fn not_modified(etag: &str) -> HttpResponse {
+    HttpResponse::NotModified()
+        .insert_header(cache_control())
+        .insert_header((header::ETAG, etag.to_string()))
+        .finish()
+}
+
+/// Request structure for uploading images
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImageUploadRequest {
+    pub name: String,
+    /// Gender the clothes are meant for, kept on the entry for gender filters
+    pub gender: Gender,
+    pub image: String, // in base64
+    pub descriptions: Option<Vec<String>>,
+    /// Arbitrary JSON metadata, e.g. price, brand or SKU
+    #[serde(default)]
+    pub metadata: serde_json::Value,
+}
+
+/// Example:
+/// ```json
+/// {
+///     "name": "Blue T-shirt",
+///     "gender": "Male",
+///     "image": "base64_encoded_image_string",
+///     "descriptions": ["casual", "cotton"]
+/// }
+/// ```
+
+/// Request structure for uploading faces
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FaceUploadRequest {
+    pub name: String,
+    pub image: String, // in base64
+    pub descriptions: Option<Vec<String>>,
+    /// Arbitrary JSON metadata returned with the entry
+    #[serde(default)]
+    pub metadata: serde_json::Value,
+}
+
+/// Example:
+/// ```json
+/// {
+///     "name": "Customer 42",
+///     "image": "base64_encoded_image_string",
+///     "descriptions": ["round", "fair"]
+/// }
+/// ```
+
+/// Request body for editing a piece of clothing
+#[derive(Deserialize)]
+struct EditRequest {
+    name: String,
+    image: String, // in base64
+    #[serde(default)]
+    descriptions: Vec<String>,
+    #[serde(default)]
+    metadata: serde_json::Value,
+    /// Version the client read, the edit is rejected if the entry changed since
+    version: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ImageUploadResponse {
+    id: String,
+    success: bool,
+}
+
+/// Request structure for appending a description to an entry
+#[derive(Deserialize)]
+struct DescriptionRequest {
+    description: String,
+}
+
+/// Example:
+/// ```json
+/// {
+///     "description": "summer collection"
+/// }
+/// ```
+
+/// Request structure for fetching several entries at once
+#[derive(Deserialize)]
+struct GetManyRequest {
+    ids: Vec<usize>,
+}
+
+/// Example:
+/// ```json
+/// {
+///     "ids": [3, 1, 42]
+/// }
+/// ```
+
+/// Request structure for tagging several entries at once
+#[derive(Deserialize)]
+struct TagBatchRequest {
+    ids: Vec<usize>,
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+/// Example:
+/// ```json
+/// {
+///     "ids": [3, 1, 42],
+///     "add": ["on_sale"],
+///     "remove": ["new_arrival"]
+/// }
+/// ```
+
+/// Outcome of tagging one entry of a batch
+#[derive(Serialize)]
+struct TagBatchResult {
+    id: usize,
+    success: bool,
+    /// Why the entry was not updated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Request structure for estimating the cost of embedding a batch
+#[derive(Deserialize)]
+struct CostEstimateRequest {
+    count: usize,
+}
+
+/// Example:
+/// ```json
+/// {
+///     "count": 500
+/// }
+/// ```
+
+/// Estimated embedding calls and cost of a batch
+#[derive(Serialize)]
+struct CostEstimate {
+    /// Number of images in the batch
+    count: usize,
+    /// Embedding calls per image
+    calls_per_image: usize,
+    /// Embedding calls for the whole batch
+    calls: usize,
+    /// Price of one call in dollars, from `STYLIST_COST_PER_CALL`
+    cost_per_call: f64,
+    /// Estimated price of the batch in dollars
+    estimated_cost: f64,
+}
+
+/// A labeled query of an evaluation request
+#[derive(Deserialize)]
+struct EvaluationQueryRequest {
+    query_image: String, // in base64
+    relevant_ids: Vec<usize>,
+}
+
+/// Request structure for evaluating the search quality
+#[derive(Deserialize)]
+struct EvaluationRequest {
+    queries: Vec<EvaluationQueryRequest>,
+    k: usize,
+}
+
+/// Example:
+/// ```json
+/// {
+///     "queries": [
+///         {"query_image": "base64_encoded_image_string", "relevant_ids": [3, 7]}
+///     ],
+///     "k": 5
+/// }
+/// ```
+
+/// Outcome of checking an image against the upload limits
+#[derive(Serialize)]
+struct ImageValidation {
+    /// Detected format, e.g. `png`
+    format: Option<String>,
+    width: u32,
+    height: u32,
+    /// Size of the encoded image, in bytes
+    bytes: usize,
+    /// Whether an upload of the image would pass the limits
+    valid: bool,
+    /// Limits the image exceeds, empty when valid
+    errors: Vec<String>,
+}
+
+/// Request structure for embedding an image without storing it
+#[derive(Deserialize)]
+struct EmbedRequest {
+    image: String, // in base64
+}
+
+/// Example:
+/// ```json
+/// {
+///     "image": "base64_encoded_image_string"
+/// }
+/// ```
+
+/// Query parameters for the preprocessing preview endpoint
+#[derive(Deserialize)]
+struct PreviewQuery {
+    format: Option<String>,
+}
+
+/// Query parameters for the embed endpoint
+#[derive(Deserialize)]
+struct EmbedQuery {
+    normalize: Option<bool>,
+}
+
+/// Request structure for the score histogram
+#[derive(Deserialize)]
+struct ScoreHistogramRequest {
+    image: String, // in base64
+    buckets: Option<usize>,
+}
+
+/// Example:
+/// ```json
+/// {
+///     "image": "base64_encoded_image_string",
+///     "buckets": 20
+/// }
+/// ```
+
+/// Query parameters for listing entries
+#[derive(Deserialize)]
+struct ListQuery {
+    sort: Option<String>,
+    dir: Option<String>,
+}
+
+/// Query parameters for listing the most frequent tags
+#[derive(Deserialize)]
+struct TagsQuery {
+    top: Option<usize>,
+}
+
+/// Query parameters for listing the entries created within a time range
+#[derive(Deserialize)]
+struct RangeQuery {
+    /// RFC 3339 timestamp, e.g. `2024-05-01T00:00:00Z`
+    since: Option<String>,
+    /// RFC 3339 timestamp
+    until: Option<String>,
+}
+
+/// Query parameters for listing the nearest neighbors of every entry
+#[derive(Deserialize)]
+struct NeighborsQuery {
+    k: Option<usize>,
+}
+
+/// An entry close to another one, and their similarity
+#[derive(Serialize)]
+struct Neighbor {
+    id: usize,
+    score: f64,
+}
+
+/// The nearest other entries of an entry, best first
+#[derive(Serialize)]
+struct EntryNeighbors {
+    id: usize,
+    neighbors: Vec<Neighbor>,
+}
+
+/// Query parameters for drawing a pair to evaluate
+#[derive(Deserialize)]
+struct EvalPairQuery {
+    k: Option<usize>,
+    seed: Option<u64>,
+}
+
+/// Two similar entries for a reviewer to judge
+#[derive(Serialize)]
+struct EvalPair {
+    query: DataEntry,
+    candidate: DataEntry,
+    score: f64,
+}
+
+/// A reviewer's verdict on a pair drawn by `/api/clothes/eval_pair`
+#[derive(Deserialize)]
+struct VerdictRequest {
+    query_id: usize,
+    candidate_id: usize,
+    similar: bool,
+}
+
+/// A snapshot just taken, without its entries
+#[derive(Serialize)]
+struct SnapshotTaken {
+    label: String,
+    taken_at: u64,
+    entries: usize,
+}
+
+/// A queued upload that was embedded and stored by a retry
+#[derive(Serialize)]
+struct RetriedUpload {
+    dead_letter_id: u64,
+    id: usize,
+}
+
+/// Outcome of retrying the dead-letter queue
+#[derive(Serialize, Default)]
+struct DeadLetterRetry {
+    /// Uploads stored, with their new entry IDs
+    added: Vec<RetriedUpload>,
+    /// Queue IDs of the uploads whose embedding failed again, still queued
+    requeued: Vec<u64>,
+    /// Queue IDs of the uploads dropped for a reason a retry cannot fix,
+    /// e.g. because the image was stored in the meantime
+    dropped: Vec<u64>,
+}
+
+/// A description and the number of entries carrying it
+#[derive(Serialize)]
+struct TagCount {
+    tag: String,
+    count: usize,
+}
+
+/// Query parameters for a fuzzy name search
+#[derive(Deserialize)]
+struct FuzzyNameQuery {
+    q: String,
+    max_distance: Option<usize>,
+}
+
+/// Request structure for importing entries with precomputed vectors
+#[derive(Deserialize)]
+struct ImportRequest {
+    entries: Vec<ImportEntry>,
+}
+
+/// Example:
+/// ```json
+/// {
+///     "entries": [
+///         {
+///             "name": "Blue T-shirt",
+///             "descriptions": ["casual"],
+///             "vector": [1.0, 3.0, 0.0]
+///         }
+///     ]
+/// }
+/// ```
+
+/// Query parameters for the import endpoint
+#[derive(Deserialize)]
+struct ImportQuery {
+    dry_run: Option<bool>,
+}
+
+/// Request structure for deleting the entries similar to an image
+#[derive(Deserialize)]
+struct PurgeRequest {
+    image: String, // in base64
+    top_n: usize,
+    min_score: f64,
+}
+
+/// Example:
+/// ```json
+/// {
+///     "image": "base64_encoded_image_string",
+///     "top_n": 20,
+///     "min_score": 0.95
+/// }
+/// ```
+
+/// Query parameters of destructive endpoints
+#[derive(Deserialize)]
+struct ConfirmQuery {
+    confirm: Option<bool>,
+}
+
+/// Prompts a store embeds with
+#[derive(Serialize)]
+struct PromptsResponse {
+    prompts: Vec<String>,
+    prompt_annotations: Vec<String>,
+    prompt_size: usize,
+}
+
+/// Query parameters for the similarity endpoint
+#[derive(Deserialize)]
+struct SimilarityQuery {
+    include_query_vector: Option<bool>,
+    timing: Option<bool>,
+}
+
+/// Query parameters of the searches that can report their timing
+#[derive(Deserialize)]
+struct TimingQuery {
+    timing: Option<bool>,
+}
+
+/// Query parameters of the upload endpoint
+#[derive(Deserialize)]
+struct UploadQuery {
+    timing: Option<bool>,
+    then_search: Option<bool>,
+    top_n: Option<usize>,
+}
+
+/// A new entry's ID together with its nearest entries, itself included
+#[derive(Serialize)]
+struct UploadWithMatches {
+    id: usize,
+    results: Vec<SearchResult>,
+}
+
+/// Milliseconds spent in each phase of handling a request
+///
+/// Only the routes embedding an image report it, when asked with
+/// `?timing=true`: the clothes upload, `/api/similarity/calculate`,
+/// `/api/clothes/search_farthest` and `/api/face/similarity`. The clustered
+/// search does not, and the vector and collection searches embed nothing.
+#[derive(Serialize, Default)]
+struct Timing {
+    decode_ms: f64,
+    embed_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    search_ms: Option<f64>,
+    total_ms: f64,
+}
+
+/// Response envelope adding an optional timing breakdown to the usual fields
+#[derive(Serialize)]
+struct TimedResponse<T: Serialize> {
+    #[serde(flatten)]
+    response: BasicResponse<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timing: Option<Timing>,
+}
+
+/// Similarity results together with the query vector they were ranked with
+#[derive(Serialize)]
+struct SimilarityWithQuery {
+    results: Vec<SearchResult>,
+    query_vector: Vec<f64>,
+}
+
+/// Response envelope of a search, adding how many results were requested,
+/// returned and filtered out next to the usual fields
+#[derive(Serialize)]
+struct SearchResponse<T: Serialize> {
+    #[serde(flatten)]
+    response: BasicResponse<T>,
+    #[serde(flatten)]
+    counts: SearchCounts,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timing: Option<Timing>,
+}
+
+/// Query parameters for the streaming similarity endpoint
+#[derive(Deserialize)]
+struct StreamQuery {
+    min_score: Option<f64>,
+}
+
+/// Query parameters for the similar entries endpoint
+#[derive(Deserialize)]
+struct SimilarQuery {
+    top_n: Option<usize>,
+    exclude_same_name: Option<bool>,
+}
+
+/// Query parameters for the comparison endpoint
+#[derive(Deserialize)]
+struct CompareQuery {
+    a: usize,
+    b: usize,
+}
+
+/// Query parameters for the centroid endpoint
+#[derive(Deserialize)]
+struct CentroidQuery {
+    per_entry: Option<bool>,
+}
+
+/// Similarity of a single entry to the store centroid
+#[derive(Serialize)]
+struct EntrySimilarity {
+    id: usize,
+    score: f64,
+}
+
+/// Mean vector of a store, with the similarity of each entry when requested
+#[derive(Serialize)]
+struct CentroidResponse {
+    centroid: Vec<f64>,
+    norm: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    per_entry: Option<Vec<EntrySimilarity>>,
+}
+
+/// Query parameters for the vector truncation endpoint
+#[derive(Deserialize)]
+struct TruncateQuery {
+    dims: usize,
+}
+
+/// Outcome of an import, listing the validation result of every entry
+#[derive(Serialize)]
+struct ImportReport {
+    dry_run: bool,
+    total: usize,
+    valid: usize,
+    invalid: usize,
+    results: Vec<ImportValidation>,
+    ids: Vec<usize>,
+    skipped: Vec<usize>,
+    coerced: Vec<usize>,
+}
+
+/// Query parameters selecting one of the stores
+#[derive(Deserialize)]
+struct StoreQuery {
+    store: Option<StoreKind>,
+}
+
+/// Request body for moving an entry to another store
+#[derive(Deserialize)]
+struct MoveRequest {
+    from: StoreKind,
+    to: StoreKind,
+    id: usize,
+}
+
+/// Public view of the service configuration
+#[derive(Serialize)]
+struct ConfigResponse {
+    clothes_prompt_sets: Vec<String>,
+}
+
+/// Estimated bytes used by each store's entries
+#[derive(Serialize)]
+struct MemoryUsage {
+    clothes: usize,
+    face: usize,
+    total: usize,
+}
+
+/// Entry counts before and after replacing a store
+#[derive(Serialize)]
+struct ReplaceStoreResponse {
+    old_entries: usize,
+    new_entries: usize,
+}
+
+/// Query parameters for fetching a stored image
+#[derive(Deserialize)]
+struct ImageQuery {
+    w: Option<u32>,
+    h: Option<u32>,
+}
+
+/// Request body for completing a collection of entries
+#[derive(Deserialize)]
+struct CollectionSearchRequest {
+    ids: Vec<usize>,
+    top_n: usize,
+}
+
+/// Request body for searching with a precomputed vector
+#[derive(Deserialize)]
+struct VectorSearchRequest {
+    vector: Vec<f64>,
+    top_n: usize,
+}
+
+/// Request structure for similarity search
+#[derive(Deserialize)]
+struct SimilarityRequest {
+    user_image: String,
+    top_n: usize,
+    /// Whether to L2-normalize the query, defaults to the store's setting
+    normalize_query: Option<bool>,
+    /// IDs of entries to leave out of the results
+    #[serde(default)]
+    exclude_ids: Vec<usize>,
+    /// Descriptions every result must have
+    #[serde(default)]
+    tags: Vec<String>,
+    /// JSON every result's metadata must contain
+    metadata_filter: Option<serde_json::Value>,
+    /// Half-life, in seconds, of a decay favouring recently added entries:
+    /// scores are multiplied by `0.5^(age / half_life)`
+    decay_half_life_secs: Option<u64>,
+    /// Shuffle results whose scores are within this epsilon of each other,
+    /// a negative or NaN epsilon answers 400
+    shuffle_ties: Option<f64>,
+    /// Seed of the tie shuffle, for reproducible orders
+    shuffle_seed: Option<u64>,
+    /// Gender every result must be meant for, unisex clothes match any gender
+    gender: Option<Gender>,
+}
+
+/// Example:
+/// ```json
+/// {
+///     "user_image": "base64_encoded_image_string",
+///     "top_n": 5,
+///     "normalize_query": true,
+///     "exclude_ids": [3, 7],
+///     "tags": ["blue"],
+///     "metadata_filter": {"brand": "X"},
+///     "decay_half_life_secs": 604800,
+///     "shuffle_ties": 0.01,
+///     "shuffle_seed": 42,
+///     "gender": "Female"
+/// }
+/// ```
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BasicResponse<T: Serialize> {
+    pub status: bool,
+    pub message: String,
+    pub data: Option<T>,
+}
+
+/// Upload a new piece of clothing
+///
+/// # HTTP Request
+/// POST /api/clothes/upload
+///
+/// Uploading an image that is already stored follows `STYLIST_DUPLICATE_STRATEGY`:
+/// `reject` answers 409 Conflict, `merge` appends the new descriptions to the
+/// existing entry, and `allow` (the default) stores a new entry.
+///
+/// # Request Body
+/// JSON object containing name, gender, base64 encoded image, and optionally
+/// descriptions and arbitrary JSON `metadata` returned with the entry
+///
+/// # Query Parameters
+/// * `timing` - When `true`, the response holds a `timing` object with the
+///   `decode_ms`, `embed_ms` and `total_ms` spent on the upload
+/// * `then_search` - When `true`, search with the new entry's vector right
+///   away and return `{id, results}` with the search counts, instead of the
+///   bare ID. The image is not embedded a second time. The new entry is part
+///   of the search, so it comes back as its own top match.
+/// * `top_n` - Number of results of `then_search`, defaults to 10
+///
+/// Uploads whose embedding fails are queued, see `GET /api/clothes/dlq`.
+#[post("/api/clothes/upload", wrap = "from_fn(rate_limit)")]
+async fn upload_clothes(
+    shared_stores: Data<Arc<SharedStores>>,
+    counters: Data<RequestCounters>,
+    dead_letters: Data<StdMutex<DeadLetterQueue>>,
+    query: web::Query<UploadQuery>,
+    request: Json<ImageUploadRequest>,
+) -> impl Responder {
+    let started: Instant = Instant::now();
+    counters.record_upload();
+    info!(
+        "Received upload request for clothes with name: {}",
+        request.name
+    );
+    if query.then_search.unwrap_or(false) {
+        if let Some(rejection) = reject_large_top_n(query.top_n.unwrap_or(10)) {
+            return rejection;
+        }
+    }
+
+    let mut clothes_store = acquire!(shared_stores.clothes.write());
+
+    let mut timing = Timing::default();
+    let decode_started: Instant = Instant::now();
+    let decoded = decode_validated_image(&request.image);
+    timing.decode_ms = millis(decode_started.elapsed());
+
+    match decoded {
+        Ok(result) => {
+            let descriptions: Vec<String> = request.descriptions.clone().unwrap_or_default();
+
+            let embed_started: Instant = Instant::now();
+            let added = clothes_store
+                .add_with_metadata(
+                    &request.name,
+                    descriptions,
+                    result,
+                    request.metadata.clone(),
+                    Some(request.gender),
+                )
+                .await;
+            timing.embed_ms = millis(embed_started.elapsed());
+
+            match added {
+                Ok(id) => {
+                    info!("Successfully added clothes: {} (id {})", request.name, id);
+                    logged!(record_put(
+                        &shared_stores,
+                        StoreKind::Clothes,
+                        &clothes_store,
+                        id
+                    ));
+
+                    if query.then_search.unwrap_or(false) {
+                        counters.record_search();
+                        let search_started: Instant = Instant::now();
+                        let options = SearchOptions::top_n(query.top_n.unwrap_or(10));
+                        let searched: Result<(Vec<SearchResult>, SearchCounts), Error> =
+                            match clothes_store.get(id) {
+                                Some(entry) => clothes_store.rank(&entry.vector, &options),
+                                None => Err(DataEntryErrors::NoDataWasFound.into()),
+                            };
+                        timing.search_ms = Some(millis(search_started.elapsed()));
+                        timing.total_ms = millis(started.elapsed());
+
+                        return match searched {
+                            Ok((results, counts)) => HttpResponse::Ok().json(SearchResponse {
+                                response: BasicResponse {
+                                    status: true,
+                                    message: "Clothes added successfully. Returning the entry id and its matches."
+                                        .to_string(),
+                                    data: Some(UploadWithMatches { id, results }),
+                                },
+                                counts,
+                                timing: query.timing.unwrap_or(false).then_some(timing),
+                            }),
+                            Err(e) => {
+                                error!("Failed to search after adding clothes {}: {}", id, e);
+                                HttpResponse::build(status_for_error(&e)).json(
+                                    BasicResponse::<usize> {
+                                        status: false,
+                                        message: format!(
+                                            "Clothes added with id {}, but the search failed: {}",
+                                            id, e
+                                        ),
+                                        data: Some(id),
+                                    },
+                                )
+                            }
+                        };
+                    }
+
+                    timing.total_ms = millis(started.elapsed());
+                    HttpResponse::Ok().json(TimedResponse {
+                        response: BasicResponse {
+                            status: true,
+                            message: "Clothes added successfully. Returning the entry id."
+                                .to_string(),
+                            data: Some(id),
+                        },
+                        timing: query.timing.unwrap_or(false).then_some(timing),
+                    })
+                }
+                Err(error) => {
+                    error!("Failed to add clothes to vector store: {}", error);
+                    if is_embedding_failure(&error) {
+                        let dead_letter_id: u64 =
+                            dead_letters.lock().unwrap().push(DeadLetter::new(
+                                request.name.clone(),
+                                request.gender,
+                                request.descriptions.clone().unwrap_or_default(),
+                                request.metadata.clone(),
+                                request.image.clone(),
+                                &error,
+                            ));
+                        warn!(
+                            "Queued the upload of {} for a retry as {}",
+                            request.name, dead_letter_id
+                        );
+                    }
+                    HttpResponse::build(status_for_error(&error)).json(BasicResponse::<String> {
+                        status: false,
+                        message: error.to_string(),
+                        data: None,
+                    })
+                }
+            }
+        }
+        Err(error) => {
+            error!("Failed to decode base64 image: {}", error);
+            HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: error.to_string(),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Get all clothes
+///
+/// Entries are returned in insertion order unless `sort` is given. The
+/// response carries an `ETag`, send it back in `If-None-Match` to get a
+/// 304 Not Modified while no store was changed.
+///
+/// # HTTP Request
+/// GET /api/clothes/get
+///
+/// # Query Parameters
+/// * `sort` - Sort by `id`, `name` or `recent` (creation time)
+/// * `dir` - Sort `asc` (default) or `desc`
+#[get("/api/clothes/get")]
+async fn get_clothes(
+    http_request: HttpRequest,
+    shared_stores: Data<Arc<SharedStores>>,
+    query: web::Query<ListQuery>,
+) -> impl Responder {
+    info!("Handling request to get all clothes");
+
+    let key: Option<SortKey> = match query.sort.as_deref() {
+        None => None,
+        Some("id") => Some(SortKey::Id),
+        Some("name") => Some(SortKey::Name),
+        Some("recent") => Some(SortKey::Recent),
+        Some(other) => {
+            warn!("Invalid sort key provided: {}", other);
+            return HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: format!(
+                    "Unknown sort key '{}', expected one of: id, name, recent",
+                    other
+                ),
+                data: None,
+            });
+        }
+    };
+
+    let direction: SortDirection = match query.dir.as_deref() {
+        None | Some("asc") => SortDirection::Ascending,
+        Some("desc") => SortDirection::Descending,
+        Some(other) => {
+            warn!("Invalid sort direction provided: {}", other);
+            return HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: format!("Unknown sort direction '{}', expected asc or desc", other),
+                data: None,
+            });
+        }
+    };
+
+    let clothes_store = acquire!(shared_stores.clothes.read());
+    let etag: String = shared_stores.etag(&clothes_store);
+    if etag_matches(&http_request, &etag) {
+        return not_modified(&etag);
+    }
+
+    match key {
+        Some(key) => cached_ok(&etag).json(clothes_store.get_all_sorted(key, direction)),
+        None => cached_ok(&etag).json(clothes_store.get_all()),
+    }
+}
+
+/// Edit a piece of clothing, keeping its ID
+///
+/// The entry is re-embedded from the new image. Send the `version` read with
+/// the entry to make sure no other client edited it since: a stale version
+/// answers 409 Conflict and leaves the entry as it is.
+///
+/// # HTTP Request
+/// POST /api/clothes/edit/{id}
+///
+/// # Request Body
+/// JSON object containing name, base64 encoded image, and optionally
+/// descriptions, `metadata` and the `version` the client read
+///
+/// # Returns
+/// The entry's new version
+#[post("/api/clothes/edit/{id}", wrap = "from_fn(rate_limit)")]
+async fn edit_clothes(
+    id: web::Path<usize>,
+    shared_stores: Data<Arc<SharedStores>>,
+    thumbnails: Data<StdMutex<ThumbnailCache>>,
+    request: Json<EditRequest>,
+) -> impl Responder {
+    let id: usize = id.into_inner();
+    let EditRequest {
+        name,
+        image,
+        descriptions,
+        metadata,
+        version,
+    } = request.into_inner();
+    info!("Received edit request for clothes id: {}", id);
+
+    let image: DynamicImage = match decode_validated_image(&image) {
+        Ok(image) => image,
+        Err(e) => {
+            error!("Failed to decode base64 image: {}", e);
+            return HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: e.to_string(),
+                data: None,
+            });
+        }
+    };
+    let data_entry = DataEntry {
+        id,
+        name,
+        vector: vec![],
+        descriptions,
+        image: None,
+        created_at: 0,
+        image_hash: None,
+        metadata,
+        version: 0,
+        gender: None,
+    };
+
+    let mut clothes_store = acquire!(shared_stores.clothes.write());
+
+    match clothes_store
+        .edit_versioned(image, data_entry, version)
+        .await
+    {
+        Ok(new_version) => {
+            info!("Successfully edited clothes with id: {}", id);
+            thumbnails.lock().unwrap().invalidate(id);
+            logged!(record_put(
+                &shared_stores,
+                StoreKind::Clothes,
+                &clothes_store,
+                id
+            ));
+            HttpResponse::Ok().json(BasicResponse {
+                status: true,
+                message: "Clothes edited successfully. Returning the new version.".to_string(),
+                data: Some(new_version),
+            })
+        }
+        Err(e) => {
+            error!("Failed to edit clothes with id {}: {}", id, e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to edit clothes: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Get several pieces of clothing by ID
+///
+/// # HTTP Request
+/// POST /api/clothes/get_many
+///
+/// # Request Body
+/// JSON object containing the list of IDs. Entries are returned in request
+/// order, with `null` for IDs that do not exist.
+#[post("/api/clothes/get_many")]
+async fn get_many_clothes(
+    shared_stores: Data<Arc<SharedStores>>,
+    request: Json<GetManyRequest>,
+) -> impl Responder {
+    info!("Handling request to get {} clothes", request.ids.len());
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Returning the entries in request order.".to_string(),
+        data: Some(clothes_store.get_many(&request.ids)),
+    })
+}
+
+/// Get the most frequent descriptions across the stored clothes
+///
+/// Lets clients build tag clouds and filters without scanning every entry.
+/// With `STYLIST_NORMALIZE_TAGS`, descriptions are counted in their
+/// normalized form.
+///
+/// # HTTP Request
+/// GET /api/clothes/tags
+///
+/// # Query Parameters
+/// * `top` - Number of tags to return, defaults to 20
+#[get("/api/clothes/tags")]
+async fn get_clothes_tags(
+    shared_stores: Data<Arc<SharedStores>>,
+    query: web::Query<TagsQuery>,
+) -> impl Responder {
+    let top: usize = query.top.unwrap_or(20);
+    info!(
+        "Handling request to get the {} most frequent clothes tags",
+        top
+    );
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    let tags: Vec<TagCount> = clothes_store
+        .tag_frequencies()
+        .into_iter()
+        .take(top)
+        .map(|(tag, count)| TagCount { tag, count })
+        .collect();
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Returning the most frequent tags, most frequent first.".to_string(),
+        data: Some(tags),
+    })
+}
+
+/// Find clothes by name, tolerating typos
+///
+/// Complements image search with a text lookup: names are compared ignoring
+/// case, by Levenshtein distance. Every entry is compared, so the cost grows
+/// with the store.
+///
+/// # HTTP Request
+/// GET /api/clothes/search_name_fuzzy
+///
+/// # Query Parameters
+/// * `q` - Name to look for
+/// * `max_distance` - Largest edit distance of a match, defaults to 2
+///
+/// # Returns
+/// The matches with their `distance`, closest first
+#[get("/api/clothes/search_name_fuzzy")]
+async fn search_clothes_name_fuzzy(
+    shared_stores: Data<Arc<SharedStores>>,
+    query: web::Query<FuzzyNameQuery>,
+) -> impl Responder {
+    let max_distance: usize = query.max_distance.unwrap_or(2);
+    info!(
+        "Handling fuzzy name search for {:?} within distance {}",
+        query.q, max_distance
+    );
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Returning the clothes with matching names, closest first.".to_string(),
+        data: Some(clothes_store.find_by_name_fuzzy(&query.q, max_distance)),
+    })
+}
+
+/// Get clothes whose vector is empty or has the wrong number of dimensions
+///
+/// Supports conditional requests like `GET /api/clothes/get`.
+///
+/// # HTTP Request
+/// GET /api/clothes/invalid
+#[get("/api/clothes/invalid")]
+async fn get_invalid_clothes(
+    http_request: HttpRequest,
+    shared_stores: Data<Arc<SharedStores>>,
+) -> impl Responder {
+    info!("Handling request to get clothes with invalid vectors");
+    let clothes_store = acquire!(shared_stores.clothes.read());
+    let etag: String = shared_stores.etag(&clothes_store);
+    if etag_matches(&http_request, &etag) {
+        return not_modified(&etag);
+    }
+
+    cached_ok(&etag).json(clothes_store.invalid_entries())
+}
+
+/// Get clothes that no search has returned yet
+///
+/// Only available with `STYLIST_TRACK_MATCHES`, otherwise this answers 409
+/// Conflict. Useful to find items to remove or re-photograph.
+///
+/// # HTTP Request
+/// GET /api/clothes/unmatched
+#[get("/api/clothes/unmatched")]
+async fn get_unmatched_clothes(shared_stores: Data<Arc<SharedStores>>) -> impl Responder {
+    info!("Handling request to get clothes that never matched a search");
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    if !clothes_store.tracks_matches() {
+        warn!("Cannot list unmatched clothes while match tracking is disabled");
+        return HttpResponse::Conflict().json(BasicResponse::<String> {
+            status: false,
+            message: "Match tracking is disabled, set STYLIST_TRACK_MATCHES to enable it."
+                .to_string(),
+            data: None,
+        });
+    }
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Returning the clothes no search has returned.".to_string(),
+        data: Some(clothes_store.unmatched_entries()),
+    })
+}
+
+/// Take a labeled snapshot of the clothes store's entries
+///
+/// The snapshot holds the ID, image hash and version of every entry, and
+/// stays in memory until the process exits or `STYLIST_MAX_SNAPSHOTS` newer
+/// ones are taken. Taking a snapshot under an existing label replaces it.
+///
+/// # HTTP Request
+/// POST /api/clothes/snapshot/{label}
+///
+/// # URL Parameters
+/// * `label` - Name to take the snapshot under
+#[post("/api/clothes/snapshot/{label}")]
+async fn snapshot_clothes(
+    label: web::Path<String>,
+    shared_stores: Data<Arc<SharedStores>>,
+    snapshots: Data<StdMutex<Snapshots>>,
+) -> impl Responder {
+    let label: String = label.into_inner();
+    info!("Handling request to snapshot the clothes as '{}'", label);
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    let snapshot: EntrySnapshot = EntrySnapshot::capture(clothes_store.iter());
+    let taken = SnapshotTaken {
+        label: label.clone(),
+        taken_at: snapshot.taken_at,
+        entries: snapshot.entries.len(),
+    };
+    for evicted in snapshots.lock().unwrap().insert(label, snapshot) {
+        info!("Dropped the oldest snapshot '{}'", evicted);
+    }
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Snapshot taken successfully.".to_string(),
+        data: Some(taken),
+    })
+}
+
+/// Compare two snapshots of the clothes store
+///
+/// Entries count as changed when their image hash or version differs, i.e.
+/// when they were re-uploaded or edited between the snapshots.
+///
+/// # HTTP Request
+/// GET /api/clothes/diff/{from}/{to}
+///
+/// # URL Parameters
+/// * `from` - Label of the earlier snapshot
+/// * `to` - Label of the later snapshot
+#[get("/api/clothes/diff/{from}/{to}")]
+async fn diff_clothes_snapshots(
+    labels: web::Path<(String, String)>,
+    snapshots: Data<StdMutex<Snapshots>>,
+) -> impl Responder {
+    let (from, to) = labels.into_inner();
+    info!(
+        "Handling request to diff the clothes snapshots '{}' and '{}'",
+        from, to
+    );
+    let snapshots = snapshots.lock().unwrap();
+
+    match (snapshots.get(&from), snapshots.get(&to)) {
+        (Some(earlier), Some(later)) => HttpResponse::Ok().json(BasicResponse {
+            status: true,
+            message: format!("Returning the changes from '{}' to '{}'.", from, to),
+            data: Some(diff_snapshots(earlier, later)),
+        }),
+        (earlier, _) => {
+            let missing: &str = if earlier.is_none() { &from } else { &to };
+            warn!("No snapshot is labeled '{}'", missing);
+            HttpResponse::NotFound().json(BasicResponse::<String> {
+                status: false,
+                message: format!("No snapshot is labeled '{}'", missing),
+                data: None,
+            })
+        }
+    }
+}
+
+/// List the uploads whose embedding failed
+///
+/// Up to `STYLIST_MAX_DEAD_LETTERS` uploads are kept, oldest dropped first,
+/// and persisted to `STYLIST_DEAD_LETTER_PATH` when it is set. Each holds the
+/// uploaded name, gender, descriptions, metadata and base64 image, and the
+/// error of its last attempt.
+///
+/// # HTTP Request
+/// GET /api/clothes/dlq
+#[get("/api/clothes/dlq")]
+async fn list_dead_letters(dead_letters: Data<StdMutex<DeadLetterQueue>>) -> impl Responder {
+    info!("Handling request to list the failed uploads");
+    let letters: Vec<DeadLetter> = dead_letters.lock().unwrap().list();
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: format!("Returning {} failed uploads.", letters.len()),
+        data: Some(letters),
+    })
+}
+
+/// Retry every upload whose embedding failed
+///
+/// Each queued upload is embedded without holding the store, then stored in
+/// queue order, as by `POST /api/clothes/upload`, and only then removed from
+/// the queue, so a failed or interrupted retry loses nothing. Uploads failing
+/// to embed again stay queued under their IDs with the new error. Uploads
+/// that cannot succeed on a retry, e.g. because the image was stored in the
+/// meantime and duplicates are rejected, are dropped.
+///
+/// # HTTP Request
+/// POST /api/clothes/dlq/retry
+#[post("/api/clothes/dlq/retry", wrap = "from_fn(rate_limit)")]
+async fn retry_dead_letters(
+    shared_stores: Data<Arc<SharedStores>>,
+    dead_letters: Data<StdMutex<DeadLetterQueue>>,
+) -> impl Responder {
+    let letters: Vec<DeadLetter> = dead_letters.lock().unwrap().list();
+    info!("Handling request to retry {} failed uploads", letters.len());
+
+    let embedder: InMemoryVectorStore = acquire!(shared_stores.clothes.read()).without_entries();
+    let mut retry = DeadLetterRetry::default();
+    for letter in letters {
+        let embedded: Result<(DynamicImage, Vec<f64>), Error> =
+            match decode_validated_image(&letter.image) {
+                Ok(image) => embedder
+                    .embed_only(image.clone())
+                    .await
+                    .map(|vector| (image, vector)),
+                Err(e) => Err(e),
+            };
+
+        let added: Result<usize, Error> = match embedded {
+            Ok((image, vector)) => {
+                let mut clothes_store = acquire!(shared_stores.clothes.write());
+                // a concurrent retry may have stored the upload already
+                if !dead_letters.lock().unwrap().contains(letter.id) {
+                    continue;
+                }
+                let added: Result<usize, Error> = clothes_store
+                    .add_with_vector(
+                        &letter.name,
+                        letter.descriptions.clone(),
+                        image,
+                        letter.metadata.clone(),
+                        Some(letter.gender),
+                        vector,
+                    )
+                    .await;
+                if let Ok(id) = added {
+                    logged!(record_put(
+                        &shared_stores,
+                        StoreKind::Clothes,
+                        &clothes_store,
+                        id
+                    ));
+                }
+                added
+            }
+            Err(e) => Err(e),
+        };
+
+        match added {
+            Ok(id) => {
+                info!("Stored the failed upload {} as {}", letter.id, id);
+                dead_letters.lock().unwrap().remove(letter.id);
+                retry.added.push(RetriedUpload {
+                    dead_letter_id: letter.id,
+                    id,
+                });
+            }
+            Err(error) if is_embedding_failure(&error) => {
+                warn!("Retrying the failed upload {} failed: {}", letter.id, error);
+                if dead_letters
+                    .lock()
+                    .unwrap()
+                    .record_failure(letter.id, &error)
+                {
+                    retry.requeued.push(letter.id);
+                }
+            }
+            Err(error) => {
+                warn!("Dropping the failed upload {}: {}", letter.id, error);
+                dead_letters.lock().unwrap().remove(letter.id);
+                retry.dropped.push(letter.id);
+            }
+        }
+    }
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: format!(
+            "Stored {} failed uploads, {} failed again and {} were dropped.",
+            retry.added.len(),
+            retry.requeued.len(),
+            retry.dropped.len()
+        ),
+        data: Some(retry),
+    })
+}
+
+/// Get the clothes created within a time range
+///
+/// The range includes `since` and leaves out `until`, so consecutive ranges
+/// never return an entry twice, e.g. for incremental syncs. Entries stored
+/// before creation times were recorded count as created at the epoch.
+///
+/// # HTTP Request
+/// GET /api/clothes/range
+///
+/// # Query Parameters
+/// * `since` - RFC 3339 timestamp of the start of the range, unbounded when unset
+/// * `until` - RFC 3339 timestamp of the end of the range, unbounded when unset
+#[get("/api/clothes/range")]
+async fn get_clothes_range(
+    shared_stores: Data<Arc<SharedStores>>,
+    query: web::Query<RangeQuery>,
+) -> impl Responder {
+    info!("Handling request to get clothes created within a time range");
+    let (since, until) = match (
+        parse_timestamp(query.since.as_deref()),
+        parse_timestamp(query.until.as_deref()),
+    ) {
+        (Ok(since), Ok(until)) => (since.unwrap_or(0), until),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("Invalid time range provided: {}", e);
+            return HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: format!("Invalid timestamp, expected RFC 3339: {}", e),
+                data: None,
+            });
+        }
+    };
+
+    let clothes_store = acquire!(shared_stores.clothes.read());
+    let entries: Vec<DataEntry> = clothes_store.created_between(since, until);
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: format!(
+            "Returning {} entries created within the range.",
+            entries.len()
+        ),
+        data: Some(entries),
+    })
+}
+
+// Helper function to parse an optional RFC 3339 timestamp into seconds since
+// the Unix epoch, clamping earlier times to the epoch
+fn parse_timestamp(timestamp: Option<&str>) -> Result<Option<u64>, Error> {
+    match timestamp {
+        Some(timestamp) => {
+            let parsed: DateTime<FixedOffset> = DateTime::parse_from_rfc3339(timestamp)?;
+            Ok(Some(parsed.timestamp().max(0) as u64))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Get the nearest other clothes of every piece of clothing
+///
+/// Meant for building similarity graphs offline. Every pair of entries is
+/// compared, which is slow for large stores, so it runs on a blocking thread
+/// and requires the API token. The result is cached until the store changes.
+///
+/// # HTTP Request
+/// GET /api/clothes/neighbors
+///
+/// # Query Parameters
+/// * `k` - Number of neighbors per entry, defaults to 5 and is capped by
+///   `STYLIST_MAX_TOP_N`
+#[get("/api/clothes/neighbors", wrap = "from_fn(require_token)")]
+async fn get_clothes_neighbors(
+    shared_stores: Data<Arc<SharedStores>>,
+    query: web::Query<NeighborsQuery>,
+) -> impl Responder {
+    let k: usize = query.k.unwrap_or(5);
+    info!(
+        "Handling request to get the {} nearest neighbors of every clothes",
+        k
+    );
+    if let Some(rejection) = reject_large_top_n(k) {
+        return rejection;
+    }
+    let clothes_store = acquire!(shared_stores.clothes.clone().read_owned());
+
+    let neighbors: Neighbors =
+        match tokio::task::spawn_blocking(move || clothes_store.neighbors(k)).await {
+            Ok(neighbors) => neighbors,
+            Err(e) => {
+                error!("Computing the clothes neighbors panicked: {}", e);
+                return HttpResponse::InternalServerError().json(BasicResponse::<String> {
+                    status: false,
+                    message: format!("Failed to compute the neighbors: {}", e),
+                    data: None,
+                });
+            }
+        };
+    let graph: Vec<EntryNeighbors> = neighbors
+        .into_iter()
+        .map(|(id, neighbors)| EntryNeighbors {
+            id,
+            neighbors: neighbors
+                .into_iter()
+                .map(|(id, score)| Neighbor { id, score })
+                .collect(),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: format!("Returning the {} nearest neighbors of every entry.", k),
+        data: Some(graph),
+    })
+}
+
+/// Draw a random piece of clothing and one of its nearest neighbors
+///
+/// Meant for human evaluation: a reviewer judges whether the two are
+/// actually similar and posts the verdict to `/api/clothes/eval_verdict`.
+///
+/// # HTTP Request
+/// GET /api/clothes/eval_pair
+///
+/// # Query Parameters
+/// * `k` - Number of nearest neighbors to draw the candidate from, defaults to 5
+/// * `seed` - Seed of the draw, for reproducible pairs
+#[get("/api/clothes/eval_pair")]
+async fn get_clothes_eval_pair(
+    shared_stores: Data<Arc<SharedStores>>,
+    query: web::Query<EvalPairQuery>,
+) -> impl Responder {
+    let k: usize = query.k.unwrap_or(5);
+    info!("Handling request for a clothes pair to evaluate");
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    let pair =
+        clothes_store
+            .random_pair(k, query.seed)
+            .and_then(|(query_id, candidate_id, score)| {
+                Some(EvalPair {
+                    query: clothes_store.get(query_id)?.clone(),
+                    candidate: clothes_store.get(candidate_id)?.clone(),
+                    score,
+                })
+            });
+
+    match pair {
+        Some(pair) => HttpResponse::Ok().json(BasicResponse {
+            status: true,
+            message: "Returning a pair to evaluate.".to_string(),
+            data: Some(pair),
+        }),
+        None => HttpResponse::NotFound().json(BasicResponse::<String> {
+            status: false,
+            message: "At least two entries are needed to draw a pair.".to_string(),
+            data: None,
+        }),
+    }
+}
+
+/// Record a reviewer's verdict on a pair of clothes
+///
+/// Verdicts are kept in memory and saved with the store, to be aggregated
+/// into ground-truth labels later.
+///
+/// # HTTP Request
+/// POST /api/clothes/eval_verdict
+///
+/// # Request Body
+/// ```json
+/// {
+///     "query_id": 1,
+///     "candidate_id": 2,
+///     "similar": true
+/// }
+/// ```
+#[post("/api/clothes/eval_verdict")]
+async fn record_clothes_eval_verdict(
+    shared_stores: Data<Arc<SharedStores>>,
+    request: Json<VerdictRequest>,
+) -> impl Responder {
+    info!(
+        "Handling request to record a verdict on clothes {} and {}",
+        request.query_id, request.candidate_id
+    );
+    let mut clothes_store = acquire!(shared_stores.clothes.write());
+
+    match clothes_store.record_verdict(request.query_id, request.candidate_id, request.similar) {
+        Ok(verdict) => {
+            logged!(record_op(
+                &shared_stores,
+                StoreOp::Verdict {
+                    store: StoreKind::Clothes,
+                    verdict: verdict.clone(),
+                },
+            ));
+            HttpResponse::Ok().json(BasicResponse {
+                status: true,
+                message: "Verdict recorded successfully.".to_string(),
+                data: Some(verdict),
+            })
+        }
+        Err(e) => {
+            error!("Failed to record the verdict: {}", e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to record the verdict: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Get the clothes most similar to a stored piece of clothing
+///
+/// The entry itself is never returned.
+///
+/// # HTTP Request
+/// GET /api/clothes/similar/{id}
+///
+/// # URL Parameters
+/// * `id` - The ID of the clothing item to compare with
+///
+/// # Query Parameters
+/// * `top_n` - Number of results to return, defaults to 10
+/// * `exclude_same_name` - When `true`, leave out entries with the same name
+#[get("/api/clothes/similar/{id}")]
+async fn get_similar_clothes(
+    id: web::Path<usize>,
+    shared_stores: Data<Arc<SharedStores>>,
+    query: web::Query<SimilarQuery>,
+) -> impl Responder {
+    let id: usize = id.into_inner();
+    let top_n: usize = query.top_n.unwrap_or(10);
+    info!(
+        "Handling request for the {} clothes most similar to id: {}",
+        top_n, id
+    );
+    if let Some(rejection) = reject_large_top_n(top_n) {
+        return rejection;
+    }
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    match clothes_store.similar_to(id, top_n, query.exclude_same_name.unwrap_or(false)) {
+        Ok(results) => HttpResponse::Ok().json(BasicResponse {
+            status: true,
+            message: "Search operation succeeded.".to_string(),
+            data: Some(results),
+        }),
+        Err(e) => {
+            error!("Failed to find clothes similar to id {}: {}", id, e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Error searching similar clothes: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Get the stored vector of a piece of clothing split by the prompts that
+/// produced it
+///
+/// `contributions` maps each prompt to the values it wrote, and is `null`
+/// when the vector does not have one `prompt_size` slice per prompt. The
+/// vector and prompts are always returned, so clients can align them by
+/// index themselves. As prompts may be proprietary, this route requires the
+/// API token like `/api/clothes/prompts`.
+///
+/// # HTTP Request
+/// GET /api/clothes/{id}/explain
+///
+/// # URL Parameters
+/// * `id` - The ID of the clothing item to explain
+#[get("/api/clothes/{id}/explain", wrap = "from_fn(require_token)")]
+async fn explain_clothes(
+    id: web::Path<usize>,
+    shared_stores: Data<Arc<SharedStores>>,
+) -> impl Responder {
+    let id: usize = id.into_inner();
+    info!(
+        "Handling request to explain the vector of clothes id: {}",
+        id
+    );
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    match clothes_store.explain(id) {
+        Ok(explanation) => HttpResponse::Ok().json(BasicResponse {
+            status: true,
+            message: "Returning the vector alongside its prompts.".to_string(),
+            data: Some(explanation),
+        }),
+        Err(e) => {
+            error!("Failed to explain clothes id {}: {}", id, e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Error explaining clothes: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Compare the vectors of two stored pieces of clothing
+///
+/// Returns the cosine similarity of both vectors, the score searches would
+/// give them, which differs slightly with `STYLIST_SIMD`, and their norms.
+/// Answers 404 Not Found if either entry does not exist.
+///
+/// # HTTP Request
+/// GET /api/clothes/compare?a=3&b=7
+///
+/// # Query Parameters
+/// * `a` - ID of the first clothing item
+/// * `b` - ID of the second clothing item
+#[get("/api/clothes/compare")]
+async fn compare_clothes(
+    shared_stores: Data<Arc<SharedStores>>,
+    query: web::Query<CompareQuery>,
+) -> impl Responder {
+    info!(
+        "Handling request to compare clothes {} and {}",
+        query.a, query.b
+    );
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    match clothes_store.compare(query.a, query.b) {
+        Ok(comparison) => HttpResponse::Ok().json(BasicResponse {
+            status: true,
+            message: "Comparison succeeded.".to_string(),
+            data: Some(comparison),
+        }),
+        Err(e) => {
+            error!(
+                "Failed to compare clothes {} and {}: {}",
+                query.a, query.b, e
+            );
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Error comparing clothes: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Get the prompts the clothes store embeds with
+///
+/// Prompts may be proprietary, so this route requires the API token as a
+/// bearer token, and is disabled while `STYLIST_API_TOKEN` is not set.
+///
+/// # HTTP Request
+/// GET /api/clothes/prompts
+#[get("/api/clothes/prompts", wrap = "from_fn(require_token)")]
+async fn get_clothes_prompts(shared_stores: Data<Arc<SharedStores>>) -> impl Responder {
+    info!("Handling request to get the clothes prompts");
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Returning the prompts in dimension order.".to_string(),
+        data: Some(PromptsResponse {
+            prompts: clothes_store.prompts().to_vec(),
+            prompt_annotations: clothes_store.prompt_annotations().to_vec(),
+            prompt_size: clothes_store.prompt_size(),
+        }),
+    })
+}
+
+/// Export the clothes store's configuration, without its entries
+///
+/// The prompts are part of it, so this route requires the API token like
+/// `/api/clothes/prompts`.
+///
+/// # HTTP Request
+/// GET /api/clothes/config/export
+#[get("/api/clothes/config/export", wrap = "from_fn(require_token)")]
+async fn export_clothes_config(shared_stores: Data<Arc<SharedStores>>) -> impl Responder {
+    info!("Handling request to export the clothes store configuration");
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Returning the clothes store configuration.".to_string(),
+        data: Some(clothes_store.store_config()),
+    })
+}
+
+/// Replace the clothes store's configuration, keeping its entries
+///
+/// A configuration the stored entries do not fit, e.g. with other
+/// `dimensions`, answers 400 and changes nothing. Entries are not
+/// re-embedded with new prompts. Requires the API token.
+///
+/// # HTTP Request
+/// POST /api/clothes/config/import
+///
+/// # Request Body
+/// A configuration as returned by `/api/clothes/config/export`
+#[post("/api/clothes/config/import", wrap = "from_fn(require_token)")]
+async fn import_clothes_config(
+    shared_stores: Data<Arc<SharedStores>>,
+    request: Json<StoreConfig>,
+) -> impl Responder {
+    info!("Handling request to import the clothes store configuration");
+    let mut clothes_store = acquire!(shared_stores.clothes.write());
+
+    if let Err(e) = clothes_store.set_store_config(request.into_inner()) {
+        error!("Rejected the clothes store configuration: {}", e);
+        return HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+            status: false,
+            message: format!("Failed to import the configuration: {}", e),
+            data: None,
+        });
+    }
+
+    // the log cannot express a configuration change, so fold it into a
+    // snapshot, written once the locks are released
+    let face_store = acquire!(shared_stores.face.read());
+    let compaction = shared_stores.compact_locked(&clothes_store, &face_store);
+    drop(face_store);
+    drop(clothes_store);
+    if let Err(e) = compaction.await {
+        error!("Failed to compact the operation log: {}", e);
+        return HttpResponse::InternalServerError().json(BasicResponse::<String> {
+            status: false,
+            message: format!(
+                "Configuration imported, but it could not be persisted: {}",
+                e
+            ),
+            data: None,
+        });
+    }
+
+    info!("Imported the clothes store configuration");
+    HttpResponse::Ok().json(BasicResponse::<String> {
+        status: true,
+        message: "Configuration imported successfully.".to_string(),
+        data: None,
+    })
+}
+
+/// Download the clothes vectors as a NumPy array
+///
+/// The body is a little-endian f64 `.npy` file of shape N×dimensions, one
+/// row per entry with a valid vector, loadable with `numpy.load`. Row `i`
+/// belongs to the entry at index `i` of `/api/clothes/ids.json`; matching
+/// ETags tell that both were taken from the same state of the store.
+///
+/// # HTTP Request
+/// GET /api/clothes/vectors.npy
+#[get("/api/clothes/vectors.npy")]
+async fn get_clothes_vectors_npy(
+    http_request: HttpRequest,
+    shared_stores: Data<Arc<SharedStores>>,
+) -> impl Responder {
+    info!("Handling request to download the clothes vectors");
+    let clothes_store = acquire!(shared_stores.clothes.read());
+    let etag: String = shared_stores.etag(&clothes_store);
+    if etag_matches(&http_request, &etag) {
+        return not_modified(&etag);
+    }
+
+    let (_, vectors) = clothes_store.valid_vectors();
+
+    cached_ok(&etag)
+        .content_type("application/octet-stream")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"vectors.npy\"",
+        ))
+        .body(encode_f64_matrix(&vectors, clothes_store.dimensions()))
+}
+
+/// Get the entry IDs of the rows of `/api/clothes/vectors.npy`
+///
+/// The body is a plain JSON array whose element `i` is the ID of row `i`.
+///
+/// # HTTP Request
+/// GET /api/clothes/ids.json
+#[get("/api/clothes/ids.json")]
+async fn get_clothes_vector_ids(
+    http_request: HttpRequest,
+    shared_stores: Data<Arc<SharedStores>>,
+) -> impl Responder {
+    info!("Handling request to get the clothes vector IDs");
+    let clothes_store = acquire!(shared_stores.clothes.read());
+    let etag: String = shared_stores.etag(&clothes_store);
+    if etag_matches(&http_request, &etag) {
+        return not_modified(&etag);
+    }
+
+    let (ids, _) = clothes_store.valid_vectors();
+
+    cached_ok(&etag).json(ids)
+}
+
+/// Get the centroid (mean vector) of the clothes store
+///
+/// Entries with an invalid vector are left out of the mean.
+///
+/// # HTTP Request
+/// GET /api/clothes/centroid
+///
+/// # Query Parameters
+/// * `per_entry` - When `true`, include the cosine similarity of every entry to the centroid
+#[get("/api/clothes/centroid")]
+async fn get_clothes_centroid(
+    http_request: HttpRequest,
+    shared_stores: Data<Arc<SharedStores>>,
+    query: web::Query<CentroidQuery>,
+) -> impl Responder {
+    info!("Handling request to get the clothes centroid");
+    let clothes_store = acquire!(shared_stores.clothes.read());
+    let etag: String = shared_stores.etag(&clothes_store);
+    if etag_matches(&http_request, &etag) {
+        return not_modified(&etag);
+    }
+
+    let centroid: Vec<f64> = match clothes_store.centroid() {
+        Some(centroid) => centroid,
+        None => {
+            warn!("Cannot compute the centroid of an empty clothes store");
+            return HttpResponse::NotFound().json(BasicResponse::<String> {
+                status: false,
+                message: DataEntryErrors::NoDataWasFound.to_string(),
+                data: None,
+            });
+        }
+    };
+
+    let per_entry: Option<Vec<EntrySimilarity>> = if query.per_entry.unwrap_or(false) {
+        Some(
+            clothes_store
+                .get_all()
+                .iter()
+                .zip(clothes_store.score_all(&centroid))
+                .map(|(entry, score)| EntrySimilarity {
+                    id: entry.id,
+                    score,
+                })
+                .collect(),
+        )
+    } else {
+        None
+    };
+
+    cached_ok(&etag).json(BasicResponse {
+        status: true,
+        message: "Centroid computed.".to_string(),
+        data: Some(CentroidResponse {
+            norm: l2_norm(&centroid),
+            centroid,
+            per_entry,
+        }),
+    })
+}
+
+/// Get the stored image of a piece of clothing, optionally resized
+///
+/// Resized images keep their aspect ratio and are cached, so repeated
+/// thumbnail requests are cheap. Only works for entries uploaded while
+/// image storage was enabled. Responses carry the store's ETag and the
+/// configured `max-age`, so clients revalidate rather than keep a stale
+/// image once the entry changes.
+///
+/// # HTTP Request
+/// GET /api/clothes/image/{id}
+///
+/// # URL Parameters
+/// * `id` - The ID of the clothing item
+///
+/// # Query Parameters
+/// * `w` - Maximum width of the returned image, between 1 and 4096
+/// * `h` - Maximum height of the returned image, between 1 and 4096
+#[get("/api/clothes/image/{id}")]
+async fn get_clothes_image(
+    http_request: HttpRequest,
+    id: web::Path<usize>,
+    query: web::Query<ImageQuery>,
+    shared_stores: Data<Arc<SharedStores>>,
+    thumbnails: Data<StdMutex<ThumbnailCache>>,
+    counters: Data<RequestCounters>,
+) -> impl Responder {
+    let id: usize = id.into_inner();
+    info!("Handling request to get image of clothes id: {}", id);
+
+    let valid_size = |size: Option<u32>| size.is_none_or(|size| (1..=4096).contains(&size));
+    if !valid_size(query.w) || !valid_size(query.h) {
+        warn!(
+            "Invalid thumbnail size provided: {:?}x{:?}",
+            query.w, query.h
+        );
+        return HttpResponse::BadRequest().json(BasicResponse::<String> {
+            status: false,
+            message: "Width and height must be between 1 and 4096".to_string(),
+            data: None,
+        });
+    }
+
+    let etag: String = {
+        let clothes_store = acquire!(shared_stores.clothes.read());
+        shared_stores.etag(&clothes_store)
+    };
+    if etag_matches(&http_request, &etag) {
+        return not_modified(&etag);
+    }
+
+    let key: ThumbnailKey = (id, query.w, query.h);
+    let resize: bool = query.w.is_some() || query.h.is_some();
+    if resize {
+        if let Some(bytes) = thumbnails.lock().unwrap().get(&key) {
+            counters.record_thumbnail_hit();
+            return image_response(bytes, &etag);
+        }
+        counters.record_thumbnail_miss();
+    }
+
+    let encoded_image: Result<String, Error> = {
+        let clothes_store = acquire!(shared_stores.clothes.read());
+        clothes_store.get_image(id)
+    };
+
+    let bytes: Result<Vec<u8>, Error> = encoded_image.and_then(|encoded_image| {
+        if resize {
+            resize_to_png(&decode_image(&encoded_image)?, query.w, query.h)
+        } else {
+            Ok(STANDARD.decode(encoded_image)?)
+        }
+    });
+
+    match bytes {
+        Ok(bytes) => {
+            if resize {
+                thumbnails.lock().unwrap().insert(key, bytes.clone());
+            }
+            image_response(bytes, &etag)
+        }
+        Err(e) => {
+            error!("Failed to get image of clothes with id {}: {}", id, e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to get image: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+// Helper function to wrap PNG bytes in a cacheable response
+fn image_response(bytes: Vec<u8>, etag: &str) -> HttpResponse {
+    cached_ok(etag).content_type("image/png").body(bytes)
+}
+
+/// Delete a piece of clothing by ID
+///
+/// # HTTP Request
+/// DELETE /api/clothes/delete/{id}
+///
+/// # URL Parameters
+/// * `id` - The ID of the clothing item to delete
+#[delete("/api/clothes/delete/{id}")]
+async fn delete_clothes(
+    id: web::Path<String>,
+    shared_stores: Data<Arc<SharedStores>>,
+    counters: Data<RequestCounters>,
+    thumbnails: Data<StdMutex<ThumbnailCache>>,
+) -> impl Responder {
+    counters.record_delete();
+    info!("Received delete request for clothes id: {}", id);
+    let mut clothes_store = acquire!(shared_stores.clothes.write());
+
+    match id.parse::<usize>() {
+        Ok(id) => match clothes_store.delete(id).await {
+            Ok(_) => {
+                info!("Successfully deleted clothes with id: {}", id);
+                thumbnails.lock().unwrap().invalidate(id);
+                logged!(record_op(
+                    &shared_stores,
+                    StoreOp::Delete {
+                        store: StoreKind::Clothes,
+                        id,
+                    },
+                ));
+                HttpResponse::Ok().json(BasicResponse::<String> {
+                    status: true,
+                    message: "Clothes deleted successfully".to_string(),
+                    data: None,
+                })
+            }
+            Err(e) => {
+                error!("Failed to delete clothes with id {}: {}", id, e);
+                HttpResponse::NotFound().json(BasicResponse::<String> {
+                    status: false,
+                    message: format!("Failed to delete clothes: {}", e),
+                    data: None,
+                })
+            }
+        },
+        Err(_) => {
+            warn!("Invalid ID format provided: {}", id);
+            HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: "Invalid ID format".to_string(),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Delete the clothes most similar to an image, e.g. a mis-photographed batch
+///
+/// The `top_n` most similar entries are searched for, and those scoring at
+/// least `min_score` are deleted. The image is embedded before locking the
+/// store; searching and deleting then happen under the same write lock, so
+/// no upload can slip in between. As this cannot be undone, the request must
+/// carry `confirm=true`.
+///
+/// # HTTP Request
+/// POST /api/clothes/purge_similar?confirm=true
+///
+/// # Request Body
+/// JSON object containing the base64 encoded image, the number of entries to
+/// consider and the score from which they are deleted
+///
+/// # Returns
+/// IDs of the deleted entries
+#[post("/api/clothes/purge_similar", wrap = "from_fn(require_token)")]
+async fn purge_similar_clothes(
+    shared_stores: Data<Arc<SharedStores>>,
+    counters: Data<RequestCounters>,
+    thumbnails: Data<StdMutex<ThumbnailCache>>,
+    query: web::Query<ConfirmQuery>,
+    request: Json<PurgeRequest>,
+) -> impl Responder {
+    info!(
+        "Received purge request for up to {} clothes scoring at least {}",
+        request.top_n, request.min_score
+    );
+    if let Some(rejection) = reject_large_top_n(request.top_n) {
+        return rejection;
+    }
+    if !query.confirm.unwrap_or(false) {
+        warn!("Refusing to purge clothes without confirmation");
+        return HttpResponse::BadRequest().json(BasicResponse::<String> {
+            status: false,
+            message: "Purging deletes entries for good, repeat the request with confirm=true."
+                .to_string(),
+            data: None,
+        });
+    }
+    let image: DynamicImage = match decode_base64_image(&request.image) {
+        Ok(image) => image,
+        Err(e) => {
+            error!("Failed to decode uploaded image: {}", e);
+            return HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to decode image: {}", e),
+                data: None,
+            });
+        }
+    };
+    let options = SearchOptions::top_n(request.top_n);
+
+    // embed without holding the lock, writers only wait for the scan and deletes
+    let embedder: InMemoryVectorStore = acquire!(shared_stores.clothes.read()).without_entries();
+    let query_vector: Vec<f64> = match embedder.embed_query(image, &options).await {
+        Ok(query_vector) => query_vector,
+        Err(e) => {
+            error!("Error embedding the image to purge by: {}", e);
+            return HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Error searching similar images: {}", e),
+                data: None,
+            });
+        }
+    };
+    let mut clothes_store = acquire!(shared_stores.clothes.write());
+
+    let results: Vec<SearchResult> = match clothes_store.rank(&query_vector, &options) {
+        Ok((results, _)) => results,
+        Err(e) => {
+            error!("Error searching the clothes to purge: {}", e);
+            return HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Error searching similar images: {}", e),
+                data: None,
+            });
+        }
+    };
+
+    let mut deleted: Vec<usize> = Vec::new();
+    for result in results {
+        let id: usize = result.data_entry.id;
+        if result.score < request.min_score {
+            continue;
+        }
+        if let Err(e) = clothes_store.delete(id).await {
+            error!("Failed to purge clothes with id {}: {}", id, e);
+            continue;
+        }
+        counters.record_delete();
+        thumbnails.lock().unwrap().invalidate(id);
+        deleted.push(id);
+    }
+    logged!(record_ops(
+        &shared_stores,
+        deleted
+            .iter()
+            .map(|&id| StoreOp::Delete {
+                store: StoreKind::Clothes,
+                id,
+            })
+            .collect(),
+    ));
+    info!("Purged {} clothes: {:?}", deleted.len(), deleted);
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: format!("Deleted {} entries.", deleted.len()),
+        data: Some(deleted),
+    })
+}
+
+/// Append a description to a piece of clothing
+///
+/// # HTTP Request
+/// POST /api/clothes/descriptions/{id}
+///
+/// # URL Parameters
+/// * `id` - The ID of the clothing item to update
+///
+/// # Request Body
+/// JSON object containing the description to append
+#[post("/api/clothes/descriptions/{id}")]
+async fn add_clothes_description(
+    id: web::Path<usize>,
+    shared_stores: Data<Arc<SharedStores>>,
+    request: Json<DescriptionRequest>,
+) -> impl Responder {
+    let id: usize = id.into_inner();
+    info!("Received description request for clothes id: {}", id);
+    let mut clothes_store = acquire!(shared_stores.clothes.write());
+
+    match clothes_store.add_description(id, request.into_inner().description) {
+        Ok(_) => {
+            info!("Successfully added description to clothes with id: {}", id);
+            logged!(record_put(
+                &shared_stores,
+                StoreKind::Clothes,
+                &clothes_store,
+                id
+            ));
+            HttpResponse::Ok().json(BasicResponse::<String> {
+                status: true,
+                message: "Description added successfully".to_string(),
+                data: None,
+            })
+        }
+        Err(e) => {
+            error!("Failed to add description to clothes with id {}: {}", id, e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to add description: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Add and remove descriptions on many pieces of clothing at once
+///
+/// Descriptions are changed in place, nothing is re-embedded. Removals are
+/// applied before additions, and a description that is already present is
+/// not added twice. Each entry is reported on separately: a missing entry
+/// or one exceeding the description limits is left unchanged and does not
+/// stop the others.
+///
+/// # HTTP Request
+/// POST /api/clothes/tag_batch
+///
+/// # Request Body
+/// JSON object containing the IDs and the descriptions to `add` and `remove`
+#[post("/api/clothes/tag_batch")]
+async fn tag_clothes_batch(
+    shared_stores: Data<Arc<SharedStores>>,
+    request: Json<TagBatchRequest>,
+) -> impl Responder {
+    info!("Received tag request for {} clothes", request.ids.len());
+    let mut clothes_store = acquire!(shared_stores.clothes.write());
+
+    let mut results: Vec<TagBatchResult> = Vec::with_capacity(request.ids.len());
+    let mut ops: Vec<StoreOp> = Vec::new();
+    for &id in &request.ids {
+        match clothes_store.retag_descriptions(id, &request.add, &request.remove) {
+            Ok(()) => {
+                ops.extend(put_op(StoreKind::Clothes, &clothes_store, id));
+                results.push(TagBatchResult {
+                    id,
+                    success: true,
+                    error: None,
+                });
+            }
+            Err(e) => {
+                warn!("Failed to tag clothes with id {}: {}", id, e);
+                results.push(TagBatchResult {
+                    id,
+                    success: false,
+                    error: Some(e.to_string()),
+                });
+            }
+        }
+    }
+
+    logged!(record_ops(&shared_stores, ops));
+
+    let updated: usize = results.iter().filter(|result| result.success).count();
+    info!("Tagged {} of {} clothes", updated, results.len());
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: format!("Tagged {} of {} entries.", updated, results.len()),
+        data: Some(results),
+    })
+}
+
+/// Import clothes with precomputed vectors, bypassing embedding
+///
+/// With `dry_run=true` every entry is validated and reported on, but nothing
+/// is inserted. Otherwise entries are only inserted if all of them are valid.
+/// Vectors of the wrong length fail validation unless
+/// `STYLIST_LENGTH_MISMATCH_POLICY` is `skip` or `pad_truncate`; the payload
+/// positions of the entries skipped or coerced are listed in the report.
+///
+/// # HTTP Request
+/// POST /api/clothes/import
+///
+/// # Query Parameters
+/// * `dry_run` - When `true`, only validate the payload
+///
+/// # Request Body
+/// JSON object containing the entries to import
+#[post("/api/clothes/import")]
+async fn import_clothes(
+    shared_stores: Data<Arc<SharedStores>>,
+    thumbnails: Data<StdMutex<ThumbnailCache>>,
+    query: web::Query<ImportQuery>,
+    request: Json<ImportRequest>,
+) -> impl Responder {
+    let dry_run: bool = query.dry_run.unwrap_or(false);
+    let entries: Vec<ImportEntry> = request.into_inner().entries;
+    info!(
+        "Received import request for {} clothes (dry run: {})",
+        entries.len(),
+        dry_run
+    );
+
+    let mut clothes_store = acquire!(shared_stores.clothes.write());
+
+    let results: Vec<ImportValidation> = clothes_store.validate_import(&entries);
+    let valid: usize = results.iter().filter(|result| result.valid).count();
+    let mut report = ImportReport {
+        dry_run,
+        total: entries.len(),
+        valid,
+        invalid: entries.len() - valid,
+        results,
+        ids: Vec::new(),
+        skipped: Vec::new(),
+        coerced: Vec::new(),
+    };
+
+    if dry_run {
+        return HttpResponse::Ok().json(BasicResponse {
+            status: true,
+            message: "Import payload validated, nothing was stored.".to_string(),
+            data: Some(report),
+        });
+    }
+
+    match clothes_store.import_with_report(entries) {
+        Ok(outcome) => {
+            info!("Successfully imported {} clothes", outcome.ids.len());
+            {
+                let mut thumbnails = thumbnails.lock().unwrap();
+                for id in &outcome.ids {
+                    thumbnails.invalidate(*id);
+                }
+            }
+            logged!(record_ops(
+                &shared_stores,
+                outcome
+                    .ids
+                    .iter()
+                    .filter_map(|&id| put_op(StoreKind::Clothes, &clothes_store, id))
+                    .collect(),
+            ));
+            report.ids = outcome.ids;
+            report.skipped = outcome.skipped;
+            report.coerced = outcome.coerced;
+            HttpResponse::Ok().json(BasicResponse {
+                status: true,
+                message: "Clothes imported successfully.".to_string(),
+                data: Some(report),
+            })
+        }
+        Err(e) => {
+            error!("Failed to import clothes: {}", e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse {
+                status: false,
+                message: format!("Failed to import clothes: {}", e),
+                data: Some(report),
+            })
+        }
+    }
+}
+
+/// Re-embed a single piece of clothing from its stored image
+///
+/// Only works for entries uploaded while image storage was enabled. The
+/// vector is replaced in place, keeping the id and metadata.
+///
+/// # HTTP Request
+/// POST /api/clothes/reembed/{id}
+///
+/// # URL Parameters
+/// * `id` - The ID of the clothing item to re-embed
+#[post("/api/clothes/reembed/{id}", wrap = "from_fn(rate_limit)")]
+async fn reembed_clothes(
+    id: web::Path<usize>,
+    shared_stores: Data<Arc<SharedStores>>,
+) -> impl Responder {
+    let id: usize = id.into_inner();
+    info!("Received re-embed request for clothes id: {}", id);
+    let mut clothes_store = acquire!(shared_stores.clothes.write());
+
+    match clothes_store.reembed(id).await {
+        Ok(norm) => {
+            info!("Successfully re-embedded clothes with id: {}", id);
+            logged!(record_put(
+                &shared_stores,
+                StoreKind::Clothes,
+                &clothes_store,
+                id
+            ));
+            HttpResponse::Ok().json(BasicResponse {
+                status: true,
+                message: "Clothes re-embedded successfully. Returning the new vector norm."
+                    .to_string(),
+                data: Some(norm),
+            })
+        }
+        Err(e) => {
+            error!("Failed to re-embed clothes with id {}: {}", id, e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to re-embed clothes: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Truncate every stored clothes vector to a target dimensionality
+///
+/// This is lossy: the dropped values are gone for good. It is a migration
+/// path after switching to a model producing longer vectors, short of
+/// re-embedding every entry.
+///
+/// # HTTP Request
+/// POST /api/clothes/truncate_vectors
+///
+/// # Query Parameters
+/// * `dims` - Number of values to keep, at most the current vector length
+#[post("/api/clothes/truncate_vectors")]
+async fn truncate_clothes_vectors(
+    shared_stores: Data<Arc<SharedStores>>,
+    query: web::Query<TruncateQuery>,
+) -> impl Responder {
+    warn!(
+        "Truncating clothes vectors to {} dimensions, this is lossy",
+        query.dims
+    );
+    let mut clothes_store = acquire!(shared_stores.clothes.write());
+
+    match clothes_store.truncate_vectors(query.dims) {
+        Ok(truncated) => {
+            logged!(record_ops(
+                &shared_stores,
+                clothes_store
+                    .get_all()
+                    .into_iter()
+                    .map(|entry| StoreOp::Put {
+                        store: StoreKind::Clothes,
+                        entry,
+                    })
+                    .collect(),
+            ));
+            info!(
+                "Successfully truncated {} clothes vectors to {} dimensions",
+                truncated, query.dims
+            );
+            HttpResponse::Ok().json(BasicResponse {
+                status: true,
+                message: "Vectors truncated. Returning the number of truncated entries."
+                    .to_string(),
+                data: Some(truncated),
+            })
+        }
+        Err(e) => {
+            error!("Failed to truncate clothes vectors: {}", e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to truncate vectors: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Check an image against the upload limits without embedding or storing it
+///
+/// Runs the same decoding and checks as uploads, so clients can fail fast on
+/// bad inputs before paying for an embedding. Undecodable images answer 400
+/// Bad Request; decodable ones answer 200 with `valid` telling whether an
+/// upload would pass `STYLIST_MAX_IMAGE_MB` and `STYLIST_MAX_IMAGE_SIDE`.
+///
+/// # HTTP Request
+/// POST /api/clothes/validate_image
+///
+/// # Request Body
+/// JSON object containing the base64 encoded image
+#[post("/api/clothes/validate_image")]
+async fn validate_clothes_image(request: Json<EmbedRequest>) -> impl Responder {
+    let config = config::get();
+    let decoded = STANDARD
+        .decode(&request.image)
+        .map_err(Error::from)
+        .and_then(|bytes| Ok((load_from_memory(&bytes)?, bytes)));
+    let (image, bytes): (DynamicImage, Vec<u8>) = match decoded {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            warn!("Validated image cannot be decoded: {}", e);
+            return HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to decode image: {}", e),
+                data: None,
+            });
+        }
+    };
+
+    let errors: Vec<String> = match validate_image(
+        bytes.len(),
+        &image,
+        config.max_image_mb * 1024 * 1024,
+        config.max_image_side,
+    ) {
+        Ok(()) => Vec::new(),
+        Err(e) => vec![e.to_string()],
+    };
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Image validated.".to_string(),
+        data: Some(ImageValidation {
+            format: guess_format(&bytes)
+                .ok()
+                .and_then(|format| format.extensions_str().first())
+                .map(|extension| extension.to_string()),
+            width: image.width(),
+            height: image.height(),
+            bytes: bytes.len(),
+            valid: errors.is_empty(),
+            errors,
+        }),
+    })
+}
+
+/// Preview the image the embedder receives after preprocessing
+///
+/// Applies the same preprocessing as uploads and searches. The result is
+/// returned as a base64 PNG in JSON, or as a raw PNG when `format=png` is given
+/// or the `Accept` header asks for `image/png`.
+///
+/// # HTTP Request
+/// POST /api/clothes/preprocess_preview
+///
+/// # Query Parameters
+/// * `format` - `json` (default) or `png`
+///
+/// # Request Body
+/// JSON object containing the base64 encoded image
+#[post("/api/clothes/preprocess_preview")]
+async fn preprocess_preview(
+    http_request: HttpRequest,
+    query: web::Query<PreviewQuery>,
+    request: Json<EmbedRequest>,
+) -> impl Responder {
+    let preprocessing = config::get().preprocessing;
+    info!("Previewing preprocessing: {:?}", preprocessing);
+
+    let wants_png: bool = match query.format.as_deref() {
+        Some(format) => format.eq_ignore_ascii_case("png"),
+        None => http_request
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("image/png")),
+    };
+
+    let image: DynamicImage = match decode_base64_image(&request.image) {
+        Ok(image) => preprocessing.apply(image),
+        Err(e) => {
+            error!("Failed to decode uploaded image: {}", e);
+            return HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to decode image: {}", e),
+                data: None,
+            });
+        }
+    };
+
+    let mut bytes: Vec<u8> = Vec::new();
+    if let Err(e) = image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png) {
+        error!("Failed to encode preprocessed image: {}", e);
+        return HttpResponse::InternalServerError().json(BasicResponse::<String> {
+            status: false,
+            message: format!("Failed to encode image: {}", e),
+            data: None,
+        });
+    }
+
+    if wants_png {
+        return HttpResponse::Ok().content_type("image/png").body(bytes);
+    }
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Returning the preprocessed image as a base64 PNG.".to_string(),
+        data: Some(STANDARD.encode(bytes)),
+    })
+}
+
+/// Embed an image with the clothes prompts and return the raw vector
+///
+/// This is meant for inspection and calibration only. Nothing is stored, and
+/// `normalize` only affects the returned vector, not how the store ranks.
+///
+/// # HTTP Request
+/// POST /api/clothes/embed
+///
+/// # Query Parameters
+/// * `normalize` - When `true`, L2-normalize the returned vector
+///
+/// # Request Body
+/// JSON object containing the base64 encoded image
+#[post("/api/clothes/embed", wrap = "from_fn(rate_limit)")]
+async fn embed_clothes(
+    shared_stores: Data<Arc<SharedStores>>,
+    query: web::Query<EmbedQuery>,
+    request: Json<EmbedRequest>,
+) -> impl Responder {
+    info!("Received embed request for clothes");
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    match decode_base64_image(&request.image) {
+        Ok(image) => match clothes_store.embed_only(image).await {
+            Ok(vector) => {
+                let vector: Vec<f64> = if query.normalize.unwrap_or(false) {
+                    l2_normalize(&vector)
+                } else {
+                    vector
+                };
+
+                HttpResponse::Ok().json(BasicResponse {
+                    status: true,
+                    message: "Embedding succeeded.".to_string(),
+                    data: Some(vector),
+                })
+            }
+            Err(e) => {
+                error!("Error during embedding: {}", e);
+                HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                    status: false,
+                    message: format!("Error embedding image: {}", e),
+                    data: None,
+                })
+            }
+        },
+        Err(e) => {
+            error!("Failed to decode uploaded image: {}", e);
+            HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to decode image: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Score an uploaded image against one stored piece of clothing
+///
+/// Cheaper than a search when only one item matters, e.g. "how well does
+/// this photo match item 12?".
+///
+/// # HTTP Request
+/// POST /api/clothes/match/{id}
+///
+/// # Request Body
+/// JSON object containing the base64 encoded image
+///
+/// # Returns
+/// The `score` and the entry, metadata included, or 404 if there is no such entry
+#[post("/api/clothes/match/{id}", wrap = "from_fn(rate_limit)")]
+async fn match_clothes(
+    id: web::Path<usize>,
+    shared_stores: Data<Arc<SharedStores>>,
+    request: Json<EmbedRequest>,
+) -> impl Responder {
+    let id: usize = id.into_inner();
+    info!("Received match request against clothes id: {}", id);
+    let image: DynamicImage = match decode_base64_image(&request.image) {
+        Ok(image) => image,
+        Err(e) => {
+            error!("Failed to decode uploaded image: {}", e);
+            return HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to decode image: {}", e),
+                data: None,
+            });
+        }
+    };
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    match clothes_store.match_entry(image, id).await {
+        Ok(result) => HttpResponse::Ok().json(BasicResponse {
+            status: true,
+            message: "Returning the similarity to the entry.".to_string(),
+            data: Some(result),
+        }),
+        Err(e) => {
+            error!("Failed to match against clothes id {}: {}", id, e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to match the image: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Measure the search quality of the clothes store on a labeled evaluation set
+///
+/// Each query image is searched for its `k` best matches, which are compared
+/// with the entries known to be relevant. Useful to compare prompts or
+/// settings: run the same set before and after a change. Evaluations do not
+/// count towards match tracking.
+///
+/// # HTTP Request
+/// POST /api/clothes/evaluate
+///
+/// # Request Body
+/// JSON object containing the queries, each a base64 encoded image and the
+/// IDs of the entries relevant to it, and the number of results `k`. More
+/// than `STYLIST_MAX_EVALUATION_QUERIES` queries answer 400.
+///
+/// # Returns
+/// Recall@k and precision@k averaged across the queries, and per query
+#[post("/api/clothes/evaluate", wrap = "from_fn(rate_limit)")]
+async fn evaluate_clothes(
+    shared_stores: Data<Arc<SharedStores>>,
+    request: Json<EvaluationRequest>,
+) -> impl Responder {
+    info!(
+        "Received evaluation request with {} queries at k = {}",
+        request.queries.len(),
+        request.k
+    );
+    if request.queries.is_empty() || request.k == 0 {
+        warn!("Evaluation requested without queries or with k = 0");
+        return HttpResponse::BadRequest().json(BasicResponse::<String> {
+            status: false,
+            message: "At least one query and a k of at least 1 are required.".to_string(),
+            data: None,
+        });
+    }
+    let max_queries: usize = config::get().max_evaluation_queries;
+    if request.queries.len() > max_queries {
+        warn!(
+            "Rejecting an evaluation of {} queries, at most {} are allowed",
+            request.queries.len(),
+            max_queries
+        );
+        return HttpResponse::BadRequest().json(BasicResponse::<String> {
+            status: false,
+            message: format!(
+                "Too many queries: {} were given, but at most {} are allowed!",
+                request.queries.len(),
+                max_queries
+            ),
+            data: None,
+        });
+    }
+    if let Some(rejection) = reject_large_top_n(request.k) {
+        return rejection;
+    }
+
+    let mut queries: Vec<EvaluationQuery> = Vec::with_capacity(request.queries.len());
+    for (index, query) in request.queries.iter().enumerate() {
+        match decode_base64_image(&query.query_image) {
+            Ok(image) => queries.push(EvaluationQuery {
+                image,
+                relevant_ids: query.relevant_ids.clone(),
+            }),
+            Err(e) => {
+                error!("Failed to decode the image of query {}: {}", index, e);
+                return HttpResponse::BadRequest().json(BasicResponse::<String> {
+                    status: false,
+                    message: format!("Failed to decode the image of query {}: {}", index, e),
+                    data: None,
+                });
+            }
+        }
+    }
+    // embed without holding the lock, writers only wait for the searches
+    let embedder: InMemoryVectorStore = acquire!(shared_stores.clothes.read()).without_entries();
+    let queries: Vec<EmbeddedEvaluationQuery> = match embedder.embed_evaluation(queries).await {
+        Ok(queries) => queries,
+        Err(e) => {
+            error!("Failed to embed the evaluation queries: {}", e);
+            return HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to evaluate the store: {}", e),
+                data: None,
+            });
+        }
+    };
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    match clothes_store.evaluate_embedded(&queries, request.k) {
+        Ok(evaluation) => {
+            info!(
+                "Evaluated recall@{} = {:.3}, precision@{} = {:.3}",
+                evaluation.k, evaluation.recall, evaluation.k, evaluation.precision
+            );
+            HttpResponse::Ok().json(BasicResponse {
+                status: true,
+                message: "Returning the retrieval scores.".to_string(),
+                data: Some(evaluation),
+            })
+        }
+        Err(e) => {
+            error!("Failed to evaluate the clothes store: {}", e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to evaluate the store: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Show how the scores of a query are distributed across the clothes store
+///
+/// Only bucket counts are returned, not the per-entry scores. This helps to
+/// pick a sensible score threshold for a deployment.
+///
+/// # HTTP Request
+/// POST /api/clothes/score_histogram
+///
+/// # Request Body
+/// JSON object containing a base64 encoded image and an optional bucket
+/// count (defaults to 10, at most 1000)
+#[post("/api/clothes/score_histogram", wrap = "from_fn(rate_limit)")]
+async fn clothes_score_histogram(
+    shared_stores: Data<Arc<SharedStores>>,
+    counters: Data<RequestCounters>,
+    request: Json<ScoreHistogramRequest>,
+) -> impl Responder {
+    counters.record_search();
+    let buckets: usize = request.buckets.unwrap_or(10);
+    info!(
+        "Processing score histogram request with {} buckets",
+        buckets
+    );
+
+    if buckets == 0 || buckets > 1000 {
+        warn!("Invalid bucket count provided: {}", buckets);
+        return HttpResponse::BadRequest().json(BasicResponse::<String> {
+            status: false,
+            message: "Bucket count must be between 1 and 1000".to_string(),
+            data: None,
+        });
+    }
+
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    match decode_base64_image(&request.image) {
+        Ok(image) => match clothes_store.embed_only(image).await {
+            Ok(vector) => {
+                let histogram: Vec<HistogramBucket> =
+                    score_histogram(&clothes_store.score_all(&vector), buckets);
+                HttpResponse::Ok().json(BasicResponse {
+                    status: true,
+                    message: "Score histogram computed.".to_string(),
+                    data: Some(histogram),
+                })
+            }
+            Err(e) => {
+                error!("Error during embedding: {}", e);
+                HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                    status: false,
+                    message: format!("Error embedding image: {}", e),
+                    data: None,
+                })
+            }
+        },
+        Err(e) => {
+            error!("Failed to decode uploaded image: {}", e);
+            HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to decode image: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Request body for a clustered similarity search
+#[derive(Deserialize)]
+struct ClusteredSearchRequest {
+    user_image: String,
+    /// Number of clusters, i.e. of representatives returned
+    clusters: usize,
+    /// Candidates fetched per cluster before clustering, defaults to 4
+    oversample: Option<usize>,
+}
+
+/// Search the clothes and group the results into clusters of similar items
+///
+/// `clusters * oversample` candidates are fetched and grouped with k-means
+/// over their vectors. Each cluster is returned as its best-scoring entry
+/// (`representative`) and its `size`, best clusters first. This surfaces
+/// diverse results rather than many near-identical ones.
+///
+/// # HTTP Request
+/// POST /api/clothes/search_clustered
+///
+/// # Request Body
+/// JSON object containing the base64 encoded image, the number of clusters
+/// and optionally how many candidates to fetch per cluster
+#[post("/api/clothes/search_clustered", wrap = "from_fn(rate_limit)")]
+async fn search_clothes_clustered(
+    shared_stores: Data<Arc<SharedStores>>,
+    counters: Data<RequestCounters>,
+    request: Json<ClusteredSearchRequest>,
+) -> impl Responder {
+    counters.record_search();
+    info!(
+        "Processing clustered similarity search for {} clusters",
+        request.clusters
+    );
+    if request.clusters == 0 {
+        warn!("Rejecting a clustered search for 0 clusters");
+        return HttpResponse::BadRequest().json(BasicResponse::<String> {
+            status: false,
+            message: "clusters must be at least 1".to_string(),
+            data: None,
+        });
+    }
+    let oversample: usize = request.oversample.unwrap_or(4).max(1);
+    let options = SearchOptions::top_n(request.clusters.saturating_mul(oversample));
+    if let Some(rejection) = reject_large_top_n(options.top_n) {
+        return rejection;
+    }
+
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    match decode_base64_image(&request.user_image) {
+        Ok(image) => match clothes_store.search_with(image, &options).await {
+            Ok(results) => {
+                info!("Successfully completed clustered similarity search");
+                HttpResponse::Ok().json(BasicResponse {
+                    status: true,
+                    message: "Search operation succeeded.".to_string(),
+                    data: Some(cluster_results(results, request.clusters)),
+                })
+            }
+            Err(e) => {
+                error!("Error during clustered similarity search: {}", e);
+                HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                    status: false,
+                    message: format!("Error searching similar images: {}", e),
+                    data: None,
+                })
+            }
+        },
+        Err(e) => {
+            error!("Failed to decode uploaded image: {}", e);
+            HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to decode image: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Estimate the embedding calls and cost of uploading or re-embedding images
+///
+/// Each image takes one call per prompt of the clothes store. The price of a
+/// call comes from `STYLIST_COST_PER_CALL`, so the estimate is 0 while it is
+/// unset. Retries after failed calls are not accounted for.
+///
+/// # HTTP Request
+/// POST /api/clothes/estimate_cost
+///
+/// # Request Body
+/// JSON object containing the number of images to embed
+#[post("/api/clothes/estimate_cost")]
+async fn estimate_clothes_cost(
+    shared_stores: Data<Arc<SharedStores>>,
+    request: Json<CostEstimateRequest>,
+) -> impl Responder {
+    info!("Estimating the cost of embedding {} images", request.count);
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    let calls_per_image: usize = clothes_store.calls_per_image();
+    let calls: usize = request.count.saturating_mul(calls_per_image);
+    let cost_per_call: f64 = config::get().cost_per_call;
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: format!("Embedding {} images takes {} calls.", request.count, calls),
+        data: Some(CostEstimate {
+            count: request.count,
+            calls_per_image,
+            calls,
+            cost_per_call,
+            estimated_cost: calls as f64 * cost_per_call,
+        }),
+    })
+}
+
+/// Calculate similarity between uploaded image and stored clothes
+///
+/// # HTTP Request
+/// POST /api/similarity/calculate
+///
+/// # Request Body
+/// JSON object containing base64 encoded image, number of results to return,
+/// whether to normalize the query, which entries to leave out and which
+/// descriptions every result must have, and what their `metadata` must contain,
+/// e.g. `{"brand": "X"}`. The query normalization has to match
+/// the store's, otherwise the request is rejected. With
+/// `STYLIST_NORMALIZE_TAGS`, tags match regardless of case and surrounding
+/// whitespace.
+///
+/// # Response
+/// Next to `status`, `message` and `data`, the response holds `requested`,
+/// `returned` and `filtered_out` counts, explaining why fewer than `top_n`
+/// results may come back.
+///
+/// Results reflect the store as it was when the search started: uploads and
+/// deletes arriving meanwhile wait for the search to finish.
+///
+/// # Query Parameters
+/// * `include_query_vector` - When `true`, `data` becomes an object holding
+///   the `results` and the `query_vector` they were ranked with
+/// * `timing` - When `true`, the response holds a `timing` object with the
+///   `decode_ms`, `embed_ms`, `search_ms` and `total_ms` spent on the search
+#[post("/api/similarity/calculate", wrap = "from_fn(rate_limit)")]
+async fn calculate_similarity(
+    http_request: HttpRequest,
+    shared_stores: Data<Arc<SharedStores>>,
+    counters: Data<RequestCounters>,
+    query: web::Query<SimilarityQuery>,
+    request: web::Json<SimilarityRequest>,
+) -> impl Responder {
+    let started: Instant = Instant::now();
+    counters.record_search();
+    info!(
+        "Processing similarity calculation request for top_n: {}",
+        request.top_n
+    );
+    if let Some(rejection) = reject_large_top_n(request.top_n) {
+        return rejection;
+    }
+    if let Some(rejection) = reject_invalid_tie_epsilon(request.shuffle_ties) {
+        return rejection;
+    }
+    let options: SearchOptions = search_options(&request);
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    let mut timing = Timing::default();
+    let decode_started: Instant = Instant::now();
+    let decoded = decode_base64_image(&request.user_image);
+    timing.decode_ms = millis(decode_started.elapsed());
+
+    match decoded {
+        Ok(image) => {
+            match timed_search(&http_request, &clothes_store, image, &options, &mut timing).await {
+                Ok((query_vector, results, counts)) => {
+                    info!("Successfully completed similarity search");
+                    timing.total_ms = millis(started.elapsed());
+                    let timing: Option<Timing> = query.timing.unwrap_or(false).then_some(timing);
+                    let message: String = if counts.returned < counts.requested {
+                        format!(
+                        "Search operation succeeded with {} of {} requested results, {} entries were filtered out.",
+                        counts.returned, counts.requested, counts.filtered_out
+                    )
+                    } else {
+                        "Search operation succeeded.".to_string()
+                    };
+                    if query.include_query_vector.unwrap_or(false) {
+                        return HttpResponse::Ok().json(SearchResponse {
+                            response: BasicResponse {
+                                status: true,
+                                message,
+                                data: Some(SimilarityWithQuery {
+                                    results,
+                                    query_vector,
+                                }),
+                            },
+                            counts,
+                            timing,
+                        });
+                    }
+                    HttpResponse::Ok().json(SearchResponse {
+                        response: BasicResponse {
+                            status: true,
+                            message,
+                            data: Some(results),
+                        },
+                        counts,
+                        timing,
+                    })
+                }
+                Err(e) => {
+                    error!("Error during similarity search: {}", e);
+                    HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                        status: false,
+                        message: format!("Error searching similar images: {}", e),
+                        data: None,
+                    })
+                }
+            }
+        }
+        Err(e) => {
+            error!("Failed to decode uploaded image: {}", e);
+            HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to decode image: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Find the clothes that best complete a collection, e.g. an outfit
+///
+/// The store is searched for the centroid of the given entries, which are
+/// left out of the results. Nothing is embedded.
+///
+/// # HTTP Request
+/// POST /api/clothes/search_collection
+///
+/// # Request Body
+/// * `ids` - IDs of the entries in the collection, a missing one answers 404
+/// * `top_n` - Number of results to return
+#[post("/api/clothes/search_collection")]
+async fn search_clothes_collection(
+    shared_stores: Data<Arc<SharedStores>>,
+    counters: Data<RequestCounters>,
+    request: Json<CollectionSearchRequest>,
+) -> impl Responder {
+    counters.record_search();
+    info!(
+        "Processing collection search request for {} entries, top_n: {}",
+        request.ids.len(),
+        request.top_n
+    );
+    if let Some(rejection) = reject_large_top_n(request.top_n) {
+        return rejection;
+    }
+    if request.ids.is_empty() {
+        return HttpResponse::BadRequest().json(BasicResponse::<String> {
+            status: false,
+            message: "A collection needs at least one entry.".to_string(),
+            data: None,
+        });
+    }
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    match clothes_store.search_collection(&request.ids, request.top_n) {
+        Ok((results, counts)) => {
+            info!("Successfully completed collection search");
+            HttpResponse::Ok().json(SearchResponse {
+                response: BasicResponse {
+                    status: true,
+                    message: "Search operation succeeded.".to_string(),
+                    data: Some(results),
+                },
+                counts,
+                timing: None,
+            })
+        }
+        Err(e) => {
+            error!("Error during collection search: {}", e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Error searching for the collection: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+// Helper function to search like `search_with_query`, recording the
+// embedding and ranking times for the slow request log and the response
+async fn timed_search(
+    http_request: &HttpRequest,
+    store: &InMemoryVectorStore,
+    image: DynamicImage,
+    options: &SearchOptions,
+    timing: &mut Timing,
+) -> Result<(Vec<f64>, Vec<SearchResult>, SearchCounts), Error> {
+    let started: Instant = Instant::now();
+    let query_vector: Vec<f64> = store.embed_query(image, options).await?;
+    let elapsed: Duration = started.elapsed();
+    record_phase(http_request, "embedding", elapsed);
+    timing.embed_ms = millis(elapsed);
+
+    let started: Instant = Instant::now();
+    let (results, counts) = store.rank(&query_vector, options)?;
+    let elapsed: Duration = started.elapsed();
+    record_phase(http_request, "search", elapsed);
+    timing.search_ms = Some(millis(elapsed));
+
+    Ok((query_vector, results, counts))
+}
+
+// Helper function to turn a similarity request into search options
+fn search_options(request: &SimilarityRequest) -> SearchOptions {
+    SearchOptions {
+        top_n: request.top_n,
+        normalize_query: request.normalize_query,
+        exclude_ids: request.exclude_ids.clone(),
+        tags: request.tags.clone(),
+        metadata_filter: request.metadata_filter.clone(),
+        decay_half_life_secs: request.decay_half_life_secs,
+        shuffle_ties: request.shuffle_ties,
+        shuffle_seed: request.shuffle_seed,
+        farthest: false,
+        gender: request.gender,
+    }
+}
+
+// Helper function to express a duration in fractional milliseconds
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Find the clothes least similar to an image
+///
+/// This is the similarity search turned upside down, for "something
+/// completely different" suggestions: `data` holds the `top_n` entries with
+/// the lowest scores, least similar first, so the scores show how dissimilar
+/// they are. Do not use it to find matches, use `/api/similarity/calculate`.
+///
+/// # HTTP Request
+/// POST /api/clothes/search_farthest
+///
+/// # Request Body
+/// Same as `/api/similarity/calculate`, filters narrowing the candidates the
+/// same way
+///
+/// # Query Parameters
+/// * `timing` - When `true`, the response holds a `timing` object with the
+///   `decode_ms`, `embed_ms`, `search_ms` and `total_ms` spent on the search
+#[post("/api/clothes/search_farthest", wrap = "from_fn(rate_limit)")]
+async fn search_farthest_clothes(
+    http_request: HttpRequest,
+    shared_stores: Data<Arc<SharedStores>>,
+    counters: Data<RequestCounters>,
+    query: web::Query<TimingQuery>,
+    request: Json<SimilarityRequest>,
+) -> impl Responder {
+    let started: Instant = Instant::now();
+    counters.record_search();
+    info!(
+        "Processing farthest clothes search for top_n: {}",
+        request.top_n
+    );
+    if let Some(rejection) = reject_large_top_n(request.top_n) {
+        return rejection;
+    }
+    if let Some(rejection) = reject_invalid_tie_epsilon(request.shuffle_ties) {
+        return rejection;
+    }
+    let options = SearchOptions {
+        farthest: true,
+        ..search_options(&request)
+    };
+
+    let mut timing = Timing::default();
+    let decode_started: Instant = Instant::now();
+    let decoded = decode_base64_image(&request.user_image);
+    timing.decode_ms = millis(decode_started.elapsed());
+    let image: DynamicImage = match decoded {
+        Ok(image) => image,
+        Err(e) => {
+            error!("Failed to decode uploaded image: {}", e);
+            return HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to decode image: {}", e),
+                data: None,
+            });
+        }
+    };
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    match timed_search(&http_request, &clothes_store, image, &options, &mut timing).await {
+        Ok((_, results, counts)) => {
+            timing.total_ms = millis(started.elapsed());
+            HttpResponse::Ok().json(SearchResponse {
+                response: BasicResponse {
+                    status: true,
+                    message: "Returning the least similar clothes, least similar first."
+                        .to_string(),
+                    data: Some(results),
+                },
+                counts,
+                timing: query.timing.unwrap_or(false).then_some(timing),
+            })
+        }
+        Err(e) => {
+            error!("Error during farthest search: {}", e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Error searching dissimilar images: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Search the stored clothes with a precomputed query vector
+///
+/// Nothing is embedded, so this answers instantly and does not count against
+/// the rate limit. The vector must come from the same embedder and prompts as
+/// the store's.
+///
+/// # HTTP Request
+/// POST /api/clothes/search_vector
+///
+/// # Request Body
+/// * `vector` - Query vector, as long as the store's `dimensions`
+/// * `top_n` - Number of results to return
+///
+/// # Returns
+/// The same results and counts as `/api/similarity/calculate`. A vector of
+/// the wrong length answers 400 with the expected and actual lengths.
+#[post("/api/clothes/search_vector")]
+async fn search_clothes_by_vector(
+    shared_stores: Data<Arc<SharedStores>>,
+    counters: Data<RequestCounters>,
+    request: Json<VectorSearchRequest>,
+) -> impl Responder {
+    counters.record_search();
+    let VectorSearchRequest { vector, top_n } = request.into_inner();
+    info!("Processing vector search request for top_n: {}", top_n);
+    if let Some(rejection) = reject_large_top_n(top_n) {
+        return rejection;
+    }
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    match clothes_store.search_vector(vector, &SearchOptions::top_n(top_n)) {
+        Ok((results, counts)) => {
+            info!("Successfully completed vector search");
+            HttpResponse::Ok().json(SearchResponse {
+                response: BasicResponse {
+                    status: true,
+                    message: "Search operation succeeded.".to_string(),
+                    data: Some(results),
+                },
+                counts,
+                timing: None,
+            })
+        }
+        Err(e) => {
+            error!("Error during vector search: {}", e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Error searching with the vector: {}", e),
+                data: None,
+            })
+        }
+    }
+}
+
+/// Number of search events buffered for a streaming client before the scan
+/// waits for it
+const STREAM_CHANNEL_CAPACITY: usize = 64;
+
+/// Calculate similarity between uploaded image and stored clothes, streaming
+/// matches as Server-Sent Events while the store is scanned
+///
+/// # HTTP Request
+/// POST /api/similarity/stream
+///
+/// # Request Body
+/// Same as `/api/similarity/calculate`.
+///
+/// # Query Parameters
+/// * `min_score` - Lowest score of matches sent during the scan, defaults to 0
+///
+/// # Response
+/// A `text/event-stream` of `candidate` events, each holding the `id` and
+/// `score` of an entry that scored at least `min_score`, in store order
+/// rather than rank order. A final `summary` event holds the ranked
+/// `results` and their `counts`, or an `error` event its `message` if the
+/// search failed. Every `data` line is the JSON of the event, including its
+/// `event` field.
+#[post("/api/similarity/stream", wrap = "from_fn(rate_limit)")]
+async fn stream_similarity(
+    shared_stores: Data<Arc<SharedStores>>,
+    counters: Data<RequestCounters>,
+    query: web::Query<StreamQuery>,
+    request: web::Json<SimilarityRequest>,
+) -> impl Responder {
+    counters.record_search();
+    info!(
+        "Processing streaming similarity request for top_n: {}",
+        request.top_n
+    );
+    if let Some(rejection) = reject_large_top_n(request.top_n) {
+        return rejection;
+    }
+    if let Some(rejection) = reject_invalid_tie_epsilon(request.shuffle_ties) {
+        return rejection;
+    }
+    let image: DynamicImage = match decode_base64_image(&request.user_image) {
+        Ok(image) => image,
+        Err(e) => {
+            error!("Failed to decode uploaded image: {}", e);
+            return HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to decode image: {}", e),
+                data: None,
+            });
+        }
+    };
+    let options: SearchOptions = search_options(&request);
+    let min_score: f64 = query.min_score.unwrap_or(0.0);
+    let clothes: Arc<RwLock<InMemoryVectorStore>> = shared_stores.clothes.clone();
+
+    // the image is embedded without holding the lock, then the scan runs on
+    // a blocking thread under the read lock, while the response drains the
+    // events; the bounded channel makes the scan wait for a slow client
+    let (sender, receiver) = mpsc::channel::<SearchEvent>(STREAM_CHANNEL_CAPACITY);
+    rt::spawn(async move {
+        let embedder: InMemoryVectorStore = match acquire(clothes.read()).await {
+            Ok(clothes_store) => clothes_store.without_entries(),
+            Err(_) => {
+                let _ = sender
+                    .send(SearchEvent::Error {
+                        message: LOCK_TIMEOUT_MESSAGE.to_string(),
+                    })
+                    .await;
+                return;
+            }
+        };
+        let query_vector: Vec<f64> = match embedder.embed_only(image).await {
+            Ok(query_vector) => query_vector,
+            Err(e) => {
+                error!("Error during streaming similarity search: {}", e);
+                let _ = sender
+                    .send(SearchEvent::Error {
+                        message: e.to_string(),
+                    })
+                    .await;
+                return;
+            }
+        };
+
+        let clothes_store = match acquire(clothes.read_owned()).await {
+            Ok(clothes_store) => clothes_store,
+            Err(_) => {
+                let _ = sender
+                    .send(SearchEvent::Error {
+                        message: LOCK_TIMEOUT_MESSAGE.to_string(),
+                    })
+                    .await;
+                return;
+            }
+        };
+        let scan = tokio::task::spawn_blocking(move || {
+            clothes_store.search_stream(query_vector, &options, min_score, &sender)
+        });
+        match scan.await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("Error during streaming similarity search: {}", e),
+            Err(e) => error!("Streaming similarity search panicked: {}", e),
+        }
+    });
+
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        let event: SearchEvent = receiver.recv().await?;
+        Some((Ok::<Bytes, actix_web::Error>(sse_frame(&event)), receiver))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(events)
+}
+
+// Helper function to encode a search event as a Server-Sent Events frame
+fn sse_frame(event: &SearchEvent) -> Bytes {
+    let name: &str = match event {
+        SearchEvent::Candidate { .. } => "candidate",
+        SearchEvent::Summary { .. } => "summary",
+        SearchEvent::Error { .. } => "error",
+    };
+    let data: String = serde_json::to_string(event).unwrap_or_default();
+
+    Bytes::from(format!("event: {}\ndata: {}\n\n", name, data))
+}
+
+/// Report that the process is up and serving requests
+///
+/// Answers 200 even while the stores are loading, so orchestrators can tell
+/// a slow start apart from a dead process.
+///
+/// # HTTP Request
+/// GET /api/live
+#[get("/api/live")]
+async fn get_liveness() -> impl Responder {
+    HttpResponse::Ok().json(BasicResponse::<String> {
+        status: true,
+        message: "The service is running.".to_string(),
+        data: None,
+    })
+}
+
+/// Report whether the embedding provider is reachable
+///
+/// Runs the smallest embedding request the clothes store can make, a single
+/// prompt against a 1×1 image, and reports its latency. Probes are reused for
+/// `STYLIST_EMBED_HEALTH_TTL_SECS`, see `age_secs`, so frequent checks cost
+/// one provider call per period. Answers 503 while the provider fails, which
+/// tells an upstream outage apart from this service being down. A probe
+/// taking longer than `STYLIST_EMBED_HEALTH_TIMEOUT_MS` counts as failed.
+/// The store is only locked to copy its embedder, never while probing.
+///
+/// # HTTP Request
+/// GET /api/embed/health
+#[get("/api/embed/health")]
+async fn get_embedder_health(
+    shared_stores: Data<Arc<SharedStores>>,
+    health_cache: Data<HealthCache>,
+) -> impl Responder {
+    info!("Handling request to check the embedding provider");
+    let timeout_ms: u64 = config::get().embed_health_timeout_ms;
+
+    // the probe only runs, and only locks the store, once the cached one expired
+    let health: EmbedderHealth = health_cache
+        .get_or_probe(async {
+            let started: Instant = Instant::now();
+            let probe = async {
+                let embedder: InMemoryVectorStore =
+                    shared_stores.clothes.read().await.without_entries();
+                embedder.probe_embedder().await
+            };
+            let outcome: Result<(), Error> =
+                match tokio::time::timeout(Duration::from_millis(timeout_ms), probe).await {
+                    Ok(outcome) => outcome,
+                    Err(_) => Err(anyhow!("The probe timed out after {} ms", timeout_ms)),
+                };
+            let latency_ms: f64 = millis(started.elapsed());
+            if let Err(e) = &outcome {
+                warn!("The embedding provider health probe failed: {}", e);
+            }
 
-use actix_web::{
-    delete, get, post,
-    web::{self, Data, Json},
-    HttpResponse, Responder,
-};
-use anyhow::Error;
-use base64;
-use image::{load_from_memory, DynamicImage};
-use log::{error, info, warn};
-use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex;
+            EmbedderHealth {
+                healthy: outcome.is_ok(),
+                latency_ms,
+                error: outcome.err().map(|e| e.to_string()),
+                age_secs: 0.0,
+            }
+        })
+        .await;
 
-use crate::{embedding::VectorStore, SharedStores};
+    let (status, message) = if health.healthy {
+        (StatusCode::OK, "The embedding provider is reachable.")
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The embedding provider is unreachable.",
+        )
+    };
+    HttpResponse::build(status).json(BasicResponse {
+        status: health.healthy,
+        message: message.to_string(),
+        data: Some(health),
+    })
+}
 
-/// Decodes a base64 encoded image string into a DynamicImage
+/// Get the request and cache counters
 ///
-/// # Arguments
-/// * `b64_str` - Base64 encoded string of the image
+/// Counters accumulate from the start of the process or the last reset.
+/// Reading them takes no lock.
 ///
-/// # Returns
-/// * `Result<DynamicImage, Error>` - The decoded image or an error
-pub fn decode_base64_image(b64_str: &str) -> Result<DynamicImage, Error> {
-    let decoded_bytes: Vec<u8> = base64::decode(b64_str)?;
-    let img: DynamicImage = load_from_memory(&decoded_bytes)?;
-    Ok(img)
-}
+/// # HTTP Request
+/// GET /api/stats
+#[get("/api/stats")]
+async fn get_stats(counters: Data<RequestCounters>) -> impl Responder {
+    info!("Handling request to get the counters");
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum Gender {
-    Male,
-    Female,
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Returning the counters.".to_string(),
+        data: Some(counters.snapshot()),
+    })
 }
 
-/// Request structure for uploading images
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ImageUploadRequest {
-    pub name: String,
-    pub gender: Gender,
-    pub image: String, // in base64
+/// Zero the request and cache counters
+///
+/// Returns the values the counters had, so a poller resetting after every
+/// read gets per-interval counts without a race between reading and
+/// resetting. Requires the API token.
+///
+/// # HTTP Request
+/// POST /api/stats/reset
+#[post("/api/stats/reset", wrap = "from_fn(require_token)")]
+async fn reset_stats(counters: Data<RequestCounters>) -> impl Responder {
+    info!("Handling request to reset the counters");
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Counters reset. Returning their previous values.".to_string(),
+        data: Some(counters.reset()),
+    })
 }
 
-/// Example:
-/// ```json
-/// {
-///     "name": "Blue T-shirt",
-///     "gender": "Male",
-///     "image": "base64_encoded_image_string"
-/// }
-/// ```
+/// Estimate the memory used by each store's entries, in bytes
+///
+/// Vectors, names, descriptions, stored images and metadata are counted, so
+/// operators can tell when to shard a store or shorten its vectors.
+///
+/// # HTTP Request
+/// GET /api/stats/memory
+#[get("/api/stats/memory")]
+async fn get_memory_usage(shared_stores: Data<Arc<SharedStores>>) -> impl Responder {
+    info!("Handling request to estimate store memory usage");
+    let clothes: usize = acquire!(shared_stores.clothes.read()).estimated_memory();
+    let face: usize = acquire!(shared_stores.face.read()).estimated_memory();
 
-#[derive(Serialize, Deserialize)]
-struct ImageUploadResponse {
-    id: String,
-    success: bool,
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Returning the estimated bytes used by each store.".to_string(),
+        data: Some(MemoryUsage {
+            clothes,
+            face,
+            total: clothes + face,
+        }),
+    })
 }
 
-/// Request structure for similarity search
-#[derive(Deserialize)]
-struct SimilarityRequest {
-    user_image: String,
-    top_n: usize,
+/// Get the configuration clients may depend on
+///
+/// Lists the names of the clothes prompt sets, loaded from the
+/// subdirectories of `STYLIST_CLOTHES_PROMPTS`. The prompts themselves stay
+/// behind `/api/clothes/prompts`.
+///
+/// # HTTP Request
+/// GET /api/config
+#[get("/api/config")]
+async fn get_config(shared_stores: Data<Arc<SharedStores>>) -> impl Responder {
+    info!("Handling request to get the configuration");
+    let clothes_store = acquire!(shared_stores.clothes.read());
+
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Returning the configuration.".to_string(),
+        data: Some(ConfigResponse {
+            clothes_prompt_sets: clothes_store.prompt_set_names(),
+        }),
+    })
 }
 
-/// Example:
-/// ```json
-/// {
-///     "user_image": "base64_encoded_image_string",
-///     "top_n": 5
-/// }
-/// ```
+/// Get every setting resolved from the environment at startup
+///
+/// Each setting is listed under its environment variable with the value in
+/// effect and its `source`: `env` when the variable was set and parsed,
+/// `default` when it is unset, and `invalid` when it is set but could not be
+/// parsed, so the default is used. Secrets such as `STYLIST_API_TOKEN` only
+/// show whether they are set, and the user and password of the
+/// `*_OPENAI_API_BASE` URLs are redacted. The `host`, `port`,
+/// `store_dimensions` and `prompt_size` settings are fixed in code and
+/// listed with the `fixed` source. Requires the API token.
+///
+/// # HTTP Request
+/// GET /api/config/effective
+#[get("/api/config/effective", wrap = "from_fn(require_token)")]
+async fn get_effective_config() -> impl Responder {
+    info!("Handling request to get the effective configuration");
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct BasicResponse<T: Serialize> {
-    pub status: bool,
-    pub message: String,
-    pub data: Option<T>,
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Returning the effective configuration.".to_string(),
+        data: Some(&config::get().resolved),
+    })
 }
 
-/// Upload a new piece of clothing
+/// Upload a face and store it in the face store
 ///
 /// # HTTP Request
-/// POST /api/clothes/upload
+/// POST /api/face/upload
 ///
 /// # Request Body
-/// JSON object containing name, gender and base64 encoded image
-#[post("/api/clothes/upload")]
-async fn upload_clothes(
-    shared_stores: Data<Arc<Mutex<SharedStores>>>,
-    request: Json<ImageUploadRequest>,
+/// JSON object containing name, base64 encoded image, and optionally
+/// descriptions and arbitrary JSON `metadata` returned with the entry
+///
+/// # Returns
+/// ID of the stored face
+#[post("/api/face/upload", wrap = "from_fn(rate_limit)")]
+async fn upload_face(
+    shared_stores: Data<Arc<SharedStores>>,
+    counters: Data<RequestCounters>,
+    request: Json<FaceUploadRequest>,
 ) -> impl Responder {
+    counters.record_upload();
     info!(
-        "Received upload request for clothes with name: {}",
+        "Received upload request for face with name: {}",
         request.name
     );
 
-    let shared_stores = shared_stores.lock().await;
-    let mut clothes_store = shared_stores.clothes.lock().await;
+    let image: DynamicImage = match decode_validated_image(&request.image) {
+        Ok(image) => image,
+        Err(e) => {
+            error!("Failed to decode base64 image: {}", e);
+            return HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: e.to_string(),
+                data: None,
+            });
+        }
+    };
 
-    match decode_base64_image(&request.image) {
-        Ok(result) => {
-            match clothes_store
-                .add(&request.name, vec!["".to_string()], result)
-                .await
-            {
-                Ok(_) => {
-                    info!("Successfully added clothes: {}", request.name);
-                    HttpResponse::Ok().json(BasicResponse::<String> {
-                        status: true,
-                        message: "Clothes added successfully.".to_string(),
-                        data: None,
-                    })
-                }
-                Err(error) => {
-                    error!("Failed to add clothes to vector store: {}", error);
-                    HttpResponse::InternalServerError().json(BasicResponse::<String> {
-                        status: false,
-                        message: error.to_string(),
-                        data: None,
-                    })
-                }
-            }
+    let mut face_store = acquire!(shared_stores.face.write());
+    let added = face_store
+        .add_with_metadata(
+            &request.name,
+            request.descriptions.clone().unwrap_or_default(),
+            image,
+            request.metadata.clone(),
+            None,
+        )
+        .await;
+
+    match added {
+        Ok(id) => {
+            info!("Successfully added face: {} (id {})", request.name, id);
+            logged!(record_put(&shared_stores, StoreKind::Face, &face_store, id));
+            HttpResponse::Ok().json(BasicResponse {
+                status: true,
+                message: "Face added successfully. Returning the entry id.".to_string(),
+                data: Some(id),
+            })
         }
-        Err(error) => {
-            error!("Failed to decode base64 image: {}", error);
-            HttpResponse::BadRequest().json(BasicResponse::<String> {
+        Err(e) => {
+            error!("Failed to add face to vector store: {}", e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
                 status: false,
-                message: error.to_string(),
+                message: e.to_string(),
                 data: None,
             })
         }
     }
 }
 
-/// Get all clothes
+/// Get all faces, in insertion order
+///
+/// Like `GET /api/clothes/get`, the response carries an `ETag` for
+/// conditional requests.
 ///
 /// # HTTP Request
-/// GET /api/clothes/get
-#[get("/api/clothes/get")]
-async fn get_clothes(shared_stores: Data<Arc<Mutex<SharedStores>>>) -> impl Responder {
-    info!("Handling request to get all clothes");
-    let shared_stores = shared_stores.lock().await;
-    let clothes_store = shared_stores.clothes.lock().await;
-    HttpResponse::Ok().json(clothes_store.get_all())
+/// GET /api/face/get
+#[get("/api/face/get")]
+async fn get_face(
+    http_request: HttpRequest,
+    shared_stores: Data<Arc<SharedStores>>,
+) -> impl Responder {
+    info!("Handling request to get all faces");
+
+    let face_store = acquire!(shared_stores.face.read());
+    let etag: String = shared_stores.etag(&face_store);
+    if etag_matches(&http_request, &etag) {
+        return not_modified(&etag);
+    }
+
+    cached_ok(&etag).json(face_store.get_all())
 }
 
-/// Delete a piece of clothing by ID
+/// Delete a face by ID
 ///
 /// # HTTP Request
-/// DELETE /api/clothes/delete/{id}
+/// DELETE /api/face/delete/{id}
 ///
 /// # URL Parameters
-/// * `id` - The ID of the clothing item to delete
-#[delete("/api/clothes/delete/{id}")]
-async fn delete_clothes(
+/// * `id` - The ID of the face to delete
+#[delete("/api/face/delete/{id}")]
+async fn delete_face(
     id: web::Path<String>,
-    shared_stores: Data<Arc<Mutex<SharedStores>>>,
+    shared_stores: Data<Arc<SharedStores>>,
+    counters: Data<RequestCounters>,
 ) -> impl Responder {
-    info!("Received delete request for clothes id: {}", id);
-    let shared_stores = shared_stores.lock().await;
-    let mut clothes_store = shared_stores.clothes.lock().await;
+    counters.record_delete();
+    info!("Received delete request for face id: {}", id);
 
-    match id.parse::<usize>() {
-        Ok(id) => match clothes_store.delete(id).await {
-            Ok(_) => {
-                info!("Successfully deleted clothes with id: {}", id);
-                HttpResponse::Ok().json(BasicResponse::<String> {
-                    status: true,
-                    message: "Clothes deleted successfully".to_string(),
-                    data: None,
-                })
-            }
-            Err(e) => {
-                error!("Failed to delete clothes with id {}: {}", id, e);
-                HttpResponse::NotFound().json(BasicResponse::<String> {
-                    status: false,
-                    message: format!("Failed to delete clothes: {}", e),
-                    data: None,
-                })
-            }
-        },
+    let id: usize = match id.parse::<usize>() {
+        Ok(id) => id,
         Err(_) => {
             warn!("Invalid ID format provided: {}", id);
-            HttpResponse::BadRequest().json(BasicResponse::<String> {
+            return HttpResponse::BadRequest().json(BasicResponse::<String> {
                 status: false,
                 message: "Invalid ID format".to_string(),
                 data: None,
+            });
+        }
+    };
+
+    let mut face_store = acquire!(shared_stores.face.write());
+    match face_store.delete(id).await {
+        Ok(_) => {
+            info!("Successfully deleted face with id: {}", id);
+            logged!(record_op(
+                &shared_stores,
+                StoreOp::Delete {
+                    store: StoreKind::Face,
+                    id,
+                },
+            ));
+            HttpResponse::Ok().json(BasicResponse::<String> {
+                status: true,
+                message: "Face deleted successfully".to_string(),
+                data: None,
+            })
+        }
+        Err(e) => {
+            error!("Failed to delete face with id {}: {}", id, e);
+            HttpResponse::NotFound().json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to delete face: {}", e),
+                data: None,
             })
         }
     }
 }
 
-/// Calculate similarity between uploaded image and stored clothes
+/// Find the faces most similar to an image
 ///
 /// # HTTP Request
-/// POST /api/similarity/calculate
+/// POST /api/face/similarity
 ///
 /// # Request Body
-/// JSON object containing base64 encoded image and number of results to return
-#[post("/api/similarity/calculate")]
-async fn calculate_similarity(
-    shared_stores: Data<Arc<Mutex<SharedStores>>>,
-    request: web::Json<SimilarityRequest>,
+/// The same body as `POST /api/similarity/calculate`. Faces carry no
+/// gender, so a `gender` filter leaves out every face.
+///
+/// # Response
+/// Like `POST /api/similarity/calculate`, the response holds `requested`,
+/// `returned` and `filtered_out` counts next to the results.
+///
+/// # Query Parameters
+/// * `timing` - When `true`, the response holds a `timing` object with the
+///   `decode_ms`, `embed_ms`, `search_ms` and `total_ms` spent on the search
+#[post("/api/face/similarity", wrap = "from_fn(rate_limit)")]
+async fn calculate_face_similarity(
+    http_request: HttpRequest,
+    shared_stores: Data<Arc<SharedStores>>,
+    counters: Data<RequestCounters>,
+    query: web::Query<TimingQuery>,
+    request: Json<SimilarityRequest>,
 ) -> impl Responder {
+    let started: Instant = Instant::now();
+    counters.record_search();
     info!(
-        "Processing similarity calculation request for top_n: {}",
+        "Processing face similarity request for top_n: {}",
         request.top_n
     );
-    let shared_stores = shared_stores.lock().await;
-    let clothes_store = shared_stores.clothes.lock().await;
+    if let Some(rejection) = reject_large_top_n(request.top_n) {
+        return rejection;
+    }
+    if let Some(rejection) = reject_invalid_tie_epsilon(request.shuffle_ties) {
+        return rejection;
+    }
+    let options: SearchOptions = search_options(&request);
 
-    match decode_base64_image(&request.user_image) {
-        Ok(image) => match clothes_store.search(image, request.top_n).await {
-            Ok(results) => {
-                info!("Successfully completed similarity search");
-                HttpResponse::Ok().json(BasicResponse {
+    let mut timing = Timing::default();
+    let decode_started: Instant = Instant::now();
+    let decoded = decode_base64_image(&request.user_image);
+    timing.decode_ms = millis(decode_started.elapsed());
+    let image: DynamicImage = match decoded {
+        Ok(image) => image,
+        Err(e) => {
+            error!("Failed to decode uploaded image: {}", e);
+            return HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to decode image: {}", e),
+                data: None,
+            });
+        }
+    };
+
+    let face_store = acquire!(shared_stores.face.read());
+    match timed_search(&http_request, &face_store, image, &options, &mut timing).await {
+        Ok((_, results, counts)) => {
+            info!("Successfully completed face similarity search");
+            timing.total_ms = millis(started.elapsed());
+            HttpResponse::Ok().json(SearchResponse {
+                response: BasicResponse {
                     status: true,
                     message: "Search operation succeeded.".to_string(),
                     data: Some(results),
-                })
-            }
-            Err(e) => {
-                error!("Error during similarity search: {}", e);
-                HttpResponse::InternalServerError().json(BasicResponse::<String> {
-                    status: false,
-                    message: format!("Error searching similar images: {}", e),
-                    data: None,
-                })
-            }
-        },
+                },
+                counts,
+                timing: query.timing.unwrap_or(false).then_some(timing),
+            })
+        }
         Err(e) => {
-            error!("Failed to decode uploaded image: {}", e);
-            HttpResponse::BadRequest().json(BasicResponse::<String> {
+            error!("Error during face similarity search: {}", e);
+            HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
                 status: false,
-                message: format!("Failed to decode image: {}", e),
+                message: format!("Error searching similar faces: {}", e),
                 data: None,
             })
         }
@@ -248,9 +4171,8 @@ async fn calculate_similarity(
 /// # Request Body
 /// Empty
 #[get("/api/store/save")]
-async fn save_store(shared_stores: Data<Arc<Mutex<SharedStores>>>) -> impl Responder {
+async fn save_store(shared_stores: Data<Arc<SharedStores>>) -> impl Responder {
     info!("Handling request to save stores to disk");
-    let shared_stores = shared_stores.lock().await;
 
     match shared_stores.save("vector_stores.json").await {
         Ok(_) => {
@@ -272,6 +4194,59 @@ async fn save_store(shared_stores: Data<Arc<Mutex<SharedStores>>>) -> impl Respo
     }
 }
 
+/// Size of the chunks a store export is streamed in
+const EXPORT_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Number of export chunks buffered for a client before serializing waits
+/// for it
+const EXPORT_CHANNEL_CAPACITY: usize = 4;
+
+/// Download both stores as a backup, in the format of `vector_stores.json`
+///
+/// The stores are copied under a brief read lock, then serialized from the
+/// copy while the response is sent, so uploads and deletes are not blocked
+/// while the backup transfers, and the export is never held in memory as a
+/// whole. The backup is a point-in-time snapshot of when the copy was taken:
+/// later mutations are not part of it. Place the file as
+/// `vector_stores.json` and call `/api/store/load` to restore it. Should
+/// serializing fail, the response is cut off rather than ended cleanly.
+///
+/// # HTTP Request
+/// GET /api/store/export
+#[get("/api/store/export", wrap = "from_fn(require_token)")]
+async fn export_store(shared_stores: Data<Arc<SharedStores>>) -> impl Responder {
+    info!("Handling request to export the stores");
+
+    let (sender, receiver) = mpsc::channel(EXPORT_CHANNEL_CAPACITY);
+    let stores: Arc<SharedStores> = shared_stores.get_ref().clone();
+    rt::spawn(async move {
+        let writer = ChannelWriter::new(sender.clone(), EXPORT_CHUNK_BYTES);
+        match stores.export_to(writer).await {
+            Ok(()) => info!("Exported the stores"),
+            Err(e) => {
+                error!("Failed to export the stores: {}", e);
+                let _ = sender.send(Err(e)).await;
+            }
+        }
+    });
+
+    let chunks = stream::unfold(receiver, |mut receiver| async move {
+        let chunk: Result<Vec<u8>, Error> = receiver.recv().await?;
+        let chunk: Result<Bytes, actix_web::Error> = chunk
+            .map(Bytes::from)
+            .map_err(|e| ErrorInternalServerError(e.to_string()));
+        Some((chunk, receiver))
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"vector_stores.json\"",
+        ))
+        .streaming(chunks)
+}
+
 /// Load the vector stores from disk
 ///
 /// # HTTP Request
@@ -280,13 +4255,17 @@ async fn save_store(shared_stores: Data<Arc<Mutex<SharedStores>>>) -> impl Respo
 /// # Request Body
 /// Empty
 #[get("/api/store/load")]
-async fn load_store(shared_stores: Data<Arc<Mutex<SharedStores>>>) -> impl Responder {
+async fn load_store(
+    shared_stores: Data<Arc<SharedStores>>,
+    thumbnails: Data<StdMutex<ThumbnailCache>>,
+) -> impl Responder {
     info!("Handling request to load stores from disk");
-    let shared_stores = shared_stores.lock().await;
 
-    match shared_stores.load("vector_stores.json").await {
+    // the log cannot express a load, so it is folded into a snapshot
+    match shared_stores.load_and_compact("vector_stores.json").await {
         Ok(_) => {
             info!("Successfully loaded vector stores from disk");
+            thumbnails.lock().unwrap().clear();
             HttpResponse::Ok().json(BasicResponse::<String> {
                 status: true,
                 message: "Vector stores loaded successfully".to_string(),
@@ -304,11 +4283,316 @@ async fn load_store(shared_stores: Data<Arc<Mutex<SharedStores>>>) -> impl Respo
     }
 }
 
+/// Move an entry from one store to the other, e.g. after uploading it to the wrong one
+///
+/// The vector is reused if both stores use the same prompts, otherwise the
+/// entry is re-embedded from its stored image, without holding either store.
+/// Both stores are then locked to store the copy and delete the original, so
+/// the entry is never lost or visible in both. An entry changed while it was
+/// being embedded is not moved and answers 409 Conflict; on any failure, the
+/// source store is left as it was.
+///
+/// # HTTP Request
+/// POST /api/move
+///
+/// # Request Body
+/// * `from` - Store holding the entry, `clothes` or `face`
+/// * `to` - Store to move the entry to
+/// * `id` - ID of the entry in the source store
+///
+/// # Returns
+/// ID of the entry in the target store
+#[post("/api/move", wrap = "from_fn(rate_limit)")]
+async fn move_entry(
+    shared_stores: Data<Arc<SharedStores>>,
+    thumbnails: Data<StdMutex<ThumbnailCache>>,
+    request: Json<MoveRequest>,
+) -> impl Responder {
+    let MoveRequest { from, to, id } = request.into_inner();
+    info!(
+        "Handling request to move entry {} from {:?} to {:?}",
+        id, from, to
+    );
+
+    if from == to {
+        return HttpResponse::BadRequest().json(BasicResponse::<String> {
+            status: false,
+            message: "The source and target stores must differ.".to_string(),
+            data: None,
+        });
+    }
+
+    // copy the entry and both layouts under short read locks
+    let (entry, source_layout) = {
+        let source = acquire!(shared_stores.get(from).read());
+        match source.get(id) {
+            Some(entry) => (entry.clone(), source.without_entries()),
+            None => {
+                warn!("No {:?} entry found with id: {}", from, id);
+                return HttpResponse::NotFound().json(BasicResponse::<String> {
+                    status: false,
+                    message: format!("No {:?} entry was found with id {}", from, id),
+                    data: None,
+                });
+            }
+        }
+    };
+    let target_layout: InMemoryVectorStore =
+        acquire!(shared_stores.get(to).read()).without_entries();
+
+    let vector: Vec<f64> = match target_layout.adoption_vector(&entry, &source_layout).await {
+        Ok(vector) => vector,
+        Err(e) => {
+            error!("Failed to move entry {} to the {:?} store: {}", id, to, e);
+            return HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to move the entry: {}", e),
+                data: None,
+            });
+        }
+    };
+
+    // always lock clothes before face, so two opposite moves cannot deadlock
+    let (mut source, mut target) = if from == StoreKind::Clothes {
+        let source = acquire!(shared_stores.clothes.write());
+        let target = acquire!(shared_stores.face.write());
+        (source, target)
+    } else {
+        let target = acquire!(shared_stores.clothes.write());
+        let source = acquire!(shared_stores.face.write());
+        (source, target)
+    };
+
+    // the entry or the target's prompts may have changed while embedding
+    let current_version: Option<u64> = source.get(id).map(|current| current.version);
+    let changed: Option<Error> = match current_version {
+        None => Some(DataEntryErrors::NoDataWasFound.into()),
+        Some(actual) if actual != entry.version => Some(
+            DataEntryErrors::VersionConflict {
+                expected: entry.version,
+                actual,
+            }
+            .into(),
+        ),
+        Some(_) if !target.shares_layout(&target_layout) => Some(anyhow!(
+            "The {:?} store's prompts changed while the entry was being embedded, please retry",
+            to
+        )),
+        Some(_) => None,
+    };
+    if let Some(e) = changed {
+        warn!("Not moving entry {} from {:?}: {}", id, from, e);
+        let status: StatusCode = match status_for_error(&e) {
+            StatusCode::INTERNAL_SERVER_ERROR => StatusCode::CONFLICT,
+            status => status,
+        };
+        return HttpResponse::build(status).json(BasicResponse::<String> {
+            status: false,
+            message: format!("Failed to move the entry: {}", e),
+            data: None,
+        });
+    }
+
+    let new_id: usize = match target.adopt_with_vector(&entry, vector) {
+        Ok(new_id) => new_id,
+        Err(e) => {
+            error!("Failed to move entry {} to the {:?} store: {}", id, to, e);
+            return HttpResponse::build(status_for_error(&e)).json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to move the entry: {}", e),
+                data: None,
+            });
+        }
+    };
+    // the entry was just checked under the same lock, so this cannot miss
+    if let Err(e) = source.delete(id).await {
+        error!(
+            "Failed to delete moved entry {} from the {:?} store: {}",
+            id, from, e
+        );
+    }
+
+    logged!(record_ops(
+        &shared_stores,
+        put_op(to, &target, new_id)
+            .into_iter()
+            .chain([StoreOp::Delete { store: from, id }])
+            .collect(),
+    ));
+    // thumbnails are cached by clothes ID, whichever side of the move it is
+    {
+        let mut thumbnails = thumbnails.lock().unwrap();
+        match from {
+            StoreKind::Clothes => thumbnails.invalidate(id),
+            StoreKind::Face => thumbnails.invalidate(new_id),
+        }
+    }
+
+    info!(
+        "Moved entry {} from {:?} to {:?} as entry {}",
+        id, from, to, new_id
+    );
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Entry moved successfully. Returning its id in the target store.".to_string(),
+        data: Some(new_id),
+    })
+}
+
+/// Replace a live store with an uploaded one in a single step
+///
+/// The upload is deserialized and validated before any lock is taken, then
+/// swapped in under the store's lock, so readers see either the old or the
+/// new store but never a mix of both.
+///
+/// # HTTP Request
+/// POST /api/store/replace
+///
+/// # Query Parameters
+/// * `store` - Store to replace, `clothes` (default) or `face`
+///
+/// # Request Body
+/// A store serialized as JSON, in the same shape as one store of the saved file
+#[post("/api/store/replace")]
+async fn replace_store(
+    shared_stores: Data<Arc<SharedStores>>,
+    thumbnails: Data<StdMutex<ThumbnailCache>>,
+    query: web::Query<StoreQuery>,
+    body: web::Bytes,
+) -> impl Responder {
+    let kind: StoreKind = query.store.unwrap_or(StoreKind::Clothes);
+    info!("Handling request to replace the {:?} store", kind);
+
+    let new_store: InMemoryVectorStore = match serde_json::from_slice(&body) {
+        Ok(new_store) => new_store,
+        Err(e) => {
+            error!("Failed to deserialize the uploaded store: {}", e);
+            return HttpResponse::BadRequest().json(BasicResponse::<String> {
+                status: false,
+                message: format!("Failed to deserialize the uploaded store: {}", e),
+                data: None,
+            });
+        }
+    };
+
+    if let Err(e) = new_store.validate() {
+        error!("Uploaded store failed validation: {}", e);
+        return HttpResponse::BadRequest().json(BasicResponse::<String> {
+            status: false,
+            message: format!("Uploaded store failed validation: {}", e),
+            data: None,
+        });
+    }
+
+    // both stores are locked for the compaction, the clothes store first
+    let mut clothes_store = acquire!(shared_stores.clothes.write());
+    let mut face_store = acquire!(shared_stores.face.write());
+    let store: &mut InMemoryVectorStore = match kind {
+        StoreKind::Clothes => &mut clothes_store,
+        StoreKind::Face => &mut face_store,
+    };
+
+    let counts = ReplaceStoreResponse {
+        old_entries: store.len(),
+        new_entries: new_store.len(),
+    };
+    store.replace(new_store);
+    if kind == StoreKind::Clothes {
+        thumbnails.lock().unwrap().clear();
+    }
+
+    // the log cannot express a replacement, so fold it into a snapshot,
+    // written once the locks are released
+    let compaction = shared_stores.compact_locked(&clothes_store, &face_store);
+    drop(face_store);
+    drop(clothes_store);
+    if let Err(e) = compaction.await {
+        error!("Failed to compact the operation log: {}", e);
+        return HttpResponse::InternalServerError().json(BasicResponse::<String> {
+            status: false,
+            message: format!("Store replaced, but it could not be persisted: {}", e),
+            data: None,
+        });
+    }
+
+    info!(
+        "Replaced the {:?} store: {} entries before, {} after",
+        kind, counts.old_entries, counts.new_entries
+    );
+    HttpResponse::Ok().json(BasicResponse {
+        status: true,
+        message: "Store replaced successfully".to_string(),
+        data: Some(counts),
+    })
+}
+
 pub fn config(cfg: &mut web::ServiceConfig) {
+    // serialized stores can be far larger than the default payload limit
+    cfg.app_data(web::PayloadConfig::new(
+        config::get().max_store_upload_mb * 1024 * 1024,
+    ));
+    cfg.default_service(web::to(unmatched_route));
+
     cfg.service(upload_clothes)
         .service(get_clothes)
+        .service(get_clothes_image)
+        .service(get_many_clothes)
+        .service(edit_clothes)
+        .service(search_clothes_name_fuzzy)
+        .service(get_clothes_tags)
+        .service(get_invalid_clothes)
+        .service(get_unmatched_clothes)
+        .service(get_clothes_range)
+        .service(snapshot_clothes)
+        .service(diff_clothes_snapshots)
+        .service(list_dead_letters)
+        .service(retry_dead_letters)
+        .service(get_clothes_neighbors)
+        .service(get_clothes_eval_pair)
+        .service(record_clothes_eval_verdict)
+        .service(get_clothes_centroid)
+        .service(get_clothes_prompts)
+        .service(export_clothes_config)
+        .service(import_clothes_config)
+        .service(get_clothes_vectors_npy)
+        .service(get_clothes_vector_ids)
+        .service(get_similar_clothes)
+        .service(compare_clothes)
+        .service(explain_clothes)
         .service(delete_clothes)
+        .service(purge_similar_clothes)
+        .service(add_clothes_description)
+        .service(tag_clothes_batch)
+        .service(embed_clothes)
+        .service(estimate_clothes_cost)
+        .service(match_clothes)
+        .service(evaluate_clothes)
+        .service(preprocess_preview)
+        .service(validate_clothes_image)
+        .service(reembed_clothes)
+        .service(truncate_clothes_vectors)
+        .service(import_clothes)
         .service(calculate_similarity)
+        .service(search_clothes_clustered)
+        .service(search_farthest_clothes)
+        .service(stream_similarity)
+        .service(search_clothes_by_vector)
+        .service(search_clothes_collection)
+        .service(clothes_score_histogram)
+        .service(upload_face)
+        .service(get_face)
+        .service(delete_face)
+        .service(calculate_face_similarity)
+        .service(get_stats)
+        .service(reset_stats)
+        .service(get_memory_usage)
+        .service(get_liveness)
+        .service(get_embedder_health)
+        .service(get_config)
+        .service(get_effective_config)
         .service(save_store)
-        .service(load_store);
+        .service(export_store)
+        .service(load_store)
+        .service(replace_store)
+        .service(move_entry);
 }
@@ -0,0 +1,141 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::embedding::{DataEntry, VectorPrecision};
+
+/// Content hash of every entry of a store at one point in time
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntrySnapshot {
+    /// When the snapshot was taken, in seconds since the Unix epoch
+    pub taken_at: u64,
+    /// Content hash of each entry, by ID, see [`content_hash`]
+    pub entries: HashMap<usize, u64>,
+}
+
+impl EntrySnapshot {
+    /// Capture the entries of a store
+    ///
+    /// # Arguments
+    /// * `entries` - The entries, e.g. from `InMemoryVectorStore::iter`
+    pub fn capture<'a>(entries: impl IntoIterator<Item = &'a DataEntry>) -> Self {
+        Self {
+            taken_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs()),
+            entries: entries
+                .into_iter()
+                .map(|entry| (entry.id, content_hash(entry)))
+                .collect(),
+        }
+    }
+}
+
+/// Hash of everything an entry holds but its version
+///
+/// The entry is serialized at full vector precision and hashed with 64-bit
+/// FNV-1a, so the hash is stable across builds. An edit that leaves the
+/// entry as it was, e.g. adding and removing a description, keeps the hash.
+///
+/// # Arguments
+/// * `entry` - The entry to hash
+pub fn content_hash(entry: &DataEntry) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    let serialized: Vec<u8> = serde_json::to_vec(
+        &entry
+            .with_precision(VectorPrecision::Full)
+            .without_version(),
+    )
+    .unwrap_or_default();
+    serialized.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+/// Entries added, removed and changed between two snapshots, by ascending ID
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotDiff {
+    /// IDs only in the later snapshot
+    pub added: Vec<usize>,
+    /// IDs only in the earlier snapshot
+    pub removed: Vec<usize>,
+    /// IDs in both whose content differs, e.g. re-uploaded, re-embedded or edited
+    pub changed: Vec<usize>,
+}
+
+/// Compare two snapshots of the same store
+///
+/// # Arguments
+/// * `from` - The earlier snapshot
+/// * `to` - The later snapshot
+pub fn diff_snapshots(from: &EntrySnapshot, to: &EntrySnapshot) -> SnapshotDiff {
+    let mut diff = SnapshotDiff::default();
+
+    for (id, fingerprint) in &to.entries {
+        match from.entries.get(id) {
+            None => diff.added.push(*id),
+            Some(previous) if previous != fingerprint => diff.changed.push(*id),
+            Some(_) => {}
+        }
+    }
+    diff.removed = from
+        .entries
+        .keys()
+        .filter(|id| !to.entries.contains_key(id))
+        .copied()
+        .collect();
+
+    diff.added.sort_unstable();
+    diff.removed.sort_unstable();
+    diff.changed.sort_unstable();
+    diff
+}
+
+/// Named snapshots kept in memory, dropping the oldest beyond a capacity
+#[derive(Debug)]
+pub struct Snapshots {
+    /// Maximum number of snapshots kept
+    capacity: usize,
+    /// Snapshots by label, from oldest to newest
+    snapshots: Vec<(String, EntrySnapshot)>,
+}
+
+impl Snapshots {
+    /// Create an empty registry
+    ///
+    /// # Arguments
+    /// * `capacity` - Maximum number of snapshots to keep, 0 keeps none
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            snapshots: Vec::new(),
+        }
+    }
+
+    /// Keep a snapshot under a label, replacing any snapshot with that label
+    ///
+    /// Returns the labels of the snapshots evicted to make room.
+    pub fn insert(&mut self, label: String, snapshot: EntrySnapshot) -> Vec<String> {
+        self.snapshots.retain(|(existing, _)| *existing != label);
+        self.snapshots.push((label, snapshot));
+
+        let excess: usize = self.snapshots.len().saturating_sub(self.capacity);
+        self.snapshots
+            .drain(..excess)
+            .map(|(label, _)| label)
+            .collect()
+    }
+
+    /// Get the snapshot taken under a label
+    pub fn get(&self, label: &str) -> Option<&EntrySnapshot> {
+        self.snapshots
+            .iter()
+            .find(|(existing, _)| existing == label)
+            .map(|(_, snapshot)| snapshot)
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+/// Per-endpoint request and cache counters shared between all workers
+#[derive(Debug, Default)]
+pub struct RequestCounters {
+    uploads: AtomicU64,
+    searches: AtomicU64,
+    deletes: AtomicU64,
+    thumbnail_hits: AtomicU64,
+    thumbnail_misses: AtomicU64,
+}
+
+/// Point-in-time copy of the request counters
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct RequestCountersSnapshot {
+    pub uploads: u64,
+    pub searches: u64,
+    pub deletes: u64,
+    pub thumbnail_hits: u64,
+    pub thumbnail_misses: u64,
+}
+
+impl RequestCounters {
+    pub fn record_upload(&self) {
+        self.uploads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_search(&self) {
+        self.searches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_delete(&self) {
+        self.deletes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_thumbnail_hit(&self) {
+        self.thumbnail_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_thumbnail_miss(&self) {
+        self.thumbnail_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Read every counter
+    pub fn snapshot(&self) -> RequestCountersSnapshot {
+        RequestCountersSnapshot {
+            uploads: self.uploads.load(Ordering::Relaxed),
+            searches: self.searches.load(Ordering::Relaxed),
+            deletes: self.deletes.load(Ordering::Relaxed),
+            thumbnail_hits: self.thumbnail_hits.load(Ordering::Relaxed),
+            thumbnail_misses: self.thumbnail_misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Zero every counter, returning the values they had
+    ///
+    /// Each counter is swapped on its own, so an increment racing with the
+    /// reset is counted either before or after it, never lost. Polling with
+    /// resets yields per-interval counts.
+    pub fn reset(&self) -> RequestCountersSnapshot {
+        RequestCountersSnapshot {
+            uploads: self.uploads.swap(0, Ordering::Relaxed),
+            searches: self.searches.swap(0, Ordering::Relaxed),
+            deletes: self.deletes.swap(0, Ordering::Relaxed),
+            thumbnail_hits: self.thumbnail_hits.swap(0, Ordering::Relaxed),
+            thumbnail_misses: self.thumbnail_misses.swap(0, Ordering::Relaxed),
+        }
+    }
+}
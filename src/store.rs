@@ -1,56 +1,945 @@
 use std::{
-    fs::File,
-    io::{BufReader, BufWriter},
-    sync::Arc,
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    future::Future,
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use crate::embedding::InMemoryVectorStore;
-use anyhow::Error;
+use crate::embedding::{
+    serialize_entry_full, DataEntry, DataEntryErrors, InMemoryVectorStore, ValidationErrors,
+    VectorStore, Verdict,
+};
+use anyhow::{anyhow, Error};
+use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
-use tokio::{self, sync::Mutex};
+use tokio::{
+    self,
+    sync::{mpsc, oneshot, Mutex, RwLock},
+};
 
+/// Identifies one of the stores held by [`SharedStores`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    Clothes,
+    Face,
+}
+
+/// A single mutation of a store, as recorded in the operation log
+///
+/// Operations are idempotent, so replaying one that is already part of the
+/// snapshot does no harm.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum StoreOp {
+    /// An entry was added or changed, and now looks like `entry`
+    Put {
+        store: StoreKind,
+        #[serde(serialize_with = "serialize_entry_full")]
+        entry: DataEntry,
+    },
+    /// An entry was deleted
+    Delete { store: StoreKind, id: usize },
+    /// A reviewer's verdict on a pair of entries was recorded
+    Verdict { store: StoreKind, verdict: Verdict },
+}
+
+/// Append-only log of store operations, compacted into a snapshot
+///
+/// Every file of the log is written by a thread of its own, so no async
+/// worker waits for the disk. A compaction first moves the logged operations
+/// aside to `<path>.compacting`, then writes the snapshot holding them, and
+/// only then deletes the moved operations. Operations logged meanwhile go to
+/// the emptied log.
+#[derive(Debug)]
+struct OpLog {
+    /// File the operations are appended to, one JSON object per line
+    path: String,
+    /// File the stores are saved to on compaction
+    snapshot_path: String,
+    /// Number of appended operations that triggers a compaction, 0 for never
+    compact_every: usize,
+    /// Operations appended since the last compaction
+    pending: AtomicUsize,
+    /// Sends appends and compaction steps to the writer thread, in order
+    writer: mpsc::UnboundedSender<LogCommand>,
+}
+
+/// A request to the writer thread of the operation log
+#[derive(Debug)]
+enum LogCommand {
+    /// Append lines and flush them to disk
+    Append {
+        lines: String,
+        done: oneshot::Sender<Result<(), Error>>,
+    },
+    /// Move the logged operations aside for a compaction, answering with the
+    /// number of the cut
+    Cut {
+        done: oneshot::Sender<Result<u64, Error>>,
+    },
+    /// The snapshot holding every operation up to a cut is on disk
+    Compacted {
+        cut: u64,
+        done: oneshot::Sender<Result<(), Error>>,
+    },
+}
+
+/// Saves the stores after a number of mutations
+#[derive(Debug)]
+struct Autosave {
+    /// File the stores are saved to
+    path: String,
+    /// Number of mutations that triggers a save
+    every: usize,
+    /// Delay between reaching the threshold and saving, so a burst of
+    /// mutations is covered by a single save
+    debounce: Duration,
+    /// Mutations since the last save
+    pending: AtomicUsize,
+    /// Whether a save is already waiting for the debounce delay
+    scheduled: AtomicBool,
+}
+
+/// The clothes and face stores, shared between all workers
+///
+/// Each store sits behind its own `RwLock`. Readers, searches included, hold
+/// the read lock for their whole operation, so they see the store as it was
+/// when they started and never a half-applied mutation. Writers wait until
+/// the readers are done. There is no lock around the two stores, so requests
+/// to one store never wait for the other; code locking both takes the clothes
+/// store first.
 #[derive(Debug, Clone)]
 pub struct SharedStores {
-    pub clothes: Arc<Mutex<InMemoryVectorStore>>,
-    pub face: Arc<Mutex<InMemoryVectorStore>>,
+    pub clothes: Arc<RwLock<InMemoryVectorStore>>,
+    pub face: Arc<RwLock<InMemoryVectorStore>>,
+    /// Startup time in milliseconds, part of the ETags so ETags issued
+    /// before a restart do not match the restarted stores
+    started_at: u64,
+    /// Operation log, disabled when unset
+    op_log: Option<Arc<OpLog>>,
+    /// Save after every N mutations, disabled when unset
+    autosave: Option<Arc<Autosave>>,
+    /// Number of backups `load` keeps of the stores it replaces, 0 for none
+    load_backups_kept: usize,
+    /// Whether `save` writes on the calling task while holding the read locks
+    blocking_save: bool,
+    /// Held while a snapshot is written, so saves and compactions sharing a
+    /// path never write its temporary file at the same time. Holds the
+    /// sequence number of the newest copy written to each path, so an older
+    /// copy never replaces a newer one. Never awaited while holding the store
+    /// locks, so a write in progress does not stall the stores.
+    save_guard: Arc<Mutex<HashMap<String, u64>>>,
+    /// Sequence number of the latest copy of the stores taken for a snapshot,
+    /// advanced under the store locks
+    save_sequence: Arc<AtomicU64>,
+}
+
+/// Version of the layout `save` writes, bumped on changes older binaries
+/// cannot read
+///
+/// Files written before versioning count as version 0.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Writer sending what is written to it over a bounded channel, in chunks
+///
+/// Writing blocks while the channel is full, so use it from a blocking
+/// thread, e.g. with [`SharedStores::export_to`]. Once the receiver is
+/// dropped, writes fail with [`std::io::ErrorKind::BrokenPipe`].
+pub struct ChannelWriter {
+    /// Where the chunks are sent
+    sender: tokio::sync::mpsc::Sender<Result<Vec<u8>, Error>>,
+    /// Bytes written since the last chunk was sent
+    buffer: Vec<u8>,
+    /// Size of the chunks sent
+    chunk_bytes: usize,
+}
+
+impl ChannelWriter {
+    /// Create a writer sending chunks of `chunk_bytes` to `sender`
+    ///
+    /// # Arguments
+    /// * `sender` - Where the chunks are sent
+    /// * `chunk_bytes` - Size of the chunks, the last one may be smaller
+    pub fn new(
+        sender: tokio::sync::mpsc::Sender<Result<Vec<u8>, Error>>,
+        chunk_bytes: usize,
+    ) -> Self {
+        Self {
+            sender,
+            buffer: Vec::with_capacity(chunk_bytes),
+            chunk_bytes: chunk_bytes.max(1),
+        }
+    }
+
+    // Helper function to send the buffered bytes as one chunk
+    fn send_buffer(&mut self) -> std::io::Result<()> {
+        let chunk: Vec<u8> =
+            std::mem::replace(&mut self.buffer, Vec::with_capacity(self.chunk_bytes));
+        self.sender
+            .blocking_send(Ok(chunk))
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(bytes);
+        if self.buffer.len() >= self.chunk_bytes {
+            self.send_buffer()?;
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.send_buffer()
+    }
+}
+
+/// The version of a saved file, read before the stores themselves
+#[derive(Deserialize)]
+struct SchemaHeader {
+    #[serde(default)]
+    schema_version: u32,
 }
 
 /// for persistant storage
 #[derive(Serialize, Deserialize)]
 struct PersistentStores {
+    #[serde(default)]
+    schema_version: u32,
     clothes: InMemoryVectorStore,
     face: InMemoryVectorStore,
 }
 
+/// [`PersistentStores`] borrowing the stores, to write them without a copy
+#[derive(Serialize)]
+struct PersistentStoresRef<'a> {
+    schema_version: u32,
+    clothes: &'a InMemoryVectorStore,
+    face: &'a InMemoryVectorStore,
+}
+
 impl SharedStores {
-    // Save both stores to disk
-    pub async fn save(&self, path: &str) -> Result<(), Error> {
-        let clothes = self.clothes.lock().await;
-        let face = self.face.lock().await;
+    pub fn new(
+        clothes: Arc<RwLock<InMemoryVectorStore>>,
+        face: Arc<RwLock<InMemoryVectorStore>>,
+    ) -> Self {
+        Self {
+            clothes,
+            face,
+            started_at: unix_millis(),
+            op_log: None,
+            autosave: None,
+            load_backups_kept: 0,
+            blocking_save: false,
+            save_guard: Arc::new(Mutex::new(HashMap::new())),
+            save_sequence: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Choose how `save` writes the stores
+    ///
+    /// By default, the stores are cloned under their read locks and written
+    /// on Tokio's blocking thread pool once the locks are released, so
+    /// neither the async workers nor writers wait for the serialization,
+    /// even while another save is being written. Blocking saves wait for
+    /// other saves first, then serialize the live stores on the calling task
+    /// while holding the read locks, trading that for not holding a copy of
+    /// both stores in memory during the save.
+    ///
+    /// # Arguments
+    /// * `enabled` - Whether saves block
+    pub fn with_blocking_save(mut self, enabled: bool) -> Self {
+        self.blocking_save = enabled;
+        self
+    }
 
-        let data = PersistentStores {
-            clothes: clothes.clone(),
-            face: face.clone(),
+    /// Save the live stores next to the loaded file before `load` replaces them
+    ///
+    /// Loading a stale or wrong file would otherwise lose the stores in
+    /// memory for good. Each backup is written to [`pre_load_backup_path`]
+    /// with the time of the load, and skipped while both stores are empty.
+    /// Only the newest `keep` backups of a file are kept.
+    ///
+    /// # Arguments
+    /// * `keep` - Number of backups to keep, 0 disables backing up
+    pub fn with_load_backup(mut self, keep: usize) -> Self {
+        self.load_backups_kept = keep;
+        self
+    }
+
+    /// Save the stores after every `every` mutations, bounding how many
+    /// operations a crash can lose
+    ///
+    /// Once the threshold is reached, the save waits for `debounce`, so the
+    /// rest of a burst is covered by the same save instead of one each.
+    ///
+    /// # Arguments
+    /// * `path` - File the stores are saved to
+    /// * `every` - Number of mutations that triggers a save, 0 disables autosaving
+    /// * `debounce` - Delay between reaching the threshold and saving
+    pub fn with_autosave(mut self, path: &str, every: usize, debounce: Duration) -> Self {
+        if every == 0 {
+            self.autosave = None;
+            return self;
+        }
+
+        self.autosave = Some(Arc::new(Autosave {
+            path: path.to_string(),
+            every,
+            debounce,
+            pending: AtomicUsize::new(0),
+            scheduled: AtomicBool::new(false),
+        }));
+        self
+    }
+
+    /// Count a mutation of an entry towards the next autosave
+    ///
+    /// Call this after the mutation, once the store's write lock is released
+    /// or about to be: the save itself runs in the background and waits for it.
+    pub fn record_mutation(&self) {
+        let autosave: &Arc<Autosave> = match &self.autosave {
+            Some(autosave) => autosave,
+            None => return,
         };
 
-        let file = File::create(path)?;
-        let writer = BufWriter::new(file);
-        serde_json::to_writer(writer, &data)?;
+        let pending: usize = autosave.pending.fetch_add(1, Ordering::SeqCst) + 1;
+        if pending < autosave.every || autosave.scheduled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let stores: SharedStores = self.clone();
+        let autosave: Arc<Autosave> = autosave.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(autosave.debounce).await;
+            // mutations from here on count towards the next save
+            let saved: usize = autosave.pending.swap(0, Ordering::SeqCst);
+            autosave.scheduled.store(false, Ordering::SeqCst);
+            match stores.save(&autosave.path).await {
+                Ok(()) => info!("Autosaved the stores after {} mutations", saved),
+                Err(e) => error!("Failed to autosave the stores: {}", e),
+            }
+        });
+    }
+
+    /// Record every mutation in an append-only operation log
+    ///
+    /// # Arguments
+    /// * `path` - File the operations are appended to
+    /// * `snapshot_path` - File the stores are saved to when the log is compacted
+    /// * `compact_every` - Number of operations after which the log is compacted, 0 for never
+    pub fn with_op_log(mut self, path: &str, snapshot_path: &str, compact_every: usize) -> Self {
+        let (writer, commands) = mpsc::unbounded_channel::<LogCommand>();
+        let log_path: String = path.to_string();
+        // the thread stops once every clone of the stores is dropped
+        thread::spawn(move || write_op_log(&log_path, commands));
+
+        self.op_log = Some(Arc::new(OpLog {
+            path: path.to_string(),
+            snapshot_path: snapshot_path.to_string(),
+            compact_every,
+            pending: AtomicUsize::new(0),
+            writer,
+        }));
+        self
+    }
+
+    /// Append an operation to the log, if one is enabled
+    ///
+    /// See [`Self::append_ops`].
+    ///
+    /// # Arguments
+    /// * `op` - The operation to record
+    pub async fn append_op(&self, op: &StoreOp) -> Result<(), Error> {
+        self.append_ops(std::slice::from_ref(op)).await
+    }
+
+    /// Append operations to the log, if one is enabled
+    ///
+    /// Call this while still holding the lock of the mutated store, so the log
+    /// keeps the order of the mutations. The operations are flushed to disk
+    /// together by the writer thread before this returns, so a mutation
+    /// acknowledged once this succeeded survives a crash. Once enough
+    /// operations were appended, a compaction is started in the background.
+    ///
+    /// # Arguments
+    /// * `ops` - The operations to record, in order
+    pub async fn append_ops(&self, ops: &[StoreOp]) -> Result<(), Error> {
+        let op_log: &Arc<OpLog> = match &self.op_log {
+            Some(op_log) => op_log,
+            None => return Ok(()),
+        };
+        if ops.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines: String = String::new();
+        for op in ops {
+            lines.push_str(&serde_json::to_string(op)?);
+            lines.push('\n');
+        }
+        let (done, appended) = oneshot::channel();
+        op_log
+            .writer
+            .send(LogCommand::Append { lines, done })
+            .map_err(|_| writer_stopped())?;
+        appended.await.map_err(|_| writer_stopped())??;
+
+        let pending: usize = op_log.pending.fetch_add(ops.len(), Ordering::SeqCst) + ops.len();
+        if op_log.compact_every > 0 && pending >= op_log.compact_every {
+            op_log.pending.store(0, Ordering::SeqCst);
+            let stores: SharedStores = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = stores.compact().await {
+                    error!("Failed to compact the operation log: {}", e);
+                }
+            });
+        }
+
         Ok(())
     }
 
-    // Load both stores from disk
+    /// Save a snapshot of both stores and empty the operation log
+    ///
+    /// The logged operations are only deleted once the snapshot is on disk,
+    /// so a crash during compaction leaves either the old snapshot and every
+    /// operation since, or the new snapshot and operations it already
+    /// contains. Does nothing when the operation log is disabled.
+    pub async fn compact(&self) -> Result<(), Error> {
+        let compaction = {
+            // both stores stay locked, so no operation can slip in between
+            let clothes = self.clothes.read().await;
+            let face = self.face.read().await;
+            self.compact_locked(&clothes, &face)
+        };
+        compaction.await
+    }
+
+    /// Start compacting the operation log while the caller holds both store
+    /// locks
+    ///
+    /// For mutations the log cannot express, e.g. replacing a store: calling
+    /// this before releasing the locks keeps other operations from being
+    /// logged between the mutation and the snapshot holding it. Lock the
+    /// clothes store before the face store, like everywhere else.
+    ///
+    /// The stores are copied and the log is cut right away. Release the locks,
+    /// then await the returned future, which writes the snapshot.
+    ///
+    /// # Arguments
+    /// * `clothes` - The locked clothes store
+    /// * `face` - The locked face store
+    pub fn compact_locked(
+        &self,
+        clothes: &InMemoryVectorStore,
+        face: &InMemoryVectorStore,
+    ) -> impl Future<Output = Result<(), Error>> + Send + 'static {
+        let started: Option<(Arc<OpLog>, InMemoryVectorStore, InMemoryVectorStore, _)> =
+            self.op_log.as_ref().map(|op_log| {
+                let (done, cut) = oneshot::channel();
+                // a stopped writer is reported by the cut's receiver
+                let _ = op_log.writer.send(LogCommand::Cut { done });
+                (op_log.clone(), clothes.clone(), face.clone(), cut)
+            });
+        let sequence: u64 = self.next_save_sequence();
+        let save_guard: Arc<Mutex<HashMap<String, u64>>> = self.save_guard.clone();
+
+        async move {
+            let Some((op_log, clothes, face, cut)) = started else {
+                return Ok(());
+            };
+            let cut: u64 = cut.await.map_err(|_| writer_stopped())??;
+
+            let mut written = save_guard.lock_owned().await;
+            let snapshot_path: String = op_log.snapshot_path.clone();
+            tokio::task::spawn_blocking(move || {
+                write_in_order(&mut written, &snapshot_path, sequence, &clothes, &face)
+            })
+            .await??;
+
+            let (done, compacted) = oneshot::channel();
+            op_log
+                .writer
+                .send(LogCommand::Compacted { cut, done })
+                .map_err(|_| writer_stopped())?;
+            compacted.await.map_err(|_| writer_stopped())??;
+
+            info!("Compacted the operation log into {}", op_log.snapshot_path);
+            Ok(())
+        }
+    }
+
+    /// Restore the stores from the snapshot and operation log, if they exist
+    ///
+    /// Does nothing when the operation log is disabled.
+    ///
+    /// # Returns
+    /// Number of replayed operations
+    pub async fn restore(&self) -> Result<usize, Error> {
+        let op_log: &Arc<OpLog> = match &self.op_log {
+            Some(op_log) => op_log,
+            None => return Ok(0),
+        };
+
+        if Path::new(&op_log.snapshot_path).exists() {
+            self.load(&op_log.snapshot_path).await?;
+        }
+
+        // operations moved aside by an unfinished compaction come first
+        let mut replayed: usize = 0;
+        for path in [compacting_path(&op_log.path), op_log.path.clone()] {
+            if Path::new(&path).exists() {
+                replayed += self.replay(&path).await?;
+            }
+        }
+        Ok(replayed)
+    }
+
+    /// Apply every operation of a log file to the stores, in order
+    ///
+    /// A malformed last line is skipped with a warning, as it is most likely
+    /// a write interrupted by a crash. Malformed lines elsewhere are errors.
+    ///
+    /// # Arguments
+    /// * `path` - The operation log to replay
+    ///
+    /// # Returns
+    /// Number of replayed operations
+    pub async fn replay(&self, path: &str) -> Result<usize, Error> {
+        let lines: Vec<String> = BufReader::new(File::open(path)?)
+            .lines()
+            .collect::<Result<Vec<String>, _>>()?;
+
+        let mut replayed: usize = 0;
+        for (index, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let op: StoreOp = match serde_json::from_str(line) {
+                Ok(op) => op,
+                Err(e) if index + 1 == lines.len() => {
+                    warn!("Skipping truncated last line of {}: {}", path, e);
+                    break;
+                }
+                Err(e) => {
+                    return Err(anyhow!(
+                        "Invalid operation on line {} of {}: {}",
+                        index + 1,
+                        path,
+                        e
+                    ))
+                }
+            };
+
+            match op {
+                StoreOp::Put { store, entry } => self.get(store).write().await.upsert(entry),
+                StoreOp::Delete { store, id } => {
+                    // deleting an entry twice is harmless
+                    if let Err(e) = self.get(store).write().await.delete(id).await {
+                        if e.downcast_ref::<DataEntryErrors>().is_none() {
+                            return Err(e);
+                        }
+                    }
+                }
+                StoreOp::Verdict { store, verdict } => {
+                    self.get(store).write().await.add_verdict(verdict)
+                }
+            }
+            replayed += 1;
+        }
+
+        Ok(replayed)
+    }
+
+    /// Strong ETag describing the current state of a store
+    ///
+    /// Derived from the store's own generation, which every mutation of its
+    /// entries or configuration advances, so no handler has to remember to
+    /// invalidate it.
+    ///
+    /// # Arguments
+    /// * `store` - The locked store, clothes or face
+    pub fn etag(&self, store: &InMemoryVectorStore) -> String {
+        format!("\"{}-{}\"", self.started_at, store.generation())
+    }
+
+    /// Get the store of the given kind
+    pub fn get(&self, kind: StoreKind) -> &Arc<RwLock<InMemoryVectorStore>> {
+        match kind {
+            StoreKind::Clothes => &self.clothes,
+            StoreKind::Face => &self.face,
+        }
+    }
+
+    // Helper function to number a copy of the stores about to be written.
+    // Call it under the store locks, so the numbers follow the copies.
+    fn next_save_sequence(&self) -> u64 {
+        self.save_sequence.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    // Save both stores to disk, keeping the entries in insertion order
+    //
+    // See `with_blocking_save` for how the stores are written.
+    pub async fn save(&self, path: &str) -> Result<(), Error> {
+        if self.blocking_save {
+            // the guard comes first, so the locks are only held for this write
+            let mut written = self.save_guard.lock().await;
+            let clothes = self.clothes.read().await;
+            let face = self.face.read().await;
+            let sequence: u64 = self.next_save_sequence();
+            return write_in_order(&mut written, path, sequence, &clothes, &face);
+        }
+
+        // the locks are released before waiting for other saves; the
+        // sequence number keeps an older copy from replacing a newer one
+        let (clothes, face, sequence) = {
+            let clothes = self.clothes.read().await;
+            let face = self.face.read().await;
+            (clothes.clone(), face.clone(), self.next_save_sequence())
+        };
+        let mut written = self.save_guard.clone().lock_owned().await;
+        let path: String = path.to_string();
+        tokio::task::spawn_blocking(move || {
+            write_in_order(&mut written, &path, sequence, &clothes, &face)
+        })
+        .await?
+    }
+
+    /// Serialize both stores the way `save` writes them, e.g. for backups
+    ///
+    /// The stores are cloned under their read locks, which are released before
+    /// serializing. The result is a point-in-time snapshot of the moment the
+    /// locks were released: mutations from then on are not part of it, and
+    /// writers only wait for the clone, not for the serialization. The stores
+    /// are serialized on a blocking thread, straight into `writer`.
+    ///
+    /// # Arguments
+    /// * `writer` - Where the serialized stores are written to
+    pub async fn export_to<W: Write + Send + 'static>(&self, mut writer: W) -> Result<(), Error> {
+        let data = {
+            let clothes = self.clothes.read().await;
+            let face = self.face.read().await;
+            PersistentStores {
+                schema_version: SCHEMA_VERSION,
+                clothes: clothes.clone(),
+                face: face.clone(),
+            }
+        };
+
+        tokio::task::spawn_blocking(move || -> Result<(), Error> {
+            serde_json::to_writer(&mut writer, &data)?;
+            writer.flush()?;
+            Ok(())
+        })
+        .await?
+    }
+
+    // Load both stores from disk, in the order they were saved in
+    //
+    // Files of an older schema are migrated, files of a newer one are refused
+    // rather than risk misreading them.
     pub async fn load(&self, path: &str) -> Result<(), Error> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        let data: PersistentStores = serde_json::from_reader(reader)?;
+        self.load_with(path, false).await
+    }
+
+    /// Load both stores from disk like `load`, then compact the operation log
+    ///
+    /// The log cannot express a load, so the loaded stores are folded into a
+    /// snapshot before their locks are released. An error after the stores
+    /// were replaced means the load is not persisted yet.
+    ///
+    /// # Arguments
+    /// * `path` - The file to load
+    pub async fn load_and_compact(&self, path: &str) -> Result<(), Error> {
+        self.load_with(path, true).await
+    }
+
+    // Helper function to load both stores, compacting the operation log
+    // before unlocking them if asked to
+    async fn load_with(&self, path: &str, compact: bool) -> Result<(), Error> {
+        // read the version alone first, then the stores straight into their
+        // types, without building a JSON tree of the whole file
+        let bytes: Vec<u8> = fs::read(path)?;
+        let header: SchemaHeader = serde_json::from_slice(&bytes)?;
+        check_schema_version(header.schema_version)?;
+        let data: PersistentStores = serde_json::from_slice(&bytes)?;
+        if data.clothes.dimensions() == 0 || data.face.dimensions() == 0 {
+            return Err(ValidationErrors::ZeroDimensions.into());
+        }
+
+        let mut clothes = self.clothes.write().await;
+        let mut face = self.face.write().await;
+
+        if self.load_backups_kept > 0 && !(clothes.is_empty() && face.is_empty()) {
+            let backup_path: String = pre_load_backup_path(path, unix_millis());
+            let (old_clothes, old_face) = (clothes.clone(), face.clone());
+            let written_path: String = backup_path.clone();
+            tokio::task::spawn_blocking(move || {
+                write_snapshot(&written_path, &old_clothes, &old_face)
+            })
+            .await??;
+            info!("Backed up the stores to {} before loading", backup_path);
+            let loaded_path: String = path.to_string();
+            let keep: usize = self.load_backups_kept;
+            tokio::task::spawn_blocking(move || prune_pre_load_backups(&loaded_path, keep)).await?;
+        }
 
-        let mut clothes = self.clothes.lock().await;
-        let mut face = self.face.lock().await;
+        clothes.replace(data.clothes);
+        face.replace(data.face);
+        let stripped: usize = clothes.strip_blank_descriptions() + face.strip_blank_descriptions();
+        if stripped > 0 {
+            info!(
+                "Removed {} blank descriptions from the loaded stores",
+                stripped
+            );
+        }
 
-        *clothes = data.clothes;
-        *face = data.face;
+        if compact {
+            let compaction = self.compact_locked(&clothes, &face);
+            drop(face);
+            drop(clothes);
+            compaction.await?;
+        }
 
         Ok(())
     }
 }
+
+// Helper function to get the current time in milliseconds since the Unix epoch
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+/// File the stores are backed up to before loading `path` at a given time
+///
+/// `vector_stores.json` loaded at 1700000000000 is backed up to
+/// `vector_stores.pre-load-backup.1700000000000.json`.
+///
+/// # Arguments
+/// * `path` - The file about to be loaded
+/// * `timestamp_ms` - Time of the load, in milliseconds since the Unix epoch
+pub fn pre_load_backup_path(path: &str, timestamp_ms: u64) -> String {
+    format!(
+        "{}.pre-load-backup.{}.json",
+        path.strip_suffix(".json").unwrap_or(path),
+        timestamp_ms
+    )
+}
+
+/// Backups taken before loading `path`, from oldest to newest
+///
+/// # Arguments
+/// * `path` - The loaded file the backups were taken for
+pub fn pre_load_backups(path: &str) -> Result<Vec<String>, Error> {
+    let stem: &Path = Path::new(path.strip_suffix(".json").unwrap_or(path));
+    let directory: &Path = stem
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let prefix: String = format!(
+        "{}.pre-load-backup.",
+        stem.file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("")
+    );
+
+    let mut backups: Vec<(u64, String)> = Vec::new();
+    for dir_entry in fs::read_dir(directory)? {
+        let file: PathBuf = dir_entry?.path();
+        let timestamp: Option<u64> =
+            file.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| {
+                    name.strip_prefix(&prefix)?
+                        .strip_suffix(".json")?
+                        .parse()
+                        .ok()
+                });
+        if let Some(timestamp) = timestamp {
+            backups.push((timestamp, file.to_string_lossy().into_owned()));
+        }
+    }
+    backups.sort();
+
+    Ok(backups.into_iter().map(|(_, file)| file).collect())
+}
+
+// Helper function to delete the oldest backups of `path` beyond `keep`. A
+// failure is logged, as the load itself already has its backup.
+fn prune_pre_load_backups(path: &str, keep: usize) {
+    let backups: Vec<String> = match pre_load_backups(path) {
+        Ok(backups) => backups,
+        Err(e) => {
+            warn!("Failed to list the backups of {}: {}", path, e);
+            return;
+        }
+    };
+
+    let excess: usize = backups.len().saturating_sub(keep);
+    for backup in &backups[..excess] {
+        match fs::remove_file(backup) {
+            Ok(()) => info!("Deleted the old backup {}", backup),
+            Err(e) => warn!("Failed to delete the old backup {}: {}", backup, e),
+        }
+    }
+}
+
+/// Check that a saved file can be read by this version of stylist
+///
+/// Fails for files written by a newer version, which this binary cannot read
+/// reliably. Older files are read as they are: every field added since
+/// version 0 has a default.
+///
+/// # Arguments
+/// * `version` - The `schema_version` of the file, 0 when it has none
+pub fn check_schema_version(version: u32) -> Result<(), Error> {
+    if version > SCHEMA_VERSION {
+        return Err(anyhow!(
+            "The stores were saved with schema version {}, but this version of stylist only reads up to {}, please upgrade it",
+            version,
+            SCHEMA_VERSION
+        ));
+    }
+
+    if version < SCHEMA_VERSION {
+        info!(
+            "Migrating the stores from schema version {} to {}",
+            version, SCHEMA_VERSION
+        );
+    }
+
+    Ok(())
+}
+
+// Helper function to get the file a compaction moves the logged operations to
+fn compacting_path(path: &str) -> String {
+    format!("{}.compacting", path)
+}
+
+// Helper function to describe a writer thread that is gone, e.g. after a panic
+fn writer_stopped() -> Error {
+    anyhow!("The operation log writer stopped")
+}
+
+// Helper function run by the writer thread of the operation log, handling the
+// commands in the order they were sent. Cuts are numbered, so the operations
+// moved aside are only deleted by the compaction of the latest cut: earlier
+// compactions do not hold the operations moved aside after them.
+fn write_op_log(path: &str, mut commands: mpsc::UnboundedReceiver<LogCommand>) {
+    let compacting: String = compacting_path(path);
+    let mut latest_cut: u64 = 0;
+
+    while let Some(command) = commands.blocking_recv() {
+        match command {
+            LogCommand::Append { lines, done } => {
+                let appended: Result<(), Error> = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .and_then(|mut file| {
+                        file.write_all(lines.as_bytes())?;
+                        file.sync_data()
+                    })
+                    .map_err(Error::from);
+                let _ = done.send(appended);
+            }
+            LogCommand::Cut { done } => {
+                let cut: Result<(), Error> = cut_op_log(path, &compacting);
+                if cut.is_ok() {
+                    latest_cut += 1;
+                }
+                let _ = done.send(cut.map(|()| latest_cut));
+            }
+            LogCommand::Compacted { cut, done } => {
+                let removed: Result<(), Error> = if cut < latest_cut {
+                    Ok(())
+                } else {
+                    match fs::remove_file(&compacting) {
+                        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+                        _ => Ok(()),
+                    }
+                };
+                let _ = done.send(removed);
+            }
+        }
+    }
+}
+
+// Helper function to move the logged operations to the end of the file of
+// operations awaiting a snapshot, leaving the log empty
+fn cut_op_log(path: &str, compacting: &str) -> Result<(), Error> {
+    if !Path::new(path).exists() {
+        return Ok(());
+    }
+    if !Path::new(compacting).exists() {
+        fs::rename(path, compacting)?;
+        return Ok(());
+    }
+
+    // an earlier compaction is still writing its snapshot, so add to what it
+    // moved aside. A crash in between repeats operations, which is harmless.
+    let lines: Vec<u8> = fs::read(path)?;
+    let mut file = OpenOptions::new().append(true).open(compacting)?;
+    file.write_all(&lines)?;
+    file.sync_data()?;
+    File::create(path)?.sync_all()?;
+    Ok(())
+}
+
+// Helper function to write a numbered copy of the stores to a snapshot file,
+// unless a newer copy was already written there. Call it under the save guard.
+fn write_in_order(
+    written: &mut HashMap<String, u64>,
+    path: &str,
+    sequence: u64,
+    clothes: &InMemoryVectorStore,
+    face: &InMemoryVectorStore,
+) -> Result<(), Error> {
+    if written.get(path).is_some_and(|newest| *newest > sequence) {
+        info!("Skipping an outdated write of {}", path);
+        return Ok(());
+    }
+
+    write_snapshot(path, clothes, face)?;
+    written.insert(path.to_string(), sequence);
+    Ok(())
+}
+
+// Helper function to write both stores to a snapshot file
+fn write_snapshot(
+    path: &str,
+    clothes: &InMemoryVectorStore,
+    face: &InMemoryVectorStore,
+) -> Result<(), Error> {
+    let data = PersistentStoresRef {
+        schema_version: SCHEMA_VERSION,
+        clothes,
+        face,
+    };
+
+    // write next to the target and rename, so a crash never leaves a torn
+    // snapshot behind
+    let temp_path: String = format!("{}.tmp", path);
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    serde_json::to_writer(&mut writer, &data)?;
+    let file: File = writer.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)?;
+
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Cursor,
+};
+
+use anyhow::{Error, Result};
+use image::{imageops::FilterType, DynamicImage, ImageFormat};
+
+/// Cache key of a resized image: entry id, requested width and height
+pub type ThumbnailKey = (usize, Option<u32>, Option<u32>);
+
+/// Least-recently-used cache of resized PNG images, bounded in bytes
+#[derive(Debug)]
+pub struct ThumbnailCache {
+    /// Maximum number of bytes held by the cache
+    capacity_bytes: usize,
+    /// Number of bytes currently held by the cache
+    used_bytes: usize,
+    /// Encoded thumbnails by key
+    entries: HashMap<ThumbnailKey, Vec<u8>>,
+    /// Keys from least to most recently used
+    order: VecDeque<ThumbnailKey>,
+}
+
+impl ThumbnailCache {
+    /// Create an empty cache
+    ///
+    /// # Arguments
+    /// * `capacity_bytes` - Maximum number of bytes to keep, 0 disables caching
+    pub fn new(capacity_bytes: usize) -> Self {
+        Self {
+            capacity_bytes,
+            used_bytes: 0,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Get a cached thumbnail and mark it as recently used
+    pub fn get(&mut self, key: &ThumbnailKey) -> Option<Vec<u8>> {
+        let bytes: Vec<u8> = self.entries.get(key)?.clone();
+        self.touch(key);
+        Some(bytes)
+    }
+
+    /// Cache a thumbnail, evicting the least recently used ones to make room
+    ///
+    /// Thumbnails larger than the whole cache are not stored.
+    pub fn insert(&mut self, key: ThumbnailKey, bytes: Vec<u8>) {
+        if bytes.len() > self.capacity_bytes {
+            return;
+        }
+
+        self.remove(&key);
+        while self.used_bytes + bytes.len() > self.capacity_bytes {
+            match self.order.pop_front() {
+                Some(oldest) => {
+                    if let Some(evicted) = self.entries.remove(&oldest) {
+                        self.used_bytes -= evicted.len();
+                    }
+                }
+                None => break,
+            }
+        }
+
+        self.used_bytes += bytes.len();
+        self.order.push_back(key);
+        self.entries.insert(key, bytes);
+    }
+
+    /// Drop every cached thumbnail of an entry
+    pub fn invalidate(&mut self, id: usize) {
+        let keys: Vec<ThumbnailKey> = self
+            .entries
+            .keys()
+            .filter(|key| key.0 == id)
+            .copied()
+            .collect();
+
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+
+    /// Drop every cached thumbnail, e.g. after the whole store was replaced
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.used_bytes = 0;
+    }
+
+    // Helper function to remove a single key from both the map and the order
+    fn remove(&mut self, key: &ThumbnailKey) {
+        if let Some(bytes) = self.entries.remove(key) {
+            self.used_bytes -= bytes.len();
+            self.order.retain(|existing| existing != key);
+        }
+    }
+
+    // Helper function to move a key to the most recently used position
+    fn touch(&mut self, key: &ThumbnailKey) {
+        if let Some(position) = self.order.iter().position(|existing| existing == key) {
+            if let Some(key) = self.order.remove(position) {
+                self.order.push_back(key);
+            }
+        }
+    }
+}
+
+/// Resize an image to fit within the requested box and encode it as PNG
+///
+/// The aspect ratio is preserved. A missing width or height keeps the
+/// original size along that axis.
+///
+/// # Arguments
+/// * `image` - The image to resize
+/// * `width` - Maximum width of the thumbnail
+/// * `height` - Maximum height of the thumbnail
+pub fn resize_to_png(
+    image: &DynamicImage,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> Result<Vec<u8>, Error> {
+    let resized: DynamicImage = image.resize(
+        width.unwrap_or(image.width()),
+        height.unwrap_or(image.height()),
+        FilterType::Triangle,
+    );
+
+    let mut bytes: Vec<u8> = Vec::new();
+    resized.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+
+    Ok(bytes)
+}
@@ -0,0 +1,102 @@
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    HttpMessage, HttpRequest,
+};
+use log::warn;
+
+use crate::config;
+
+/// Durations of the phases a handler went through, e.g. embedding and search
+#[derive(Debug, Default)]
+pub struct RequestTimings {
+    phases: RefCell<Vec<(&'static str, Duration)>>,
+}
+
+/// Record how long a phase of the current request took
+///
+/// This does nothing unless [`log_slow_requests`] is timing the request.
+///
+/// # Arguments
+/// * `request` - The request being handled
+/// * `phase` - Name of the phase, e.g. `embedding`
+/// * `duration` - Time spent in the phase
+pub fn record_phase(request: &HttpRequest, phase: &'static str, duration: Duration) {
+    if let Some(timings) = request.extensions().get::<RequestTimings>() {
+        timings.phases.borrow_mut().push((phase, duration));
+    }
+}
+
+/// Describe a slow request for the log
+///
+/// # Arguments
+/// * `route` - Method and path of the request
+/// * `elapsed` - Time taken by the whole request
+/// * `request_id` - The client's `X-Request-Id`, if it sent one
+/// * `phases` - Phases recorded by the handler, in order
+pub fn describe_slow_request(
+    route: &str,
+    elapsed: Duration,
+    request_id: Option<&str>,
+    phases: &[(&'static str, Duration)],
+) -> String {
+    let mut message: String = format!("Slow request {} took {} ms", route, elapsed.as_millis());
+    if let Some(request_id) = request_id {
+        message.push_str(&format!(" (request id {})", request_id));
+    }
+    if !phases.is_empty() {
+        let phases: Vec<String> = phases
+            .iter()
+            .map(|(phase, duration)| format!("{} {} ms", phase, duration.as_millis()))
+            .collect();
+        message.push_str(&format!(": {}", phases.join(", ")));
+    }
+
+    message
+}
+
+/// Middleware logging requests slower than `STYLIST_SLOW_REQUEST_MS` at warn level
+///
+/// Handlers may split the time with [`record_phase`], which is then logged
+/// along. Every request is let through untimed while the threshold is 0.
+pub async fn log_slow_requests<B: MessageBody>(
+    request: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, actix_web::Error> {
+    let threshold: Duration = Duration::from_millis(config::get().slow_request_ms);
+    if threshold.is_zero() {
+        return next.call(request).await;
+    }
+
+    request.extensions_mut().insert(RequestTimings::default());
+    let route: String = format!("{} {}", request.method(), request.path());
+    let request_id: Option<String> = request
+        .headers()
+        .get("X-Request-Id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let started: Instant = Instant::now();
+
+    let response: ServiceResponse<B> = next.call(request).await?;
+
+    let elapsed: Duration = started.elapsed();
+    if elapsed >= threshold {
+        let extensions = response.request().extensions();
+        let phases: Vec<(&'static str, Duration)> = extensions
+            .get::<RequestTimings>()
+            .map(|timings| timings.phases.borrow().clone())
+            .unwrap_or_default();
+        warn!(
+            "{}",
+            describe_slow_request(&route, elapsed, request_id.as_deref(), &phases)
+        );
+    }
+
+    Ok(response)
+}
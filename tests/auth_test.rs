@@ -0,0 +1,35 @@
+use stylist::auth::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorize() {
+        assert_eq!(authorize(Some("Bearer secret"), Some("secret")), Ok(()));
+        assert_eq!(
+            authorize(Some("Bearer wrong"), Some("secret")),
+            Err(AuthErrors::InvalidToken)
+        );
+        assert_eq!(
+            authorize(Some("Basic secret"), Some("secret")),
+            Err(AuthErrors::MissingToken)
+        );
+        assert_eq!(
+            authorize(None, Some("secret")),
+            Err(AuthErrors::MissingToken)
+        );
+    }
+
+    #[test]
+    fn test_authorize_without_configured_token() {
+        assert_eq!(
+            authorize(Some("Bearer secret"), None),
+            Err(AuthErrors::NoTokenConfigured)
+        );
+        assert_eq!(
+            authorize(Some("Bearer "), Some("")),
+            Err(AuthErrors::NoTokenConfigured)
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use stylist::clustering::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kmeans_groups_similar_vectors() {
+        let vectors: Vec<Vec<f64>> = vec![
+            vec![1.0, 0.0, 0.0],
+            vec![0.0, 1.0, 0.0],
+            vec![0.9, 0.1, 0.0],
+            vec![0.1, 0.9, 0.0],
+            vec![0.0, 0.0, 1.0],
+            vec![0.95, 0.05, 0.0],
+        ];
+        let slices: Vec<&[f64]> = vectors.iter().map(Vec::as_slice).collect();
+
+        let labels: Vec<usize> = kmeans(&slices, 3, 20);
+        assert_eq!(labels[0], labels[2]);
+        assert_eq!(labels[0], labels[5]);
+        assert_eq!(labels[1], labels[3]);
+        assert_ne!(labels[0], labels[1]);
+        assert_ne!(labels[4], labels[0]);
+        assert_ne!(labels[4], labels[1]);
+    }
+
+    #[test]
+    fn test_kmeans_clamps_the_cluster_count() {
+        let vectors: Vec<Vec<f64>> = vec![vec![1.0, 0.0], vec![0.0, 1.0]];
+        let slices: Vec<&[f64]> = vectors.iter().map(Vec::as_slice).collect();
+
+        assert_eq!(kmeans(&slices, 5, 20), vec![0, 1]);
+        assert_eq!(kmeans(&slices, 0, 20), vec![0, 0]);
+        assert!(kmeans(&[], 3, 20).is_empty());
+    }
+}
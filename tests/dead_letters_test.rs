@@ -0,0 +1,105 @@
+use stylist::dead_letters::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use serde_json::Value;
+    use stylist::embedding::Gender;
+
+    // Helper function to describe a failed upload of the given name
+    fn create_letter(name: &str) -> DeadLetter {
+        DeadLetter::new(
+            name.to_string(),
+            Gender::Unisex,
+            vec![],
+            Value::Null,
+            "aW1hZ2U=".to_string(),
+            &anyhow!("connection refused"),
+        )
+    }
+
+    #[test]
+    fn test_queue_drops_oldest_beyond_capacity() {
+        let mut queue = DeadLetterQueue::new(2);
+        assert_eq!(queue.push(create_letter("a")), 1);
+        assert_eq!(queue.push(create_letter("b")), 2);
+        assert_eq!(queue.push(create_letter("c")), 3);
+
+        let names: Vec<String> = queue.list().into_iter().map(|letter| letter.name).collect();
+        assert_eq!(names, vec!["b", "c"]);
+        assert_eq!(queue.list()[0].error, "connection refused");
+
+        assert_eq!(queue.remove(2).unwrap().name, "b");
+        assert!(queue.remove(2).is_none());
+        assert!(!queue.contains(2));
+
+        assert!(queue.record_failure(3, &anyhow!("timed out")));
+        assert_eq!(queue.list()[0].error, "timed out");
+        assert!(!queue.record_failure(2, &anyhow!("timed out")));
+        assert_eq!(queue.len(), 1);
+    }
+
+    // Helper function to get a queue file path unique to a test
+    fn create_path(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("stylist-dlq-{}-{}.json", name, std::process::id()));
+        let path: String = path.to_str().unwrap().to_string();
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_queue_is_restored_from_its_file() {
+        let path: String = create_path("restore");
+
+        let mut queue = DeadLetterQueue::new(10).with_path(&path).unwrap();
+        queue.push(create_letter("a"));
+        queue.push(create_letter("b"));
+        queue.push(create_letter("c"));
+        queue.remove(3);
+        let letters: Vec<DeadLetter> = queue.list();
+        // dropping the queue waits for its pending writes
+        drop(queue);
+
+        let mut restored = DeadLetterQueue::new(10).with_path(&path).unwrap();
+        assert_eq!(restored.list(), letters);
+        // IDs keep counting past the removed upload
+        assert_eq!(restored.push(create_letter("d")), 4);
+        drop(restored);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_queue_reads_the_older_file_format() {
+        let path: String = create_path("legacy");
+        let mut letter: DeadLetter = create_letter("a");
+        letter.id = 5;
+        std::fs::write(&path, serde_json::to_vec(&vec![letter.clone()]).unwrap()).unwrap();
+
+        let mut restored = DeadLetterQueue::new(10).with_path(&path).unwrap();
+        assert_eq!(restored.list(), vec![letter]);
+        assert_eq!(restored.push(create_letter("b")), 6);
+        drop(restored);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_torn_queue_file_is_moved_aside() {
+        let path: String = create_path("torn");
+        std::fs::write(&path, b"{\"next_id\": 3, \"letters\": [").unwrap();
+
+        let restored = DeadLetterQueue::new(10).with_path(&path).unwrap();
+        assert!(restored.is_empty());
+        drop(restored);
+
+        let aside: String = format!("{}.corrupt", path);
+        assert_eq!(
+            std::fs::read(&aside).unwrap(),
+            b"{\"next_id\": 3, \"letters\": [".to_vec()
+        );
+        std::fs::remove_file(&aside).unwrap();
+    }
+}
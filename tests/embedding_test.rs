@@ -3,9 +3,150 @@ use stylist::embedding::*;
 #[cfg(test)]
 mod tests {
     use super::*;
+    use anyhow::Error;
     use dim::prompt::load_prompts;
     use image::{DynamicImage, ImageBuffer, Rgba};
+    use std::{
+        collections::HashSet,
+        sync::{Arc, Mutex},
+    };
+    use stylist::prompts::{
+        dedup_prompts, load_prompt_files, load_prompt_sets, shuffle_prompts, DEFAULT_PROMPT_SET,
+    };
     use tokio;
+    use tokio::sync::RwLock;
+
+    // Embedder returning a constant vector and recording every request it gets
+    #[derive(Debug, Default)]
+    struct MockEmbedder {
+        requests: Mutex<Vec<EmbeddingRequest>>,
+    }
+
+    impl Embedder for MockEmbedder {
+        fn embed(&self, request: EmbeddingRequest, _image: DynamicImage) -> EmbeddingFuture<'_> {
+            let result: Result<Vec<f64>, Error> = Ok(vec![1.0; request.dimensions]);
+            self.requests.lock().unwrap().push(request);
+            Box::pin(async move { result })
+        }
+    }
+
+    // Embedder using the first pixel's RGB channels as the vector
+    #[derive(Debug, Default)]
+    struct PixelEmbedder;
+
+    impl Embedder for PixelEmbedder {
+        fn embed(&self, _request: EmbeddingRequest, image: DynamicImage) -> EmbeddingFuture<'_> {
+            let pixel = image.to_rgb8().get_pixel(0, 0).0;
+            let result: Result<Vec<f64>, Error> =
+                Ok(pixel.iter().map(|channel| *channel as f64).collect());
+            Box::pin(async move { result })
+        }
+    }
+
+    // Embedder returning the channel count and first pixel it receives
+    #[derive(Debug)]
+    struct ChannelEmbedder;
+
+    impl Embedder for ChannelEmbedder {
+        fn embed(&self, _request: EmbeddingRequest, image: DynamicImage) -> EmbeddingFuture<'_> {
+            let mut vector: Vec<f64> = vec![image.color().channel_count() as f64];
+            vector.extend(image.as_bytes().iter().take(4).map(|byte| *byte as f64));
+            let result: Result<Vec<f64>, Error> = Ok(vector);
+            Box::pin(async move { result })
+        }
+    }
+
+    // Embedder like PixelEmbedder, but yielding once so other tasks can interleave
+    #[derive(Debug)]
+    struct YieldingEmbedder;
+
+    impl Embedder for YieldingEmbedder {
+        fn embed(&self, request: EmbeddingRequest, image: DynamicImage) -> EmbeddingFuture<'_> {
+            Box::pin(async move {
+                tokio::task::yield_now().await;
+                PixelEmbedder.embed(request, image).await
+            })
+        }
+    }
+
+    // Embedder scoring each prompt with its own numeric value, and tracking
+    // the largest number of prompts it was scoring at the same time
+    #[derive(Debug, Default)]
+    struct ConcurrencyEmbedder {
+        in_flight: Mutex<usize>,
+        max_in_flight: Mutex<usize>,
+    }
+
+    impl Embedder for ConcurrencyEmbedder {
+        fn embed(&self, request: EmbeddingRequest, _image: DynamicImage) -> EmbeddingFuture<'_> {
+            Box::pin(async move {
+                {
+                    let mut in_flight = self.in_flight.lock().unwrap();
+                    *in_flight += request.prompts.len();
+                    let mut max_in_flight = self.max_in_flight.lock().unwrap();
+                    *max_in_flight = (*max_in_flight).max(*in_flight);
+                }
+                tokio::task::yield_now().await;
+                *self.in_flight.lock().unwrap() -= request.prompts.len();
+
+                let result: Result<Vec<f64>, Error> = Ok(request
+                    .prompts
+                    .iter()
+                    .flat_map(|prompt| vec![prompt.parse::<f64>().unwrap(); request.prompt_size])
+                    .collect());
+                result
+            })
+        }
+    }
+
+    // Embedder returning a vector of the wrong length for its first `failures` calls
+    #[derive(Debug)]
+    struct FlakyEmbedder {
+        failures: usize,
+        calls: Mutex<usize>,
+    }
+
+    impl Embedder for FlakyEmbedder {
+        fn embed(&self, request: EmbeddingRequest, _image: DynamicImage) -> EmbeddingFuture<'_> {
+            let mut calls = self.calls.lock().unwrap();
+            *calls += 1;
+            let length: usize = if *calls <= self.failures {
+                request.dimensions + 1
+            } else {
+                request.dimensions
+            };
+            let result: Result<Vec<f64>, Error> = Ok(vec![1.0; length]);
+            Box::pin(async move { result })
+        }
+    }
+
+    // Helper function to create a single-colour test image
+    fn create_colour_image(red: u8, green: u8, blue: u8) -> DynamicImage {
+        let img_buffer: ImageBuffer<Rgba<u8>, Vec<u8>> =
+            ImageBuffer::from_fn(4, 4, |_, _| Rgba([red, green, blue, 255]));
+        DynamicImage::ImageRgba8(img_buffer)
+    }
+
+    // Helper function to create a store embedding images by colour
+    async fn create_colour_store() -> InMemoryVectorStore {
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder));
+
+        for (name, (red, green, blue)) in [
+            ("red", (255, 0, 0)),
+            ("green", (0, 255, 0)),
+            ("blue", (0, 0, 255)),
+            ("orange", (255, 128, 0)),
+        ] {
+            store
+                .add(name, vec![], create_colour_image(red, green, blue))
+                .await
+                .unwrap();
+        }
+
+        store
+    }
 
     // Helper function to create a test image
     fn create_test_image() -> DynamicImage {
@@ -19,7 +160,7 @@ mod tests {
         let prompts =
             load_prompts("/Users/xinyubao/Documents/aesthetic-prototype/prompts").unwrap();
 
-        InMemoryVectorStore::new(30, vec![], prompts, 2)
+        InMemoryVectorStore::new(30, vec![], prompts, 2).unwrap()
     }
 
     #[test]
@@ -29,6 +170,12 @@ mod tests {
             name: "test".to_string(),
             vector: vec![0.1, 0.2, 0.3],
             descriptions: vec!["test desc".to_string()],
+            image: None,
+            created_at: 0,
+            image_hash: None,
+            metadata: serde_json::Value::Null,
+            version: 0,
+            gender: None,
         };
 
         assert_eq!(entry.id, 1);
@@ -37,12 +184,2176 @@ mod tests {
         assert_eq!(entry.descriptions, vec!["test desc"]);
     }
 
+    #[test]
+    fn test_search_result_derefs_to_its_entry() {
+        let result: SearchResult = SearchResult {
+            score: 0.5,
+            data_entry: DataEntry {
+                id: 7,
+                name: "test".to_string(),
+                vector: vec![],
+                descriptions: vec![],
+                image: None,
+                created_at: 0,
+                image_hash: None,
+                metadata: serde_json::Value::Null,
+                version: 0,
+                gender: None,
+            },
+        };
+
+        assert_eq!(result.id, 7);
+        assert_eq!(result.name, result.data_entry.name);
+    }
+
     #[test]
     fn test_data_entry_errors_display() {
         let error = DataEntryErrors::NoDataWasFound;
         assert_eq!(error.to_string(), "No data entry was found!");
     }
 
+    #[test]
+    fn test_validate_descriptions_count_boundary() {
+        let at_limit: Vec<String> = vec!["casual".to_string(); 3];
+        assert!(validate_descriptions(&at_limit, 3, 16).is_ok());
+
+        let over_limit: Vec<String> = vec!["casual".to_string(); 4];
+        let error = validate_descriptions(&over_limit, 3, 16).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::TooManyDescriptions { max: 3, actual: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_validate_descriptions_length_boundary() {
+        // length is counted in characters, not bytes
+        let at_limit: Vec<String> = vec!["é".repeat(8)];
+        assert!(validate_descriptions(&at_limit, 3, 8).is_ok());
+
+        let over_limit: Vec<String> = vec!["é".repeat(9)];
+        let error = validate_descriptions(&over_limit, 3, 8).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::DescriptionTooLong { max: 8, actual: 9 })
+        ));
+    }
+
+    #[test]
+    fn test_validate_prompt_layout() {
+        assert!(validate_prompt_layout(15, 2, 30).is_ok());
+        // a skipped prompt file leaves a dimension pair unfilled
+        let error = validate_prompt_layout(14, 2, 30).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::PromptLayoutMismatch {
+                prompts: 14,
+                prompt_size: 2,
+                dimensions: 30
+            })
+        ));
+    }
+
+    #[test]
+    fn test_round_to_decimals() {
+        assert_eq!(round_to_decimals(0.123456789, 3), 0.123);
+        assert_eq!(round_to_decimals(-0.98765, 2), -0.99);
+        assert_eq!(round_to_decimals(0.5, 0), 1.0);
+    }
+
+    #[test]
+    fn test_entry_serializes_with_the_given_precision() {
+        let entry = DataEntry {
+            id: 1,
+            name: "shirt".to_string(),
+            vector: vec![0.123456789, -0.98765],
+            descriptions: vec![],
+            image: None,
+            created_at: 0,
+            image_hash: None,
+            metadata: serde_json::Value::Null,
+            version: 0,
+            gender: None,
+        };
+
+        let rounded =
+            serde_json::to_value(entry.with_precision(VectorPrecision::Decimals(2))).unwrap();
+        assert_eq!(rounded["vector"], serde_json::json!([0.12, -0.99]));
+        assert!(rounded.get("metadata").is_none());
+
+        // the full-precision form is what gets persisted, and reads back as is
+        let full = serde_json::to_value(entry.with_precision(VectorPrecision::Full)).unwrap();
+        let restored: DataEntry = serde_json::from_value(full).unwrap();
+        assert_eq!(restored, entry);
+    }
+
+    #[test]
+    fn test_l2_normalize() {
+        let normalized: Vec<f64> = l2_normalize(&[3.0, 4.0]);
+        assert_eq!(normalized, vec![0.6, 0.8]);
+        assert!((l2_norm(&normalized) - 1.0).abs() < 1e-12);
+
+        // a zero vector has no direction and stays as is
+        assert_eq!(l2_normalize(&[0.0, 0.0]), vec![0.0, 0.0]);
+    }
+
+    #[tokio::test]
+    async fn test_prompt_size_is_clamped_after_reloading_fewer_prompts() {
+        let embedder: Arc<MockEmbedder> = Arc::new(MockEmbedder::default());
+        let mut store = InMemoryVectorStore::new(
+            6,
+            vec![],
+            vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            2,
+        )
+        .unwrap()
+        .with_embedder(embedder.clone());
+        assert_eq!(store.effective_prompt_size(), 2);
+
+        store.set_prompts(vec!["a".to_string()]);
+        assert_eq!(store.effective_prompt_size(), 1);
+
+        let vector = store.embed_only(create_test_image()).await;
+        assert!(vector.is_ok());
+
+        let requests = embedder.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].prompt_size, 1);
+        assert_eq!(requests[0].prompts, vec!["a".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_reembed_errors() {
+        let mut store = InMemoryVectorStore::new(2, vec![], vec!["a".to_string()], 2)
+            .unwrap()
+            .with_embedder(Arc::new(MockEmbedder::default()));
+
+        let missing = store.reembed(1).await.unwrap_err();
+        assert!(matches!(
+            missing.downcast_ref::<DataEntryErrors>(),
+            Some(DataEntryErrors::NoDataWasFound)
+        ));
+
+        // image storage is disabled by default
+        store
+            .add("test_image", vec![], create_test_image())
+            .await
+            .unwrap();
+        let not_stored = store.reembed(1).await.unwrap_err();
+        assert!(matches!(
+            not_stored.downcast_ref::<DataEntryErrors>(),
+            Some(DataEntryErrors::ImageNotStored)
+        ));
+    }
+
+    #[test]
+    fn test_image_round_trip() {
+        let image: DynamicImage = create_test_image();
+        let decoded: DynamicImage = decode_image(&encode_image(&image).unwrap()).unwrap();
+        assert_eq!(decoded.to_rgba8(), image.to_rgba8());
+    }
+
+    #[tokio::test]
+    async fn test_search_keeps_best_top_n_in_order() {
+        let store = create_colour_store().await;
+
+        let results = store
+            .search(create_colour_image(255, 32, 0), 2)
+            .await
+            .unwrap();
+        let names: Vec<&str> = results
+            .iter()
+            .map(|result| result.data_entry.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["red", "orange"]);
+        assert!(results[0].score >= results[1].score);
+
+        // asking for more than the store holds returns everything
+        let all = store
+            .search(create_colour_image(255, 32, 0), 10)
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 4);
+        assert!(all.windows(2).all(|pair| pair[0].score >= pair[1].score));
+    }
+
+    #[tokio::test]
+    async fn test_normalized_store_rejects_mismatched_query() {
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_normalized_vectors(true)
+            .with_embedder(Arc::new(PixelEmbedder));
+        store
+            .add("orange", vec![], create_colour_image(255, 128, 0))
+            .await
+            .unwrap();
+
+        // vectors are stored at unit length
+        let norm: f64 = l2_norm(&store.get_all()[0].vector);
+        assert!((norm - 1.0).abs() < 1e-9);
+        assert!(store.validate().is_ok());
+
+        let results = store
+            .search_with(create_colour_image(255, 128, 0), &SearchOptions::top_n(1))
+            .await
+            .unwrap();
+        assert!((results[0].score - 1.0).abs() < 1e-9);
+
+        let raw_query = SearchOptions {
+            top_n: 1,
+            normalize_query: Some(false),
+            ..SearchOptions::default()
+        };
+        let error = store
+            .search_with(create_colour_image(255, 128, 0), &raw_query)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::NormalizationMismatch {
+                store: true,
+                query: false
+            })
+        ));
+    }
+
+    #[test]
+    fn test_score_histogram_bins_full_range() {
+        let histogram = score_histogram(&[-1.0, -0.2, 0.0, 0.4, 0.99, 1.0], 4);
+
+        assert_eq!(histogram.len(), 4);
+        assert_eq!(histogram[0].lower, -1.0);
+        assert_eq!(histogram[3].upper, 1.0);
+        let counts: Vec<usize> = histogram.iter().map(|bucket| bucket.count).collect();
+        assert_eq!(counts, vec![1, 1, 2, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_truncate_vectors() {
+        let mut store = create_colour_store().await;
+
+        let error = store.truncate_vectors(4).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::InvalidTruncation {
+                requested: 4,
+                available: 3
+            })
+        ));
+        assert!(store.truncate_vectors(0).is_err());
+
+        assert_eq!(store.truncate_vectors(2).unwrap(), 4);
+        assert!(store.get_all().iter().all(|entry| entry.vector.len() == 2));
+        assert!(store.validate().is_ok());
+
+        // queries are embedded into the truncated layout as well
+        let results = store
+            .search(create_colour_image(0, 255, 0), 1)
+            .await
+            .unwrap();
+        assert_eq!(results[0].name, "green");
+        assert_eq!(
+            store
+                .embed_only(create_colour_image(255, 128, 0))
+                .await
+                .unwrap(),
+            vec![255.0, 128.0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_centroid() {
+        let empty = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1).unwrap();
+        assert!(empty.centroid().is_none());
+
+        let store = create_colour_store().await;
+        let centroid = store.centroid().unwrap();
+        let expected = [510.0 / 4.0, 383.0 / 4.0, 255.0 / 4.0];
+        for (value, expected) in centroid.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-9);
+        }
+    }
+
+    // Helper function to create a colour store with one entry and the given duplicate strategy
+    async fn create_duplicate_store(strategy: DuplicateStrategy) -> InMemoryVectorStore {
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder))
+            .with_duplicate_strategy(strategy);
+        store
+            .add(
+                "red",
+                vec!["warm".to_string()],
+                create_colour_image(255, 0, 0),
+            )
+            .await
+            .unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_strategy_reject() {
+        let mut store = create_duplicate_store(DuplicateStrategy::Reject).await;
+
+        let error = store
+            .add("red again", vec![], create_colour_image(255, 0, 0))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<DataEntryErrors>(),
+            Some(DataEntryErrors::DuplicateImage(1))
+        ));
+        assert_eq!(store.len(), 1);
+
+        // other images are still accepted
+        store
+            .add("green", vec![], create_colour_image(0, 255, 0))
+            .await
+            .unwrap();
+        assert_eq!(store.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_strategy_merge() {
+        let mut store = create_duplicate_store(DuplicateStrategy::Merge).await;
+
+        let id = store
+            .add(
+                "red again",
+                vec!["warm".to_string(), "bold".to_string()],
+                create_colour_image(255, 0, 0),
+            )
+            .await
+            .unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(store.len(), 1);
+
+        let entry = store.get(1).unwrap();
+        assert_eq!(entry.name, "red");
+        assert_eq!(entry.descriptions, vec!["warm", "bold"]);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_strategy_allow() {
+        let mut store = create_duplicate_store(DuplicateStrategy::Allow).await;
+
+        let id = store
+            .add("red again", vec![], create_colour_image(255, 0, 0))
+            .await
+            .unwrap();
+        assert_eq!(id, 2);
+        assert_eq!(store.len(), 2);
+        assert_eq!(
+            store.find_by_image_hash(image_hash(&create_colour_image(255, 0, 0))),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_duplicate_strategy_from_str() {
+        assert_eq!(
+            "MERGE".parse::<DuplicateStrategy>().unwrap(),
+            DuplicateStrategy::Merge
+        );
+        assert!("skip".parse::<DuplicateStrategy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_many_keeps_request_order() {
+        let store = create_colour_store().await;
+
+        let entries = store.get_many(&[3, 42, 1]);
+        let names: Vec<Option<&str>> = entries
+            .iter()
+            .map(|entry| entry.as_ref().map(|entry| entry.name.as_str()))
+            .collect();
+        assert_eq!(names, vec![Some("blue"), None, Some("red")]);
+    }
+
+    #[test]
+    fn test_prompts_load_in_file_name_order() {
+        let directory =
+            std::env::temp_dir().join(format!("stylist-prompts-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for name in ["c.txt", "a.txt", "b.txt"] {
+            std::fs::write(directory.join(name), name).unwrap();
+        }
+
+        let prompts = load_prompt_files(directory.to_str().unwrap(), "txt", true).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(prompts, vec!["a.txt", "b.txt", "c.txt"]);
+    }
+
+    #[test]
+    fn test_prompts_skip_non_utf8_files_unless_strict() {
+        let directory =
+            std::env::temp_dir().join(format!("stylist-binary-prompts-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("a.txt"), "a casual look").unwrap();
+        std::fs::write(directory.join("b.txt"), [0xff, 0xfe, 0x00, 0x80]).unwrap();
+
+        let lenient = load_prompt_files(directory.to_str().unwrap(), "txt", false);
+        let strict = load_prompt_files(directory.to_str().unwrap(), "txt", true);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(lenient.unwrap(), vec!["a casual look"]);
+        assert!(strict.is_err());
+    }
+
+    #[test]
+    fn test_prompt_set_named_default_is_rejected() {
+        let directory =
+            std::env::temp_dir().join(format!("stylist-default-set-{}", std::process::id()));
+        std::fs::create_dir_all(directory.join(DEFAULT_PROMPT_SET)).unwrap();
+        std::fs::write(directory.join("a.txt"), "a casual look").unwrap();
+        std::fs::write(
+            directory.join(DEFAULT_PROMPT_SET).join("a.txt"),
+            "a formal look",
+        )
+        .unwrap();
+
+        let sets = load_prompt_sets(directory.to_str().unwrap(), "txt", true);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let error = sets.unwrap_err();
+        assert!(error.to_string().contains("rename"));
+    }
+
+    #[test]
+    fn test_prompt_sets_load_from_subdirectories() {
+        let directory =
+            std::env::temp_dir().join(format!("stylist-prompt-sets-{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("formal")).unwrap();
+        std::fs::create_dir_all(directory.join("empty")).unwrap();
+        std::fs::write(directory.join("a.txt"), "a casual look").unwrap();
+        std::fs::write(directory.join("formal").join("a.txt"), "a formal look").unwrap();
+
+        let with_empty = load_prompt_sets(directory.to_str().unwrap(), "txt", true);
+        std::fs::remove_dir_all(directory.join("empty")).unwrap();
+        let sets = load_prompt_sets(directory.to_str().unwrap(), "txt", true).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(with_empty.is_err());
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets[DEFAULT_PROMPT_SET], vec!["a casual look"]);
+        assert_eq!(sets["formal"], vec!["a formal look"]);
+
+        let store = InMemoryVectorStore::new(8, vec![], vec![], 1)
+            .unwrap()
+            .with_prompt_sets(sets);
+        assert_eq!(store.prompt_set_names(), vec!["default", "formal"]);
+        assert_eq!(store.prompt_set("formal").unwrap(), ["a formal look"]);
+        assert!(store.prompt_set("missing").is_none());
+    }
+
+    #[test]
+    fn test_dedup_prompts_keeps_first_seen_order() {
+        let mut prompts: Vec<String> = ["red", "blue", "red", "green", "blue"]
+            .iter()
+            .map(|prompt| prompt.to_string())
+            .collect();
+
+        assert_eq!(dedup_prompts(&mut prompts), 2);
+        assert_eq!(prompts, vec!["red", "blue", "green"]);
+        assert_eq!(dedup_prompts(&mut prompts), 0);
+    }
+
+    #[test]
+    fn test_shuffle_prompts_is_seeded() {
+        let prompts: Vec<String> = (0..16).map(|index| index.to_string()).collect();
+
+        let mut first = prompts.clone();
+        let mut second = prompts.clone();
+        shuffle_prompts(&mut first, 42);
+        shuffle_prompts(&mut second, 42);
+        assert_eq!(first, second);
+        assert_ne!(first, prompts);
+
+        let mut sorted = first.clone();
+        sorted.sort_by_key(|prompt| prompt.parse::<usize>().unwrap());
+        assert_eq!(sorted, prompts);
+    }
+
+    #[tokio::test]
+    async fn test_rgba_and_rgb_embed_identically() {
+        let store = InMemoryVectorStore::new(5, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(ChannelEmbedder));
+
+        let rgba = create_test_image();
+        let rgb = DynamicImage::ImageRgb8(rgba.to_rgb8());
+
+        let from_rgba = store.embed_only(rgba).await.unwrap();
+        let from_rgb = store.embed_only(rgb).await.unwrap();
+        assert_eq!(from_rgba, from_rgb);
+        assert_eq!(from_rgba[0], 3.0);
+    }
+
+    #[tokio::test]
+    async fn test_similar_to_excludes_entry_and_same_name() {
+        let mut store = create_colour_store().await;
+        store
+            .add("red", vec![], create_colour_image(250, 10, 0))
+            .await
+            .unwrap();
+
+        let results = store.similar_to(1, 2, false).unwrap();
+        let ids: Vec<usize> = results.iter().map(|result| result.data_entry.id).collect();
+        assert_eq!(ids, vec![5, 4]);
+
+        let results = store.similar_to(1, 2, true).unwrap();
+        let names: Vec<&str> = results
+            .iter()
+            .map(|result| result.data_entry.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["orange", "green"]);
+
+        assert!(store.similar_to(42, 2, true).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search_sees_store_as_of_its_start() {
+        let store = Arc::new(RwLock::new(
+            create_colour_store()
+                .await
+                .with_embedder(Arc::new(YieldingEmbedder)),
+        ));
+
+        // the search takes the read lock first, the adds queue up behind it
+        let search = async {
+            let guard = store.read().await;
+            guard.search(create_colour_image(255, 32, 0), 10).await
+        };
+        let adds = async {
+            for index in 0..8u8 {
+                store
+                    .write()
+                    .await
+                    .add(
+                        &format!("extra {}", index),
+                        vec![],
+                        create_colour_image(255, index * 30, 0),
+                    )
+                    .await
+                    .unwrap();
+            }
+        };
+        let (results, ()) = tokio::join!(search, adds);
+
+        let results = results.unwrap();
+        assert_eq!(results.len(), 4);
+        assert!(results
+            .windows(2)
+            .all(|pair| pair[0].score >= pair[1].score));
+        let ids: HashSet<usize> = results.iter().map(|result| result.data_entry.id).collect();
+        assert_eq!(ids.len(), 4);
+        assert_eq!(store.read().await.len(), 12);
+    }
+
+    #[tokio::test]
+    async fn test_search_with_query_returns_ranking_vector() {
+        let store = create_colour_store().await;
+
+        let (query_vector, results, _) = store
+            .search_with_query(create_colour_image(255, 32, 0), &SearchOptions::top_n(2))
+            .await
+            .unwrap();
+        assert_eq!(query_vector, vec![255.0, 32.0, 0.0]);
+        assert_eq!(results.len(), 2);
+    }
+
+    #[test]
+    fn test_zero_dimensions_are_rejected() {
+        let error = InMemoryVectorStore::new(0, vec![], vec!["a".to_string()], 1).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::ZeroDimensions)
+        ));
+        assert!(InMemoryVectorStore::new(3, vec![], vec!["a".to_string()], 1).is_ok());
+
+        // stores deserialized from a snapshot are checked as well
+        let mut json: serde_json::Value =
+            serde_json::to_value(InMemoryVectorStore::new(3, vec![], vec![], 1).unwrap()).unwrap();
+        json["dimensions"] = 0.into();
+        let store: InMemoryVectorStore = serde_json::from_value(json).unwrap();
+        assert!(store.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_explain_splits_vector_by_prompt() {
+        let prompts: Vec<String> = (0..3).map(|prompt| prompt.to_string()).collect();
+        let mut store = InMemoryVectorStore::new(6, vec![], prompts.clone(), 2)
+            .unwrap()
+            .with_embedder(Arc::new(ConcurrencyEmbedder::default()));
+        let id = store
+            .add("test_image", vec![], create_test_image())
+            .await
+            .unwrap();
+
+        let explanation = store.explain(id).unwrap();
+        assert_eq!(explanation.prompts, prompts);
+        let contributions = explanation.contributions.unwrap();
+        assert_eq!(contributions.len(), 3);
+        assert_eq!(contributions[2].prompt, "2");
+        assert_eq!(contributions[2].offset, 4);
+        assert_eq!(contributions[2].values, vec![2.0, 2.0]);
+
+        // vectors not laid out one slice per prompt are returned as they are
+        let store = create_colour_store().await;
+        let explanation = store.explain(1).unwrap();
+        assert_eq!(explanation.vector, vec![255.0, 0.0, 0.0]);
+        assert!(explanation.contributions.is_none());
+        assert!(store.explain(42).is_err());
+    }
+
+    #[test]
+    fn test_validate_image_limits() {
+        let image: DynamicImage = create_test_image();
+
+        assert!(validate_image(1000, &image, 1000, 100).is_ok());
+        assert!(matches!(
+            validate_image(1001, &image, 1000, 100)
+                .unwrap_err()
+                .downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::ImageTooLarge {
+                max: 1000,
+                actual: 1001
+            })
+        ));
+        assert!(matches!(
+            validate_image(1000, &image, 1000, 99)
+                .unwrap_err()
+                .downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::ImageDimensionsTooLarge {
+                max: 99,
+                width: 100,
+                height: 100
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_match_tracking() {
+        let mut store = create_colour_store().await.with_match_tracking(true);
+
+        for _ in 0..2 {
+            store
+                .search(create_colour_image(255, 0, 0), 1)
+                .await
+                .unwrap();
+        }
+        store
+            .search(create_colour_image(0, 0, 255), 1)
+            .await
+            .unwrap();
+        // similar_to ranks every entry and does not count
+        store.similar_to(2, 3, false).unwrap();
+
+        assert_eq!(store.match_counts().get(1), 2);
+        assert_eq!(store.match_counts().get(3), 1);
+        let unmatched: Vec<usize> = store
+            .unmatched_entries()
+            .iter()
+            .map(|entry| entry.id)
+            .collect();
+        assert_eq!(unmatched, vec![2, 4]);
+
+        // counts are persisted, and forgotten with their entry
+        let json: String = serde_json::to_string(&store).unwrap();
+        let restored: InMemoryVectorStore = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.match_counts().get(1), 2);
+        store.delete(1).await.unwrap();
+        assert_eq!(store.match_counts().get(1), 0);
+    }
+
+    #[tokio::test]
+    async fn test_match_tracking_is_opt_in() {
+        let store = create_colour_store().await;
+        store
+            .search(create_colour_image(255, 0, 0), 1)
+            .await
+            .unwrap();
+
+        assert!(!store.tracks_matches());
+        assert_eq!(store.match_counts().get(1), 0);
+    }
+
+    #[tokio::test]
+    async fn test_adopt_reuses_vector_of_shared_layout() {
+        let source = create_colour_store().await;
+        let mut target = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder));
+        target
+            .add("white", vec![], create_colour_image(255, 255, 255))
+            .await
+            .unwrap();
+
+        let entry = source.get(2).unwrap().clone();
+        let new_id: usize = target.adopt(&entry, &source).await.unwrap();
+
+        assert_eq!(new_id, 2);
+        let adopted = target.get(new_id).unwrap();
+        assert_eq!(adopted.name, "green");
+        assert_eq!(adopted.vector, entry.vector);
+        // the source is left for the caller to update
+        assert_eq!(source.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_adopt_needs_image_for_other_layout() {
+        let source = create_colour_store().await;
+        let mut target = InMemoryVectorStore::new(3, vec![], vec!["hue".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder));
+
+        assert!(!target.shares_layout(&source));
+        let entry = source.get(1).unwrap().clone();
+        let error = target.adopt(&entry, &source).await.unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<DataEntryErrors>(),
+            Some(DataEntryErrors::ImageNotStored)
+        ));
+        assert!(target.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_vector_skips_embedding() {
+        let store = create_colour_store().await;
+
+        let (results, counts) = store
+            .search_vector(vec![0.0, 0.0, 200.0], &SearchOptions::top_n(1))
+            .unwrap();
+        assert_eq!(results[0].data_entry.name, "blue");
+        assert_eq!(counts.returned, 1);
+
+        let error = store
+            .search_vector(vec![1.0; 4], &SearchOptions::top_n(1))
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::QueryDimensionMismatch {
+                expected: 3,
+                actual: 4
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_search_with_huge_top_n_returns_every_entry() {
+        let store = create_colour_store().await;
+
+        let (results, counts) = store
+            .search_vector(vec![0.0, 0.0, 200.0], &SearchOptions::top_n(usize::MAX))
+            .unwrap();
+        assert_eq!(results.len(), store.len());
+        assert_eq!(counts.requested, usize::MAX);
+    }
+
+    #[test]
+    fn test_validate_top_n_boundary() {
+        assert!(validate_top_n(1000, 1000).is_ok());
+
+        let error = validate_top_n(1001, 1000).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::TopNTooLarge {
+                max: 1000,
+                actual: 1001
+            })
+        ));
+    }
+
+    #[test]
+    fn test_validate_tie_epsilon() {
+        assert!(validate_tie_epsilon(0.0).is_ok());
+        assert!(validate_tie_epsilon(0.01).is_ok());
+
+        for epsilon in [-0.01, f64::NAN] {
+            let error = validate_tie_epsilon(epsilon).unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<ValidationErrors>(),
+                Some(ValidationErrors::InvalidTieEpsilon(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_estimated_memory_grows_with_entries() {
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder));
+        assert_eq!(store.estimated_memory(), 0);
+
+        store
+            .add("red", vec![], create_colour_image(255, 0, 0))
+            .await
+            .unwrap();
+        let one_entry: usize = store.estimated_memory();
+        assert!(one_entry >= 3 * std::mem::size_of::<f64>() + "red".len());
+
+        store
+            .add(
+                "a much longer name",
+                vec!["blue".to_string()],
+                create_colour_image(0, 0, 255),
+            )
+            .await
+            .unwrap();
+        assert!(store.estimated_memory() > 2 * one_entry);
+    }
+
+    #[tokio::test]
+    async fn test_find_by_name_fuzzy() {
+        let store = create_colour_store().await;
+
+        let matches = store.find_by_name_fuzzy("Gren", 2);
+        let found: Vec<(&str, usize)> = matches
+            .iter()
+            .map(|name_match| (name_match.data_entry.name.as_str(), name_match.distance))
+            .collect();
+        assert_eq!(found, vec![("green", 1), ("red", 2)]);
+
+        assert!(store.find_by_name_fuzzy("purple", 1).is_empty());
+        assert_eq!(store.find_by_name_fuzzy("blue", 0).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_edit_keeps_id_and_checks_version() {
+        let mut store = create_colour_store().await;
+        let mut entry = store.get(1).unwrap().clone();
+        assert_eq!(entry.version, 0);
+
+        entry.name = "crimson".to_string();
+        let version: u64 = store
+            .edit_versioned(create_colour_image(200, 0, 0), entry.clone(), Some(0))
+            .await
+            .unwrap();
+        assert_eq!(version, 1);
+        let edited = store.get(1).unwrap();
+        assert_eq!(edited.name, "crimson");
+        assert_eq!(edited.vector, vec![200.0, 0.0, 0.0]);
+        assert_eq!(store.len(), 4);
+
+        // a second client still holding version 0 must not overwrite the edit
+        entry.name = "scarlet".to_string();
+        let error = store
+            .edit_versioned(create_colour_image(255, 0, 0), entry, Some(0))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<DataEntryErrors>(),
+            Some(DataEntryErrors::VersionConflict {
+                expected: 0,
+                actual: 1
+            })
+        ));
+        assert_eq!(store.get(1).unwrap().name, "crimson");
+    }
+
+    #[tokio::test]
+    async fn test_store_config_round_trip() {
+        let mut store = create_colour_store().await;
+        let mut store_config: StoreConfig = store.store_config();
+        assert_eq!(store_config.dimensions, 3);
+        assert_eq!(store_config.prompts, vec!["colour"]);
+
+        store_config.prompts = vec!["hue".to_string()];
+        store_config.prompt_size = 3;
+        store.set_store_config(store_config.clone()).unwrap();
+        assert_eq!(store.store_config(), store_config);
+        assert_eq!(store.len(), 4);
+
+        // the stored vectors have 3 dimensions, so 4 must be rejected
+        let mut wider = store_config.clone();
+        wider.dimensions = 4;
+        wider.prompt_size = 4;
+        let error = store.set_store_config(wider).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::DimensionMismatch { .. })
+        ));
+        assert_eq!(store.store_config(), store_config);
+
+        // two prompts of size 3 cannot fill 3 dimensions
+        let mut extra_prompt = store_config.clone();
+        extra_prompt.prompts.push("tone".to_string());
+        let error = store.set_store_config(extra_prompt).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::PromptLayoutMismatch {
+                prompts: 2,
+                prompt_size: 3,
+                dimensions: 3
+            })
+        ));
+        assert_eq!(store.store_config(), store_config);
+    }
+
+    #[test]
+    fn test_decay_factor_halves_every_half_life() {
+        assert_eq!(decay_factor(0, 100), 1.0);
+        assert_eq!(decay_factor(100, 100), 0.5);
+        assert_eq!(decay_factor(300, 100), 0.125);
+        assert_eq!(decay_factor(1_000, 0), 1.0);
+    }
+
+    #[test]
+    fn test_decay_leaves_negative_scores_alone() {
+        assert_eq!(decay_score(0.8, 100, 100), 0.4);
+        assert_eq!(decay_score(-0.5, 100, 100), -0.5);
+        assert_eq!(decay_score(0.0, 100, 100), 0.0);
+    }
+
+    #[test]
+    fn test_decay_favours_recent_entries() {
+        let now: u64 = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let create_entry = |id: usize, name: &str, vector: Vec<f64>, created_at: u64| DataEntry {
+            id,
+            name: name.to_string(),
+            vector,
+            descriptions: vec![],
+            image: None,
+            created_at,
+            image_hash: None,
+            metadata: serde_json::Value::Null,
+            version: 0,
+            gender: None,
+        };
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1).unwrap();
+        store
+            .set_entries(vec![
+                create_entry(1, "old red", vec![255.0, 0.0, 0.0], now - 10 * 86_400),
+                create_entry(2, "new orange", vec![255.0, 128.0, 0.0], now),
+            ])
+            .unwrap();
+        let query: Vec<f64> = vec![255.0, 0.0, 0.0];
+
+        let (results, _) = store
+            .search_vector(query.clone(), &SearchOptions::top_n(2))
+            .unwrap();
+        assert_eq!(results[0].data_entry.name, "old red");
+
+        let decayed = SearchOptions {
+            decay_half_life_secs: Some(86_400),
+            ..SearchOptions::top_n(2)
+        };
+        let (results, _) = store.search_vector(query, &decayed).unwrap();
+        assert_eq!(results[0].data_entry.name, "new orange");
+        assert!(results[1].score < 0.001);
+    }
+
+    #[tokio::test]
+    async fn test_search_collection() {
+        let store = create_colour_store().await;
+
+        // red and green average to a yellow, which orange is closest to
+        let (results, counts) = store.search_collection(&[1, 2], 1).unwrap();
+        assert_eq!(results[0].data_entry.name, "orange");
+        assert_eq!(counts.filtered_out, 2);
+
+        let error = store.search_collection(&[1, 42], 1).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<DataEntryErrors>(),
+            Some(DataEntryErrors::NoDataWasFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_projection_maps_raw_embeddings() {
+        let mut store = InMemoryVectorStore::new(2, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder));
+        // keep red and blue, dropping green
+        store
+            .set_projection(Some(vec![vec![1.0, 0.0, 0.0], vec![0.0, 0.0, 1.0]]))
+            .unwrap();
+
+        let vector = store
+            .embed_only(create_colour_image(10, 20, 30))
+            .await
+            .unwrap();
+        assert_eq!(vector, vec![10.0, 30.0]);
+
+        let error = store
+            .set_projection(Some(vec![vec![1.0, 0.0, 0.0]]))
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::InvalidProjection {
+                expected_rows: 2,
+                rows: 1
+            })
+        ));
+        let error = store
+            .set_projection(Some(vec![vec![1.0, 0.0, 0.0], vec![1.0]]))
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::RaggedProjection { row: 1 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tag_frequencies() {
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder));
+        for (name, descriptions, (red, green, blue)) in [
+            ("red", vec!["Casual", "cotton"], (255, 0, 0)),
+            ("green", vec!["casual", ""], (0, 255, 0)),
+            ("blue", vec!["cotton", "wool"], (0, 0, 255)),
+        ] {
+            let descriptions: Vec<String> = descriptions.iter().map(|d| d.to_string()).collect();
+            store
+                .add(name, descriptions, create_colour_image(red, green, blue))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            store.tag_frequencies(),
+            vec![
+                ("cotton".to_string(), 2),
+                ("Casual".to_string(), 1),
+                ("casual".to_string(), 1),
+                ("wool".to_string(), 1),
+            ]
+        );
+
+        // entries stored before normalization was enabled are counted alike
+        let store = store.with_normalized_tags(true);
+        assert_eq!(
+            store.tag_frequencies()[0..2].to_vec(),
+            vec![("casual".to_string(), 2), ("cotton".to_string(), 2),]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_phantom_empty_tag() {
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder));
+        store
+            .add("red", vec![], create_colour_image(255, 0, 0))
+            .await
+            .unwrap();
+        store
+            .add(
+                "green",
+                vec!["".to_string()],
+                create_colour_image(0, 255, 0),
+            )
+            .await
+            .unwrap();
+        assert!(store.get(1).unwrap().descriptions.is_empty());
+        assert!(store.get(2).unwrap().descriptions.is_empty());
+        assert!(store.tag_frequencies().is_empty());
+
+        // stores saved with a blank default description are cleaned up
+        let mut legacy: DataEntry = store.get(1).unwrap().clone();
+        legacy.descriptions = vec!["".to_string()];
+        store.upsert(legacy);
+        assert_eq!(store.strip_blank_descriptions(), 1);
+        assert!(store.get(1).unwrap().descriptions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_match_entry() {
+        let store = create_colour_store().await;
+
+        let result = store
+            .match_entry(create_colour_image(200, 0, 0), 1)
+            .await
+            .unwrap();
+        assert_eq!(result.data_entry.name, "red");
+        assert!((result.score - 1.0).abs() < 1e-9);
+
+        let blue = store
+            .match_entry(create_colour_image(200, 0, 0), 3)
+            .await
+            .unwrap();
+        assert_eq!(blue.score, 0.0);
+
+        let error = store
+            .match_entry(create_colour_image(200, 0, 0), 42)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<DataEntryErrors>(),
+            Some(DataEntryErrors::NoDataWasFound)
+        ));
+    }
+
+    #[test]
+    fn test_calls_per_image_counts_prompts() {
+        let prompts: Vec<String> = vec!["colour".to_string(), "style".to_string()];
+        let store = InMemoryVectorStore::new(6, vec![], prompts, 3).unwrap();
+        assert_eq!(store.calls_per_image(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_probe_embedder_sends_a_single_prompt() {
+        let embedder: Arc<MockEmbedder> = Arc::new(MockEmbedder::default());
+        let prompts: Vec<String> = vec!["colour".to_string(), "style".to_string()];
+        let store = InMemoryVectorStore::new(6, vec![], prompts, 3)
+            .unwrap()
+            .with_embedder(embedder.clone());
+
+        store.probe_embedder().await.unwrap();
+        let requests = embedder.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].prompts, vec!["colour"]);
+        assert_eq!(requests[0].dimensions, 1);
+
+        let empty = InMemoryVectorStore::new(6, vec![], vec![], 3)
+            .unwrap()
+            .with_embedder(embedder.clone());
+        assert!(empty.probe_embedder().await.is_err());
+    }
+
+    #[test]
+    fn test_shuffle_ties_stays_within_tiers() {
+        let results: Vec<SearchResult> = [0.9, 0.899, 0.895, 0.5, 0.495, 0.1]
+            .iter()
+            .enumerate()
+            .map(|(id, score)| SearchResult {
+                data_entry: DataEntry {
+                    id,
+                    name: id.to_string(),
+                    vector: vec![],
+                    descriptions: vec![],
+                    image: None,
+                    created_at: 0,
+                    image_hash: None,
+                    metadata: serde_json::Value::Null,
+                    version: 0,
+                    gender: None,
+                },
+                score: *score,
+            })
+            .collect();
+        let ids = |results: &[SearchResult]| -> Vec<usize> {
+            results.iter().map(|result| result.data_entry.id).collect()
+        };
+
+        for seed in 0..20 {
+            let mut shuffled: Vec<SearchResult> = results.clone();
+            shuffle_ties(&mut shuffled, 0.01, seed);
+            let order: Vec<usize> = ids(&shuffled);
+            let mut top: Vec<usize> = order[..3].to_vec();
+            top.sort();
+            assert_eq!(top, vec![0, 1, 2]);
+            let mut middle: Vec<usize> = order[3..5].to_vec();
+            middle.sort();
+            assert_eq!(middle, vec![3, 4]);
+            assert_eq!(order[5], 5);
+
+            let mut again: Vec<SearchResult> = results.clone();
+            shuffle_ties(&mut again, 0.01, seed);
+            assert_eq!(ids(&again), order);
+        }
+
+        let mut untouched: Vec<SearchResult> = results.clone();
+        shuffle_ties(&mut untouched, 0.0, 7);
+        assert_eq!(ids(&untouched), ids(&results));
+    }
+
+    #[tokio::test]
+    async fn test_negative_tie_epsilon_keeps_the_farthest_order() {
+        let store = create_colour_store().await;
+        let query: Vec<f64> = store.get(1).unwrap().vector.clone();
+        let farthest = SearchOptions {
+            top_n: 4,
+            farthest: true,
+            ..SearchOptions::default()
+        };
+        let (expected, _) = store.rank(&query, &farthest).unwrap();
+
+        let shuffled = SearchOptions {
+            shuffle_ties: Some(-1.0),
+            shuffle_seed: Some(7),
+            ..farthest
+        };
+        let (results, _) = store.rank(&query, &shuffled).unwrap();
+        let ids = |results: &[SearchResult]| -> Vec<usize> {
+            results.iter().map(|result| result.data_entry.id).collect()
+        };
+        assert_eq!(ids(&results), ids(&expected));
+        assert!(results
+            .windows(2)
+            .all(|pair| pair[0].score <= pair[1].score));
+    }
+
+    #[test]
+    fn test_created_between() {
+        let create_entry = |id: usize, created_at: u64| DataEntry {
+            id,
+            name: id.to_string(),
+            vector: vec![1.0, 0.0, 0.0],
+            descriptions: vec![],
+            image: None,
+            created_at,
+            image_hash: None,
+            metadata: serde_json::Value::Null,
+            version: 0,
+            gender: None,
+        };
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1).unwrap();
+        store
+            .set_entries(vec![
+                create_entry(1, 100),
+                create_entry(2, 200),
+                create_entry(3, 300),
+            ])
+            .unwrap();
+        let ids = |entries: Vec<DataEntry>| -> Vec<usize> {
+            entries.iter().map(|entry| entry.id).collect()
+        };
+
+        assert_eq!(ids(store.created_between(200, None)), vec![2, 3]);
+        assert_eq!(ids(store.created_between(0, Some(200))), vec![1]);
+        assert_eq!(ids(store.created_between(100, Some(300))), vec![1, 2]);
+        assert!(store.created_between(400, None).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_generation_advances_on_mutations_only() {
+        let mut store = create_colour_store().await;
+        let mut generation: u64 = store.generation();
+
+        // reads leave the counter alone
+        store.get_all();
+        store
+            .search_vector(vec![255.0, 0.0, 0.0], &SearchOptions::top_n(2))
+            .unwrap();
+        store
+            .search_with_query(create_colour_image(255, 0, 0), &SearchOptions::top_n(2))
+            .await
+            .unwrap();
+        assert_eq!(store.generation(), generation);
+
+        let id: usize = store
+            .add("purple", vec![], create_colour_image(128, 0, 128))
+            .await
+            .unwrap();
+        assert!(store.generation() > generation);
+        generation = store.generation();
+
+        store.add_description(id, "dark".to_string()).unwrap();
+        assert!(store.generation() > generation);
+        generation = store.generation();
+
+        let mut entry: DataEntry = store.get(id).unwrap().clone();
+        entry.name = "violet".to_string();
+        store
+            .edit(create_colour_image(128, 0, 128), entry)
+            .await
+            .unwrap();
+        assert!(store.generation() > generation);
+        generation = store.generation();
+
+        store.delete(id).await.unwrap();
+        assert!(store.generation() > generation);
+        generation = store.generation();
+
+        // replacing the store keeps counting rather than starting over
+        store.replace(InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1).unwrap());
+        assert!(store.generation() > generation);
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_recall_and_precision() {
+        let store = create_colour_store().await;
+        let evaluation = store
+            .evaluate(
+                vec![
+                    EvaluationQuery {
+                        image: create_colour_image(255, 0, 0),
+                        relevant_ids: vec![1],
+                    },
+                    EvaluationQuery {
+                        image: create_colour_image(255, 128, 0),
+                        relevant_ids: vec![4, 2],
+                    },
+                ],
+                2,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(evaluation.queries[0].retrieved_ids, vec![1, 4]);
+        assert_eq!(evaluation.queries[0].recall, 1.0);
+        assert_eq!(evaluation.queries[0].precision, 0.5);
+        assert_eq!(evaluation.queries[1].retrieved_ids, vec![4, 1]);
+        assert_eq!(evaluation.queries[1].recall, 0.5);
+        assert_eq!(evaluation.recall, 0.75);
+        assert_eq!(evaluation.precision, 0.5);
+
+        // embedding on a copy without entries, then searching, scores the same
+        let queries = vec![EvaluationQuery {
+            image: create_colour_image(255, 0, 0),
+            relevant_ids: vec![1],
+        }];
+        let embedded: Vec<EmbeddedEvaluationQuery> = store
+            .without_entries()
+            .embed_evaluation(queries.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.evaluate_embedded(&embedded, 2).unwrap(),
+            store.evaluate(queries, 2).await.unwrap()
+        );
+
+        let unlabeled = evaluate_retrieval(&[1, 2], &[], 2);
+        assert_eq!((unlabeled.hits, unlabeled.recall), (0, 0.0));
+    }
+
+    #[tokio::test]
+    async fn test_neighbors_exclude_self_and_follow_changes() {
+        let mut store = create_colour_store().await;
+
+        let neighbors: Neighbors = store.neighbors(2);
+        assert_eq!(neighbors.len(), 4);
+        let (id, red) = &neighbors[0];
+        assert_eq!(*id, 1);
+        assert_eq!(
+            red.iter().map(|(id, _)| *id).collect::<Vec<usize>>(),
+            vec![4, 2]
+        );
+        assert!(neighbors
+            .iter()
+            .all(|(id, others)| others.len() == 2 && others.iter().all(|(other, _)| other != id)));
+        assert_eq!(store.neighbors(2), neighbors);
+
+        // a smaller k is sliced from the cached graph
+        let nearest: Neighbors = store.neighbors(1);
+        assert!(nearest
+            .iter()
+            .zip(&neighbors)
+            .all(
+                |((id, others), (cached_id, cached))| id == cached_id && others[..] == cached[..1]
+            ));
+
+        // a new entry invalidates the cached graph
+        store
+            .add("dark red", vec![], create_colour_image(200, 0, 0))
+            .await
+            .unwrap();
+        let neighbors: Neighbors = store.neighbors(1);
+        assert_eq!(neighbors.len(), 5);
+        assert_eq!(neighbors[0].1[0].0, 5);
+    }
+
+    #[tokio::test]
+    async fn test_random_pair_and_verdicts() {
+        let mut store = create_colour_store().await;
+
+        let (query_id, candidate_id, score) = store.random_pair(1, Some(7)).unwrap();
+        assert_ne!(query_id, candidate_id);
+        assert_eq!(
+            store.random_pair(1, Some(7)),
+            Some((query_id, candidate_id, score))
+        );
+        let neighbors: Neighbors = store.neighbors(1);
+        assert!(neighbors
+            .iter()
+            .any(|(id, others)| *id == query_id && others[0] == (candidate_id, score)));
+
+        let verdict = store.record_verdict(query_id, candidate_id, true).unwrap();
+        assert_eq!(verdict.score, score);
+        assert!(store.record_verdict(query_id, 99, false).is_err());
+        assert_eq!(store.verdicts(), [verdict]);
+
+        let empty = InMemoryVectorStore::new(3, vec![], vec![], 1).unwrap();
+        assert!(empty.random_pair(5, None).is_none());
+    }
+
+    #[test]
+    fn test_vector_buffer_pads_rows_to_lanes() {
+        let rows: Vec<Vec<f64>> = vec![vec![1.0; 10], vec![2.0; 3]];
+        let buffer = VectorBuffer::from_rows(rows.iter().map(Vec::as_slice));
+
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.stride(), 2 * VECTOR_LANES);
+        assert_eq!(buffer.row(0), rows[0].as_slice());
+        assert_eq!(buffer.row(1), rows[1].as_slice());
+        assert!(VectorBuffer::from_rows(std::iter::empty()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_contiguous_vectors_search_the_same() {
+        let scattered = create_colour_store().await;
+        let mut contiguous = create_colour_store().await.with_contiguous_vectors(true);
+
+        let scored = |results: Vec<SearchResult>| -> Vec<(usize, f64)> {
+            results
+                .into_iter()
+                .map(|result| (result.data_entry.id, result.score))
+                .collect()
+        };
+        let query = create_colour_image(255, 32, 0);
+        let expected = scored(scattered.search(query.clone(), 4).await.unwrap());
+        assert_eq!(
+            scored(contiguous.search(query.clone(), 4).await.unwrap()),
+            expected
+        );
+
+        // the contiguous copy follows changes of the store
+        contiguous.delete(expected[0].0).await.unwrap();
+        let results = scored(contiguous.search(query, 4).await.unwrap());
+        assert_eq!(results, expected[1..]);
+    }
+
+    #[tokio::test]
+    async fn test_iter_borrows_entries_in_order() {
+        let store = create_colour_store().await;
+
+        let ids: Vec<usize> = store.iter().map(|entry| entry.id).collect();
+        let owned: Vec<usize> = store.get_all().iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, owned);
+        assert_eq!(store.iter().count(), store.len());
+    }
+
+    #[tokio::test]
+    async fn test_add_batch_keeps_input_order() {
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(YieldingEmbedder));
+        let items: Vec<(String, Vec<String>, DynamicImage)> = vec![
+            ("red".to_string(), vec![], create_colour_image(255, 0, 0)),
+            ("green".to_string(), vec![], create_colour_image(0, 255, 0)),
+            (" ".to_string(), vec![], create_colour_image(0, 0, 255)),
+            (
+                "orange".to_string(),
+                vec![],
+                create_colour_image(255, 128, 0),
+            ),
+        ];
+
+        let results = store.add_batch(items, 2).await;
+        assert_eq!(results.len(), 4);
+        assert!(results[2].is_err());
+
+        let ids: Vec<usize> = results.into_iter().filter_map(|id| id.ok()).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        let entries: Vec<(&str, f64)> = store
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.vector[1]))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![("red", 0.0), ("green", 255.0), ("orange", 128.0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gender_filter_includes_unisex_entries() {
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder));
+        for (name, (red, green, blue), gender) in [
+            ("red", (255, 0, 0), Some(Gender::Male)),
+            ("orange", (255, 128, 0), Some(Gender::Female)),
+            ("green", (0, 255, 0), Some(Gender::Unisex)),
+            ("blue", (0, 0, 255), None),
+        ] {
+            store
+                .add_with_metadata(
+                    name,
+                    vec![],
+                    create_colour_image(red, green, blue),
+                    serde_json::Value::Null,
+                    gender,
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(store.get(1).unwrap().gender, Some(Gender::Male));
+
+        let options = SearchOptions {
+            top_n: 4,
+            gender: Some(Gender::Female),
+            ..SearchOptions::default()
+        };
+        let (results, counts) = store
+            .rank(&store.get(1).unwrap().vector.clone(), &options)
+            .unwrap();
+        let mut names: Vec<&str> = results
+            .iter()
+            .map(|result| result.data_entry.name.as_str())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["green", "orange"]);
+        assert_eq!(counts.filtered_out, 2);
+    }
+
+    #[tokio::test]
+    async fn test_compare_entries() {
+        let store = create_colour_store().await;
+
+        let comparison = store.compare(1, 4).unwrap();
+        let orange_norm: f64 = (255.0f64 * 255.0 + 128.0 * 128.0).sqrt();
+        assert_eq!((comparison.a, comparison.b), (1, 4));
+        assert!((comparison.cosine - 255.0 / orange_norm).abs() < 1e-9);
+        assert!((comparison.score - comparison.cosine).abs() < COSINE_SIMD_EPSILON);
+        assert!((comparison.norm_a - 255.0).abs() < 1e-9);
+        assert!((comparison.norm_b - orange_norm).abs() < 1e-9);
+
+        // red and blue share no channel
+        assert_eq!(store.compare(1, 3).unwrap().cosine, 0.0);
+
+        let error = store.compare(1, 42).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<DataEntryErrors>(),
+            Some(DataEntryErrors::NoDataWasFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_set_entries_then_search() {
+        let create_entry = |id: usize, name: &str, vector: Vec<f64>| DataEntry {
+            id,
+            name: name.to_string(),
+            vector,
+            descriptions: vec![],
+            image: None,
+            created_at: 0,
+            image_hash: None,
+            metadata: serde_json::Value::Null,
+            version: 0,
+            gender: None,
+        };
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder));
+
+        store
+            .set_entries(vec![
+                create_entry(5, "red", vec![255.0, 0.0, 0.0]),
+                create_entry(9, "blue", vec![0.0, 0.0, 255.0]),
+            ])
+            .unwrap();
+        let results = store
+            .search(create_colour_image(250, 10, 0), 1)
+            .await
+            .unwrap();
+        assert_eq!(results[0].data_entry.id, 5);
+
+        // new entries continue after the highest given ID
+        let id = store
+            .add("green", vec![], create_colour_image(0, 255, 0))
+            .await
+            .unwrap();
+        assert_eq!(id, 10);
+
+        // invalid entries leave the store untouched
+        let error = store
+            .set_entries(vec![
+                create_entry(1, "red", vec![255.0, 0.0, 0.0]),
+                create_entry(1, "blue", vec![0.0, 0.0, 255.0]),
+            ])
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::DuplicateId(1))
+        ));
+        assert!(store
+            .set_entries(vec![create_entry(1, "short", vec![1.0])])
+            .is_err());
+        assert_eq!(store.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_normalized_tags_match_filters() {
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder))
+            .with_normalized_tags(true);
+        for (description, (red, green, blue)) in [
+            ("Blue", (0, 0, 255)),
+            ("blue ", (0, 0, 200)),
+            ("red", (255, 0, 0)),
+        ] {
+            store
+                .add(
+                    description,
+                    vec![description.to_string()],
+                    create_colour_image(red, green, blue),
+                )
+                .await
+                .unwrap();
+        }
+        store.add_description(3, " Bright ".to_string()).unwrap();
+        assert_eq!(store.get(1).unwrap().descriptions, vec!["blue"]);
+        assert_eq!(store.get(3).unwrap().descriptions, vec!["red", "bright"]);
+
+        for tag in ["blue", "BLUE "] {
+            let options = SearchOptions {
+                top_n: 3,
+                tags: vec![tag.to_string()],
+                ..SearchOptions::default()
+            };
+            let (_, results, counts) = store
+                .search_with_query(create_colour_image(0, 0, 255), &options)
+                .await
+                .unwrap();
+            let ids: HashSet<usize> = results.iter().map(|result| result.data_entry.id).collect();
+            assert_eq!(ids, HashSet::from([1, 2]));
+            assert_eq!(counts.filtered_out, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remove_description() {
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder))
+            .with_normalized_tags(true);
+        store
+            .add(
+                "red",
+                vec!["red".to_string(), "on_sale".to_string()],
+                create_colour_image(255, 0, 0),
+            )
+            .await
+            .unwrap();
+        store.add_description(1, "On_Sale".to_string()).unwrap();
+
+        assert_eq!(store.remove_description(1, " ON_SALE").unwrap(), 2);
+        assert_eq!(store.get(1).unwrap().descriptions, vec!["red"]);
+        assert_eq!(store.remove_description(1, "missing").unwrap(), 0);
+
+        let error = store.remove_description(2, "red").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<DataEntryErrors>(),
+            Some(DataEntryErrors::NoDataWasFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_retag_descriptions_is_all_or_nothing() {
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder));
+        store
+            .add(
+                "red",
+                vec!["red".to_string(), "on_sale".to_string()],
+                create_colour_image(255, 0, 0),
+            )
+            .await
+            .unwrap();
+
+        store
+            .retag_descriptions(
+                1,
+                &["new".to_string(), "red".to_string(), "new".to_string()],
+                &["on_sale".to_string()],
+            )
+            .unwrap();
+        assert_eq!(store.get(1).unwrap().descriptions, vec!["red", "new"]);
+        assert_eq!(store.get(1).unwrap().version, 1);
+
+        // the removal would apply, but the addition is too long
+        let too_long: String = "x".repeat(stylist::config::get().max_description_length + 1);
+        let error = store
+            .retag_descriptions(1, &[too_long], &["red".to_string()])
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::DescriptionTooLong { .. })
+        ));
+        assert_eq!(store.get(1).unwrap().descriptions, vec!["red", "new"]);
+        assert_eq!(store.get(1).unwrap().version, 1);
+    }
+
+    #[tokio::test]
+    async fn test_every_entry_change_bumps_its_version() {
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder));
+        store
+            .add("red", vec![], create_colour_image(255, 0, 0))
+            .await
+            .unwrap();
+        assert_eq!(store.get(1).unwrap().version, 0);
+
+        store.add_description(1, "red".to_string()).unwrap();
+        assert_eq!(store.get(1).unwrap().version, 1);
+
+        // removing nothing is not a change
+        store.remove_description(1, "missing").unwrap();
+        assert_eq!(store.get(1).unwrap().version, 1);
+        store.remove_description(1, "red").unwrap();
+        assert_eq!(store.get(1).unwrap().version, 2);
+
+        store.truncate_vectors(2).unwrap();
+        assert_eq!(store.get(1).unwrap().version, 3);
+    }
+
+    #[tokio::test]
+    async fn test_tags_match_exactly_by_default() {
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder));
+        store
+            .add(
+                "blue",
+                vec!["Blue".to_string()],
+                create_colour_image(0, 0, 255),
+            )
+            .await
+            .unwrap();
+
+        let options = SearchOptions {
+            top_n: 1,
+            tags: vec!["blue".to_string()],
+            ..SearchOptions::default()
+        };
+        let (_, results, _) = store
+            .search_with_query(create_colour_image(0, 0, 255), &options)
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+        assert_eq!(store.get(1).unwrap().descriptions, vec!["Blue"]);
+    }
+
+    #[tokio::test]
+    async fn test_farthest_search_returns_least_similar_first() {
+        let store = create_colour_store().await;
+
+        let options = SearchOptions {
+            top_n: 2,
+            farthest: true,
+            ..SearchOptions::default()
+        };
+        let results = store
+            .search_with(create_colour_image(255, 0, 0), &options)
+            .await
+            .unwrap();
+        let names: Vec<&str> = results
+            .iter()
+            .map(|result| result.data_entry.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["green", "blue"]);
+        assert!(results[0].score <= results[1].score);
+    }
+
+    #[tokio::test]
+    async fn test_search_stream_sends_candidates_then_summary() {
+        let store = create_colour_store().await;
+        let query_vector: Vec<f64> = store
+            .embed_only(create_colour_image(255, 0, 0))
+            .await
+            .unwrap();
+        // a single slot makes the scan wait for every event to be received
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+
+        let scan = tokio::task::spawn_blocking(move || {
+            store.search_stream(query_vector, &SearchOptions::top_n(2), 0.85, &sender)
+        });
+        let mut events: Vec<SearchEvent> = Vec::new();
+        while let Some(event) = receiver.recv().await {
+            events.push(event);
+        }
+        scan.await.unwrap().unwrap();
+
+        // red and orange clear the threshold, in store order
+        let candidates: Vec<usize> = events
+            .iter()
+            .filter_map(|event| match event {
+                SearchEvent::Candidate { id, .. } => Some(*id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(candidates, vec![1, 4]);
+        match events.last() {
+            Some(SearchEvent::Summary { results, counts }) => {
+                assert_eq!(results[0].data_entry.id, 1);
+                assert_eq!(counts.returned, 2);
+            }
+            other => panic!("expected a summary, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metadata_filter_and_persistence() {
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder));
+        for (name, (red, green, blue), metadata) in [
+            (
+                "red",
+                (255, 0, 0),
+                serde_json::json!({"brand": "X", "price": {"amount": 20, "currency": "EUR"}}),
+            ),
+            ("orange", (255, 128, 0), serde_json::json!({"brand": "Y"})),
+            ("blue", (0, 0, 255), serde_json::Value::Null),
+        ] {
+            store
+                .add_with_metadata(
+                    name,
+                    vec![],
+                    create_colour_image(red, green, blue),
+                    metadata,
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+
+        let options = SearchOptions {
+            top_n: 3,
+            metadata_filter: Some(serde_json::json!({"price": {"currency": "EUR"}})),
+            ..SearchOptions::default()
+        };
+        let (_, results, counts) = store
+            .search_with_query(create_colour_image(255, 0, 0), &options)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data_entry.metadata["brand"], "X");
+        assert_eq!(counts.filtered_out, 2);
+
+        // metadata survives serialization, and is left out when absent
+        let json: String = serde_json::to_string(&store).unwrap();
+        let restored: InMemoryVectorStore = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.get_all(), store.get_all());
+        assert!(!serde_json::to_string(restored.get(3).unwrap())
+            .unwrap()
+            .contains("metadata"));
+    }
+
+    #[test]
+    fn test_metadata_contains() {
+        let metadata = serde_json::json!({"brand": "X", "sizes": ["S", "M"]});
+
+        assert!(metadata_contains(&metadata, &serde_json::json!({})));
+        assert!(metadata_contains(
+            &metadata,
+            &serde_json::json!({"brand": "X"})
+        ));
+        assert!(!metadata_contains(
+            &metadata,
+            &serde_json::json!({"brand": "Y"})
+        ));
+        assert!(!metadata_contains(
+            &metadata,
+            &serde_json::json!({"sku": "1"})
+        ));
+        assert!(metadata_contains(
+            &metadata,
+            &serde_json::json!({"sizes": ["S", "M"]})
+        ));
+        assert!(!metadata_contains(
+            &serde_json::Value::Null,
+            &serde_json::json!({"brand": "X"})
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_search_counts_filtered_candidates() {
+        let store = create_colour_store().await;
+        let options = SearchOptions {
+            top_n: 3,
+            exclude_ids: vec![1, 4],
+            ..SearchOptions::default()
+        };
+
+        let (_, results, counts) = store
+            .search_with_query(create_colour_image(255, 32, 0), &options)
+            .await
+            .unwrap();
+        assert_eq!(
+            counts,
+            SearchCounts {
+                requested: 3,
+                returned: 2,
+                filtered_out: 2
+            }
+        );
+        assert!(results
+            .iter()
+            .all(|result| ![1, 4].contains(&result.data_entry.id)));
+
+        // filtering out every entry is a short result, not a missing one
+        let options = SearchOptions {
+            top_n: 3,
+            exclude_ids: vec![1, 2, 3, 4],
+            ..SearchOptions::default()
+        };
+        let (_, results, counts) = store
+            .search_with_query(create_colour_image(255, 32, 0), &options)
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+        assert_eq!(counts.filtered_out, 4);
+    }
+
+    #[tokio::test]
+    async fn test_prompt_batches_limit_concurrency() {
+        let prompts: Vec<String> = (0..5).map(|prompt| prompt.to_string()).collect();
+        let embedder = Arc::new(ConcurrencyEmbedder::default());
+        let store = InMemoryVectorStore::new(10, vec![], prompts.clone(), 2)
+            .unwrap()
+            .with_embedder(embedder.clone())
+            .with_prompt_batch_size(2);
+
+        let vector: Vec<f64> = store.embed_only(create_test_image()).await.unwrap();
+        assert_eq!(
+            vector,
+            vec![0.0, 0.0, 1.0, 1.0, 2.0, 2.0, 3.0, 3.0, 4.0, 4.0]
+        );
+        assert_eq!(*embedder.max_in_flight.lock().unwrap(), 2);
+
+        // without batching every prompt is scored at once
+        let embedder = Arc::new(ConcurrencyEmbedder::default());
+        let store = InMemoryVectorStore::new(10, vec![], prompts, 2)
+            .unwrap()
+            .with_embedder(embedder.clone());
+        let unbatched: Vec<f64> = store.embed_only(create_test_image()).await.unwrap();
+        assert_eq!(unbatched, vector);
+        assert_eq!(*embedder.max_in_flight.lock().unwrap(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_embedding_retries_on_dimension_mismatch() {
+        let flaky = Arc::new(FlakyEmbedder {
+            failures: 1,
+            calls: Mutex::new(0),
+        });
+        let mut store = InMemoryVectorStore::new(2, vec![], vec!["a".to_string()], 2)
+            .unwrap()
+            .with_embedder(flaky.clone())
+            .with_embedding_retries(1);
+
+        store
+            .add("test_image", vec![], create_test_image())
+            .await
+            .unwrap();
+        assert_eq!(*flaky.calls.lock().unwrap(), 2);
+        assert_eq!(store.get(1).unwrap().vector.len(), 2);
+
+        // without retries the same hiccup fails the upload
+        let mut strict = InMemoryVectorStore::new(2, vec![], vec!["a".to_string()], 2)
+            .unwrap()
+            .with_embedder(Arc::new(FlakyEmbedder {
+                failures: 1,
+                calls: Mutex::new(0),
+            }));
+        let error = strict
+            .add("test_image", vec![], create_test_image())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<EmbeddingErrors>(),
+            Some(EmbeddingErrors::UnexpectedDimensions {
+                expected: 2,
+                actual: 3
+            })
+        ));
+        assert!(strict.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_constant_vectors_warn_or_reject() {
+        // the mock embedder scores every prompt the same
+        let mut lenient = InMemoryVectorStore::new(2, vec![], vec!["a".to_string()], 2)
+            .unwrap()
+            .with_embedder(Arc::new(MockEmbedder::default()));
+        lenient
+            .add("test_image", vec![], create_test_image())
+            .await
+            .unwrap();
+        assert_eq!(lenient.len(), 1);
+
+        let mut strict = InMemoryVectorStore::new(2, vec![], vec!["a".to_string()], 2)
+            .unwrap()
+            .with_embedder(Arc::new(MockEmbedder::default()))
+            .with_constant_vector_rejection(true);
+        let error = strict
+            .add("test_image", vec![], create_test_image())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<EmbeddingErrors>(),
+            Some(EmbeddingErrors::ConstantVector { .. })
+        ));
+        assert!(strict.is_empty());
+
+        assert!(is_constant_vector(&[0.5, 0.5, 0.5]));
+        assert!(!is_constant_vector(&[0.5, 0.6, 0.5]));
+        assert!(!is_constant_vector(&[0.5]));
+        assert_eq!(vector_variance(&[1.0, 3.0]), 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_get_all_sorted() {
+        let store = create_colour_store().await;
+
+        let by_name: Vec<String> = store
+            .get_all_sorted(SortKey::Name, SortDirection::Ascending)
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(by_name, vec!["blue", "green", "orange", "red"]);
+
+        let by_id_desc: Vec<usize> = store
+            .get_all_sorted(SortKey::Id, SortDirection::Descending)
+            .into_iter()
+            .map(|entry| entry.id)
+            .collect();
+        assert_eq!(by_id_desc, vec![4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn test_import_validation() {
+        let mut store = InMemoryVectorStore::new(3, vec![], vec!["a".to_string()], 1).unwrap();
+        let entry = |name: &str, vector: Vec<f64>| ImportEntry {
+            name: name.to_string(),
+            descriptions: vec![],
+            vector,
+        };
+        let entries: Vec<ImportEntry> = vec![
+            entry("valid", vec![1.0, 2.0, 3.0]),
+            entry("short", vec![1.0, 2.0]),
+            entry("nan", vec![1.0, f64::NAN, 3.0]),
+            entry("  ", vec![1.0, 2.0, 3.0]),
+        ];
+
+        let validations = store.validate_import(&entries);
+        let valid: Vec<bool> = validations.iter().map(|v| v.valid).collect();
+        assert_eq!(valid, vec![true, false, false, false]);
+        assert!(validations[0].errors.is_empty());
+
+        // a dry run leaves the store untouched, a failed import as well
+        assert!(store.get_all().is_empty());
+        let error = store.import(entries.clone()).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::InvalidImport {
+                invalid: 3,
+                total: 4
+            })
+        ));
+        assert!(store.get_all().is_empty());
+
+        let ids = store.import(entries[..1].to_vec()).unwrap();
+        assert_eq!(ids, vec![1]);
+        assert_eq!(store.get_all()[0].vector, vec![1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_import_length_mismatch_policies() {
+        let entry = |name: &str, vector: Vec<f64>| ImportEntry {
+            name: name.to_string(),
+            descriptions: vec![],
+            vector,
+        };
+        let entries: Vec<ImportEntry> = vec![
+            entry("valid", vec![1.0, 2.0, 3.0]),
+            entry("short", vec![1.0, 2.0]),
+            entry("long", vec![1.0, 2.0, 3.0, 4.0]),
+        ];
+        let store = |policy: LengthMismatchPolicy| {
+            InMemoryVectorStore::new(3, vec![], vec!["a".to_string()], 1)
+                .unwrap()
+                .with_length_mismatch_policy(policy)
+        };
+
+        let mut rejecting = store(LengthMismatchPolicy::Reject);
+        assert!(rejecting.import_with_report(entries.clone()).is_err());
+        assert!(rejecting.is_empty());
+
+        let mut skipping = store(LengthMismatchPolicy::Skip);
+        let validations = skipping.validate_import(&entries);
+        assert!(validations.iter().all(|validation| validation.valid));
+        assert_eq!(validations[1].warnings.len(), 1);
+        let outcome = skipping.import_with_report(entries.clone()).unwrap();
+        assert_eq!(outcome.ids, vec![1]);
+        assert_eq!(outcome.skipped, vec![1, 2]);
+        assert!(outcome.coerced.is_empty());
+
+        let mut coercing = store(LengthMismatchPolicy::PadTruncate);
+        let outcome = coercing.import_with_report(entries).unwrap();
+        assert_eq!(outcome.ids, vec![1, 2, 3]);
+        assert_eq!(outcome.coerced, vec![1, 2]);
+        let vectors: Vec<Vec<f64>> = coercing.iter().map(|entry| entry.vector.clone()).collect();
+        assert_eq!(
+            vectors,
+            vec![
+                vec![1.0, 2.0, 3.0],
+                vec![1.0, 2.0, 0.0],
+                vec![1.0, 2.0, 3.0]
+            ]
+        );
+        assert_eq!(
+            "pad_truncate".parse::<LengthMismatchPolicy>().unwrap(),
+            LengthMismatchPolicy::PadTruncate
+        );
+    }
+
+    #[test]
+    fn test_simd_cosine_matches_scalar() {
+        // small deterministic LCG so the test needs no extra dependency
+        let mut state: u64 = 42;
+        let mut next = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 53) as f64 * 10.0 - 5.0
+        };
+
+        // cover lengths with and without a partial trailing chunk
+        for length in [1, 7, 8, 30, 101] {
+            let a: Vec<f64> = (0..length).map(|_| next()).collect();
+            let b: Vec<f64> = (0..length).map(|_| next()).collect();
+
+            let scalar: f64 = cosine_similarity(&a, &b);
+            let simd: f64 = cosine_similarity_simd(&a, &b);
+            assert!(
+                (scalar - simd).abs() < COSINE_SIMD_EPSILON,
+                "length {}: scalar {} vs simd {}",
+                length,
+                scalar,
+                simd
+            );
+        }
+
+        assert_eq!(cosine_similarity_simd(&[0.0; 8], &[1.0; 8]), 0.0);
+    }
+
+    #[test]
+    fn test_sanitize_name() {
+        assert_eq!(
+            sanitize_name("  Blue\u{7} T-shirt\n ", 16).unwrap(),
+            "Blue T-shirt"
+        );
+
+        for empty in ["", "   ", "\n\t\u{0}"] {
+            let error = sanitize_name(empty, 16).unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<ValidationErrors>(),
+                Some(ValidationErrors::EmptyName)
+            ));
+        }
+
+        assert!(sanitize_name(&"a".repeat(16), 16).is_ok());
+        let error = sanitize_name(&"a".repeat(17), 16).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationErrors>(),
+            Some(ValidationErrors::NameTooLong {
+                max: 16,
+                actual: 17
+            })
+        ));
+    }
+
     #[tokio::test]
     async fn test_vector_store_crud_operations() {
         let mut store = create_test_store();
@@ -0,0 +1,34 @@
+use stylist::health::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // Helper function to build the outcome of a probe
+    fn health(healthy: bool) -> EmbedderHealth {
+        EmbedderHealth {
+            healthy,
+            latency_ms: 1.0,
+            error: None,
+            age_secs: 0.0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_health_cache_reuses_recent_probes() {
+        let cache = HealthCache::new(Duration::from_secs(60));
+
+        assert!(cache.get_or_probe(async { health(true) }).await.healthy);
+        // a recent probe is returned without running the new one
+        assert!(cache.get_or_probe(async { health(false) }).await.healthy);
+    }
+
+    #[tokio::test]
+    async fn test_health_cache_without_ttl_always_probes() {
+        let cache = HealthCache::new(Duration::ZERO);
+
+        assert!(cache.get_or_probe(async { health(true) }).await.healthy);
+        assert!(!cache.get_or_probe(async { health(false) }).await.healthy);
+    }
+}
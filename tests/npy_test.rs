@@ -0,0 +1,41 @@
+use stylist::npy::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_f64_matrix() {
+        let rows: Vec<Vec<f64>> = vec![vec![1.0, 2.0, 3.0], vec![-0.5, 0.0, 0.25]];
+        let rows: Vec<&[f64]> = rows.iter().map(|row| row.as_slice()).collect();
+        let bytes: Vec<u8> = encode_f64_matrix(&rows, 3);
+
+        assert_eq!(&bytes[..6], b"\x93NUMPY");
+        assert_eq!(&bytes[6..8], &[1, 0]);
+
+        let header_length: usize = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        let data_start: usize = 10 + header_length;
+        assert_eq!(data_start % 64, 0);
+
+        let header: &str = std::str::from_utf8(&bytes[10..data_start]).unwrap();
+        assert!(header.starts_with("{'descr': '<f8', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with('\n'));
+
+        let values: Vec<f64> = bytes[data_start..]
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        assert_eq!(values, vec![1.0, 2.0, 3.0, -0.5, 0.0, 0.25]);
+    }
+
+    #[test]
+    fn test_encode_empty_matrix() {
+        let bytes: Vec<u8> = encode_f64_matrix(&[], 30);
+        let header_length: usize = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+
+        assert_eq!(bytes.len(), 10 + header_length);
+        assert!(std::str::from_utf8(&bytes[10..])
+            .unwrap()
+            .contains("'shape': (0, 30)"));
+    }
+}
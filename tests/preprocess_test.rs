@@ -0,0 +1,48 @@
+use stylist::preprocess::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageBuffer, Rgba};
+
+    // Helper function to create a transparent test image of the given size
+    fn create_test_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(width, height, |_, _| {
+            Rgba([10, 20, 30, 128])
+        }))
+    }
+
+    #[test]
+    fn test_disabled_preprocessing_keeps_image() {
+        let preprocessing = Preprocessing::default();
+        assert!(!preprocessing.is_enabled());
+
+        let image = preprocessing.apply(create_test_image(40, 20));
+        assert_eq!((image.width(), image.height()), (40, 20));
+        assert!(image.color().has_alpha());
+    }
+
+    #[test]
+    fn test_preprocessing_steps() {
+        let preprocessing = Preprocessing {
+            square_crop: true,
+            max_side: Some(8),
+            rgb: true,
+        };
+
+        let image = preprocessing.apply(create_test_image(40, 20));
+        assert_eq!((image.width(), image.height()), (8, 8));
+        assert!(!image.color().has_alpha());
+    }
+
+    #[test]
+    fn test_max_side_does_not_upscale() {
+        let preprocessing = Preprocessing {
+            max_side: Some(100),
+            ..Preprocessing::default()
+        };
+
+        let image = preprocessing.apply(create_test_image(40, 20));
+        assert_eq!((image.width(), image.height()), (40, 20));
+    }
+}
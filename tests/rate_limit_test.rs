@@ -0,0 +1,33 @@
+use stylist::rate_limit::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn test_rate_limiter_allows_burst_then_rejects() {
+        let limiter = RateLimiter::new(2);
+        let client = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+        assert!(limiter.check(client).is_ok());
+        assert!(limiter.check(client).is_ok());
+
+        // one token comes back every 30 seconds
+        let retry_after = limiter.check(client).unwrap_err();
+        assert!(retry_after.as_secs_f64() > 29.0 && retry_after.as_secs_f64() <= 30.0);
+
+        // other clients have their own bucket
+        assert!(limiter
+            .check(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_rate_limiter_disabled() {
+        let limiter = RateLimiter::new(0);
+        let client = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+        assert!((0..100).all(|_| limiter.check(client).is_ok()));
+    }
+}
@@ -0,0 +1,42 @@
+use stylist::readiness::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, middleware::from_fn, test, web::Data, App};
+    use serde_json::Value;
+
+    #[actix_web::test]
+    async fn test_requests_wait_for_readiness() {
+        let readiness: Data<Readiness> = Data::new(Readiness::new());
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(require_ready))
+                .app_data(readiness.clone()),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/api/clothes/get")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["message"], INITIALIZING_MESSAGE);
+
+        // liveness and provider health are answered while loading
+        for path in READINESS_EXEMPT_PATHS {
+            let request = test::TestRequest::get().uri(path).to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+
+        // once ready, requests reach the routes, none of which exist here
+        readiness.set_ready();
+        let request = test::TestRequest::get()
+            .uri("/api/clothes/get")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
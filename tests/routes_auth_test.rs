@@ -0,0 +1,223 @@
+use stylist::routes::*;
+
+// Routes behind `require_token` read STYLIST_API_TOKEN from the process-wide
+// config, which is resolved once. They are tested in their own binary, so
+// every test here can set the token before anything reads the config.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        path::Path,
+        sync::{Arc, Mutex, Once},
+    };
+
+    use actix_web::{
+        http::{header, StatusCode},
+        test,
+        web::Data,
+        App,
+    };
+    use image::{DynamicImage, ImageBuffer, Rgb};
+    use serde_json::{json, Value};
+    use stylist::{
+        embedding::{
+            encode_image, DataEntry, Embedder, EmbeddingFuture, EmbeddingRequest,
+            InMemoryVectorStore,
+        },
+        stats::RequestCounters,
+        thumbnails::ThumbnailCache,
+        SharedStores,
+    };
+    use tokio::sync::RwLock;
+
+    const TOKEN: &str = "purge-secret";
+
+    // Embedder using the first pixel's RGB channels as the vector
+    #[derive(Debug, Default)]
+    struct PixelEmbedder;
+
+    impl Embedder for PixelEmbedder {
+        fn embed(&self, _request: EmbeddingRequest, image: DynamicImage) -> EmbeddingFuture<'_> {
+            let pixel = image.to_rgb8().get_pixel(0, 0).0;
+            let result: anyhow::Result<Vec<f64>> =
+                Ok(pixel.iter().map(|channel| *channel as f64).collect());
+            Box::pin(async move { result })
+        }
+    }
+
+    // Helper function to configure the API token, before the config is first read
+    fn configure_token() {
+        static CONFIGURE: Once = Once::new();
+        CONFIGURE.call_once(|| std::env::set_var("STYLIST_API_TOKEN", TOKEN));
+        assert_eq!(stylist::config::get().api_token.as_deref(), Some(TOKEN));
+    }
+
+    // Helper function to create a single-colour image
+    fn create_colour_image(r: u8, g: u8, b: u8) -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_fn(8, 8, |_, _| Rgb([r, g, b])))
+    }
+
+    // Helper function to create an entry with the given id, name and colour
+    fn create_entry(id: usize, name: &str, colour: [f64; 3]) -> DataEntry {
+        DataEntry {
+            id,
+            name: name.to_string(),
+            vector: colour.to_vec(),
+            descriptions: vec![],
+            image: None,
+            created_at: 0,
+            image_hash: None,
+            metadata: Value::Null,
+            version: 0,
+            gender: None,
+        }
+    }
+
+    // Helper function to create logged stores whose clothes store holds two
+    // reds, an orange and a blue
+    fn create_logged_stores(directory: &Path) -> SharedStores {
+        let mut clothes = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder));
+        clothes.upsert(create_entry(1, "red", [255.0, 0.0, 0.0]));
+        clothes.upsert(create_entry(2, "dark red", [200.0, 0.0, 0.0]));
+        clothes.upsert(create_entry(3, "orange", [255.0, 128.0, 0.0]));
+        clothes.upsert(create_entry(4, "blue", [0.0, 0.0, 255.0]));
+        let face = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1).unwrap();
+
+        SharedStores::new(Arc::new(RwLock::new(clothes)), Arc::new(RwLock::new(face))).with_op_log(
+            directory.join("ops.jsonl").to_str().unwrap(),
+            directory.join("snapshot.json").to_str().unwrap(),
+            0,
+        )
+    }
+
+    // Helper function to create a purge request for everything close to red
+    fn create_purge_request(uri: &str, token: Option<&str>) -> test::TestRequest {
+        let mut request = test::TestRequest::post().uri(uri).set_json(json!({
+            "image": encode_image(&create_colour_image(255, 0, 0)).unwrap(),
+            "top_n": 10,
+            "min_score": 0.95,
+        }));
+        if let Some(token) = token {
+            request = request.insert_header((header::AUTHORIZATION, format!("Bearer {}", token)));
+        }
+        request
+    }
+
+    // Helper function to list the IDs left in the clothes store
+    async fn clothes_ids(stores: &SharedStores) -> Vec<usize> {
+        stores
+            .clothes
+            .read()
+            .await
+            .get_all()
+            .iter()
+            .map(|entry| entry.id)
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn test_purge_requires_a_token_and_confirmation() {
+        configure_token();
+        let directory =
+            std::env::temp_dir().join(format!("stylist-purge-refused-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let stores = Arc::new(create_logged_stores(&directory));
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(stores.clone()))
+                .app_data(Data::new(Mutex::new(ThumbnailCache::new(1024 * 1024))))
+                .app_data(Data::new(RequestCounters::default()))
+                .configure(config),
+        )
+        .await;
+
+        let cases = [
+            ("/api/clothes/purge_similar?confirm=true", None),
+            (
+                "/api/clothes/purge_similar?confirm=true",
+                Some("wrong-secret"),
+            ),
+            ("/api/clothes/purge_similar", Some(TOKEN)),
+            ("/api/clothes/purge_similar?confirm=false", Some(TOKEN)),
+        ];
+        let expected = [
+            StatusCode::UNAUTHORIZED,
+            StatusCode::UNAUTHORIZED,
+            StatusCode::BAD_REQUEST,
+            StatusCode::BAD_REQUEST,
+        ];
+        for ((uri, token), status) in cases.into_iter().zip(expected) {
+            let request = create_purge_request(uri, token).to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), status, "{} {:?}", uri, token);
+            let body: Value = test::read_body_json(response).await;
+            assert_eq!(body["status"], false);
+        }
+
+        // nothing was deleted, and so nothing was logged
+        assert_eq!(clothes_ids(&stores).await, vec![1, 2, 3, 4]);
+        assert!(!directory.join("ops.jsonl").exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_purge_deletes_only_entries_above_the_min_score() {
+        configure_token();
+        let directory = std::env::temp_dir().join(format!("stylist-purge-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let stores = Arc::new(create_logged_stores(&directory));
+        let thumbnails = Data::new(Mutex::new(ThumbnailCache::new(1024 * 1024)));
+        for id in 1..=4 {
+            thumbnails
+                .lock()
+                .unwrap()
+                .insert((id, Some(4), None), vec![id as u8]);
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(stores.clone()))
+                .app_data(thumbnails.clone())
+                .app_data(Data::new(RequestCounters::default()))
+                .configure(config),
+        )
+        .await;
+
+        let request = create_purge_request("/api/clothes/purge_similar?confirm=true", Some(TOKEN))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(response).await;
+        let mut deleted: Vec<usize> = serde_json::from_value(body["data"].clone()).unwrap();
+        deleted.sort();
+        assert_eq!(deleted, vec![1, 2]);
+
+        // orange scores about 0.89 and blue 0, both below the min score
+        assert_eq!(clothes_ids(&stores).await, vec![3, 4]);
+
+        let mut thumbnails = thumbnails.lock().unwrap();
+        assert!(thumbnails.get(&(1, Some(4), None)).is_none());
+        assert!(thumbnails.get(&(2, Some(4), None)).is_none());
+        assert!(thumbnails.get(&(3, Some(4), None)).is_some());
+        assert!(thumbnails.get(&(4, Some(4), None)).is_some());
+        drop(thumbnails);
+
+        // the deletes were logged before the response, and survive a restart
+        let log: String = std::fs::read_to_string(directory.join("ops.jsonl")).unwrap();
+        let mut logged: Vec<usize> = log
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .inspect(|op| {
+                assert_eq!(op["op"], "delete");
+                assert_eq!(op["store"], "clothes");
+            })
+            .map(|op| op["id"].as_u64().unwrap() as usize)
+            .collect();
+        logged.sort();
+        assert_eq!(logged, vec![1, 2]);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+use stylist::routes::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    use actix_web::{
+        http::{header, StatusCode},
+        middleware::{from_fn, NormalizePath},
+        test,
+        web::Data,
+        App,
+    };
+    use image::{DynamicImage, ImageBuffer, Rgb};
+    use serde_json::{json, Value};
+    use stylist::{
+        embedding::{
+            encode_image, DataEntry, Embedder, EmbeddingFuture, EmbeddingRequest,
+            InMemoryVectorStore,
+        },
+        readiness::{require_ready, Readiness, INITIALIZING_MESSAGE},
+        thumbnails::ThumbnailCache,
+        SharedStores,
+    };
+    use tokio::sync::RwLock;
+
+    // Embedder using the first pixel's RGB channels as the vector
+    #[derive(Debug, Default)]
+    struct PixelEmbedder;
+
+    impl Embedder for PixelEmbedder {
+        fn embed(&self, _request: EmbeddingRequest, image: DynamicImage) -> EmbeddingFuture<'_> {
+            let pixel = image.to_rgb8().get_pixel(0, 0).0;
+            let result: anyhow::Result<Vec<f64>> =
+                Ok(pixel.iter().map(|channel| *channel as f64).collect());
+            Box::pin(async move { result })
+        }
+    }
+
+    // Embedder scoring each prompt with its own numeric value
+    #[derive(Debug, Default)]
+    struct PromptValueEmbedder;
+
+    impl Embedder for PromptValueEmbedder {
+        fn embed(&self, request: EmbeddingRequest, _image: DynamicImage) -> EmbeddingFuture<'_> {
+            let result: anyhow::Result<Vec<f64>> = Ok(request
+                .prompts
+                .iter()
+                .flat_map(|prompt| vec![prompt.parse::<f64>().unwrap(); request.prompt_size])
+                .collect());
+            Box::pin(async move { result })
+        }
+    }
+
+    // Helper function to create a single-colour image
+    fn create_colour_image(r: u8, g: u8, b: u8) -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_fn(8, 8, |_, _| Rgb([r, g, b])))
+    }
+
+    // Helper function to create stores whose clothes store holds entry 1 with its image
+    fn create_stores_with_image() -> SharedStores {
+        let mut clothes = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1)
+            .unwrap()
+            .with_embedder(Arc::new(PixelEmbedder));
+        clothes.upsert(DataEntry {
+            id: 1,
+            name: "red".to_string(),
+            vector: vec![255.0, 0.0, 0.0],
+            descriptions: vec![],
+            image: Some(encode_image(&create_colour_image(255, 0, 0)).unwrap()),
+            created_at: 0,
+            image_hash: None,
+            metadata: Value::Null,
+            version: 0,
+            gender: None,
+        });
+        let face = InMemoryVectorStore::new(3, vec![], vec!["colour".to_string()], 1).unwrap();
+
+        SharedStores::new(Arc::new(RwLock::new(clothes)), Arc::new(RwLock::new(face)))
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_route_returns_json() {
+        let app =
+            test::init_service(App::new().wrap(json_error_handlers()).configure(config)).await;
+
+        let request = test::TestRequest::get().uri("/api/unknown").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["status"], false);
+        assert_eq!(body["message"], "No route matches GET /api/unknown");
+        assert!(body["data"].is_null());
+    }
+
+    #[actix_web::test]
+    async fn test_wrong_method_returns_json() {
+        let app =
+            test::init_service(App::new().wrap(json_error_handlers()).configure(config)).await;
+
+        let request = test::TestRequest::post()
+            .uri("/api/clothes/get")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["status"], false);
+        assert_eq!(
+            body["message"],
+            "Method POST is not allowed on /api/clothes/get"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_trailing_slash_resolves_to_route() {
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(Arc::new(create_stores_with_image())))
+                .wrap(json_error_handlers())
+                .wrap(NormalizePath::trim())
+                .configure(config),
+        )
+        .await;
+
+        for uri in ["/api/clothes/get", "/api/clothes/get/"] {
+            let request = test::TestRequest::get().uri(uri).to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+            let body: Value = test::read_body_json(response).await;
+            assert_eq!(body[0]["name"], "red", "{}", uri);
+
+            // the wrong method is reported on the canonical path
+            let request = test::TestRequest::post().uri(uri).to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED, "{}", uri);
+            let body: Value = test::read_body_json(response).await;
+            assert_eq!(
+                body["message"],
+                "Method POST is not allowed on /api/clothes/get"
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_routes_answer_once_the_stores_are_ready() {
+        let readiness: Data<Readiness> = Data::new(Readiness::new());
+        // wrapped as the server wraps its app
+        let app = test::init_service(
+            App::new()
+                .wrap(json_error_handlers())
+                .wrap(from_fn(require_ready))
+                .wrap(NormalizePath::trim())
+                .app_data(Data::new(Arc::new(create_stores_with_image())))
+                .app_data(readiness.clone())
+                .configure(config),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/api/clothes/get")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["message"], INITIALIZING_MESSAGE);
+
+        // liveness reaches its handler while loading
+        let request = test::TestRequest::get().uri("/api/live").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        readiness.set_ready();
+        let request = test::TestRequest::get()
+            .uri("/api/clothes/get")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body[0]["name"], "red");
+    }
+
+    #[actix_web::test]
+    async fn test_face_routes_are_registered() {
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(Arc::new(create_stores_with_image())))
+                .app_data(Data::new(stylist::stats::RequestCounters::default()))
+                .wrap(json_error_handlers())
+                .configure(config),
+        )
+        .await;
+
+        // bodies missing their fields reach the handlers' extractors
+        for uri in ["/api/face/upload", "/api/face/similarity"] {
+            let request = test::TestRequest::post()
+                .uri(uri)
+                .set_json(json!({}))
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        }
+
+        let request = test::TestRequest::get().uri("/api/face/get").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body, json!([]));
+
+        let request = test::TestRequest::delete()
+            .uri("/api/face/delete/1")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["status"], false);
+    }
+
+    #[actix_web::test]
+    async fn test_thumbnail_is_served_and_invalidated_by_an_edit() {
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(Arc::new(create_stores_with_image())))
+                .app_data(Data::new(Mutex::new(ThumbnailCache::new(1024 * 1024))))
+                .app_data(Data::new(stylist::stats::RequestCounters::default()))
+                .configure(config),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/api/clothes/image/1?w=4")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        let etag_before = response.headers()[header::ETAG].clone();
+        let before = test::read_body(response).await;
+        assert!(!before.is_empty());
+
+        let request = test::TestRequest::post()
+            .uri("/api/clothes/edit/1")
+            .set_json(json!({
+                "name": "blue",
+                "image": encode_image(&create_colour_image(0, 0, 255)).unwrap(),
+            }))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // the old thumbnail is neither revalidated nor served from the cache
+        let request = test::TestRequest::get()
+            .uri("/api/clothes/image/1?w=4")
+            .insert_header((header::IF_NONE_MATCH, etag_before.clone()))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        assert_ne!(response.headers()[header::ETAG], etag_before);
+        assert_ne!(test::read_body(response).await, before);
+    }
+
+    #[actix_web::test]
+    async fn test_embed_route_keeps_the_prompt_order() {
+        let prompts: Vec<String> = ["1", "2", "3"].map(String::from).to_vec();
+        let clothes = InMemoryVectorStore::new(3, vec![], prompts.clone(), 1)
+            .unwrap()
+            .with_prompt_batch_size(1)
+            .with_embedder(Arc::new(PromptValueEmbedder));
+        let face = InMemoryVectorStore::new(3, vec![], prompts, 1).unwrap();
+        let stores = SharedStores::new(Arc::new(RwLock::new(clothes)), Arc::new(RwLock::new(face)));
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(Arc::new(stores)))
+                .configure(config),
+        )
+        .await;
+
+        // the same image embeds to the same vector, laid out in prompt order
+        // although every prompt is scored in its own batch
+        let image: String = encode_image(&create_colour_image(12, 34, 56)).unwrap();
+        for _ in 0..2 {
+            let request = test::TestRequest::post()
+                .uri("/api/clothes/embed")
+                .set_json(json!({ "image": image }))
+                .to_request();
+            let body: Value = test::call_and_read_body_json(&app, request).await;
+            assert_eq!(body["data"], json!([1.0, 2.0, 3.0]));
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use stylist::snapshots::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use stylist::embedding::DataEntry;
+
+    // Helper function to create an entry with the given image hash and version
+    fn create_entry(id: usize, image_hash: u64, version: u64) -> DataEntry {
+        DataEntry {
+            id,
+            name: id.to_string(),
+            vector: vec![1.0, 0.0],
+            descriptions: vec![],
+            image: None,
+            created_at: 0,
+            image_hash: Some(image_hash),
+            metadata: Value::Null,
+            version,
+            gender: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_snapshots() {
+        let before = EntrySnapshot::capture(&[
+            create_entry(1, 10, 0),
+            create_entry(2, 20, 0),
+            create_entry(3, 30, 0),
+        ]);
+        let described = DataEntry {
+            descriptions: vec!["red".to_string()],
+            ..create_entry(2, 20, 0)
+        };
+        let after = EntrySnapshot::capture(&[
+            // a version bump alone leaves the content as it was
+            create_entry(1, 10, 3),
+            described,
+            create_entry(3, 31, 0),
+            create_entry(4, 40, 0),
+        ]);
+
+        let diff: SnapshotDiff = diff_snapshots(&before, &after);
+        assert_eq!(diff.added, vec![4]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.changed, vec![2, 3]);
+
+        let reversed: SnapshotDiff = diff_snapshots(&after, &before);
+        assert_eq!(reversed.removed, vec![4]);
+        assert!(reversed.added.is_empty());
+    }
+
+    #[test]
+    fn test_content_hash_covers_the_full_vector_and_metadata() {
+        let entry: DataEntry = create_entry(1, 10, 0);
+        assert_eq!(content_hash(&entry), content_hash(&entry.clone()));
+
+        // differences below the precision of responses still count
+        let nudged = DataEntry {
+            vector: vec![1.0 + 1e-12, 0.0],
+            ..entry.clone()
+        };
+        assert_ne!(content_hash(&nudged), content_hash(&entry));
+
+        let tagged = DataEntry {
+            metadata: serde_json::json!({"brand": "X"}),
+            ..entry.clone()
+        };
+        assert_ne!(content_hash(&tagged), content_hash(&entry));
+    }
+
+    #[test]
+    fn test_snapshots_drop_the_oldest_beyond_capacity() {
+        let mut snapshots = Snapshots::new(2);
+
+        assert!(snapshots
+            .insert("a".to_string(), EntrySnapshot::default())
+            .is_empty());
+        assert!(snapshots
+            .insert("b".to_string(), EntrySnapshot::default())
+            .is_empty());
+        // retaking a label replaces it and makes it the newest
+        assert!(snapshots
+            .insert("a".to_string(), EntrySnapshot::default())
+            .is_empty());
+        assert_eq!(
+            snapshots.insert("c".to_string(), EntrySnapshot::default()),
+            vec!["b"]
+        );
+
+        assert!(snapshots.get("a").is_some());
+        assert!(snapshots.get("b").is_none());
+        assert!(snapshots.get("c").is_some());
+    }
+}
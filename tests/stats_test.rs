@@ -0,0 +1,26 @@
+use stylist::stats::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reset_returns_and_zeroes_counters() {
+        let counters = RequestCounters::default();
+        counters.record_upload();
+        counters.record_search();
+        counters.record_search();
+        counters.record_thumbnail_miss();
+
+        let before: RequestCountersSnapshot = counters.reset();
+        assert_eq!(before.uploads, 1);
+        assert_eq!(before.searches, 2);
+        assert_eq!(before.deletes, 0);
+        assert_eq!(before.thumbnail_misses, 1);
+
+        let after: RequestCountersSnapshot = counters.snapshot();
+        assert_eq!(after.uploads, 0);
+        assert_eq!(after.searches, 0);
+        assert_eq!(after.thumbnail_misses, 0);
+    }
+}
@@ -0,0 +1,519 @@
+use stylist::store::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::Duration,
+    };
+    use stylist::embedding::{DataEntry, InMemoryVectorStore, VectorStore, Verdict};
+    use tokio::sync::RwLock;
+
+    // Helper function to create empty stores logging to the given directory
+    fn create_logged_stores(directory: &PathBuf) -> SharedStores {
+        let store = || {
+            Arc::new(RwLock::new(
+                InMemoryVectorStore::new(2, vec![], vec!["a".to_string()], 2).unwrap(),
+            ))
+        };
+
+        SharedStores::new(store(), store()).with_op_log(
+            directory.join("ops.jsonl").to_str().unwrap(),
+            directory.join("snapshot.json").to_str().unwrap(),
+            0,
+        )
+    }
+
+    // Helper function to create logged stores holding a few megabytes of
+    // entries, so saving them takes a while
+    async fn create_large_stores(directory: &Path) -> SharedStores {
+        let stores = create_logged_stores(&directory.to_path_buf());
+        {
+            let mut clothes = stores.clothes.write().await;
+            // few but long entries, as upserting is linear in the entries
+            for id in 1..=2_000 {
+                clothes.upsert(DataEntry {
+                    descriptions: vec!["x".repeat(1_000); 4],
+                    ..create_entry(id, &format!("entry {}", id))
+                });
+            }
+        }
+        stores
+    }
+
+    // Helper function to start saving the stores in the background, returning
+    // once the save writes its file
+    async fn start_save(
+        stores: &SharedStores,
+        path: &Path,
+    ) -> tokio::task::JoinHandle<Result<(), anyhow::Error>> {
+        let save = {
+            let stores = stores.clone();
+            let path: String = path.to_str().unwrap().to_string();
+            tokio::spawn(async move { stores.save(&path).await })
+        };
+
+        let temp_path = PathBuf::from(format!("{}.tmp", path.to_str().unwrap()));
+        for _ in 0..1_000 {
+            if temp_path.exists() || save.is_finished() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        save
+    }
+
+    // Helper function to create an entry with the given id and name
+    fn create_entry(id: usize, name: &str) -> DataEntry {
+        DataEntry {
+            id,
+            name: name.to_string(),
+            vector: vec![0.6, 0.8],
+            descriptions: vec![],
+            image: None,
+            created_at: 0,
+            image_hash: None,
+            metadata: serde_json::Value::Null,
+            version: 0,
+            gender: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_save_load_preserves_entry_order() {
+        let path = std::env::temp_dir().join(format!("stylist-order-{}.json", std::process::id()));
+        let path: &str = path.to_str().unwrap();
+        let store = || {
+            Arc::new(RwLock::new(
+                InMemoryVectorStore::new(2, vec![], vec!["a".to_string()], 2).unwrap(),
+            ))
+        };
+
+        let stores = SharedStores::new(store(), store());
+        {
+            let mut clothes = stores.clothes.write().await;
+            for (id, name) in [(3, "coat"), (1, "shirt"), (7, "skirt"), (2, "scarf")] {
+                clothes.upsert(create_entry(id, name));
+            }
+            clothes.delete(7).await.unwrap();
+        }
+        stores.save(path).await.unwrap();
+
+        let loaded = SharedStores::new(store(), store());
+        loaded.load(path).await.unwrap();
+        let saved: Vec<DataEntry> = stores.clothes.read().await.get_all();
+        let restored: Vec<DataEntry> = loaded.clothes.read().await.get_all();
+        assert_eq!(restored, saved);
+        assert_eq!(
+            restored
+                .iter()
+                .map(|entry| entry.id)
+                .collect::<Vec<usize>>(),
+            vec![3, 1, 2]
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_blocking_and_background_saves_write_the_same_file() {
+        let store = || {
+            Arc::new(RwLock::new(
+                InMemoryVectorStore::new(2, vec![], vec!["a".to_string()], 2).unwrap(),
+            ))
+        };
+        let clothes = store();
+        {
+            let mut clothes = clothes.write().await;
+            for (id, name) in [(1, "coat"), (2, "shirt")] {
+                clothes.upsert(create_entry(id, name));
+            }
+        }
+        let face = store();
+
+        let mut files: Vec<Vec<u8>> = Vec::new();
+        for blocking in [true, false] {
+            let path = std::env::temp_dir().join(format!(
+                "stylist-save-{}-{}.json",
+                blocking,
+                std::process::id()
+            ));
+            let path: &str = path.to_str().unwrap();
+            SharedStores::new(clothes.clone(), face.clone())
+                .with_blocking_save(blocking)
+                .save(path)
+                .await
+                .unwrap();
+            files.push(std::fs::read(path).unwrap());
+            std::fs::remove_file(path).unwrap();
+        }
+        assert_eq!(files[0], files[1]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_writers_do_not_wait_for_a_large_save() {
+        let directory =
+            std::env::temp_dir().join(format!("stylist-large-save-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let stores = create_large_stores(&directory).await;
+
+        let path = directory.join("large.json");
+        let save = start_save(&stores, &path).await;
+
+        let mut clothes = tokio::time::timeout(Duration::from_millis(500), stores.clothes.write())
+            .await
+            .expect("the save held the store lock");
+        clothes.upsert(create_entry(2_001, "upserted during the save"));
+        drop(clothes);
+
+        save.await.unwrap().unwrap();
+        let saved: String = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("entry 2000"));
+        // the save holds the stores as they were when it started
+        assert!(!saved.contains("upserted during the save"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_writers_do_not_wait_for_concurrent_saves() {
+        let directory =
+            std::env::temp_dir().join(format!("stylist-concurrent-save-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let stores = create_large_stores(&directory).await;
+
+        let path = directory.join("large.json");
+        let first = start_save(&stores, &path).await;
+        stores
+            .clothes
+            .write()
+            .await
+            .upsert(create_entry(2_001, "upserted between the saves"));
+        // the second save waits for the first, without holding the locks
+        let second = {
+            let stores = stores.clone();
+            let path: String = path.to_str().unwrap().to_string();
+            tokio::spawn(async move { stores.save(&path).await })
+        };
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        let mut clothes = tokio::time::timeout(Duration::from_millis(500), stores.clothes.write())
+            .await
+            .expect("a waiting save held the store lock");
+        clothes.upsert(create_entry(2_002, "upserted during the saves"));
+        drop(clothes);
+
+        first.await.unwrap().unwrap();
+        second.await.unwrap().unwrap();
+        // the newer copy is the one left on disk
+        let saved: String = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("upserted between the saves"));
+        assert!(!saved.contains("upserted during the saves"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_etag_changes_across_restarts() {
+        let directory = std::env::temp_dir();
+        let etag = |stores: SharedStores| stores.etag(&stores.clothes.blocking_read());
+        let before_restart = etag(create_logged_stores(&directory));
+        std::thread::sleep(Duration::from_millis(5));
+        let after_restart = etag(create_logged_stores(&directory));
+
+        // untouched stores of a later start never match an earlier ETag
+        assert_ne!(before_restart, after_restart);
+    }
+
+    #[tokio::test]
+    async fn test_etag_changes_with_the_store() {
+        let stores = create_logged_stores(&std::env::temp_dir());
+        let before: String = stores.etag(&stores.clothes.read().await);
+        assert_eq!(stores.etag(&stores.clothes.read().await), before);
+
+        // no handler bumps anything, mutating the store is enough
+        stores
+            .clothes
+            .write()
+            .await
+            .upsert(create_entry(1, "shirt"));
+        assert_ne!(stores.etag(&stores.clothes.read().await), before);
+    }
+
+    #[test]
+    fn test_pre_load_backup_path() {
+        assert_eq!(
+            pre_load_backup_path("vector_stores.json", 1_700_000_000_000),
+            "vector_stores.pre-load-backup.1700000000000.json"
+        );
+        assert_eq!(
+            pre_load_backup_path("stores", 42),
+            "stores.pre-load-backup.42.json"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_checks_the_schema_version() {
+        let path = std::env::temp_dir().join(format!("stylist-schema-{}.json", std::process::id()));
+        let path: &str = path.to_str().unwrap();
+        let store = || {
+            Arc::new(RwLock::new(
+                InMemoryVectorStore::new(2, vec![], vec!["a".to_string()], 2).unwrap(),
+            ))
+        };
+
+        let stores = SharedStores::new(store(), store());
+        stores
+            .clothes
+            .write()
+            .await
+            .upsert(create_entry(1, "shirt"));
+        stores.save(path).await.unwrap();
+        let mut saved: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(saved["schema_version"], SCHEMA_VERSION);
+
+        // files from before versioning are migrated
+        saved.as_object_mut().unwrap().remove("schema_version");
+        std::fs::write(path, saved.to_string()).unwrap();
+        let loaded = SharedStores::new(store(), store());
+        loaded.load(path).await.unwrap();
+        assert_eq!(loaded.clothes.read().await.len(), 1);
+
+        // files from a newer version are refused, leaving the stores alone
+        saved["schema_version"] = serde_json::Value::from(SCHEMA_VERSION + 1);
+        std::fs::write(path, saved.to_string()).unwrap();
+        let refused = SharedStores::new(store(), store());
+        let error = refused.load(path).await.unwrap_err();
+        assert!(error.to_string().contains("upgrade"));
+        assert!(refused.clothes.read().await.is_empty());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_is_a_loadable_snapshot() {
+        let path = std::env::temp_dir().join(format!("stylist-export-{}.json", std::process::id()));
+        let path: &str = path.to_str().unwrap();
+        let store = || {
+            Arc::new(RwLock::new(
+                InMemoryVectorStore::new(2, vec![], vec!["a".to_string()], 2).unwrap(),
+            ))
+        };
+
+        let stores = SharedStores::new(store(), store());
+        stores
+            .clothes
+            .write()
+            .await
+            .upsert(create_entry(1, "shirt"));
+        // a small channel and chunks, so serializing waits for the reader
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        let exporting = tokio::spawn({
+            let stores: SharedStores = stores.clone();
+            async move { stores.export_to(ChannelWriter::new(sender, 16)).await }
+        });
+
+        let mut exported: Vec<u8> = receiver.recv().await.unwrap().unwrap();
+        // later mutations are not part of the snapshot
+        stores.clothes.write().await.upsert(create_entry(2, "coat"));
+        while let Some(chunk) = receiver.recv().await {
+            exported.extend(chunk.unwrap());
+        }
+        exporting.await.unwrap().unwrap();
+
+        std::fs::write(path, &exported).unwrap();
+        let loaded = SharedStores::new(store(), store());
+        loaded.load(path).await.unwrap();
+        let restored: Vec<DataEntry> = loaded.clothes.read().await.get_all();
+        assert_eq!(restored, vec![create_entry(1, "shirt")]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_load_backs_up_live_stores() {
+        let directory = std::env::temp_dir().join(format!("stylist-backup-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("stores.json");
+        let path: &str = path.to_str().unwrap();
+        let store = || {
+            Arc::new(RwLock::new(
+                InMemoryVectorStore::new(2, vec![], vec!["a".to_string()], 2).unwrap(),
+            ))
+        };
+
+        // an empty file on disk, about to overwrite a live entry
+        SharedStores::new(store(), store())
+            .save(path)
+            .await
+            .unwrap();
+        let stores = SharedStores::new(store(), store()).with_load_backup(2);
+        for name in ["shirt", "skirt", "coat"] {
+            stores.clothes.write().await.upsert(create_entry(1, name));
+            stores.load(path).await.unwrap();
+            assert!(stores.clothes.read().await.is_empty());
+            // backups are named by the millisecond of the load
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+
+        // only the two newest backups are kept
+        let backups: Vec<String> = pre_load_backups(path).unwrap();
+        assert_eq!(backups.len(), 2);
+        let backup = SharedStores::new(store(), store());
+        backup.load(&backups[0]).await.unwrap();
+        assert_eq!(backup.clothes.read().await.get(1).unwrap().name, "skirt");
+        backup.load(&backups[1]).await.unwrap();
+        assert_eq!(backup.clothes.read().await.get(1).unwrap().name, "coat");
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_autosave_after_n_mutations() {
+        let path =
+            std::env::temp_dir().join(format!("stylist-autosave-{}.json", std::process::id()));
+        let store = || {
+            Arc::new(RwLock::new(
+                InMemoryVectorStore::new(2, vec![], vec!["a".to_string()], 2).unwrap(),
+            ))
+        };
+        let stores = SharedStores::new(store(), store()).with_autosave(
+            path.to_str().unwrap(),
+            3,
+            Duration::from_millis(20),
+        );
+
+        stores
+            .clothes
+            .write()
+            .await
+            .upsert(create_entry(1, "shirt"));
+        stores.record_mutation();
+        stores.record_mutation();
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(!path.exists());
+
+        // the burst after the threshold is covered by the debounced save
+        stores.record_mutation();
+        stores
+            .clothes
+            .write()
+            .await
+            .upsert(create_entry(2, "skirt"));
+        stores.record_mutation();
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(path.exists());
+
+        let loaded = SharedStores::new(store(), store());
+        loaded.load(path.to_str().unwrap()).await.unwrap();
+        assert_eq!(loaded.clothes.read().await.len(), 2);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_op_log_replay_and_compaction() {
+        let directory = std::env::temp_dir().join(format!("stylist-op-log-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let stores = create_logged_stores(&directory);
+        let verdict = StoreOp::Verdict {
+            store: StoreKind::Clothes,
+            verdict: Verdict {
+                query_id: 1,
+                candidate_id: 2,
+                similar: true,
+                score: 0.5,
+                created_at: 1,
+            },
+        };
+        for op in [
+            StoreOp::Put {
+                store: StoreKind::Clothes,
+                entry: create_entry(1, "shirt"),
+            },
+            StoreOp::Put {
+                store: StoreKind::Clothes,
+                entry: create_entry(2, "skirt"),
+            },
+            StoreOp::Put {
+                store: StoreKind::Face,
+                entry: create_entry(1, "face"),
+            },
+            StoreOp::Put {
+                store: StoreKind::Clothes,
+                entry: create_entry(1, "renamed shirt"),
+            },
+            StoreOp::Delete {
+                store: StoreKind::Clothes,
+                id: 2,
+            },
+            // replaying a verdict twice keeps it once
+            verdict.clone(),
+            verdict,
+        ] {
+            stores.append_op(&op).await.unwrap();
+        }
+
+        let restored = create_logged_stores(&directory);
+        assert_eq!(restored.restore().await.unwrap(), 7);
+        {
+            let clothes = restored.clothes.read().await;
+            assert_eq!(clothes.len(), 1);
+            assert_eq!(clothes.verdicts().len(), 1);
+            assert_eq!(clothes.get(1).unwrap().name, "renamed shirt");
+            assert_eq!(restored.face.read().await.len(), 1);
+        }
+
+        // after compaction everything comes from the snapshot
+        restored.compact().await.unwrap();
+        assert!(!directory.join("snapshot.json.tmp").exists());
+        assert!(!directory.join("ops.jsonl.compacting").exists());
+        let compacted = create_logged_stores(&directory);
+        assert_eq!(compacted.restore().await.unwrap(), 0);
+        assert_eq!(compacted.clothes.read().await.len(), 1);
+
+        // operations moved aside by a compaction that did not finish are
+        // replayed before the log
+        std::fs::write(
+            directory.join("ops.jsonl.compacting"),
+            serde_json::to_string(&StoreOp::Put {
+                store: StoreKind::Clothes,
+                entry: create_entry(3, "coat"),
+            })
+            .unwrap()
+                + "\n",
+        )
+        .unwrap();
+        compacted
+            .append_op(&StoreOp::Delete {
+                store: StoreKind::Clothes,
+                id: 3,
+            })
+            .await
+            .unwrap();
+        let interrupted = create_logged_stores(&directory);
+        assert_eq!(interrupted.restore().await.unwrap(), 2);
+        assert!(interrupted.clothes.read().await.get(3).is_none());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_append_is_reported() {
+        let directory =
+            std::env::temp_dir().join(format!("stylist-missing-{}/nested", std::process::id()));
+        let stores = create_logged_stores(&directory);
+
+        let appended = stores
+            .append_op(&StoreOp::Delete {
+                store: StoreKind::Clothes,
+                id: 1,
+            })
+            .await;
+        assert!(appended.is_err());
+    }
+}
@@ -0,0 +1,34 @@
+use stylist::timing::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_describe_slow_request() {
+        assert_eq!(
+            describe_slow_request(
+                "GET /api/clothes/get",
+                Duration::from_millis(1500),
+                None,
+                &[]
+            ),
+            "Slow request GET /api/clothes/get took 1500 ms"
+        );
+
+        let phases = [
+            ("embedding", Duration::from_millis(1200)),
+            ("search", Duration::from_millis(35)),
+        ];
+        assert_eq!(
+            describe_slow_request(
+                "POST /api/similarity/calculate",
+                Duration::from_millis(1250),
+                Some("abc-123"),
+                &phases
+            ),
+            "Slow request POST /api/similarity/calculate took 1250 ms (request id abc-123): embedding 1200 ms, search 35 ms"
+        );
+    }
+}